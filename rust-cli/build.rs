@@ -0,0 +1,205 @@
+// Collects build provenance (git revision, build date, features, locked
+// dependencies compiled in) into $OUT_DIR/build_info.json for `--version --verbose`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn build_date() -> String {
+    // Honor SOURCE_DATE_EPOCH for reproducible builds
+    let secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0)
+        });
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let rem = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// The features this build enables, named as in Cargo.toml (CARGO_FEATURE_*
+// upper-cases them and turns '-' into '_')
+fn enabled_features(manifest: &toml::Table) -> Vec<String> {
+    let mut features: Vec<String> = manifest
+        .get("features")
+        .and_then(|f| f.as_table())
+        .map(|table| {
+            table
+                .keys()
+                .filter(|name| env::var_os(format!("CARGO_FEATURE_{}", name.to_uppercase().replace('-', "_"))).is_some())
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    features.sort();
+    features
+}
+
+// Checksums from Cargo.lock, by name, version, and source
+fn lock_checksums(manifest_dir: &Path) -> HashMap<(String, String, String), String> {
+    let lock_path = manifest_dir.join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock_path.display());
+
+    let lock = fs::read_to_string(&lock_path).ok().and_then(|content| content.parse::<toml::Table>().ok());
+    let packages = lock.as_ref().and_then(|lock| lock.get("package")).and_then(|p| p.as_array());
+    packages
+        .into_iter()
+        .flatten()
+        .filter_map(|p| {
+            let field = |key: &str| p.get(key).and_then(|v| v.as_str()).map(str::to_string);
+            Some(((field("name")?, field("version")?, field("source")?), field("checksum")?))
+        })
+        .collect()
+}
+
+// The registry and git packages compiled into this binary: the normal
+// dependencies reachable from this package in the graph cargo resolves for the
+// enabled features and the target platform. Build and dev dependencies, and
+// optional ones no enabled feature turns on, aren't in the binary.
+fn locked_dependencies(manifest_dir: &Path, features: &[String]) -> Vec<serde_json::Value> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let manifest_path = manifest_dir.join("Cargo.toml");
+    let target = env::var("TARGET").unwrap_or_default();
+    let features = features.join(",");
+    let args = [
+        "metadata",
+        "--format-version=1",
+        "--offline",
+        "--locked",
+        "--no-default-features",
+        "--features",
+        &features,
+        "--filter-platform",
+        &target,
+        "--manifest-path",
+        manifest_path.to_str().unwrap_or_default(),
+    ];
+    let Some(metadata) = command_output(&cargo, &args).and_then(|out| serde_json::from_str::<serde_json::Value>(&out).ok()) else {
+        println!("cargo:warning=cargo metadata failed; the build info lists no dependencies");
+        return Vec::new();
+    };
+
+    let resolve = &metadata["resolve"];
+    let nodes: HashMap<&str, &serde_json::Value> = resolve["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|node| Some((node["id"].as_str()?, node)))
+        .collect();
+    let mut reachable = BTreeSet::new();
+    let mut pending: Vec<&str> = resolve["root"].as_str().into_iter().collect();
+    while let Some(id) = pending.pop() {
+        let Some(node) = nodes.get(id) else { continue };
+        for dep in node["deps"].as_array().into_iter().flatten() {
+            let normal = dep["dep_kinds"].as_array().into_iter().flatten().any(|kind| kind["kind"].is_null());
+            if let Some(pkg) = dep["pkg"].as_str().filter(|_| normal) {
+                if reachable.insert(pkg) {
+                    pending.push(pkg);
+                }
+            }
+        }
+    }
+
+    let checksums = lock_checksums(manifest_dir);
+    let mut dependencies: Vec<serde_json::Value> = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|p| p["id"].as_str().is_some_and(|id| reachable.contains(id)))
+        .filter_map(|p| {
+            let (name, version, source) = (p["name"].as_str()?, p["version"].as_str()?, p["source"].as_str()?);
+            let checksum = checksums.get(&(name.to_string(), version.to_string(), source.to_string()));
+            Some(serde_json::json!({
+                "name": name,
+                "version": version,
+                "source": source,
+                "checksum": checksum,
+            }))
+        })
+        .collect();
+    dependencies.sort_by_key(|d| (d["name"].as_str().map(str::to_string), d["version"].as_str().map(str::to_string)));
+    dependencies
+}
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let manifest_dir = Path::new(&manifest_dir);
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    if let Some(git_dir) = command_output("git", &["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/index", git_dir);
+    }
+
+    let git_sha = command_output("git", &["rev-parse", "HEAD"]);
+    let git_dirty = command_output("git", &["status", "--porcelain", "--untracked-files=no"])
+        .map(|s| !s.is_empty());
+
+    let manifest_path = manifest_dir.join("Cargo.toml");
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+    let manifest = fs::read_to_string(&manifest_path)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+        .unwrap_or_default();
+    let features = enabled_features(&manifest);
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+
+    let info = serde_json::json!({
+        "name": env::var("CARGO_PKG_NAME").unwrap_or_default(),
+        "version": env::var("CARGO_PKG_VERSION").unwrap_or_default(),
+        "gitSha": git_sha,
+        "gitDirty": git_dirty,
+        "buildDate": build_date(),
+        "target": env::var("TARGET").unwrap_or_default(),
+        "profile": env::var("PROFILE").unwrap_or_default(),
+        "rustc": command_output(&rustc, &["--version"]),
+        "features": features,
+        "lockedDependencies": locked_dependencies(manifest_dir, &features),
+    });
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("build_info.json"),
+        serde_json::to_string_pretty(&info).unwrap(),
+    )
+    .unwrap();
+}
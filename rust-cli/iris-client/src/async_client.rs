@@ -0,0 +1,276 @@
+use anyhow::{Context, Result};
+use reqwest::header::HeaderMap;
+use reqwest::{Body, Client, RequestBuilder, Response, StatusCode};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::client::{ExtractionOptions, ExtractionState, ExtractionStatus, RequestObserver, RetryPolicy, TokenProvider};
+use crate::content_type::detect_content_type;
+use crate::models::{
+    CompleteUploadRequest, ExtractionResultData, MultipartUpload, StartExtractionRequest, StartExtractionResponse,
+    StartUploadRequest, StartUploadResponse,
+};
+use crate::settings::{Attempts, Settings};
+use crate::spool::{self, SpooledBody, TextSpill};
+
+/// Async client for the Iris extraction API; one instance can drive many
+/// uploads and status polls concurrently on a tokio runtime
+#[derive(Clone)]
+pub struct AsyncIrisClient {
+    http: Client,
+    settings: Settings,
+}
+
+impl AsyncIrisClient {
+    /// Client for an organization; `api_url` is the API root such as `https://api.vectorize.io`
+    pub fn new(api_url: &str, org_id: &str, token: Arc<dyn TokenProvider>) -> AsyncIrisClient {
+        AsyncIrisClient {
+            http: Client::new(),
+            settings: Settings::new(api_url, org_id, token),
+        }
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<AsyncIrisClient> {
+        self.settings.set_user_agent(user_agent)?;
+        Ok(self)
+    }
+
+    /// Send `X-Correlation-ID` with every request to the API (not to upload
+    /// URLs on other hosts)
+    pub fn with_correlation_id(mut self, correlation_id: &str) -> Result<AsyncIrisClient> {
+        self.settings.set_correlation_id(correlation_id)?;
+        Ok(self)
+    }
+
+    pub fn with_observer(mut self, observer: Arc<dyn RequestObserver>) -> AsyncIrisClient {
+        self.settings.observer = Some(observer);
+        self
+    }
+
+    /// Replace the default retry policy (3 retries starting at 1 second)
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> AsyncIrisClient {
+        self.settings.retry = retry;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.settings.base_url
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.settings.correlation_id.as_deref()
+    }
+
+    // Send a request, retrying network errors and 5xx responses with backoff
+    // and waiting out rate limits
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        self.send_with(|| builder.try_clone().context("Request cannot be cloned")).await
+    }
+
+    // Like `send`, for requests that are rebuilt for every attempt
+    async fn send_with(&self, build: impl Fn() -> Result<RequestBuilder>) -> Result<Response> {
+        let mut attempts = Attempts::default();
+        loop {
+            let outcome = build()?.send().await;
+            match self.settings.retry_delay(&mut attempts, outcome.as_ref().map(|r| (r.status(), r.headers()))) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Ok(outcome?),
+            }
+        }
+    }
+
+    // Send an API request with `extra` headers, fetching a fresh token and
+    // retrying once if it was rejected
+    async fn send_authorized(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&str>,
+        extra: &HeaderMap,
+        build: impl Fn() -> RequestBuilder,
+    ) -> Result<Response> {
+        let mut refreshed = false;
+        loop {
+            let headers = self.settings.authorized_headers(url, extra)?;
+            self.settings.observe_request(method, url, &headers, body);
+            let response = self.send(build().headers(headers)).await?;
+
+            if self.settings.refresh_token(response.status(), &mut refreshed) {
+                let (status, headers) = (response.status(), response.headers().clone());
+                self.settings.observe_token_refresh(status, &headers, &response.text().await?);
+                continue;
+            }
+            return Ok(response);
+        }
+    }
+
+    async fn post_json<T: serde::Serialize>(&self, url: &str, payload: &T) -> Result<(StatusCode, String)> {
+        let body = serde_json::to_vec(payload)?;
+        let pretty = serde_json::to_string_pretty(payload)?;
+        let response = self.send_authorized("POST", url, Some(&pretty), &Settings::json_headers(), || {
+            self.http.post(url).body(body.clone())
+        }).await?;
+        self.read_text(response).await
+    }
+
+    async fn read_text(&self, response: Response) -> Result<(StatusCode, String)> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text().await?;
+        self.settings.observe_response(status, &headers, &text);
+        Ok((status, text))
+    }
+
+    /// Register a file and get a presigned URL to upload its content to
+    pub async fn start_upload(&self, name: &str, content_type: &str) -> Result<StartUploadResponse> {
+        self.start_upload_request(Settings::upload_request(name, content_type, None)).await
+    }
+
+    /// Like `start_upload`, but asks for a multipart upload when the file is
+    /// at least `MULTIPART_THRESHOLD` bytes; if the service agrees, the
+    /// response has `multipart` set and the content goes to `upload_parts`
+    pub async fn start_sized_upload(&self, name: &str, content_type: &str, size: u64) -> Result<StartUploadResponse> {
+        self.start_upload_request(Settings::upload_request(name, content_type, Some(size))).await
+    }
+
+    async fn start_upload_request(&self, request: StartUploadRequest) -> Result<StartUploadResponse> {
+        let (status, text) = self.post_json(&self.settings.files_url(), &request).await.context("Failed to start upload")?;
+        Settings::upload_response(status, &text)
+    }
+
+    /// Upload file content to the presigned URL returned by `start_upload`
+    pub async fn upload_file(&self, upload_url: &str, content: Vec<u8>) -> Result<()> {
+        let headers = self.settings.upload_headers(upload_url, content.len() as u64);
+        self.settings.observe_request("PUT", upload_url, &headers, Some(&format!("<binary data: {} bytes>", content.len())));
+        let response = self.send(self.http.put(upload_url).headers(headers).body(content))
+            .await
+            .context("Failed to upload file")?;
+        self.finish_upload(response).await
+    }
+
+    /// Stream a file to the presigned URL returned by `start_upload` without
+    /// reading it into memory
+    pub async fn upload_path(&self, upload_url: &str, path: &Path) -> Result<()> {
+        let size = tokio::fs::metadata(path)
+            .await
+            .context(format!("Failed to read file: {}", path.display()))?
+            .len();
+        let headers = self.settings.upload_headers(upload_url, size);
+        self.settings.observe_request("PUT", upload_url, &headers, Some(&format!("<binary data: {} bytes>", size)));
+
+        // A streamed body can't be cloned, so every attempt reopens the file
+        let response = self.send_with(|| {
+            let file = std::fs::File::open(path).context(format!("Failed to read file: {}", path.display()))?;
+            Ok(self.http.put(upload_url).headers(headers.clone()).body(Body::from(tokio::fs::File::from_std(file))))
+        }).await.context("Failed to upload file")?;
+        self.finish_upload(response).await
+    }
+
+    /// Upload a file in parts to the URLs of a multipart upload, then tell the
+    /// service the upload is complete. Each part is read into memory while it
+    /// is sent and retried on its own, so a dropped connection only costs the
+    /// part in flight.
+    pub async fn upload_parts(&self, file_id: &str, multipart: &MultipartUpload, path: &Path) -> Result<()> {
+        let size = tokio::fs::metadata(path)
+            .await
+            .context(format!("Failed to read file: {}", path.display()))?
+            .len();
+        let mut completed = Vec::new();
+        for (part, (offset, len)) in multipart.parts.iter().zip(Settings::part_ranges(multipart, size)?) {
+            let mut file = tokio::fs::File::open(path).await.context(format!("Failed to read file: {}", path.display()))?;
+            file.seek(SeekFrom::Start(offset)).await?;
+            let mut content = vec![0; len as usize];
+            file.read_exact(&mut content).await.context(format!("Failed to read file: {}", path.display()))?;
+
+            let headers = self.settings.upload_headers(&part.upload_url, len);
+            self.settings.observe_request("PUT", &part.upload_url, &headers, Some(&format!("<part {}: {} bytes>", part.part_number, len)));
+            let response = self.send(self.http.put(&part.upload_url).headers(headers).body(content))
+                .await
+                .context(format!("Failed to upload part {}", part.part_number))?;
+            let headers = response.headers().clone();
+            self.finish_upload(response).await.context(format!("Failed to upload part {}", part.part_number))?;
+            completed.push(Settings::completed_part(part, &headers)?);
+        }
+
+        let (status, text) = self.post_json(&self.settings.complete_url(file_id), &CompleteUploadRequest { parts: completed })
+            .await
+            .context("Failed to complete upload")?;
+        Settings::check_status("complete upload", status, &text)
+    }
+
+    async fn finish_upload(&self, response: Response) -> Result<()> {
+        let (status, text) = self.read_text(response).await?;
+        Settings::check_status("upload file", status, &text)
+    }
+
+    pub async fn start_extraction(&self, request: &StartExtractionRequest) -> Result<StartExtractionResponse> {
+        let (status, text) = self.post_json(&self.settings.extraction_url(), request)
+            .await
+            .context("Failed to start extraction")?;
+        Settings::api_response("start extraction", status, &text)
+    }
+
+    /// Check an extraction once; pass the previous ETag to get a 304 while it is unchanged
+    pub async fn get_extraction(&self, extraction_id: &str, etag: Option<&str>) -> Result<ExtractionStatus> {
+        self.get_extraction_spilling(extraction_id, etag, None).await
+    }
+
+    /// Like `get_extraction`, but a text over `spill.max_size` bytes is streamed
+    /// from the response into a file, reported as the status's `text_file`
+    pub async fn get_extraction_spilling(&self, extraction_id: &str, etag: Option<&str>, spill: Option<&TextSpill>) -> Result<ExtractionStatus> {
+        let url = self.settings.status_url(extraction_id);
+        let response = self.send_authorized("GET", &url, None, &Settings::status_headers(etag)?, || self.http.get(&url))
+            .await
+            .context("Failed to check status")?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        // Completed results can carry the full text, so large bodies go to disk
+        let body = SpooledBody::read_async(response, spool::MEMORY_LIMIT)
+            .await
+            .context("Failed to check status")?;
+        self.settings.extraction_status(extraction_id, etag, status, &headers, body, spill)
+    }
+
+    /// Poll until the extraction is ready or `timeout` has passed
+    pub async fn wait_for_extraction(
+        &self,
+        extraction_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<ExtractionResultData> {
+        let start = Instant::now();
+        let mut etag: Option<String> = None;
+        loop {
+            Settings::check_timeout(start, timeout)?;
+            let status = self.get_extraction(extraction_id, etag.as_deref()).await?;
+            if let ExtractionState::Ready(data) = status.state {
+                return Ok(*data);
+            }
+            etag = status.etag;
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Upload a local file, start an extraction, and wait for the result
+    pub async fn extract_file(&self, path: &Path, options: &ExtractionOptions) -> Result<ExtractionResultData> {
+        let name = Settings::upload_name(path)?;
+        let size = tokio::fs::metadata(path)
+            .await
+            .context(format!("Failed to read file: {}", path.display()))?
+            .len();
+        let content_type = options.content_type.clone().unwrap_or_else(|| detect_content_type(path));
+        let upload = self.start_sized_upload(&name, &content_type, size).await?;
+        match &upload.multipart {
+            Some(multipart) => self.upload_parts(&upload.file_id, multipart, path).await?,
+            None => self.upload_path(&upload.upload_url, path).await?,
+        }
+        let extraction = self
+            .start_extraction(&Settings::extraction_request(upload.file_id, options))
+            .await?;
+        self.wait_for_extraction(&extraction.extraction_id, options.poll_interval, options.timeout)
+            .await
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{Context, Result};
+use reqwest::blocking::{Body, Client, RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Seek, SeekFrom, Take};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::content_type::detect_content_type;
+use crate::models::{
+    CompleteUploadRequest, ExtractionResultData, MetadataStrategy, MultipartUpload, StartExtractionRequest,
+    StartExtractionResponse, StartUploadRequest, StartUploadResponse,
+};
+use crate::settings::{Attempts, Settings};
+use crate::spool::{self, SpooledBody, TextSpill};
+
+pub const DEFAULT_API_URL: &str = "https://api.vectorize.io";
+
+/// Files at least this large are uploaded in parts when the service supports it
+pub const MULTIPART_THRESHOLD: u64 = 100 * 1024 * 1024;
+
+/// Supplies the bearer token for each request
+pub trait TokenProvider: Send + Sync {
+    fn token(&self) -> Result<String>;
+
+    /// Drop a rejected token; returns true if asking again may yield a new one
+    fn invalidate(&self) -> bool {
+        false
+    }
+}
+
+impl TokenProvider for String {
+    fn token(&self) -> Result<String> {
+        Ok(self.clone())
+    }
+}
+
+/// Sees every request and response, e.g. for verbose logging
+pub trait RequestObserver: Send + Sync {
+    fn on_request(&self, method: &str, url: &str, headers: &HeaderMap, body: Option<&str>);
+
+    fn on_response(&self, status: StatusCode, headers: &HeaderMap, body: &str);
+
+    /// Called when a rejected token is about to be replaced and the request retried
+    fn on_token_refresh(&self) {}
+
+    /// Called before waiting `delay` to retry a request that failed with a transient error
+    fn on_retry(&self, _attempt: u32, _delay: Duration, _reason: &str) {}
+
+    /// Called before waiting `delay` to resend a request the API rate-limited (HTTP 429)
+    fn on_throttle(&self, _delay: Duration) {}
+}
+
+/// How requests that fail with a network error or a 5xx response are retried
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled for each further retry, with jitter
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    const MAX_DELAY: Duration = Duration::from_secs(60);
+
+    /// Delay before retry number `attempt` (0-based): between half and all of
+    /// `backoff * 2^attempt`, so concurrent clients don't retry in lockstep
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let full = self.backoff.saturating_mul(1 << attempt.min(16)).min(Self::MAX_DELAY);
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        full.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+/// Outcome of a single status check
+pub enum ExtractionState {
+    /// Nothing changed since the ETag passed to `get_extraction`
+    NotModified,
+    Pending,
+    Ready(Box<ExtractionResultData>),
+}
+
+pub struct ExtractionStatus {
+    pub state: ExtractionState,
+    /// Pass back to `get_extraction` to get a cheap 304 while nothing changes
+    pub etag: Option<String>,
+    pub bytes_received: u64,
+    /// Where the text of a ready result was spilled by `get_extraction_spilling`;
+    /// the result's `text` is then None
+    pub text_file: Option<PathBuf>,
+    /// Fields of a ready result this version of the client doesn't know about
+    pub unknown_fields: Vec<String>,
+}
+
+/// Settings for `IrisClient::extract_file`
+#[derive(Clone)]
+pub struct ExtractionOptions {
+    /// Extraction backend the service runs; iris when not set
+    pub extraction_type: Option<String>,
+    pub chunk_size: Option<u32>,
+    /// How much of the end of each chunk the next one repeats, in the units of the chunk size
+    pub chunk_overlap: Option<u32>,
+    /// Where chunks are split: fixed, markdown, sentence, or semantic
+    pub chunking_strategy: Option<String>,
+    pub metadata: Option<MetadataStrategy>,
+    pub parsing_instructions: Option<String>,
+    /// Webhook to notify when the extraction finishes (`extract_file` still polls)
+    pub callback_url: Option<String>,
+    /// Ask for named entities (people, organizations, dates, amounts)
+    pub entities: bool,
+    /// Ask for the fields of fillable forms
+    pub form_fields: bool,
+    /// Tune recognition for handwritten documents
+    pub handwriting: bool,
+    /// MIME type to upload as, instead of the one detected from the file
+    pub content_type: Option<String>,
+    pub poll_interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> ExtractionOptions {
+        ExtractionOptions {
+            extraction_type: None,
+            chunk_size: None,
+            chunk_overlap: None,
+            chunking_strategy: None,
+            metadata: Some(MetadataStrategy {
+                schemas: None,
+                infer_schema: Some(true),
+            }),
+            parsing_instructions: None,
+            callback_url: None,
+            entities: false,
+            form_fields: false,
+            handwriting: false,
+            content_type: None,
+            poll_interval: Duration::from_secs(2),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Blocking client for the Iris extraction API
+pub struct IrisClient {
+    http: Client,
+    settings: Settings,
+}
+
+impl IrisClient {
+    /// Client for an organization; `api_url` is the API root such as `https://api.vectorize.io`
+    pub fn new(api_url: &str, org_id: &str, token: Arc<dyn TokenProvider>) -> IrisClient {
+        IrisClient {
+            http: Client::new(),
+            settings: Settings::new(api_url, org_id, token),
+        }
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<IrisClient> {
+        self.settings.set_user_agent(user_agent)?;
+        Ok(self)
+    }
+
+    /// Send `X-Correlation-ID` with every request to the API (not to upload
+    /// URLs on other hosts)
+    pub fn with_correlation_id(mut self, correlation_id: &str) -> Result<IrisClient> {
+        self.settings.set_correlation_id(correlation_id)?;
+        Ok(self)
+    }
+
+    pub fn with_observer(mut self, observer: Arc<dyn RequestObserver>) -> IrisClient {
+        self.settings.observer = Some(observer);
+        self
+    }
+
+    /// Replace the default retry policy (3 retries starting at 1 second)
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> IrisClient {
+        self.settings.retry = retry;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.settings.base_url
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.settings.correlation_id.as_deref()
+    }
+
+    // Send a request, retrying network errors and 5xx responses with backoff
+    // and waiting out rate limits
+    fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        self.send_with(|| builder.try_clone().context("Request cannot be cloned"))
+    }
+
+    // Like `send`, for requests that are rebuilt for every attempt
+    fn send_with(&self, build: impl Fn() -> Result<RequestBuilder>) -> Result<Response> {
+        let mut attempts = Attempts::default();
+        loop {
+            let outcome = build()?.send();
+            match self.settings.retry_delay(&mut attempts, outcome.as_ref().map(|r| (r.status(), r.headers()))) {
+                Some(delay) => thread::sleep(delay),
+                None => return Ok(outcome?),
+            }
+        }
+    }
+
+    // Send an API request with `extra` headers, fetching a fresh token and
+    // retrying once if it was rejected
+    fn send_authorized(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&str>,
+        extra: &HeaderMap,
+        build: impl Fn() -> RequestBuilder,
+    ) -> Result<Response> {
+        let mut refreshed = false;
+        loop {
+            let headers = self.settings.authorized_headers(url, extra)?;
+            self.settings.observe_request(method, url, &headers, body);
+            let response = self.send(build().headers(headers))?;
+
+            if self.settings.refresh_token(response.status(), &mut refreshed) {
+                let (status, headers) = (response.status(), response.headers().clone());
+                self.settings.observe_token_refresh(status, &headers, &response.text()?);
+                continue;
+            }
+            return Ok(response);
+        }
+    }
+
+    fn post_json<T: serde::Serialize>(&self, url: &str, payload: &T) -> Result<(StatusCode, String)> {
+        let body = serde_json::to_vec(payload)?;
+        let pretty = serde_json::to_string_pretty(payload)?;
+        let response = self.send_authorized("POST", url, Some(&pretty), &Settings::json_headers(), || {
+            self.http.post(url).body(body.clone())
+        })?;
+        self.read_text(response)
+    }
+
+    fn read_text(&self, response: Response) -> Result<(StatusCode, String)> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text()?;
+        self.settings.observe_response(status, &headers, &text);
+        Ok((status, text))
+    }
+
+    /// Register a file and get a presigned URL to upload its content to
+    pub fn start_upload(&self, name: &str, content_type: &str) -> Result<StartUploadResponse> {
+        self.start_upload_request(Settings::upload_request(name, content_type, None))
+    }
+
+    /// Like `start_upload`, but asks for a multipart upload when the file is
+    /// at least `MULTIPART_THRESHOLD` bytes; if the service agrees, the
+    /// response has `multipart` set and the content goes to `upload_parts`
+    pub fn start_sized_upload(&self, name: &str, content_type: &str, size: u64) -> Result<StartUploadResponse> {
+        self.start_upload_request(Settings::upload_request(name, content_type, Some(size)))
+    }
+
+    fn start_upload_request(&self, request: StartUploadRequest) -> Result<StartUploadResponse> {
+        let (status, text) = self.post_json(&self.settings.files_url(), &request).context("Failed to start upload")?;
+        Settings::upload_response(status, &text)
+    }
+
+    /// Upload file content to the presigned URL returned by `start_upload`
+    pub fn upload_file(&self, upload_url: &str, content: Vec<u8>) -> Result<()> {
+        let headers = self.settings.upload_headers(upload_url, content.len() as u64);
+        self.settings.observe_request("PUT", upload_url, &headers, Some(&format!("<binary data: {} bytes>", content.len())));
+        let response = self.send(self.http.put(upload_url).headers(headers).body(content)).context("Failed to upload file")?;
+        self.finish_upload(response)
+    }
+
+    /// Stream a file to the presigned URL returned by `start_upload` without
+    /// reading it into memory
+    pub fn upload_path(&self, upload_url: &str, path: &Path) -> Result<()> {
+        self.upload_path_with(upload_url, path, |file| file)
+    }
+
+    /// Like `upload_path`, but each attempt reads the file through `wrap`,
+    /// for example to report how many bytes have been sent
+    pub fn upload_path_with<R>(&self, upload_url: &str, path: &Path, wrap: impl Fn(File) -> R) -> Result<()>
+    where
+        R: Read + Send + 'static,
+    {
+        let size = fs::metadata(path).context(format!("Failed to read file: {}", path.display()))?.len();
+        let headers = self.settings.upload_headers(upload_url, size);
+        self.settings.observe_request("PUT", upload_url, &headers, Some(&format!("<binary data: {} bytes>", size)));
+
+        // A streamed body can't be cloned, so every attempt reopens the file
+        let response = self.send_with(|| {
+            let file = File::open(path).context(format!("Failed to read file: {}", path.display()))?;
+            Ok(self.http.put(upload_url).headers(headers.clone()).body(Body::sized(wrap(file), size)))
+        }).context("Failed to upload file")?;
+        self.finish_upload(response)
+    }
+
+    /// Upload a file in parts to the URLs of a multipart upload, then tell the
+    /// service the upload is complete. Each part is retried on its own, so a
+    /// dropped connection only costs the part in flight.
+    pub fn upload_parts(&self, file_id: &str, multipart: &MultipartUpload, path: &Path) -> Result<()> {
+        self.upload_parts_with(file_id, multipart, path, |_, part| part)
+    }
+
+    /// Like `upload_parts`, but each attempt reads its part through `wrap`,
+    /// which also gets the part's offset in the file
+    pub fn upload_parts_with<R>(
+        &self,
+        file_id: &str,
+        multipart: &MultipartUpload,
+        path: &Path,
+        wrap: impl Fn(u64, Take<File>) -> R,
+    ) -> Result<()>
+    where
+        R: Read + Send + 'static,
+    {
+        let size = fs::metadata(path).context(format!("Failed to read file: {}", path.display()))?.len();
+        let mut completed = Vec::new();
+        for (part, (offset, len)) in multipart.parts.iter().zip(Settings::part_ranges(multipart, size)?) {
+            let headers = self.settings.upload_headers(&part.upload_url, len);
+            self.settings.observe_request("PUT", &part.upload_url, &headers, Some(&format!("<part {}: {} bytes>", part.part_number, len)));
+
+            let response = self.send_with(|| {
+                let mut file = File::open(path).context(format!("Failed to read file: {}", path.display()))?;
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(self.http.put(&part.upload_url).headers(headers.clone()).body(Body::sized(wrap(offset, file.take(len)), len)))
+            }).context(format!("Failed to upload part {}", part.part_number))?;
+            let headers = response.headers().clone();
+            self.finish_upload(response).context(format!("Failed to upload part {}", part.part_number))?;
+            completed.push(Settings::completed_part(part, &headers)?);
+        }
+
+        let (status, text) = self.post_json(&self.settings.complete_url(file_id), &CompleteUploadRequest { parts: completed })
+            .context("Failed to complete upload")?;
+        Settings::check_status("complete upload", status, &text)
+    }
+
+    fn finish_upload(&self, response: Response) -> Result<()> {
+        let (status, text) = self.read_text(response)?;
+        Settings::check_status("upload file", status, &text)
+    }
+
+    pub fn start_extraction(&self, request: &StartExtractionRequest) -> Result<StartExtractionResponse> {
+        let (status, text) = self.post_json(&self.settings.extraction_url(), request).context("Failed to start extraction")?;
+        Settings::api_response("start extraction", status, &text)
+    }
+
+    /// Check an extraction once; pass the previous ETag to get a 304 while it is unchanged
+    pub fn get_extraction(&self, extraction_id: &str, etag: Option<&str>) -> Result<ExtractionStatus> {
+        self.get_extraction_spilling(extraction_id, etag, None)
+    }
+
+    /// Like `get_extraction`, but a text over `spill.max_size` bytes is streamed
+    /// from the response into a file, reported as the status's `text_file`
+    pub fn get_extraction_spilling(&self, extraction_id: &str, etag: Option<&str>, spill: Option<&TextSpill>) -> Result<ExtractionStatus> {
+        let url = self.settings.status_url(extraction_id);
+        let response = self.send_authorized("GET", &url, None, &Settings::status_headers(etag)?, || self.http.get(&url))
+            .context("Failed to check status")?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        // Completed results can carry the full text, so large bodies go to disk
+        let body = SpooledBody::read(response, spool::MEMORY_LIMIT).context("Failed to check status")?;
+        self.settings.extraction_status(extraction_id, etag, status, &headers, body, spill)
+    }
+
+    /// Poll until the extraction is ready or `timeout` has passed
+    pub fn wait_for_extraction(
+        &self,
+        extraction_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<ExtractionResultData> {
+        let start = Instant::now();
+        let mut etag: Option<String> = None;
+        loop {
+            Settings::check_timeout(start, timeout)?;
+            let status = self.get_extraction(extraction_id, etag.as_deref())?;
+            if let ExtractionState::Ready(data) = status.state {
+                return Ok(*data);
+            }
+            etag = status.etag;
+            thread::sleep(poll_interval);
+        }
+    }
+
+    /// Upload a local file, start an extraction, and wait for the result
+    pub fn extract_file(&self, path: &Path, options: &ExtractionOptions) -> Result<ExtractionResultData> {
+        let name = Settings::upload_name(path)?;
+        let size = fs::metadata(path).context(format!("Failed to read file: {}", path.display()))?.len();
+        let content_type = options.content_type.clone().unwrap_or_else(|| detect_content_type(path));
+        let upload = self.start_sized_upload(&name, &content_type, size)?;
+        match &upload.multipart {
+            Some(multipart) => self.upload_parts(&upload.file_id, multipart, path)?,
+            None => self.upload_path(&upload.upload_url, path)?,
+        }
+        let extraction = self.start_extraction(&Settings::extraction_request(upload.file_id, options))?;
+        self.wait_for_extraction(&extraction.extraction_id, options.poll_interval, options.timeout)
+    }
+}
@@ -0,0 +1,41 @@
+// Content type detection for uploads, so the service's parser gets an
+// accurate hint instead of application/octet-stream.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Sent when neither the content nor the extension gives the type away
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Bytes read to recognize a file; enough for the zip entries of Office files
+const SNIFF_LEN: u64 = 8192;
+
+// Magic-byte matches that only name the container, such as the zip archive
+// inside a .docx or .epub; the extension is more specific for these
+const CONTAINERS: &[&str] = &["application/zip", "application/x-ole-storage"];
+
+/// The MIME type of a file: from its magic bytes when they're recognizable,
+/// otherwise from its extension, otherwise `application/octet-stream`
+pub fn detect_content_type(path: &Path) -> String {
+    let mut head = Vec::new();
+    if let Ok(file) = File::open(path) {
+        // An unreadable file falls back to its extension; the upload reports the error
+        let _ = file.take(SNIFF_LEN).read_to_end(&mut head);
+    }
+    content_type(path, &head)
+}
+
+/// Like `detect_content_type`, for content that's already in memory; `name`
+/// is the file name it will be uploaded as
+pub fn content_type(name: &Path, head: &[u8]) -> String {
+    let by_magic = infer::get(head).map(|kind| kind.mime_type());
+    let by_extension = mime_guess::from_path(name).first_raw();
+    match (by_magic, by_extension) {
+        (Some(magic), Some(extension)) if CONTAINERS.contains(&magic) => extension,
+        (Some(magic), _) => magic,
+        (None, Some(extension)) => extension,
+        (None, None) => DEFAULT_CONTENT_TYPE,
+    }
+    .to_string()
+}
@@ -0,0 +1,61 @@
+//! Client for the Vectorize Iris extraction API.
+//!
+//! ```no_run
+//! use iris_client::{ExtractionOptions, IrisClient, DEFAULT_API_URL};
+//! use std::path::Path;
+//! use std::sync::Arc;
+//!
+//! let client = IrisClient::new(DEFAULT_API_URL, "my-org-id", Arc::new("my-token".to_string()));
+//! let result = client.extract_file(Path::new("document.pdf"), &ExtractionOptions::default())?;
+//! println!("{}", result.text.unwrap_or_default());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! For progress reporting, call the individual steps yourself: `start_upload`,
+//! `upload_file`, `start_extraction`, then `get_extraction` until it is ready.
+//! Files of `MULTIPART_THRESHOLD` bytes or more can be uploaded in parts with
+//! `start_sized_upload` and `upload_parts`, if the service offers it.
+//!
+//! With the `async` feature (on by default), `AsyncIrisClient` offers the same
+//! methods as futures so many files can be extracted concurrently:
+//!
+//! ```no_run
+//! # #[cfg(feature = "async")]
+//! # async fn run() -> anyhow::Result<()> {
+//! use iris_client::{AsyncIrisClient, ExtractionOptions, DEFAULT_API_URL};
+//! use std::path::Path;
+//! use std::sync::Arc;
+//!
+//! let client = AsyncIrisClient::new(DEFAULT_API_URL, "my-org-id", Arc::new("my-token".to_string()));
+//! let options = ExtractionOptions::default();
+//! let (a, b) = tokio::join!(
+//!     client.extract_file(Path::new("a.pdf"), &options),
+//!     client.extract_file(Path::new("b.pdf"), &options),
+//! );
+//! println!("{} / {}", a?.text.unwrap_or_default(), b?.text.unwrap_or_default());
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "async")]
+mod async_client;
+mod client;
+mod content_type;
+mod models;
+mod settings;
+mod spool;
+
+#[cfg(feature = "async")]
+pub use async_client::AsyncIrisClient;
+pub use client::{
+    ExtractionOptions, ExtractionState, ExtractionStatus, IrisClient, RequestObserver, RetryPolicy,
+    TokenProvider, DEFAULT_API_URL, MULTIPART_THRESHOLD,
+};
+pub use content_type::{content_type, detect_content_type, DEFAULT_CONTENT_TYPE};
+pub use settings::is_under;
+pub use spool::TextSpill;
+pub use models::{
+    CompleteUploadRequest, CompletedPart, Entity, EntityKind, ExtractionResult, FormField, ExtractionResultData, MetadataSchema,
+    MetadataStrategy, MultipartUpload, Region, StartExtractionRequest, StartExtractionResponse, StartUploadRequest,
+    StartUploadResponse, UploadPart, UsageInfo,
+};
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct UsageInfo {
+    #[serde(rename = "irisPages")]
+    pub iris_pages: u32,
+}
+
+#[derive(Serialize)]
+pub struct StartUploadRequest {
+    pub name: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Ask for part URLs instead of a single upload URL; the service may decline
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub multipart: bool,
+}
+
+/// Where to upload a file's content: `upload_url`, or the parts of
+/// `multipart`; a response with neither is rejected
+#[derive(Deserialize)]
+pub struct StartUploadResponse {
+    #[serde(rename = "fileId")]
+    pub file_id: String,
+    /// Empty when the service accepted a multipart upload
+    #[serde(rename = "uploadUrl", default)]
+    pub upload_url: String,
+    #[serde(default)]
+    pub multipart: Option<MultipartUpload>,
+}
+
+/// Presigned URLs for uploading a file in parts, in file order; every part
+/// but the last is `part_size` bytes
+#[derive(Deserialize, Clone)]
+pub struct MultipartUpload {
+    #[serde(rename = "partSize")]
+    pub part_size: u64,
+    pub parts: Vec<UploadPart>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct UploadPart {
+    #[serde(rename = "partNumber")]
+    pub part_number: u32,
+    #[serde(rename = "uploadUrl")]
+    pub upload_url: String,
+}
+
+/// Body of `POST /files/{fileId}/complete`, sent once every part of a
+/// multipart upload is uploaded, with the ETag each part URL answered with
+#[derive(Serialize)]
+pub struct CompleteUploadRequest {
+    pub parts: Vec<CompletedPart>,
+}
+
+#[derive(Serialize)]
+pub struct CompletedPart {
+    #[serde(rename = "partNumber")]
+    pub part_number: u32,
+    pub etag: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MetadataSchema {
+    pub id: String,
+    pub schema: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MetadataStrategy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schemas: Option<Vec<MetadataSchema>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "inferSchema")]
+    pub infer_schema: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct StartExtractionRequest {
+    #[serde(rename = "fileId")]
+    pub file_id: String,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "type")]
+    pub extraction_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "chunkSize")]
+    pub chunk_size: Option<u32>,
+    /// How much of the end of each chunk the next one repeats, in the units of the chunk size
+    #[serde(skip_serializing_if = "Option::is_none", rename = "chunkOverlap")]
+    pub chunk_overlap: Option<u32>,
+    /// Where chunks are split: fixed, markdown, sentence, or semantic
+    #[serde(skip_serializing_if = "Option::is_none", rename = "chunkingStrategy")]
+    pub chunking_strategy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataStrategy>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "parsingInstructions")]
+    pub parsing_instructions: Option<String>,
+    /// Webhook the service POSTs `{"extractionId": ...}` to when the extraction finishes
+    #[serde(skip_serializing_if = "Option::is_none", rename = "callbackUrl")]
+    pub callback_url: Option<String>,
+    /// Ask for the named entities of the document and of each chunk
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub entities: bool,
+    /// Ask for the names and values of fillable form fields (AcroForm or XFA)
+    #[serde(skip_serializing_if = "std::ops::Not::not", rename = "formFields")]
+    pub form_fields: bool,
+    /// Hint that the document is handwritten, so recognition is tuned for handwriting
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub handwriting: bool,
+}
+
+#[derive(Deserialize)]
+pub struct StartExtractionResponse {
+    #[serde(rename = "extractionId")]
+    pub extraction_id: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ExtractionResultData {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "metadataSchema")]
+    pub metadata_schema: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "chunksMetadata")]
+    pub chunks_metadata: Option<Vec<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "chunksSchema")]
+    pub chunks_schema: Option<Vec<Option<String>>>,
+    /// Named entities of the whole document, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entities: Option<Vec<Entity>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "chunksEntities")]
+    pub chunks_entities: Option<Vec<Vec<Entity>>>,
+    /// Fields of a fillable form, in document order, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub form: Option<Vec<FormField>>,
+    /// Recognized regions of scanned pages and the service's confidence in each, when it reports them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regions: Option<Vec<Region>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A named entity found in the text, such as a person or an amount
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entity {
+    #[serde(rename = "type")]
+    pub kind: EntityKind,
+    pub text: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    Person,
+    Organization,
+    Date,
+    Amount,
+    /// A type this version of the client doesn't know about
+    #[serde(other)]
+    Other,
+}
+
+impl EntityKind {
+    pub const ALL: [EntityKind; 5] = [
+        EntityKind::Person,
+        EntityKind::Organization,
+        EntityKind::Date,
+        EntityKind::Amount,
+        EntityKind::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityKind::Person => "person",
+            EntityKind::Organization => "organization",
+            EntityKind::Date => "date",
+            EntityKind::Amount => "amount",
+            EntityKind::Other => "other",
+        }
+    }
+}
+
+/// A fillable form field and its value: a string for text and choice fields,
+/// a boolean for checkboxes, an array for multi-select lists, or null when empty
+#[derive(Deserialize, Serialize, Clone)]
+pub struct FormField {
+    /// Fully qualified name, such as `applicant.address.city`
+    pub name: String,
+    #[serde(default)]
+    pub value: serde_json::Value,
+    /// Field type as reported by the service, such as `text`, `checkbox`, `choice`, or `signature`
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "type")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+}
+
+/// A block of recognized text, such as a handwritten answer on a scanned form
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Region {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(default)]
+    pub text: String,
+    /// From 0 (a guess) to 1 (certain)
+    pub confidence: f64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub handwritten: bool,
+    /// Set client-side: the confidence is low enough that a person should check the text
+    #[serde(default, skip_serializing_if = "std::ops::Not::not", rename = "needsReview")]
+    pub needs_review: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ExtractionResult {
+    pub ready: bool,
+    pub data: Option<ExtractionResultData>,
+}
@@ -0,0 +1,362 @@
+// What the blocking and async clients share: the endpoint, credentials, and
+// identification, how requests are built and retried, and how responses are
+// interpreted. The clients only send requests and wait, each in its own way.
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER,
+    USER_AGENT,
+};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::client::{
+    ExtractionOptions, ExtractionState, ExtractionStatus, RequestObserver, RetryPolicy, TokenProvider, MULTIPART_THRESHOLD,
+};
+use crate::models::{
+    CompletedPart, MultipartUpload, StartExtractionRequest, StartUploadRequest, StartUploadResponse, UploadPart,
+};
+use crate::spool::{ParsedResult, SpooledBody, TextSpill};
+
+// Rate-limited requests are resent without using up retries, within these bounds
+const MAX_THROTTLE_WAIT: Duration = Duration::from_secs(300);
+const MAX_THROTTLED: u32 = 20;
+
+/// Whether `url` is `root` or a URL below it; a URL on another host, or one
+/// that merely starts with the same characters (api.example.com.evil), isn't
+pub fn is_under(url: &str, root: &str) -> bool {
+    let root = root.trim_end_matches('/');
+    url.strip_prefix(root)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']))
+}
+
+// Wait requested by a Retry-After header, either seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let at = httpdate::parse_http_date(value).ok()?;
+            Some(at.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+        }
+    }
+}
+
+// Why a request is worth retrying, if it is
+fn transient_failure(outcome: Result<StatusCode, &reqwest::Error>) -> Option<String> {
+    match outcome {
+        Ok(status) if status.is_server_error() => Some(status.to_string()),
+        Ok(_) => None,
+        Err(e) if e.is_connect() || e.is_timeout() || e.is_request() || e.is_body() => Some(e.to_string()),
+        Err(_) => None,
+    }
+}
+
+/// Retries and rate-limit waits of one request so far
+#[derive(Default)]
+pub(crate) struct Attempts {
+    retries: u32,
+    throttled: u32,
+}
+
+#[derive(Clone)]
+pub(crate) struct Settings {
+    // The API's root URL and the org's URL under it
+    pub(crate) api_url: String,
+    pub(crate) base_url: String,
+    pub(crate) token: Arc<dyn TokenProvider>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) correlation_id: Option<String>,
+    pub(crate) observer: Option<Arc<dyn RequestObserver>>,
+    pub(crate) retry: RetryPolicy,
+}
+
+impl Settings {
+    pub(crate) fn new(api_url: &str, org_id: &str, token: Arc<dyn TokenProvider>) -> Settings {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_static(concat!("iris-client/", env!("CARGO_PKG_VERSION"))),
+        );
+        let api_url = api_url.trim_end_matches('/').to_string();
+        Settings {
+            base_url: format!("{}/v1/org/{}", api_url, org_id),
+            api_url,
+            token,
+            headers,
+            correlation_id: None,
+            observer: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub(crate) fn set_user_agent(&mut self, user_agent: &str) -> Result<()> {
+        self.headers.insert(USER_AGENT, HeaderValue::from_str(user_agent)?);
+        Ok(())
+    }
+
+    pub(crate) fn set_correlation_id(&mut self, correlation_id: &str) -> Result<()> {
+        HeaderValue::from_str(correlation_id)?;
+        self.correlation_id = Some(correlation_id.to_string());
+        Ok(())
+    }
+
+    /// Identification headers for a request to `url`; the correlation ID is
+    /// only sent to the API, not to presigned upload URLs of other hosts
+    pub(crate) fn identification(&self, url: &str) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        let correlation_id = self.correlation_id.as_deref().and_then(|id| HeaderValue::from_str(id).ok());
+        if let Some(value) = correlation_id.filter(|_| is_under(url, &self.api_url)) {
+            headers.insert(HeaderName::from_static("x-correlation-id"), value);
+        }
+        headers
+    }
+
+    pub(crate) fn files_url(&self) -> String {
+        format!("{}/files", self.base_url)
+    }
+
+    pub(crate) fn complete_url(&self, file_id: &str) -> String {
+        format!("{}/files/{}/complete", self.base_url, file_id)
+    }
+
+    pub(crate) fn extraction_url(&self) -> String {
+        format!("{}/extraction", self.base_url)
+    }
+
+    pub(crate) fn status_url(&self, extraction_id: &str) -> String {
+        format!("{}/extraction/{}", self.base_url, extraction_id)
+    }
+
+    /// Headers of an API request to `url`: identification, a current token, and `extra`
+    pub(crate) fn authorized_headers(&self, url: &str, extra: &HeaderMap) -> Result<HeaderMap> {
+        let mut headers = self.identification(url);
+        let mut authorization = HeaderValue::from_str(&format!("Bearer {}", self.token.token()?))
+            .context("Invalid token")?;
+        authorization.set_sensitive(true);
+        headers.insert(AUTHORIZATION, authorization);
+        headers.extend(extra.clone());
+        Ok(headers)
+    }
+
+    pub(crate) fn json_headers() -> HeaderMap {
+        HeaderMap::from_iter([(CONTENT_TYPE, HeaderValue::from_static("application/json"))])
+    }
+
+    /// Headers of a status check; with the ETag of the last one, the service
+    /// answers 304 while nothing changed
+    pub(crate) fn status_headers(etag: Option<&str>) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        if let Some(tag) = etag {
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_str(tag)?);
+        }
+        Ok(headers)
+    }
+
+    /// Headers of an upload of `len` bytes to the presigned `url`
+    pub(crate) fn upload_headers(&self, url: &str, len: u64) -> HeaderMap {
+        let mut headers = self.identification(url);
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+        headers
+    }
+
+    pub(crate) fn observe_request(&self, method: &str, url: &str, headers: &HeaderMap, body: Option<&str>) {
+        if let Some(observer) = &self.observer {
+            observer.on_request(method, url, headers, body);
+        }
+    }
+
+    pub(crate) fn observe_response(&self, status: StatusCode, headers: &HeaderMap, body: &str) {
+        if let Some(observer) = &self.observer {
+            observer.on_response(status, headers, body);
+        }
+    }
+
+    /// How long to wait before sending a request again after `outcome`, or
+    /// None when the outcome is final: retries of network errors and 5xx
+    /// responses are limited by the retry policy, rate-limit waits are not
+    pub(crate) fn retry_delay(
+        &self,
+        attempts: &mut Attempts,
+        outcome: Result<(StatusCode, &HeaderMap), &reqwest::Error>,
+    ) -> Option<Duration> {
+        if let Ok((StatusCode::TOO_MANY_REQUESTS, headers)) = outcome {
+            if attempts.throttled < MAX_THROTTLED {
+                let delay = retry_after(headers)
+                    .unwrap_or_else(|| self.retry.delay(attempts.throttled))
+                    .min(MAX_THROTTLE_WAIT);
+                if let Some(observer) = &self.observer {
+                    observer.on_throttle(delay);
+                }
+                attempts.throttled += 1;
+                return Some(delay);
+            }
+        }
+
+        let reason = transient_failure(outcome.map(|(status, _)| status))?;
+        if attempts.retries >= self.retry.max_retries {
+            return None;
+        }
+        let delay = self.retry.delay(attempts.retries);
+        attempts.retries += 1;
+        if let Some(observer) = &self.observer {
+            observer.on_retry(attempts.retries, delay, &reason);
+        }
+        Some(delay)
+    }
+
+    /// Whether a response rejected the token and the request should be sent
+    /// again with a fresh one, which is tried once per request
+    pub(crate) fn refresh_token(&self, status: StatusCode, refreshed: &mut bool) -> bool {
+        if status != StatusCode::UNAUTHORIZED || *refreshed || !self.token.invalidate() {
+            return false;
+        }
+        *refreshed = true;
+        true
+    }
+
+    pub(crate) fn observe_token_refresh(&self, status: StatusCode, headers: &HeaderMap, body: &str) {
+        if let Some(observer) = &self.observer {
+            observer.on_response(status, headers, body);
+            observer.on_token_refresh();
+        }
+    }
+
+    /// Fail unless `status` is a success, saying what failed
+    pub(crate) fn check_status(action: &str, status: StatusCode, text: &str) -> Result<()> {
+        if !status.is_success() {
+            return Err(anyhow!("Failed to {}: {} - {}", action, status, text));
+        }
+        Ok(())
+    }
+
+    /// The value of a JSON API response
+    pub(crate) fn api_response<T: DeserializeOwned>(action: &str, status: StatusCode, text: &str) -> Result<T> {
+        Self::check_status(action, status, text)?;
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub(crate) fn upload_request(name: &str, content_type: &str, size: Option<u64>) -> StartUploadRequest {
+        StartUploadRequest {
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            size,
+            multipart: size.is_some_and(|size| size >= MULTIPART_THRESHOLD),
+        }
+    }
+
+    /// A start-upload response, which must say where the content goes
+    pub(crate) fn upload_response(status: StatusCode, text: &str) -> Result<StartUploadResponse> {
+        let upload: StartUploadResponse = Self::api_response("start upload", status, text)?;
+        if upload.upload_url.is_empty() && upload.multipart.is_none() {
+            return Err(anyhow!("The service gave neither an upload URL nor upload parts for file {}", upload.file_id));
+        }
+        Ok(upload)
+    }
+
+    /// Offset and length in a file of `size` bytes of each part of a multipart
+    /// upload that has any content
+    pub(crate) fn part_ranges(multipart: &MultipartUpload, size: u64) -> Result<Vec<(u64, u64)>> {
+        let covered = multipart.part_size.saturating_mul(multipart.parts.len() as u64);
+        if multipart.part_size == 0 || covered < size {
+            return Err(anyhow!(
+                "The service offered {} parts of {} bytes for a {} byte file",
+                multipart.parts.len(), multipart.part_size, size
+            ));
+        }
+        Ok((0..multipart.parts.len() as u64)
+            .map(|index| index * multipart.part_size)
+            .take_while(|offset| *offset < size)
+            .map(|offset| (offset, multipart.part_size.min(size - offset)))
+            .collect())
+    }
+
+    /// The uploaded part, with the ETag its upload URL answered with
+    pub(crate) fn completed_part(part: &UploadPart, headers: &HeaderMap) -> Result<CompletedPart> {
+        let etag = headers.get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        Ok(CompletedPart {
+            part_number: part.part_number,
+            etag: etag.ok_or_else(|| anyhow!("Part {} upload returned no ETag", part.part_number))?,
+        })
+    }
+
+    pub(crate) fn extraction_request(file_id: String, options: &ExtractionOptions) -> StartExtractionRequest {
+        StartExtractionRequest {
+            file_id,
+            extraction_type: Some(options.extraction_type.clone().unwrap_or_else(|| "iris".to_string())),
+            chunk_size: options.chunk_size,
+            chunk_overlap: options.chunk_overlap,
+            chunking_strategy: options.chunking_strategy.clone(),
+            metadata: options.metadata.clone(),
+            parsing_instructions: options.parsing_instructions.clone(),
+            callback_url: options.callback_url.clone(),
+            entities: options.entities,
+            form_fields: options.form_fields,
+            handwriting: options.handwriting,
+        }
+    }
+
+    /// Interpret a status response whose body has been read
+    pub(crate) fn extraction_status(
+        &self,
+        extraction_id: &str,
+        etag: Option<&str>,
+        status: StatusCode,
+        headers: &HeaderMap,
+        mut body: SpooledBody,
+        spill: Option<&TextSpill>,
+    ) -> Result<ExtractionStatus> {
+        self.observe_response(status, headers, &body.preview());
+        let bytes_received = body.len();
+
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(ExtractionStatus {
+                state: ExtractionState::NotModified,
+                etag: etag.map(str::to_string),
+                bytes_received,
+                text_file: None,
+                unknown_fields: Vec::new(),
+            });
+        }
+        if !status.is_success() {
+            return Err(anyhow!("Failed to check status: {} - {}", status, body.preview()));
+        }
+
+        let ParsedResult { result, text_file, unknown_fields } = body.parse_result(spill, extraction_id)?;
+        drop(body);
+        let etag = headers
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        let state = if result.ready {
+            let data = result.data.context("No data in extraction result")?;
+            if !data.success {
+                let error_msg = data.error.unwrap_or_else(|| "Unknown error".to_string());
+                return Err(anyhow!("Extraction failed: {}", error_msg));
+            }
+            ExtractionState::Ready(Box::new(data))
+        } else {
+            ExtractionState::Pending
+        };
+
+        Ok(ExtractionStatus { state, etag, bytes_received, text_file, unknown_fields })
+    }
+
+    /// Fail once polling has gone on for longer than `timeout`
+    pub(crate) fn check_timeout(start: Instant, timeout: Duration) -> Result<()> {
+        if start.elapsed() > timeout {
+            return Err(anyhow!("Extraction timed out after {} seconds", timeout.as_secs()));
+        }
+        Ok(())
+    }
+
+    /// The name a file is uploaded as
+    pub(crate) fn upload_name(path: &Path) -> Result<String> {
+        Ok(path.file_name().context("Invalid file name")?.to_string_lossy().to_string())
+    }
+}
@@ -0,0 +1,340 @@
+// Response bodies above a threshold are spooled to a temporary file and parsed
+// from a reader, so completed results with huge texts are not buffered twice.
+// With a TextSpill, a text over its size goes from the body straight into a
+// file of its own and never into memory.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tempfile::NamedTempFile;
+
+use crate::models::{ExtractionResult, ExtractionResultData};
+
+/// Response bodies larger than this are spooled to disk instead of held in memory
+pub const MEMORY_LIMIT: u64 = 8 * 1024 * 1024;
+
+// Named so files left behind by a killed process can be found and removed
+fn spool_file() -> Result<NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix("vectorize-iris-spool-")
+        .tempfile()
+        .context("Failed to create spool file")
+}
+
+pub enum SpooledBody {
+    Memory(Vec<u8>),
+    Disk { file: NamedTempFile, len: u64 },
+}
+
+impl SpooledBody {
+    /// Read a body, switching to a temporary file once it exceeds `memory_limit`
+    pub fn read(mut reader: impl Read, memory_limit: u64) -> Result<SpooledBody> {
+        let mut buffer = Vec::new();
+        (&mut reader)
+            .take(memory_limit + 1)
+            .read_to_end(&mut buffer)
+            .context("Failed to read response body")?;
+        if buffer.len() as u64 <= memory_limit {
+            return Ok(SpooledBody::Memory(buffer));
+        }
+
+        let mut file = spool_file()?;
+        file.write_all(&buffer)?;
+        drop(buffer);
+        let len = memory_limit + 1 + io::copy(&mut reader, &mut file).context("Failed to read response body")?;
+        Ok(SpooledBody::Disk { file, len })
+    }
+
+    pub fn len(&self) -> u64 {
+        match self {
+            SpooledBody::Memory(buffer) => buffer.len() as u64,
+            SpooledBody::Disk { len, .. } => *len,
+        }
+    }
+
+    /// Deserialize straight from the buffer or spool file
+    pub fn parse<T: DeserializeOwned>(&mut self) -> Result<T> {
+        match self {
+            SpooledBody::Memory(buffer) => Ok(serde_json::from_slice(buffer)?),
+            SpooledBody::Disk { file, .. } => {
+                file.seek(SeekFrom::Start(0))?;
+                Ok(serde_json::from_reader(BufReader::new(file.as_file()))?)
+            }
+        }
+    }
+
+    /// Parse an extraction status; with `spill`, a text of more than
+    /// `spill.max_size` bytes is decoded into NAME.txt in `spill.dir` and
+    /// returned as the `text_file` instead of the result's `text`
+    pub fn parse_result(&mut self, spill: Option<&TextSpill>, name: &str) -> Result<ParsedResult> {
+        // The text can't be larger than the body holding it
+        let Some(spill) = spill.filter(|spill| self.len() > spill.max_size) else {
+            return Ok(self.parse::<StatusBody>()?.into_parsed(None));
+        };
+        fs::create_dir_all(&spill.dir).context(format!("Failed to create {}", spill.dir.display()))?;
+        let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')).collect();
+        let path = spill.dir.join(format!("{}.txt", name));
+        let mut text = BufWriter::new(File::create(&path).context(format!("Failed to create {}", path.display()))?);
+
+        let mut json = Vec::new();
+        let length = match self {
+            SpooledBody::Memory(buffer) => split_text(&mut buffer.as_slice(), &mut json, &mut text)?,
+            SpooledBody::Disk { file, .. } => {
+                file.seek(SeekFrom::Start(0))?;
+                split_text(&mut BufReader::new(file.as_file()), &mut json, &mut text)?
+            }
+        };
+        text.flush().context(format!("Failed to write {}", path.display()))?;
+        drop(text);
+
+        let mut body: StatusBody = serde_json::from_slice(&json)?;
+        let mut text_file = None;
+        match (length, body.data.as_mut()) {
+            (Some(length), Some(_)) if length > spill.max_size => text_file = Some(path),
+            (Some(_), Some(received)) => {
+                received.data.text = Some(fs::read_to_string(&path)?);
+                fs::remove_file(&path)?;
+            }
+            _ => fs::remove_file(&path)?,
+        }
+        Ok(body.into_parsed(text_file))
+    }
+
+    /// Body text for logging; spooled bodies are summarized, not printed
+    pub fn preview(&self) -> String {
+        match self {
+            SpooledBody::Memory(buffer) => String::from_utf8_lossy(buffer).to_string(),
+            SpooledBody::Disk { len, .. } => format!("<{} bytes spooled to disk>", len),
+        }
+    }
+}
+
+/// A status response and what was noticed while parsing it
+pub struct ParsedResult {
+    pub result: ExtractionResult,
+    /// The file the text was spilled to, when it was
+    pub text_file: Option<PathBuf>,
+    /// Result fields this version of the client doesn't know about
+    pub unknown_fields: Vec<String>,
+}
+
+// The status body as received: the fields ExtractionResultData doesn't claim
+// are collected by name, so callers can tell the service has added some
+#[derive(Deserialize)]
+struct StatusBody {
+    ready: bool,
+    data: Option<ReceivedData>,
+}
+
+#[derive(Deserialize)]
+struct ReceivedData {
+    #[serde(flatten)]
+    data: ExtractionResultData,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl StatusBody {
+    fn into_parsed(self, text_file: Option<PathBuf>) -> ParsedResult {
+        let (data, unknown_fields) = match self.data {
+            Some(received) => (Some(received.data), received.unknown.into_keys().collect()),
+            None => (None, Vec::new()),
+        };
+        ParsedResult { result: ExtractionResult { ready: self.ready, data }, text_file, unknown_fields }
+    }
+}
+
+/// Where `get_extraction_spilling` puts texts too large to hold in memory
+#[derive(Clone, Debug)]
+pub struct TextSpill {
+    /// Texts of more bytes than this are written to a file
+    pub max_size: u64,
+    /// Directory of the files, named after the extraction ID
+    pub dir: PathBuf,
+}
+
+// Copy the JSON of `input` to `output`, except the string at data.text, which
+// is decoded into `text` and replaced by null; returns its length in bytes, or
+// None when there is no such string. Nesting is tracked on a stack rather than
+// by recursion, so no body can overflow the call stack.
+fn split_text(input: &mut impl BufRead, output: &mut Vec<u8>, text: &mut impl Write) -> Result<Option<u64>> {
+    // Each open container: whether it's an object, and the key of the value being read
+    let mut open: Vec<(bool, Option<String>)> = Vec::new();
+    let mut expect_key = false;
+    let mut length = None;
+    while let Some(byte) = next_byte(input)? {
+        match byte {
+            b'{' | b'[' => {
+                open.push((byte == b'{', None));
+                expect_key = byte == b'{';
+                output.push(byte);
+            }
+            b'}' | b']' => {
+                open.pop();
+                expect_key = false;
+                output.push(byte);
+            }
+            b',' => {
+                expect_key = open.last().is_some_and(|(object, _)| *object);
+                output.push(byte);
+            }
+            b':' => {
+                expect_key = false;
+                output.push(byte);
+            }
+            b'"' if expect_key => {
+                let start = output.len();
+                output.push(byte);
+                copy_string(input, output)?;
+                let key: String = serde_json::from_slice(&output[start..])?;
+                if let Some((_, current)) = open.last_mut() {
+                    *current = Some(key);
+                }
+            }
+            b'"' => match &open[..] {
+                [(true, Some(data)), (true, Some(field))] if data == "data" && field == "text" && length.is_none() => {
+                    length = Some(decode_string(input, text)?);
+                    output.extend_from_slice(b"null");
+                }
+                _ => {
+                    output.push(byte);
+                    copy_string(input, output)?;
+                }
+            },
+            _ => output.push(byte),
+        }
+    }
+    Ok(length)
+}
+
+fn next_byte(input: &mut impl BufRead) -> Result<Option<u8>> {
+    let Some(&byte) = input.fill_buf()?.first() else {
+        return Ok(None);
+    };
+    input.consume(1);
+    Ok(Some(byte))
+}
+
+// Copy the rest of a string as it is, through its closing quote
+fn copy_string(input: &mut impl BufRead, output: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            bail!("Unterminated string in response");
+        }
+        let Some(i) = buffer.iter().position(|b| matches!(b, b'"' | b'\\')) else {
+            let n = buffer.len();
+            output.extend_from_slice(buffer);
+            input.consume(n);
+            continue;
+        };
+        let closing = buffer[i] == b'"';
+        output.extend_from_slice(&buffer[..=i]);
+        input.consume(i + 1);
+        if closing {
+            return Ok(());
+        }
+        output.push(next_byte(input)?.context("Unterminated string in response")?);
+    }
+}
+
+// Decode the rest of a string into `text`, through its closing quote;
+// returns the number of bytes written
+fn decode_string(input: &mut impl BufRead, text: &mut impl Write) -> Result<u64> {
+    let mut written = 0;
+    loop {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            bail!("Unterminated string in response");
+        }
+        let run = buffer.iter().position(|b| matches!(b, b'"' | b'\\'));
+        let end = run.unwrap_or(buffer.len());
+        text.write_all(&buffer[..end])?;
+        written += end as u64;
+        let Some(i) = run else {
+            input.consume(end);
+            continue;
+        };
+        let closing = buffer[i] == b'"';
+        input.consume(i + 1);
+        if closing {
+            return Ok(written);
+        }
+        let decoded = match next_byte(input)?.context("Unterminated string in response")? {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let unit = hex_escape(input)?;
+                // Characters outside the BMP are escaped as a surrogate pair
+                let code = if (0xD800..0xDC00).contains(&unit) {
+                    if next_byte(input)? != Some(b'\\') || next_byte(input)? != Some(b'u') {
+                        bail!("Invalid surrogate pair in response");
+                    }
+                    let low = hex_escape(input)?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        bail!("Invalid surrogate pair in response");
+                    }
+                    0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    unit
+                };
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            other => bail!("Invalid escape '\\{}' in response", other as char),
+        };
+        let mut utf8 = [0; 4];
+        let decoded = decoded.encode_utf8(&mut utf8);
+        text.write_all(decoded.as_bytes())?;
+        written += decoded.len() as u64;
+    }
+}
+
+// The four hex digits of a \u escape
+fn hex_escape(input: &mut impl BufRead) -> Result<u32> {
+    let mut digits = [0; 4];
+    input.read_exact(&mut digits).context("Unterminated string in response")?;
+    let digits = std::str::from_utf8(&digits).map_err(|_| anyhow!("Invalid \\u escape in response"))?;
+    u32::from_str_radix(digits, 16).map_err(|_| anyhow!("Invalid \\u escape '{}' in response", digits))
+}
+
+#[cfg(feature = "async")]
+impl SpooledBody {
+    /// Async counterpart of `read` for `reqwest::Response`, consumed chunk by chunk
+    pub async fn read_async(mut response: reqwest::Response, memory_limit: u64) -> Result<SpooledBody> {
+        let mut buffer = Vec::new();
+        let mut spool: Option<(NamedTempFile, u64)> = None;
+
+        while let Some(chunk) = response.chunk().await.context("Failed to read response body")? {
+            match spool.as_mut() {
+                Some((file, len)) => {
+                    file.write_all(&chunk)?;
+                    *len += chunk.len() as u64;
+                }
+                None => {
+                    buffer.extend_from_slice(&chunk);
+                    if buffer.len() as u64 > memory_limit {
+                        let mut file = spool_file()?;
+                        file.write_all(&buffer)?;
+                        spool = Some((file, buffer.len() as u64));
+                        buffer = Vec::new();
+                    }
+                }
+            }
+        }
+
+        Ok(match spool {
+            Some((file, len)) => SpooledBody::Disk { file, len },
+            None => SpooledBody::Memory(buffer),
+        })
+    }
+}
@@ -0,0 +1,272 @@
+#[cfg(feature = "async")]
+use iris_client::AsyncIrisClient;
+use iris_client::{is_under, ExtractionOptions, ExtractionState, IrisClient, RetryPolicy, TextSpill};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A request the stub API received
+#[derive(Clone)]
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Status, extra headers, and body of a stub response
+type Reply = (u16, Vec<(&'static str, String)>, String);
+
+// Serve HTTP/1.1 on a local port, answering each request with `route`, which
+// also gets the stub's base URL; returns that URL and every request received
+fn start_stub(route: impl Fn(&str, &Request) -> Reply + Send + 'static) -> (String, Arc<Mutex<Vec<Request>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind stub API");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (base, requests) = (url.clone(), received.clone());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut parts = request_line.split_whitespace();
+            let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+            let mut headers = Vec::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            let length = headers.iter().find(|(n, _)| n.eq_ignore_ascii_case("content-length")).map_or(0, |(_, v)| v.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let request = Request { method: method.to_string(), path: path.to_string(), headers, body };
+            let (status, extra, body) = route(&base, &request);
+            requests.lock().unwrap().push(request);
+            let mut response = format!("HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+            for (name, value) in extra {
+                response.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            let _ = stream.write_all(format!("{}\r\n{}", response, body).as_bytes());
+        }
+    });
+    (url, received)
+}
+
+// Answers for the extraction steps after the upload
+fn extraction_reply(request: &Request) -> Option<Reply> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/v1/org/org-1/extraction") => Some((200, vec![], r#"{"extractionId":"extraction-1"}"#.to_string())),
+        ("GET", "/v1/org/org-1/extraction/extraction-1") => {
+            Some((200, vec![], r#"{"ready":true,"data":{"success":true,"text":"Extracted text"}}"#.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn client(url: &str) -> IrisClient {
+    IrisClient::new(url, "org-1", Arc::new("test-token".to_string()))
+        .with_retry_policy(RetryPolicy { max_retries: 3, backoff: Duration::from_millis(10) })
+}
+
+#[test]
+fn test_multipart_upload() {
+    let failed = Mutex::new(false);
+    let (url, received) = start_stub(move |base, request| {
+        if let Some(reply) = extraction_reply(request) {
+            return reply;
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/v1/org/org-1/files") => {
+                let parts: Vec<serde_json::Value> = (1..=3)
+                    .map(|n| serde_json::json!({ "partNumber": n, "uploadUrl": format!("{}/parts/{}", base, n) }))
+                    .collect();
+                (200, vec![], serde_json::json!({ "fileId": "file-1", "multipart": { "partSize": 4, "parts": parts } }).to_string())
+            }
+            // The second part fails once and is sent again on its own
+            ("PUT", "/parts/2") if !std::mem::replace(&mut *failed.lock().unwrap(), true) => (503, vec![], String::new()),
+            ("PUT", path) => (200, vec![("ETag", format!("\"etag-{}\"", &path[7..]))], String::new()),
+            ("POST", "/v1/org/org-1/files/file-1/complete") => (200, vec![], "{}".to_string()),
+            _ => (404, vec![], String::new()),
+        }
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("large.bin");
+    std::fs::write(&file, "0123456789").unwrap();
+
+    let result = client(&url).extract_file(&file, &ExtractionOptions::default()).unwrap();
+    assert_eq!(result.text.as_deref(), Some("Extracted text"));
+
+    let received = received.lock().unwrap();
+    let puts: Vec<(&str, &[u8])> = received.iter().filter(|r| r.method == "PUT").map(|r| (r.path.as_str(), r.body.as_slice())).collect();
+    assert_eq!(puts, [("/parts/1", &b"0123"[..]), ("/parts/2", b"4567"), ("/parts/2", b"4567"), ("/parts/3", b"89")]);
+    let complete = received.iter().find(|r| r.path.ends_with("/complete")).expect("No completion request");
+    assert_eq!(complete.header("authorization"), Some("Bearer test-token"));
+    let parts: serde_json::Value = serde_json::from_slice(&complete.body).unwrap();
+    assert_eq!(
+        parts,
+        serde_json::json!({ "parts": [
+            { "partNumber": 1, "etag": "\"etag-1\"" },
+            { "partNumber": 2, "etag": "\"etag-2\"" },
+            { "partNumber": 3, "etag": "\"etag-3\"" },
+        ] })
+    );
+}
+
+#[test]
+fn test_upload_without_destination() {
+    let (url, received) = start_stub(|_, request| match request.path.as_str() {
+        "/v1/org/org-1/files" => (200, vec![], r#"{"fileId":"file-1"}"#.to_string()),
+        _ => (404, vec![], String::new()),
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let Err(error) = client(&url).extract_file(&file, &ExtractionOptions::default()) else {
+        panic!("The upload should have been rejected");
+    };
+    assert!(error.to_string().contains("neither an upload URL nor upload parts"), "{}", error);
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_status_reports_spilled_text_and_unknown_fields() {
+    let (url, _) = start_stub(|_, _| {
+        (200, vec![], r#"{"ready":true,"data":{"success":true,"text":"Extracted text","layout":{"columns":2},"pages":3}}"#.to_string())
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let spill = TextSpill { max_size: 4, dir: dir.path().to_path_buf() };
+
+    let status = client(&url).get_extraction_spilling("extraction-1", None, Some(&spill)).unwrap();
+    let ExtractionState::Ready(data) = status.state else {
+        panic!("The extraction should be ready");
+    };
+    assert!(data.text.is_none());
+    let text_file = status.text_file.expect("The text should have been spilled");
+    assert_eq!(std::fs::read_to_string(text_file).unwrap(), "Extracted text");
+    assert_eq!(status.unknown_fields, ["layout", "pages"]);
+}
+
+#[test]
+fn test_spilled_text_surrogate_pairs() {
+    let (url, _) = start_stub(|_, request| {
+        // A valid pair for U+1F600, then a high surrogate followed by a non-surrogate
+        let text = if request.path.ends_with("/valid") { r"Smile \ud83d\ude00" } else { r"Broken \ud83d\u0041" };
+        (200, vec![], format!(r#"{{"ready":true,"data":{{"success":true,"text":"{}"}}}}"#, text))
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let spill = TextSpill { max_size: 4, dir: dir.path().to_path_buf() };
+    let client = client(&url);
+
+    let status = client.get_extraction_spilling("valid", None, Some(&spill)).unwrap();
+    let text_file = status.text_file.expect("The text should have been spilled");
+    assert_eq!(std::fs::read_to_string(text_file).unwrap(), "Smile \u{1F600}");
+
+    let Err(error) = client.get_extraction_spilling("invalid", None, Some(&spill)) else {
+        panic!("The unpaired surrogate should have been rejected");
+    };
+    assert!(error.to_string().contains("Invalid surrogate pair"), "{}", error);
+}
+
+#[test]
+fn test_correlation_id_only_sent_to_the_api() {
+    let (storage, uploads) = start_stub(|_, _| (200, vec![], String::new()));
+    let (url, received) = start_stub(move |_, request| {
+        if let Some(reply) = extraction_reply(request) {
+            return reply;
+        }
+        let upload_url = format!("{}/bucket/doc.txt?signature=abc", storage);
+        (200, vec![], serde_json::json!({ "fileId": "file-1", "uploadUrl": upload_url }).to_string())
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let client = client(&url).with_user_agent("test-agent/1.0").unwrap().with_correlation_id("run-42").unwrap();
+    client.extract_file(&file, &ExtractionOptions::default()).unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 3);
+    assert!(received.iter().all(|r| r.header("x-correlation-id") == Some("run-42")));
+    let uploads = uploads.lock().unwrap();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0].header("x-correlation-id"), None);
+    assert_eq!(uploads[0].header("authorization"), None);
+    assert_eq!(uploads[0].header("user-agent"), Some("test-agent/1.0"));
+}
+
+#[test]
+fn test_is_under() {
+    assert!(is_under("https://api.example.com/v1/org/1/files", "https://api.example.com/"));
+    assert!(is_under("https://api.example.com", "https://api.example.com"));
+    assert!(is_under("https://api.example.com?x=1", "https://api.example.com"));
+    assert!(!is_under("https://api.example.com.evil/v1", "https://api.example.com"));
+    assert!(!is_under("https://api.example.com@evil/v1", "https://api.example.com"));
+    assert!(!is_under("https://storage.example.com/bucket", "https://api.example.com"));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_extract_file() {
+    let (throttled, polled) = (Mutex::new(false), Mutex::new(0));
+    let (url, received) = start_stub(move |base, request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            // The first registration is rate limited and waited out
+            ("POST", "/v1/org/org-1/files") if !std::mem::replace(&mut *throttled.lock().unwrap(), true) => {
+                (429, vec![("Retry-After", "0".to_string())], String::new())
+            }
+            ("POST", "/v1/org/org-1/files") => {
+                (200, vec![], serde_json::json!({ "fileId": "file-1", "uploadUrl": format!("{}/upload", base) }).to_string())
+            }
+            ("PUT", "/upload") => (200, vec![], String::new()),
+            // Not ready on the first poll, then unchanged for the one after
+            ("GET", "/v1/org/org-1/extraction/extraction-1") => {
+                let mut polled = polled.lock().unwrap();
+                *polled += 1;
+                match *polled {
+                    1 => (200, vec![("ETag", "\"v1\"".to_string())], r#"{"ready":false}"#.to_string()),
+                    2 => (304, vec![("ETag", "\"v1\"".to_string())], String::new()),
+                    _ => extraction_reply(request).unwrap(),
+                }
+            }
+            _ => extraction_reply(request).unwrap_or((404, vec![], String::new())),
+        }
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "async doc").unwrap();
+
+    let client = AsyncIrisClient::new(&url, "org-1", Arc::new("test-token".to_string()))
+        .with_retry_policy(RetryPolicy { max_retries: 3, backoff: Duration::from_millis(10) });
+    let options = ExtractionOptions { poll_interval: Duration::from_millis(10), ..ExtractionOptions::default() };
+    let result = client.extract_file(&file, &options).await.unwrap();
+    assert_eq!(result.text.as_deref(), Some("Extracted text"));
+
+    let received = received.lock().unwrap();
+    let registrations = received.iter().filter(|r| r.path == "/v1/org/org-1/files").count();
+    assert_eq!(registrations, 2);
+    let upload = received.iter().find(|r| r.method == "PUT").expect("No upload request");
+    assert_eq!(upload.body, b"async doc");
+    assert_eq!(upload.header("authorization"), None);
+    let polls: Vec<Option<&str>> = received
+        .iter()
+        .filter(|r| r.method == "GET")
+        .map(|r| r.header("if-none-match"))
+        .collect();
+    assert_eq!(polls, [None, Some("\"v1\""), Some("\"v1\"")]);
+}
@@ -0,0 +1,398 @@
+// A result cache shared between runs and machines (--result-cache), so a fleet
+// of batch workers extracts each document once. Entries are keyed by the
+// document's content and the options that shape its result. A worker claims
+// a key before extracting the document, and the others wait for its result
+// instead of extracting it again; claims expire, so a worker that dies
+// doesn't hold up the rest. Every write is a compare-and-swap on the entry's
+// version (optimistic locking), so two workers never both win a claim.
+//
+//   spec := PATH                  (a directory, local or on a shared file system)
+//         | "s3://" BUCKET [/PREFIX]  (with the s3-cache feature)
+//         | "redis://" HOST[:PORT][/DB]  (with the redis-cache feature)
+
+use crate::snapshot::hash_file;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use crate::result::{AnnotatedResult, Warning};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Where cache entries are stored
+pub trait Backend: Send + Sync {
+    /// The entry at `key` and its version
+    fn get(&self, key: &str) -> Result<Option<(Vec<u8>, String)>>;
+    /// Write `value` at `key` if it has no entry (`version` None) or still has
+    /// `version`; the new version, or None when another worker changed it first
+    fn put(&self, key: &str, value: &[u8], version: Option<&str>) -> Result<Option<String>>;
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+enum Entry {
+    /// A worker is extracting the document
+    Claimed { owner: String, expires: i64 },
+    Done { result: Box<AnnotatedResult> },
+}
+
+pub struct ResultCache {
+    backend: Box<dyn Backend>,
+}
+
+impl ResultCache {
+    /// Open the cache at a --result-cache spec
+    pub fn open(spec: &str) -> Result<ResultCache> {
+        let backend: Box<dyn Backend> = if spec.starts_with("s3://") {
+            s3_backend(spec)?
+        } else if spec.starts_with("redis://") || spec.starts_with("rediss://") {
+            redis_backend(spec)?
+        } else {
+            Box::new(DirBackend::open(Path::new(spec))?)
+        };
+        Ok(ResultCache { backend })
+    }
+
+    /// The cached result for `file` and `options` (a fingerprint of the
+    /// options that shape the result), or the result of `extract`, which is
+    /// then cached. When another worker is extracting the same document,
+    /// `on_wait` is called and its result is awaited; `claim_ttl` bounds how
+    /// long a claim is honored. Results are only returned from the cache
+    /// when the bool is true.
+    pub fn extract(
+        &self,
+        file: &Path,
+        options: &str,
+        claim_ttl: Duration,
+        poll: Duration,
+        on_wait: impl FnOnce(),
+        extract: impl FnOnce() -> Result<AnnotatedResult>,
+    ) -> Result<(AnnotatedResult, bool)> {
+        let key = key(&hash_file(file)?, options);
+        let claim = match self.claim(&key, claim_ttl, poll, on_wait) {
+            Ok(Claim::Hit(result)) => return Ok((*result, true)),
+            Ok(Claim::Won(version)) => Some(version),
+            // A cache that can't be reached only costs the savings
+            Err(e) => {
+                let mut result = extract()?;
+                result.warnings.push(Warning::new("cache-unavailable", format!("Result cache couldn't be read: {:#}", e)));
+                return Ok((result, false));
+            }
+        };
+
+        let result = extract();
+        let entry = match &result {
+            // Spilled text lives in a file on this machine
+            Ok(result) if result.text_file.is_none() => Entry::Done { result: Box::new(result.clone()) },
+            // Release the claim, so the next worker extracts the document itself
+            _ => Entry::Claimed { owner: owner(), expires: 0 },
+        };
+        let stored = serde_json::to_vec(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|value| self.backend.put(&key, &value, claim.as_deref()));
+        let mut result = result?;
+        if let Err(e) = stored {
+            result.warnings.push(Warning::new("cache-unavailable", format!("Result couldn't be cached: {:#}", e)));
+        }
+        // When the claim expired and another worker took over, its entry is kept
+        Ok((result, false))
+    }
+
+    fn claim(&self, key: &str, claim_ttl: Duration, poll: Duration, on_wait: impl FnOnce()) -> Result<Claim> {
+        let mut on_wait = Some(on_wait);
+        loop {
+            let (entry, version) = match self.backend.get(key)? {
+                Some((value, version)) => (serde_json::from_slice::<Entry>(&value).ok(), Some(version)),
+                None => (None, None),
+            };
+            match entry {
+                Some(Entry::Done { result }) => return Ok(Claim::Hit(result)),
+                Some(Entry::Claimed { expires, .. }) if expires > Utc::now().timestamp() => {
+                    if let Some(on_wait) = on_wait.take() {
+                        on_wait();
+                    }
+                    thread::sleep(poll);
+                }
+                // No entry, an expired claim, or an entry this version can't read
+                _ => {
+                    let claim = Entry::Claimed { owner: owner(), expires: Utc::now().timestamp() + claim_ttl.as_secs() as i64 };
+                    if let Some(version) = self.backend.put(key, &serde_json::to_vec(&claim)?, version.as_deref())? {
+                        return Ok(Claim::Won(version));
+                    }
+                    // Another worker claimed it first; see what it wrote
+                }
+            }
+        }
+    }
+}
+
+enum Claim {
+    Hit(Box<AnnotatedResult>),
+    /// This worker extracts the document; the version of its claim
+    Won(String),
+}
+
+// Results depend on the document, the options, and this version's post-processing
+fn key(file_hash: &str, options: &str) -> String {
+    let digest = Sha256::digest(format!("{}\n{}\n{}", env!("CARGO_PKG_VERSION"), file_hash, options).as_bytes());
+    format!("{:x}", digest)
+}
+
+/// This process, as HOSTNAME:PID, to tell workers apart
+pub fn owner() -> String {
+    let host = hostname().unwrap_or_else(|| "unknown".to_string());
+    format!("{}:{}", host, std::process::id())
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok().map(|name| name.trim().to_string()))
+        .filter(|name| !name.is_empty())
+}
+
+fn version_of(value: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(value))[..16].to_string()
+}
+
+// Locks of writers that died are broken after this long
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+/// Entries as files in a directory, which may be on a shared file system.
+/// New entries are created by hard-linking a complete file into place, and
+/// replaced under a lock file, both of which are atomic on NFS
+struct DirBackend {
+    dir: PathBuf,
+}
+
+impl DirBackend {
+    fn open(dir: &Path) -> Result<DirBackend> {
+        fs::create_dir_all(dir).context(format!("Failed to create result cache: {}", dir.display()))?;
+        Ok(DirBackend { dir: dir.to_path_buf() })
+    }
+
+    // Spread over subdirectories, so no directory holds every entry
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(format!("{}.json", key))
+    }
+
+    // A complete copy of `value` next to `path`, to link or rename into place
+    fn write_temp(&self, path: &Path, value: &[u8]) -> Result<tempfile::TempPath> {
+        let dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+        let mut temp = tempfile::NamedTempFile::new_in(dir).context(format!("Failed to write to {}", dir.display()))?;
+        temp.write_all(value)?;
+        temp.as_file().sync_all()?;
+        Ok(temp.into_temp_path())
+    }
+}
+
+impl Backend for DirBackend {
+    fn get(&self, key: &str) -> Result<Option<(Vec<u8>, String)>> {
+        let path = self.path(key);
+        match fs::read(&path) {
+            Ok(value) => {
+                let version = version_of(&value);
+                Ok(Some((value, version)))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8], version: Option<&str>) -> Result<Option<String>> {
+        let path = self.path(key);
+        let temp = self.write_temp(&path, value)?;
+        let Some(version) = version else {
+            return match fs::hard_link(&temp, &path) {
+                Ok(()) => Ok(Some(version_of(value))),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+                Err(e) => Err(e).context(format!("Failed to write {}", path.display())),
+            };
+        };
+
+        let lock = path.with_extension("lock");
+        if let Err(e) = OpenOptions::new().write(true).create_new(true).open(&lock) {
+            let stale = fs::metadata(&lock)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| SystemTime::now().duration_since(modified).unwrap_or_default() > STALE_LOCK);
+            if e.kind() == ErrorKind::AlreadyExists && stale {
+                let _ = fs::remove_file(&lock);
+            }
+            return if e.kind() == ErrorKind::AlreadyExists { Ok(None) } else { Err(e).context(format!("Failed to lock {}", path.display())) };
+        }
+        let replaced = match self.get(key) {
+            Ok(Some((_, current))) if current == version => temp
+                .persist(&path)
+                .map(|()| Some(version_of(value)))
+                .context(format!("Failed to write {}", path.display())),
+            Ok(_) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_file(&lock);
+        replaced
+    }
+}
+
+#[cfg(feature = "s3-cache")]
+fn s3_backend(spec: &str) -> Result<Box<dyn Backend>> {
+    Ok(Box::new(s3::S3Backend::open(spec)?))
+}
+
+#[cfg(not(feature = "s3-cache"))]
+fn s3_backend(_spec: &str) -> Result<Box<dyn Backend>> {
+    Err(anyhow::anyhow!("This build doesn't include the S3 result cache; rebuild with 'cargo build --features s3-cache'"))
+}
+
+#[cfg(feature = "redis-cache")]
+fn redis_backend(spec: &str) -> Result<Box<dyn Backend>> {
+    Ok(Box::new(redis_store::RedisBackend::open(spec)?))
+}
+
+#[cfg(not(feature = "redis-cache"))]
+fn redis_backend(_spec: &str) -> Result<Box<dyn Backend>> {
+    Err(anyhow::anyhow!("This build doesn't include the Redis result cache; rebuild with 'cargo build --features redis-cache'"))
+}
+
+/// Entries as objects in an S3 bucket, written with conditional puts
+/// (If-None-Match for new entries, If-Match on the ETag to replace one)
+#[cfg(feature = "s3-cache")]
+mod s3 {
+    use super::Backend;
+    use anyhow::{anyhow, Context, Result};
+    use aws_sdk_s3::error::SdkError;
+    use aws_sdk_s3::primitives::ByteStream;
+    use aws_sdk_s3::Client;
+    use tokio::runtime::Runtime;
+
+    pub struct S3Backend {
+        runtime: Runtime,
+        client: Client,
+        bucket: String,
+        prefix: String,
+    }
+
+    impl S3Backend {
+        pub fn open(spec: &str) -> Result<S3Backend> {
+            let path = spec.trim_start_matches("s3://");
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+            if bucket.is_empty() {
+                return Err(anyhow!("Invalid result cache '{}': expected s3://BUCKET/PREFIX", spec));
+            }
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .enable_all()
+                .build()
+                .context("Failed to start async runtime")?;
+            let config = runtime.block_on(aws_config::load_defaults(aws_config::BehaviorVersion::latest()));
+            let prefix = prefix.trim_end_matches('/');
+            Ok(S3Backend {
+                runtime,
+                client: Client::new(&config),
+                bucket: bucket.to_string(),
+                prefix: if prefix.is_empty() { String::new() } else { format!("{}/", prefix) },
+            })
+        }
+    }
+
+    // A put whose condition failed: another worker wrote first
+    fn conflicted<E>(error: &SdkError<E>) -> bool {
+        error.raw_response().is_some_and(|response| matches!(response.status().as_u16(), 409 | 412))
+    }
+
+    impl Backend for S3Backend {
+        fn get(&self, key: &str) -> Result<Option<(Vec<u8>, String)>> {
+            let object_key = format!("{}{}.json", self.prefix, key);
+            self.runtime.block_on(async {
+                let object = match self.client.get_object().bucket(&self.bucket).key(&object_key).send().await {
+                    Ok(object) => object,
+                    Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+                    Err(e) => return Err(anyhow!("Failed to read s3://{}/{}: {}", self.bucket, object_key, e)),
+                };
+                let version = object.e_tag().unwrap_or_default().to_string();
+                let value = object.body.collect().await.context("Failed to read result cache entry")?.into_bytes();
+                Ok(Some((value.to_vec(), version)))
+            })
+        }
+
+        fn put(&self, key: &str, value: &[u8], version: Option<&str>) -> Result<Option<String>> {
+            let object_key = format!("{}{}.json", self.prefix, key);
+            self.runtime.block_on(async {
+                let request = self
+                    .client
+                    .put_object()
+                    .bucket(&self.bucket)
+                    .key(&object_key)
+                    .content_type("application/json")
+                    .body(ByteStream::from(value.to_vec()));
+                let request = match version {
+                    Some(version) => request.if_match(version),
+                    None => request.if_none_match("*"),
+                };
+                match request.send().await {
+                    Ok(output) => Ok(Some(output.e_tag().unwrap_or_default().to_string())),
+                    Err(e) if conflicted(&e) => Ok(None),
+                    Err(e) => Err(anyhow!("Failed to write s3://{}/{}: {}", self.bucket, object_key, e)),
+                }
+            })
+        }
+    }
+}
+
+/// Entries as Redis strings: new entries are set with NX, and replaced in a
+/// transaction that WATCHes the key
+#[cfg(feature = "redis-cache")]
+mod redis_store {
+    use super::{version_of, Backend};
+    use anyhow::{Context, Result};
+    use redis::Commands;
+
+    const KEY_PREFIX: &str = "vectorize-iris:result:";
+
+    pub struct RedisBackend {
+        client: redis::Client,
+    }
+
+    impl RedisBackend {
+        pub fn open(spec: &str) -> Result<RedisBackend> {
+            let client = redis::Client::open(spec).context(format!("Invalid result cache '{}'", spec))?;
+            Ok(RedisBackend { client })
+        }
+
+        fn connection(&self) -> Result<redis::Connection> {
+            self.client.get_connection().context("Failed to connect to the result cache")
+        }
+    }
+
+    impl Backend for RedisBackend {
+        fn get(&self, key: &str) -> Result<Option<(Vec<u8>, String)>> {
+            let value: Option<Vec<u8>> = self.connection()?.get(format!("{}{}", KEY_PREFIX, key))?;
+            Ok(value.map(|value| {
+                let version = version_of(&value);
+                (value, version)
+            }))
+        }
+
+        fn put(&self, key: &str, value: &[u8], version: Option<&str>) -> Result<Option<String>> {
+            let key = format!("{}{}", KEY_PREFIX, key);
+            let mut connection = self.connection()?;
+            let Some(version) = version else {
+                let created: bool = redis::cmd("SET").arg(&key).arg(value).arg("NX").query::<Option<String>>(&mut connection)?.is_some();
+                return Ok(created.then(|| version_of(value)));
+            };
+            redis::cmd("WATCH").arg(&key).query::<()>(&mut connection)?;
+            let current: Option<Vec<u8>> = connection.get(&key)?;
+            if current.as_deref().map(version_of).as_deref() != Some(version) {
+                redis::cmd("UNWATCH").query::<()>(&mut connection)?;
+                return Ok(None);
+            }
+            // EXEC answers nil when the key changed after WATCH
+            let replaced: Option<()> = redis::pipe().atomic().set(&key, value).ignore().query(&mut connection)?;
+            Ok(replaced.map(|()| version_of(value)))
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Built-in listener for extraction webhooks (--callback-listen): the service
+// POSTs {"extractionId": ...} when an extraction finishes, so waiting runs can
+// block on the event instead of checking the status endpoint every few seconds.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_EVENT_BYTES: usize = 64 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Events {
+    finished: Mutex<HashSet<String>>,
+    arrived: Condvar,
+}
+
+#[derive(Clone)]
+pub struct CallbackListener {
+    /// Default webhook URL when --callback-url is not given
+    pub url: String,
+    events: Arc<Events>,
+}
+
+impl CallbackListener {
+    pub fn start(addr: SocketAddr) -> Result<CallbackListener> {
+        let listener = TcpListener::bind(addr)
+            .context(format!("Failed to listen for callbacks on {}", addr))?;
+        let local = listener.local_addr()?;
+        let events = Arc::new(Events::default());
+
+        let shared = Arc::clone(&events);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Ok(id) = handle_event(stream) {
+                    shared.finished.lock().unwrap().insert(id);
+                    shared.arrived.notify_all();
+                }
+            }
+        });
+
+        Ok(CallbackListener { url: format!("http://{}/", local), events })
+    }
+
+    /// Whether the completion event for an extraction has arrived
+    pub fn received(&self, extraction_id: &str) -> bool {
+        self.events.finished.lock().unwrap().contains(extraction_id)
+    }
+
+    /// Block until the completion event for an extraction arrives or `timeout` passes
+    pub fn wait(&self, extraction_id: &str, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut finished = self.events.finished.lock().unwrap();
+        while !finished.contains(extraction_id) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
+            }
+            finished = self.events.arrived.wait_timeout(finished, remaining).unwrap().0;
+        }
+    }
+}
+
+// Read one webhook request and acknowledge it; returns the extraction ID
+fn handle_event(stream: TcpStream) -> Result<String> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let result = read_event(&mut reader, &request_line);
+
+    let status = if result.is_ok() { "200 OK" } else { "400 Bad Request" };
+    let mut stream = reader.into_inner();
+    write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)?;
+    stream.flush()?;
+    result
+}
+
+fn read_event(reader: &mut BufReader<&TcpStream>, request_line: &str) -> Result<String> {
+    if !request_line.starts_with("POST ") {
+        return Err(anyhow!("Expected POST request, got: {}", request_line.trim()));
+    }
+
+    let mut content_length = 0;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.trim_end() != "" {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("Invalid Content-Length header")?;
+            }
+        }
+        line.clear();
+    }
+    if content_length > MAX_EVENT_BYTES {
+        return Err(anyhow!("Callback body too large ({} bytes)", content_length));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let event: serde_json::Value = serde_json::from_slice(&body).context("Callback body is not JSON")?;
+    event["extractionId"]
+        .as_str()
+        .map(|id| id.to_string())
+        .context("Callback body has no extractionId")
+}
@@ -0,0 +1,337 @@
+// A SQLite catalog of the results in an output directory (index-outputs), so
+// large output folders can be searched by source, tag, chunk count, or
+// extraction ID without opening every file (query).
+
+use anyhow::{Context, Result};
+use rusqlite::{params, params_from_iter, Connection, ToSql};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
+
+use crate::layout::INDEX_FILE;
+use crate::snapshot::relative_key;
+
+/// Written into the output directory unless --db names another file
+pub const DEFAULT_DATABASE: &str = "catalog.sqlite";
+
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS tags;
+    DROP TABLE IF EXISTS documents;
+    CREATE TABLE documents (
+        id INTEGER PRIMARY KEY,
+        output TEXT NOT NULL,
+        source TEXT,
+        extraction_id TEXT,
+        correlation_id TEXT,
+        chunks INTEGER NOT NULL,
+        characters INTEGER NOT NULL,
+        warnings INTEGER NOT NULL,
+        modified INTEGER
+    );
+    CREATE TABLE tags (
+        document INTEGER NOT NULL REFERENCES documents(id),
+        key TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+    CREATE INDEX tags_key_value ON tags (key, value);
+    CREATE INDEX documents_source ON documents (source);
+";
+
+/// One cataloged result
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    /// Result file, relative to the output directory; for sink feeds, the
+    /// feed file, shared by all of its documents
+    pub output: String,
+    /// Source document, when the layout index or the sink record names it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extraction_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    pub chunks: usize,
+    pub characters: usize,
+    pub warnings: usize,
+    /// Metadata fields with one value across the document and its chunks,
+    /// including --tag and --tags-from-path tags
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    #[serde(skip)]
+    modified: Option<i64>,
+}
+
+pub struct Summary {
+    pub documents: usize,
+    pub chunks: usize,
+    /// JSON and JSONL files that aren't results or sink feeds
+    pub skipped: usize,
+}
+
+/// Catalog every JSON result and JSONL sink feed under `dir` into `database`,
+/// replacing what it held before
+pub fn build(dir: &Path, database: &Path) -> Result<Summary> {
+    let mut documents = Vec::new();
+    let mut sources: HashMap<String, String> = HashMap::new();
+    let mut skipped = 0;
+
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.context(format!("Failed to read {}", dir.display()))?;
+        let path = entry.path();
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let output = relative_key(dir, path);
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs() as i64);
+        let found = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {
+                let content: Option<Value> = fs::read(path).ok().and_then(|c| serde_json::from_slice(&c).ok());
+                match content {
+                    Some(Value::Object(index)) if output == INDEX_FILE && !index.contains_key("success") => {
+                        // The --layout index maps sources to outputs
+                        sources.extend(index.into_iter().filter_map(|(source, out)| Some((out.as_str()?.to_string(), source))));
+                        continue;
+                    }
+                    Some(result) if is_result(&result) => {
+                        vec![from_result(&result, output, modified)]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            Some("jsonl") => from_sink_feed(path, &output, modified)?,
+            _ => continue,
+        };
+        if found.is_empty() {
+            skipped += 1;
+        }
+        documents.extend(found);
+    }
+    for document in &mut documents {
+        if document.source.is_none() {
+            document.source = sources.get(&document.output).cloned();
+        }
+    }
+
+    let mut connection = Connection::open(database).context(format!("Failed to open {}", database.display()))?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(SCHEMA)?;
+    for document in &documents {
+        transaction.execute(
+            "INSERT INTO documents (output, source, extraction_id, correlation_id, chunks, characters, warnings, modified)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                document.output,
+                document.source,
+                document.extraction_id,
+                document.correlation_id,
+                document.chunks as i64,
+                document.characters as i64,
+                document.warnings as i64,
+                document.modified,
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        for (key, value) in &document.tags {
+            transaction.execute("INSERT INTO tags (document, key, value) VALUES (?1, ?2, ?3)", params![id, key, value])?;
+        }
+    }
+    transaction.commit().context(format!("Failed to write {}", database.display()))?;
+
+    Ok(Summary {
+        documents: documents.len(),
+        chunks: documents.iter().map(|d| d.chunks).sum(),
+        skipped,
+    })
+}
+
+/// Whether a JSON document is an extraction result written by the CLI
+pub fn is_result(content: &Value) -> bool {
+    content.get("success").is_some_and(Value::is_boolean)
+}
+
+fn from_result(result: &Value, output: String, modified: Option<i64>) -> Document {
+    let string = |key: &str| result[key].as_str().map(str::to_string);
+    let chunks = result["chunks"].as_array();
+    let characters = match result["text"].as_str() {
+        Some(text) => text.chars().count(),
+        None => chunks.into_iter().flatten().filter_map(Value::as_str).map(|c| c.chars().count()).sum(),
+    };
+    let metadata = std::iter::once(&result["metadata"]).chain(result["chunksMetadata"].as_array().into_iter().flatten());
+    Document {
+        output,
+        extraction_id: string("extractionId"),
+        correlation_id: string("correlationId"),
+        chunks: chunks.map_or(0, Vec::len),
+        characters,
+        warnings: result["warnings"].as_array().map_or(0, Vec::len),
+        tags: single_valued_fields(metadata),
+        modified,
+        ..Default::default()
+    }
+}
+
+// Documents of a JSONL sink feed, replaying its upserts and deletes
+fn from_sink_feed(path: &Path, output: &str, modified: Option<i64>) -> Result<Vec<Document>> {
+    let file = fs::File::open(path).context(format!("Failed to read {}", path.display()))?;
+    // Chunk text lengths and metadata by source, in order of appearance
+    let mut chunks: Vec<(String, Vec<(usize, Value)>)> = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(record) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        let Some(source) = record["source"].as_str() else {
+            continue;
+        };
+        let position = match chunks.iter().position(|(s, _)| s == source) {
+            Some(position) => position,
+            None => {
+                chunks.push((source.to_string(), Vec::new()));
+                chunks.len() - 1
+            }
+        };
+        match record["op"].as_str() {
+            Some("upsert") => {
+                let length = record["text"].as_str().map_or(0, |t| t.chars().count());
+                chunks[position].1.push((length, record["metadata"].clone()));
+            }
+            Some("delete") => chunks[position].1.clear(),
+            _ => {}
+        }
+    }
+
+    Ok(chunks
+        .into_iter()
+        .filter(|(_, chunks)| !chunks.is_empty())
+        .map(|(source, chunks)| Document {
+            output: output.to_string(),
+            source: Some(source),
+            chunks: chunks.len(),
+            characters: chunks.iter().map(|(length, _)| length).sum(),
+            tags: single_valued_fields(chunks.iter().map(|(_, metadata)| metadata)),
+            modified,
+            ..Default::default()
+        })
+        .collect())
+}
+
+// Top-level scalar fields of metadata objects (or JSON strings holding them)
+// that have the same value wherever they appear
+fn single_valued_fields<'a>(metadata: impl Iterator<Item = &'a Value>) -> BTreeMap<String, String> {
+    let mut values: BTreeMap<String, Option<String>> = BTreeMap::new();
+    for metadata in metadata {
+        let parsed;
+        let object = match metadata {
+            Value::String(raw) => {
+                parsed = serde_json::from_str::<Value>(raw).ok();
+                parsed.as_ref().and_then(Value::as_object)
+            }
+            other => other.as_object(),
+        };
+        for (key, value) in object.into_iter().flatten() {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => continue,
+            };
+            values
+                .entry(key.clone())
+                .and_modify(|seen| {
+                    if seen.as_ref() != Some(&value) {
+                        *seen = None;
+                    }
+                })
+                .or_insert(Some(value));
+        }
+    }
+    values.into_iter().filter_map(|(key, value)| Some((key, value?))).collect()
+}
+
+/// Conditions a cataloged document must meet; unset ones match everything
+#[derive(Default)]
+pub struct Filters<'a> {
+    /// SQLite GLOB pattern matched against the source and the output
+    pub source: Option<&'a str>,
+    pub tags: &'a [(String, String)],
+    pub extraction_id: Option<&'a str>,
+    pub min_chunks: Option<usize>,
+    pub max_chunks: Option<usize>,
+}
+
+/// Documents in `database` that meet `filters`, ordered by output and source
+pub fn query(database: &Path, filters: &Filters) -> Result<Vec<Document>> {
+    if !database.exists() {
+        anyhow::bail!("No catalog at {} (create it with 'vectorize-iris index-outputs')", database.display());
+    }
+    let connection = Connection::open(database).context(format!("Failed to open {}", database.display()))?;
+
+    let mut sql = String::from(
+        "SELECT id, output, source, extraction_id, correlation_id, chunks, characters, warnings FROM documents WHERE 1 = 1",
+    );
+    let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+    if let Some(source) = filters.source {
+        sql.push_str(" AND (source GLOB ? OR output GLOB ?)");
+        values.push(Box::new(source.to_string()));
+        values.push(Box::new(source.to_string()));
+    }
+    if let Some(extraction_id) = filters.extraction_id {
+        sql.push_str(" AND extraction_id = ?");
+        values.push(Box::new(extraction_id.to_string()));
+    }
+    if let Some(min) = filters.min_chunks {
+        sql.push_str(" AND chunks >= ?");
+        values.push(Box::new(min as i64));
+    }
+    if let Some(max) = filters.max_chunks {
+        sql.push_str(" AND chunks <= ?");
+        values.push(Box::new(max as i64));
+    }
+    for (key, value) in filters.tags {
+        sql.push_str(" AND EXISTS (SELECT 1 FROM tags WHERE document = documents.id AND key = ? AND value = ?)");
+        values.push(Box::new(key.clone()));
+        values.push(Box::new(value.clone()));
+    }
+    sql.push_str(" ORDER BY output, source");
+
+    let mut statement = connection.prepare(&sql).context("The catalog is from an incompatible version; run index-outputs again")?;
+    let rows = statement.query_map(params_from_iter(values.iter()), |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            Document {
+                output: row.get(1)?,
+                source: row.get(2)?,
+                extraction_id: row.get(3)?,
+                correlation_id: row.get(4)?,
+                chunks: row.get::<_, i64>(5)? as usize,
+                characters: row.get::<_, i64>(6)? as usize,
+                warnings: row.get::<_, i64>(7)? as usize,
+                ..Default::default()
+            },
+        ))
+    })?;
+
+    let mut tags = connection.prepare("SELECT key, value FROM tags WHERE document = ? ORDER BY key")?;
+    let mut documents = Vec::new();
+    for row in rows {
+        let (id, mut document) = row?;
+        document.tags = tags
+            .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        documents.push(document);
+    }
+    Ok(documents)
+}
+
+/// Where the catalog of `dir` is kept
+pub fn database_path(dir: &Path, database: Option<&PathBuf>) -> PathBuf {
+    database.cloned().unwrap_or_else(|| dir.join(DEFAULT_DATABASE))
+}
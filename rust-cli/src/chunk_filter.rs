@@ -0,0 +1,380 @@
+// Expressions such as `metadata.section == "Risk Factors"` that select which
+// chunks of a result are kept (--filter-chunks).
+//
+//   expr       := term (("||" | "or") term)*
+//   term       := factor (("&&" | "and") factor)*
+//   factor     := ("!" | "not") factor | "(" expr ")" | path (op literal)?
+//   path       := ("metadata" | "text" | "index") ("." name | "[" number | string "]")*
+//   op         := "==" | "!=" | "<" | "<=" | ">" | ">=" | "contains"
+//   literal    := string | number | true | false | null
+//
+// A path without a comparison is true when it exists and is not null or false.
+
+use iris_client::ExtractionResultData;
+use serde_json::{json, Value};
+
+const ROOTS: &[&str] = &["metadata", "text", "index"];
+
+/// A parsed filter and the expression it was parsed from
+#[derive(Clone, Debug)]
+pub struct ChunkFilter(Expr, String);
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Vec<Segment>, Op, Value),
+    Present(Vec<Segment>),
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Name(String),
+    Literal(Value),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    Dot,
+    LBracket,
+    RBracket,
+}
+
+/// Parse a --filter-chunks expression
+pub fn parse(value: &str) -> Result<ChunkFilter, String> {
+    let tokens = tokenize(value)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(ChunkFilter(expr, value.trim().to_string())),
+        Some(token) => Err(format!("unexpected {} in '{}'", describe(token), value)),
+    }
+}
+
+impl ChunkFilter {
+    /// The expression as given
+    pub fn source(&self) -> &str {
+        &self.1
+    }
+
+    /// Whether the chunk at `index` passes; `metadata` is its metadata, or the
+    /// document's when the chunk has none
+    pub fn matches(&self, index: usize, text: &str, metadata: Option<Value>) -> bool {
+        let chunk = json!({ "metadata": metadata, "text": text, "index": index });
+        self.0.eval(&chunk)
+    }
+
+    /// Drop the chunks that don't match, keeping per-chunk metadata aligned. The
+    /// text is replaced by the kept chunks; a result without chunks is filtered
+    /// as a single chunk holding the whole text.
+    pub fn apply(&self, data: &mut ExtractionResultData) {
+        let document = data.metadata.as_deref().and_then(parse_metadata);
+        let chunks = match data.chunks.take() {
+            Some(chunks) if !chunks.is_empty() => chunks,
+            chunks => {
+                data.chunks = chunks;
+                if data.text.as_ref().is_some_and(|text| !self.matches(0, text, document)) {
+                    data.text = None;
+                }
+                return;
+            }
+        };
+
+        let keep: Vec<bool> = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let metadata = data.chunks_metadata.as_ref()
+                    .and_then(|m| m.get(i))
+                    .and_then(|m| m.as_deref())
+                    .and_then(parse_metadata)
+                    .or_else(|| document.clone());
+                self.matches(i, text, metadata)
+            })
+            .collect();
+
+        let chunks = retain(chunks, &keep);
+        data.chunks_metadata = data.chunks_metadata.take().map(|m| retain(m, &keep));
+        data.chunks_schema = data.chunks_schema.take().map(|s| retain(s, &keep));
+        data.chunks_entities = data.chunks_entities.take().map(|e| retain(e, &keep));
+        data.text = (!chunks.is_empty()).then(|| chunks.join("\n\n"));
+        data.chunks = Some(chunks);
+    }
+}
+
+fn parse_metadata(raw: &str) -> Option<Value> {
+    serde_json::from_str(raw).ok()
+}
+
+fn retain<T>(items: Vec<T>, keep: &[bool]) -> Vec<T> {
+    items
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| keep.get(*i).copied().unwrap_or(false))
+        .map(|(_, item)| item)
+        .collect()
+}
+
+impl Expr {
+    fn eval(&self, chunk: &Value) -> bool {
+        match self {
+            Expr::Or(a, b) => a.eval(chunk) || b.eval(chunk),
+            Expr::And(a, b) => a.eval(chunk) && b.eval(chunk),
+            Expr::Not(a) => !a.eval(chunk),
+            Expr::Present(path) => !matches!(lookup(chunk, path), None | Some(Value::Null) | Some(Value::Bool(false))),
+            Expr::Compare(path, op, expected) => compare(lookup(chunk, path), *op, expected),
+        }
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        Segment::Key(key) => value.get(key),
+        Segment::Index(index) => value.get(index),
+    })
+}
+
+fn compare(actual: Option<&Value>, op: Op, expected: &Value) -> bool {
+    let actual = actual.unwrap_or(&Value::Null);
+    match op {
+        Op::Eq => equal(actual, expected),
+        Op::Ne => !equal(actual, expected),
+        Op::Contains => match actual {
+            Value::String(s) => expected.as_str().is_some_and(|e| s.contains(e)),
+            Value::Array(items) => items.iter().any(|item| equal(item, expected)),
+            _ => false,
+        },
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+            let ordering = match (actual, expected) {
+                (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                _ => None,
+            };
+            ordering.is_some_and(|o| match op {
+                Op::Lt => o.is_lt(),
+                Op::Le => o.is_le(),
+                Op::Gt => o.is_gt(),
+                _ => o.is_ge(),
+            })
+        }
+    }
+}
+
+// Numbers compare by value so 1 == 1.0
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => text.push(escaped),
+                        None => return Err(format!("unterminated string in '{}'", input)),
+                    },
+                    Some((_, ch)) if ch == c => break,
+                    Some((_, ch)) => text.push(ch),
+                    None => return Err(format!("unterminated string in '{}'", input)),
+                }
+            }
+            tokens.push(Token::Literal(Value::String(text)));
+            continue;
+        }
+
+        if c.is_ascii_digit() || c == '-' {
+            let end = input[start..]
+                .find(|ch: char| !(ch.is_ascii_digit() || ch == '.' || ch == '-'))
+                .map_or(input.len(), |len| start + len);
+            let number: f64 = input[start..end].parse().map_err(|_| format!("invalid number '{}'", &input[start..end]))?;
+            tokens.push(Token::Literal(json!(number)));
+            while chars.peek().is_some_and(|&(i, _)| i < end) {
+                chars.next();
+            }
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let end = input[start..]
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .map_or(input.len(), |len| start + len);
+            tokens.push(match &input[start..end] {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                "contains" => Token::Op(Op::Contains),
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                "null" => Token::Literal(Value::Null),
+                name => Token::Name(name.to_string()),
+            });
+            while chars.peek().is_some_and(|&(i, _)| i < end) {
+                chars.next();
+            }
+            continue;
+        }
+
+        chars.next();
+        let next = chars.peek().map(|&(_, ch)| ch);
+        let (token, pair) = match (c, next) {
+            ('=', Some('=')) => (Token::Op(Op::Eq), true),
+            ('!', Some('=')) => (Token::Op(Op::Ne), true),
+            ('<', Some('=')) => (Token::Op(Op::Le), true),
+            ('>', Some('=')) => (Token::Op(Op::Ge), true),
+            ('&', Some('&')) => (Token::And, true),
+            ('|', Some('|')) => (Token::Or, true),
+            ('<', _) => (Token::Op(Op::Lt), false),
+            ('>', _) => (Token::Op(Op::Gt), false),
+            ('!', _) => (Token::Not, false),
+            ('(', _) => (Token::LParen, false),
+            (')', _) => (Token::RParen, false),
+            ('.', _) => (Token::Dot, false),
+            ('[', _) => (Token::LBracket, false),
+            (']', _) => (Token::RBracket, false),
+            _ => return Err(format!("unexpected '{}' in '{}'", c, input)),
+        };
+        if pair {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Name(name) => format!("'{}'", name),
+        Token::Literal(value) => value.to_string(),
+        Token::Op(op) => format!("{:?}", op).to_lowercase(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.tokens.get(self.pos) == Some(token);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.factor()?)));
+        }
+        if self.eat(&Token::LParen) {
+            let expr = self.expr()?;
+            return match self.next() {
+                Some(Token::RParen) => Ok(expr),
+                _ => Err("missing ')'".to_string()),
+            };
+        }
+
+        let path = self.path()?;
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.pos += 1;
+                match self.next() {
+                    Some(Token::Literal(value)) => Ok(Expr::Compare(path, op, value)),
+                    _ => Err("expected a string, number, true, false, or null after the comparison".to_string()),
+                }
+            }
+            _ => Ok(Expr::Present(path)),
+        }
+    }
+
+    fn path(&mut self) -> Result<Vec<Segment>, String> {
+        let root = match self.next() {
+            Some(Token::Name(name)) if ROOTS.contains(&name.as_str()) => name,
+            Some(Token::Name(name)) => return Err(format!("unknown field '{}' (use metadata, text, or index)", name)),
+            Some(token) => return Err(format!("expected a field, found {}", describe(&token))),
+            None => return Err("expected a field, found the end of the expression".to_string()),
+        };
+
+        let mut path = vec![Segment::Key(root)];
+        loop {
+            if self.eat(&Token::Dot) {
+                match self.next() {
+                    Some(Token::Name(name)) => path.push(Segment::Key(name)),
+                    _ => return Err("expected a name after '.'".to_string()),
+                }
+            } else if self.eat(&Token::LBracket) {
+                let segment = match self.next() {
+                    Some(Token::Literal(Value::String(key))) => Segment::Key(key),
+                    Some(Token::Literal(Value::Number(n))) if n.as_f64().is_some_and(|f| f >= 0.0 && f.fract() == 0.0) => {
+                        Segment::Index(n.as_f64().unwrap() as usize)
+                    }
+                    _ => return Err("expected an index or a quoted key inside [ ]".to_string()),
+                };
+                if !self.eat(&Token::RBracket) {
+                    return Err("missing ']'".to_string());
+                }
+                path.push(segment);
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+}
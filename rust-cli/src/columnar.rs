@@ -0,0 +1,165 @@
+// Parquet output (-o parquet, with the parquet feature), so batch results can
+// be loaded straight into Spark, DuckDB, or pandas: one row per chunk, with
+// the document's source, extraction ID, and metadata on every row, so the
+// files of a batch can be read as one table. Also the parquet sink
+// (--sink parquet:PATH), which writes a row group per flush of a batch.
+
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use crate::result::AnnotatedResult;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::sink::{ChunkRecord, Sink, Text};
+
+/// The Parquet file of a result whose rows are `chunks`
+pub fn to_parquet(data: &AnnotatedResult, source: Option<&str>, chunks: &[impl AsRef<str>]) -> Result<Vec<u8>> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("source", DataType::Utf8, true),
+        Field::new("extraction_id", DataType::Utf8, true),
+        Field::new("chunk_index", DataType::Int64, false),
+        Field::new("text", DataType::Utf8, false),
+        Field::new("char_count", DataType::Int64, false),
+        Field::new("chunk_metadata", DataType::Utf8, true),
+        Field::new("document_metadata", DataType::Utf8, true),
+    ]));
+    let rows = chunks.len();
+    let repeated = |value: Option<&str>| -> ArrayRef { Arc::new(StringArray::from(vec![value; rows])) };
+    let chunk_metadata: Vec<Option<&str>> = (0..rows)
+        .map(|i| data.chunks_metadata.as_ref().and_then(|metadata| metadata.get(i)?.as_deref()))
+        .collect();
+    let columns: Vec<ArrayRef> = vec![
+        repeated(source),
+        repeated(data.extraction_id.as_deref()),
+        Arc::new(Int64Array::from_iter_values(0..rows as i64)),
+        Arc::new(StringArray::from_iter_values(chunks.iter().map(AsRef::as_ref))),
+        Arc::new(Int64Array::from_iter_values(chunks.iter().map(|chunk| chunk.as_ref().chars().count() as i64))),
+        Arc::new(StringArray::from(chunk_metadata)),
+        repeated(data.metadata.as_deref()),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut content = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut content, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(content)
+}
+
+/// Writes upsert/delete operations as Parquet rows (op, id, source, index,
+/// text, metadata, entities as JSON). Records are kept only until the next
+/// flush, which writes them as one row group; the file is replaced by each
+/// run and is readable once the run finishes.
+pub struct ParquetSink {
+    path: PathBuf,
+    schema: SchemaRef,
+    // Taken when the file is finished
+    writer: Option<ArrowWriter<File>>,
+    rows: Vec<SinkRow>,
+}
+
+struct SinkRow {
+    op: &'static str,
+    id: String,
+    source: String,
+    index: Option<i64>,
+    text: Option<String>,
+    metadata: Option<String>,
+    entities: Option<String>,
+}
+
+impl ParquetSink {
+    pub fn open(path: PathBuf) -> Result<ParquetSink> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("op", DataType::Utf8, false),
+            Field::new("id", DataType::Utf8, false),
+            Field::new("source", DataType::Utf8, false),
+            Field::new("index", DataType::Int64, true),
+            Field::new("text", DataType::Utf8, true),
+            Field::new("metadata", DataType::Utf8, true),
+            Field::new("entities", DataType::Utf8, true),
+        ]));
+        let file = File::create(&path).context(format!("Failed to open sink file: {}", path.display()))?;
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+        Ok(ParquetSink { path, schema, writer: Some(writer), rows: Vec::new() })
+    }
+
+    fn write_rows(&mut self) -> Result<()> {
+        let (Some(writer), false) = (&mut self.writer, self.rows.is_empty()) else {
+            return Ok(());
+        };
+        let rows = std::mem::take(&mut self.rows);
+        let strings = |column: fn(&SinkRow) -> Option<&str>| -> ArrayRef {
+            Arc::new(StringArray::from(rows.iter().map(column).collect::<Vec<_>>()))
+        };
+        let columns: Vec<ArrayRef> = vec![
+            strings(|row| Some(row.op)),
+            strings(|row| Some(&row.id)),
+            strings(|row| Some(&row.source)),
+            Arc::new(Int64Array::from(rows.iter().map(|row| row.index).collect::<Vec<_>>())),
+            strings(|row| row.text.as_deref()),
+            strings(|row| row.metadata.as_deref()),
+            strings(|row| row.entities.as_deref()),
+        ];
+        writer.write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        writer.flush().context(format!("Failed to write {}", self.path.display()))
+    }
+}
+
+impl Sink for ParquetSink {
+    fn upsert(&mut self, record: &ChunkRecord) -> Result<()> {
+        // Parquet columns are built in memory, so a text in a file is loaded until the next flush
+        let text = match record.text {
+            Text::Inline(text) => text.to_string(),
+            Text::File(path) => fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?,
+        };
+        self.rows.push(SinkRow {
+            op: "upsert",
+            id: record.id.clone(),
+            source: record.source.to_string(),
+            index: Some(record.index as i64),
+            text: Some(text),
+            metadata: record.metadata.map(str::to_string),
+            entities: record.entities.map(serde_json::to_string).transpose()?,
+        });
+        Ok(())
+    }
+
+    fn delete_prefix(&mut self, source: &str, prefix: &str) -> Result<()> {
+        // A delete row carries the ID prefix in the id column, as in the CSV sink
+        self.rows.push(SinkRow {
+            op: "delete",
+            id: prefix.to_string(),
+            source: source.to_string(),
+            index: None,
+            text: None,
+            metadata: None,
+            entities: None,
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_rows()
+    }
+}
+
+impl Drop for ParquetSink {
+    // The footer that makes the file readable is written last
+    fn drop(&mut self) {
+        let finished = self.write_rows().and_then(|_| match self.writer.take() {
+            Some(writer) => writer.close().map(|_| ()).map_err(Into::into),
+            None => Ok(()),
+        });
+        if let Err(e) = finished {
+            eprintln!("Failed to finish sink file {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// Side-by-side extraction with another provider (compare), for bake-offs
+// between Iris and an incumbent extractor: both run on the same documents,
+// and the report shows how similar their text is, their chunk counts, and how
+// long each took.
+
+use crate::regress::text_change;
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+
+/// Replaced with the path of the document in provider commands
+const FILE_PLACEHOLDER: &str = "{file}";
+
+/// An extractor to compare Iris with
+#[derive(Clone)]
+pub enum Provider {
+    /// A shell command that prints the text of `{file}`, or a JSON result with text and chunks
+    Command(String),
+}
+
+impl Provider {
+    /// Parse a --compare-provider value, such as `cmd:other-tool {file}`
+    pub fn parse(spec: &str) -> Result<Provider, String> {
+        match spec.split_once(':') {
+            Some(("cmd", command)) if command.contains(FILE_PLACEHOLDER) => Ok(Provider::Command(command.to_string())),
+            Some(("cmd", _)) => Err(format!("the command must contain {} where the document's path goes", FILE_PLACEHOLDER)),
+            _ => Err(format!("invalid provider '{}': expected cmd:COMMAND, such as cmd:'other-tool {{file}}'", spec)),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Provider::Command(command) => format!("cmd:{}", command),
+        }
+    }
+
+    /// The text and chunks the provider extracts from `file`
+    pub fn extract(&self, file: &Path) -> Result<Extracted> {
+        let Provider::Command(template) = self;
+        let command = template.replace(FILE_PLACEHOLDER, &shell_quote(&file.to_string_lossy()));
+        let output = if cfg!(windows) {
+            Command::new("cmd").args(["/C", &command]).output()
+        } else {
+            Command::new("sh").args(["-c", &command]).output()
+        }
+        .context(format!("Failed to run provider command: {}", command))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Provider command exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        // Providers that print a JSON result are read like Iris results
+        match serde_json::from_str::<Value>(&stdout) {
+            Ok(result) if result["text"].is_string() || result["chunks"].is_array() => Ok(Extracted::from_result(&result)),
+            _ => Ok(Extracted { text: stdout, chunks: None }),
+        }
+    }
+}
+
+/// What one extractor produced for a document
+pub struct Extracted {
+    pub text: String,
+    pub chunks: Option<usize>,
+}
+
+impl Extracted {
+    fn from_result(result: &Value) -> Extracted {
+        let chunks: Vec<&str> = result["chunks"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+        let text = match result["text"].as_str() {
+            Some(text) => text.to_string(),
+            None => chunks.join("\n\n"),
+        };
+        Extracted { text, chunks: result["chunks"].is_array().then_some(chunks.len()) }
+    }
+}
+
+// Single-quoted for sh, double-quoted for cmd
+fn shell_quote(path: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", path)
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Side {
+    pub seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub characters: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Side {
+    pub fn new(extracted: &Result<Extracted>, seconds: f64) -> Side {
+        let seconds = (seconds * 100.0).round() / 100.0;
+        match extracted {
+            Ok(extracted) => Side {
+                seconds,
+                characters: Some(extracted.text.chars().count()),
+                chunks: extracted.chunks,
+                error: None,
+            },
+            Err(e) => Side { seconds, characters: None, chunks: None, error: Some(format!("{:#}", e)) },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentComparison {
+    pub source: String,
+    /// Share of words both extractors agree on, in percent; word order isn't compared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f64>,
+    pub iris: Side,
+    pub provider: Side,
+}
+
+/// Compare what Iris and the provider extracted from `source`, and how long each took
+pub fn compare_document(
+    source: &str,
+    iris: (Result<Extracted>, f64),
+    provider: (Result<Extracted>, f64),
+) -> DocumentComparison {
+    let similarity = match (&iris.0, &provider.0) {
+        (Ok(iris), Ok(provider)) => Some(((100.0 - text_change(&iris.text, &provider.text)) * 100.0).round() / 100.0),
+        _ => None,
+    };
+    DocumentComparison {
+        source: source.to_string(),
+        similarity,
+        iris: Side::new(&iris.0, iris.1),
+        provider: Side::new(&provider.0, provider.1),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub provider: String,
+    /// Sent with every Iris request of the run
+    pub correlation_id: String,
+    /// Mean similarity of the documents both extractors handled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_similarity: Option<f64>,
+    pub iris_seconds: f64,
+    pub provider_seconds: f64,
+    pub iris_failed: usize,
+    pub provider_failed: usize,
+    pub documents: Vec<DocumentComparison>,
+}
+
+impl Report {
+    pub fn new(provider: &Provider, correlation_id: &str, documents: Vec<DocumentComparison>) -> Report {
+        let similarities: Vec<f64> = documents.iter().filter_map(|d| d.similarity).collect();
+        let mean_similarity = (!similarities.is_empty())
+            .then(|| (similarities.iter().sum::<f64>() / similarities.len() as f64 * 100.0).round() / 100.0);
+        let total = |side: fn(&DocumentComparison) -> &Side| {
+            (documents.iter().map(|d| side(d).seconds).sum::<f64>() * 100.0).round() / 100.0
+        };
+        let failed = |side: fn(&DocumentComparison) -> &Side| documents.iter().filter(|d| side(d).error.is_some()).count();
+        Report {
+            provider: provider.describe(),
+            correlation_id: correlation_id.to_string(),
+            mean_similarity,
+            iris_seconds: total(|d| &d.iris),
+            provider_seconds: total(|d| &d.provider),
+            iris_failed: failed(|d| &d.iris),
+            provider_failed: failed(|d| &d.provider),
+            documents,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// User settings from ~/.config/vectorize/config.toml (or --config PATH).
+// Flags and environment variables take precedence over everything here.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::theme::ThemeConfig;
+use crate::tokens::Tokenizer;
+use crate::{ChunkUnit, ChunkingStrategy, ExtractionType, OutputFormat};
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub api_token: Option<String>,
+    pub token_command: Option<String>,
+    pub org_id: Option<String>,
+    #[serde(alias = "base_url")]
+    pub api_url: Option<String>,
+    pub output: Option<OutputFormat>,
+    pub chunk_size: Option<u32>,
+    pub chunk_overlap: Option<u32>,
+    pub chunk_unit: Option<ChunkUnit>,
+    pub tokenizer: Option<Tokenizer>,
+    pub chunking_strategy: Option<ChunkingStrategy>,
+    pub extraction_type: Option<ExtractionType>,
+    /// Seconds to wait for an extraction
+    pub timeout: Option<u64>,
+    /// Seconds between status checks
+    pub poll_interval: Option<u64>,
+    pub theme: ThemeConfig,
+    pub retention: Retention,
+    /// Named credential sets selected with --profile
+    pub profiles: BTreeMap<String, Profile>,
+    /// Credentials for URL inputs, by host
+    pub sites: BTreeMap<String, SiteCredentials>,
+    // The profile applied by select_profile
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+/// Credentials for one organization, under `[profiles.NAME]`
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub api_token: Option<String>,
+    pub token_command: Option<String>,
+    pub org_id: Option<String>,
+    #[serde(alias = "base_url")]
+    pub api_url: Option<String>,
+}
+
+/// Credentials sent when downloading URL inputs from one host, under
+/// `[sites."HOST"]` (`*.example.com` covers its subdomains)
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteCredentials {
+    pub bearer_token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub cookies: BTreeMap<String, String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+/// How long 'vectorize-iris gc' keeps files, under `[retention]`; ages are
+/// durations like `30d`, and flags take precedence
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    pub cache: Option<String>,
+    pub temp: Option<String>,
+    pub outputs: Option<String>,
+    /// Output directories whose results expire after `outputs`
+    pub output_dirs: Vec<PathBuf>,
+}
+
+/// Directory of the config file, saved schemas, and presets
+pub fn config_dir() -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(".config").join("vectorize"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+impl Config {
+    /// Load `path`, or the default config file if there is one; a missing
+    /// default file means all defaults
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) if !path.exists() => return Err(anyhow!("Config file not found: {}", path.display())),
+            Some(path) => path.to_path_buf(),
+            None => match config_path().filter(|p| p.exists()) {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            },
+        };
+        let content = fs::read_to_string(&path)
+            .context(format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content).context(format!("Invalid config file {}", path.display()))
+    }
+
+    /// Use the credentials of a named profile in place of the top-level ones;
+    /// settings the profile leaves out still come from the top level
+    pub fn select_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| match self.profiles.len() {
+            0 => anyhow!("Unknown profile '{}': the config file has no [profiles] section", name),
+            _ => anyhow!(
+                "Unknown profile '{}' (available: {})",
+                name,
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        })?;
+
+        // Replace both token settings so a top-level token_command can't outrank the profile's token
+        if profile.api_token.is_some() || profile.token_command.is_some() {
+            self.api_token = profile.api_token;
+            self.token_command = profile.token_command;
+        }
+        self.org_id = profile.org_id.or(self.org_id.take());
+        self.api_url = profile.api_url.or(self.api_url.take());
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+// Comparing extracted text with the source of born-digital text inputs
+// (Markdown, plain text, HTML), to catch content the service silently dropped
+// or truncated.
+
+use crate::encoding::{self, InputEncoding};
+use crate::theme;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+// A section counts as missing when less than this share of its words were extracted
+const MISSING_BELOW: f64 = 0.5;
+// Characters of a missing section shown in reports
+const PREVIEW_CHARS: usize = 60;
+
+// Elements whose content isn't rendered as text
+const HTML_SKIPPED: &[&str] = &["head", "script", "style", "noscript", "template", "svg"];
+// Elements that start a new section
+const HTML_BLOCKS: &[&str] = &[
+    "html", "body", "main", "article", "section", "header", "footer", "nav", "aside", "div", "p", "br", "hr",
+    "blockquote", "pre", "ul", "ol", "li", "dl", "dt", "dd", "table", "caption", "tr", "td", "th", "figure",
+    "figcaption", "form", "fieldset", "h1", "h2", "h3", "h4", "h5", "h6",
+];
+
+/// How much of a text source file's content appears in the extracted text
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Coverage {
+    /// Share of the source's words found in the extracted text, from 0 to 100
+    pub percent: f64,
+    #[serde(rename = "sourceWords")]
+    pub source_words: usize,
+    /// Sections of the source with less than half of their words in the extracted text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<MissingSection>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MissingSection {
+    /// The heading the section is under, when the source has headings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    /// The start of the section's text
+    pub text: String,
+}
+
+#[derive(Clone, Copy)]
+enum SourceFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+fn source_format(path: &Path) -> Option<SourceFormat> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "txt" | "text" => Some(SourceFormat::Text),
+        "md" | "markdown" => Some(SourceFormat::Markdown),
+        "html" | "htm" => Some(SourceFormat::Html),
+        _ => None,
+    }
+}
+
+// A block of source text and the heading it's under
+struct Section {
+    heading: Option<String>,
+    text: String,
+}
+
+/// Compare the extracted text with the text of the source file; returns None
+/// when the file isn't Markdown, plain text, or HTML. The text is read line by
+/// line, so one spilled to a file for its size isn't loaded whole.
+pub fn verify(path: &Path, encoding: InputEncoding, extracted: impl BufRead) -> io::Result<Option<Coverage>> {
+    let Some(format) = source_format(path) else {
+        return Ok(None);
+    };
+    let (content, _) = encoding::to_utf8(fs::read(path)?, encoding);
+    let content = String::from_utf8_lossy(&content);
+    let sections = match format {
+        SourceFormat::Text => text_sections(&content, false),
+        SourceFormat::Markdown => text_sections(&content, true),
+        SourceFormat::Html => html_sections(&content),
+    };
+    // Words never span lines, so counting them line by line gives the same counts
+    let mut available: HashMap<String, usize> = HashMap::new();
+    for line in extracted.lines() {
+        for word in words(&line?) {
+            *available.entry(word).or_default() += 1;
+        }
+    }
+    Ok(Some(compare(&sections, available)))
+}
+
+// Match the words of each section, in order, against the counts of words of
+// the extracted text; each extracted word can only be matched once
+fn compare(sections: &[Section], mut available: HashMap<String, usize>) -> Coverage {
+    let (mut total, mut found) = (0usize, 0usize);
+    let mut missing = Vec::new();
+    for section in sections {
+        let words = words(&section.text);
+        let mut section_found = 0;
+        for word in &words {
+            if let Some(count) = available.get_mut(word).filter(|count| **count > 0) {
+                *count -= 1;
+                section_found += 1;
+            }
+        }
+        total += words.len();
+        found += section_found;
+        if !words.is_empty() && (section_found as f64) < words.len() as f64 * MISSING_BELOW {
+            missing.push(MissingSection { heading: section.heading.clone(), text: preview(&section.text) });
+        }
+    }
+
+    // Rounded down, so anything missing keeps it under 100
+    let percent = (found * 1000).checked_div(total).map_or(100.0, |permille| permille as f64 / 10.0);
+    Coverage { percent, source_words: total, missing }
+}
+
+/// Lowercased words, normalized to NFKC so ligatures and full-width letters
+/// match their plain forms
+pub fn words(text: &str) -> Vec<String> {
+    let text: String = text.nfkc().collect::<String>().to_lowercase();
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn preview(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}{}", &text[..end], theme::glyph("…", "...")),
+        None => text,
+    }
+}
+
+// Paragraphs separated by blank lines; Markdown headings are sections of
+// their own and name the sections under them
+fn text_sections(content: &str, markdown: bool) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut block = String::new();
+    for line in content.lines().chain(std::iter::once("")) {
+        let line = line.trim();
+        let title = if markdown { markdown_heading(line) } else { None };
+        if line.is_empty() || title.is_some() {
+            let text = std::mem::take(&mut block);
+            if !text.trim().is_empty() {
+                sections.push(Section { heading: heading.clone(), text });
+            }
+        }
+        match title {
+            Some(title) => {
+                heading = Some(title.to_string());
+                sections.push(Section { heading: heading.clone(), text: title.to_string() });
+            }
+            None if markdown => block.push_str(&strip_link_targets(line)),
+            None => block.push_str(line),
+        }
+        block.push(' ');
+    }
+    sections
+}
+
+// The title of an ATX heading such as `## Results`
+fn markdown_heading(line: &str) -> Option<&str> {
+    let title = line.trim_start_matches('#');
+    let level = line.len() - title.len();
+    ((1..=6).contains(&level) && (title.is_empty() || title.starts_with(' ')))
+        .then(|| title.trim().trim_end_matches('#').trim())
+        .filter(|title| !title.is_empty())
+}
+
+// Drop the targets of links and images, [text](target), which aren't rendered
+fn strip_link_targets(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(i) = rest.find("](") {
+        out.push_str(&rest[..=i]);
+        match rest[i + 2..].find(')') {
+            Some(end) => rest = &rest[i + 2 + end + 1..],
+            None => {
+                rest = &rest[i + 1..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// The text of block elements; h1-h6 name the sections after them
+fn html_sections(content: &str) -> Vec<Section> {
+    // ASCII lowercasing keeps byte offsets, so positions in one apply to the other
+    let lower = content.to_ascii_lowercase();
+    let mut sections = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut block = String::new();
+    let mut pos = 0;
+
+    while pos < content.len() {
+        let Some(lt) = content[pos..].find('<') else {
+            block.push_str(&decode_entities(&content[pos..]));
+            break;
+        };
+        block.push_str(&decode_entities(&content[pos..pos + lt]));
+        let start = pos + lt;
+
+        if lower[start..].starts_with("<!--") {
+            pos = lower[start..].find("-->").map_or(content.len(), |end| start + end + 3);
+            continue;
+        }
+        // A < that doesn't start a tag is text
+        if !lower[start + 1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+            block.push('<');
+            pos = start + 1;
+            continue;
+        }
+        let Some(end) = content[start..].find('>') else {
+            break;
+        };
+        let tag = &lower[start + 1..start + end];
+        pos = start + end + 1;
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        if !closing && HTML_SKIPPED.contains(&name) {
+            pos = lower[pos..].find(&format!("</{}", name)).map_or(content.len(), |end| pos + end);
+            continue;
+        }
+        if HTML_BLOCKS.contains(&name) {
+            let text = std::mem::take(&mut block);
+            if !text.trim().is_empty() {
+                if closing && matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+                    heading = Some(text.split_whitespace().collect::<Vec<_>>().join(" "));
+                }
+                sections.push(Section { heading: heading.clone(), text });
+            }
+        }
+    }
+
+    if !block.trim().is_empty() {
+        sections.push(Section { heading, text: block });
+    }
+    sections
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest
+            .char_indices()
+            .take(12)
+            .find(|(_, c)| *c == ';')
+            .map(|(semi, _)| &rest[1..semi])
+            .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '#'));
+        let Some(entity) = entity else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix('#')
+                .and_then(|number| match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                })
+                .and_then(char::from_u32),
+        };
+        // Other named entities are punctuation and symbols, which aren't words
+        out.push(decoded.unwrap_or(' '));
+        rest = &rest[entity.len() + 2..];
+    }
+    out.push_str(rest);
+    out
+}
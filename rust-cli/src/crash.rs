@@ -0,0 +1,165 @@
+// Panic hook that saves a redacted crash report to ~/.vectorize-iris/crash-reports
+// so bug reports contain a backtrace and versions without leaking file names,
+// URLs, or tokens passed on the command line.
+
+use crate::privacy;
+use sha2::{Digest, Sha256};
+use std::backtrace::Backtrace;
+use std::env;
+use std::fs;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Install the crash-report hook. `keep` lists argument values that are safe to
+/// record verbatim (e.g. subcommand names); all other values are hashed.
+/// `short_values` are the short flags that take a value, which may be attached
+/// to them (-ofile).
+pub fn install(build_info: &'static str, keep: Vec<String>, short_values: Vec<char>) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The default hook prints the panic message, which can quote document content
+        if privacy::enabled() {
+            let location = info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+            eprintln!("\nthread panicked at {}: {}", location.unwrap_or_default(), privacy::redact(&panic_message(info)));
+        } else {
+            default_hook(info);
+        }
+
+        match write_report(info, build_info, &keep, &short_values) {
+            Some(path) => eprintln!(
+                "\nvectorize-iris crashed. A crash report was saved to:\n  {}\nPlease attach it when reporting this issue.",
+                path.display()
+            ),
+            None => eprintln!("\nvectorize-iris crashed and the crash report could not be saved."),
+        }
+    }));
+}
+
+fn hash_value(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    format!("<sha256:{}>", &format!("{:x}", digest)[..12])
+}
+
+/// Keep flag names, hash every value
+fn redact_args(args: impl IntoIterator<Item = String>, keep: &[String], short_values: &[char]) -> Vec<String> {
+    let value = |value: &str| match keep.iter().any(|kept| kept == value) {
+        true => value.to_string(),
+        false => hash_value(value),
+    };
+    let mut redacted = vec!["vectorize-iris".to_string()];
+    let mut positional_only = false;
+    for arg in args {
+        let arg = if positional_only || arg == "-" || !arg.starts_with('-') {
+            value(&arg)
+        } else if arg == "--" {
+            positional_only = true;
+            arg
+        } else if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((flag, flag_value)) => format!("--{}={}", flag, value(flag_value)),
+                None => arg,
+            }
+        } else {
+            redact_short_flags(&arg[1..], short_values, value)
+        };
+        redacted.push(arg);
+    }
+    redacted
+}
+
+// A cluster of short flags (-vq), where the first flag taking a value takes
+// the rest of the argument as its value (-ofile, -o=file)
+fn redact_short_flags(flags: &str, short_values: &[char], value: impl Fn(&str) -> String) -> String {
+    for (i, flag) in flags.char_indices() {
+        if short_values.contains(&flag) {
+            let (names, attached) = flags.split_at(i + flag.len_utf8());
+            return match attached.strip_prefix('=').unwrap_or(attached) {
+                "" => format!("-{}{}", names, attached),
+                attached_value => format!("-{}{}{}", names, if attached.starts_with('=') { "=" } else { "" }, value(attached_value)),
+            };
+        }
+    }
+    format!("-{}", flags)
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// Where crash reports are saved
+pub fn reports_dir() -> Option<PathBuf> {
+    Some(crate::get_config_dir().ok()?.join("crash-reports"))
+}
+
+fn write_report(info: &PanicHookInfo, build_info: &str, keep: &[String], short_values: &[char]) -> Option<PathBuf> {
+    let dir = reports_dir()?;
+    fs::create_dir_all(&dir).ok()?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = dir.join(format!("crash-{}-{}.json", timestamp, process::id()));
+
+    let build: serde_json::Value = serde_json::from_str(build_info).unwrap_or_default();
+    let report = serde_json::json!({
+        "message": privacy::redact(&panic_message(info)),
+        "location": info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+        "thread": std::thread::current().name().unwrap_or("unnamed"),
+        "timestamp": timestamp,
+        "args": redact_args(env::args().skip(1), keep, short_values),
+        "version": env!("CARGO_PKG_VERSION"),
+        "gitSha": build.get("gitSha"),
+        "target": build.get("target"),
+        "features": build.get("features"),
+        "os": env::consts::OS,
+        "arch": env::consts::ARCH,
+        "backtrace": Backtrace::force_capture()
+            .to_string()
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>(),
+    });
+
+    fs::write(&path, serde_json::to_string_pretty(&report).ok()?).ok()?;
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(args: &[&str]) -> Vec<String> {
+        let keep = ["extract".to_string(), "json".to_string()];
+        redact_args(args.iter().map(|arg| arg.to_string()), &keep, &['o', 'f'])
+    }
+
+    #[test]
+    fn hashes_attached_short_flag_values() {
+        let args = redact(&["extract", "-vfpath/to/secret.pdf", "-o/home/user/secret/out.json", "-ojson", "-o=json", "-v"]);
+        assert_eq!(args[1], "extract");
+        assert_eq!(args[2], format!("-vf{}", hash_value("path/to/secret.pdf")));
+        assert_eq!(args[3], format!("-o{}", hash_value("/home/user/secret/out.json")));
+        assert_eq!(args[4], "-ojson");
+        assert_eq!(args[5], "-o=json");
+        assert_eq!(args[6], "-v");
+    }
+
+    #[test]
+    fn hashes_long_flag_values() {
+        let args = redact(&["--output-file=/home/user/secret/out.json", "--output=json", "--verbose", "--", "-secret.pdf"]);
+        assert_eq!(args[1], format!("--output-file={}", hash_value("/home/user/secret/out.json")));
+        assert_eq!(args[2], "--output=json");
+        assert_eq!(args[3], "--verbose");
+        assert_eq!(args[4], "--");
+        assert_eq!(args[5], hash_value("-secret.pdf"));
+    }
+}
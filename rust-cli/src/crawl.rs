@@ -0,0 +1,93 @@
+// Crawl state of URL lists extracted into an output directory: the ETag,
+// Last-Modified date, and content hash of every page whose result was written,
+// so a repeat run asks servers whether each page changed and only downloads
+// and extracts the ones that did. Pages without validators are downloaded
+// again but only extracted when their content changed. --refetch-all ignores
+// the state, and a page whose result file was removed is always fetched.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const STATE_FILE: &str = ".vectorize-iris-crawl.json";
+
+/// What was fetched from a URL
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Page {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub sha256: String,
+    pub fetched: DateTime<Local>,
+    /// Result file written for the page, relative to the output directory
+    #[serde(default)]
+    pub output: PathBuf,
+}
+
+#[derive(Default)]
+struct Pages {
+    saved: BTreeMap<String, Page>,
+    // Downloaded in this run; saved once their results are written
+    fetched: HashMap<String, Page>,
+}
+
+pub struct CrawlState {
+    dir: PathBuf,
+    refetch_all: bool,
+    pages: Mutex<Pages>,
+}
+
+impl CrawlState {
+    pub fn load(dir: &Path, refetch_all: bool) -> Result<CrawlState> {
+        let path = dir.join(STATE_FILE);
+        let saved = match fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).context(format!("Invalid crawl state: {}", path.display()))?,
+            Err(_) => BTreeMap::new(),
+        };
+        Ok(CrawlState { dir: dir.to_path_buf(), refetch_all, pages: Mutex::new(Pages { saved, fetched: HashMap::new() }) })
+    }
+
+    /// The last fetch of `url`, unless its result has to be extracted again
+    pub fn previous(&self, url: &str) -> Option<Page> {
+        if self.refetch_all {
+            return None;
+        }
+        let pages = self.pages.lock().unwrap();
+        pages.saved.get(url).filter(|page| self.dir.join(&page.output).exists()).cloned()
+    }
+
+    /// Record a download of `url`; false when its content is the same as
+    /// last time, so it needn't be extracted
+    pub fn fetched(&self, url: &str, page: Page) -> bool {
+        let mut pages = self.pages.lock().unwrap();
+        match pages.saved.get_mut(url) {
+            Some(saved) if !self.refetch_all && saved.sha256 == page.sha256 && self.dir.join(&saved.output).exists() => {
+                *saved = Page { output: saved.output.clone(), ..page };
+                false
+            }
+            _ => {
+                pages.fetched.insert(url.to_string(), page);
+                true
+            }
+        }
+    }
+
+    /// Save the pages whose results were written, as (source, output file)
+    pub fn save(&self, outputs: &[(String, PathBuf)]) -> Result<()> {
+        let mut pages = self.pages.lock().unwrap();
+        for (source, output) in outputs {
+            if let Some(page) = pages.fetched.remove(source) {
+                let output = output.strip_prefix(&self.dir).unwrap_or(output).to_path_buf();
+                pages.saved.insert(source.clone(), Page { output, ..page });
+            }
+        }
+        let path = self.dir.join(STATE_FILE);
+        fs::write(&path, serde_json::to_vec_pretty(&pages.saved)?).context(format!("Failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Context, Result};
+use iris_client::TokenProvider;
+use std::env;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A source of access tokens, consulted in order until one returns a value
+pub trait CredentialProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn token(&self) -> Result<Option<String>>;
+
+    /// Whether asking again can produce a different token (e.g. after expiry)
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+}
+
+/// A token known up front, such as the --api-token flag or the credentials file
+pub struct StaticProvider {
+    name: &'static str,
+    value: Option<String>,
+}
+
+impl StaticProvider {
+    pub fn new(name: &'static str, value: Option<String>) -> StaticProvider {
+        StaticProvider { name, value }
+    }
+}
+
+impl CredentialProvider for StaticProvider {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn token(&self) -> Result<Option<String>> {
+        Ok(self.value.clone())
+    }
+}
+
+pub struct EnvProvider {
+    var: &'static str,
+}
+
+impl EnvProvider {
+    pub fn new(var: &'static str) -> EnvProvider {
+        EnvProvider { var }
+    }
+}
+
+impl CredentialProvider for EnvProvider {
+    fn name(&self) -> &'static str {
+        "environment"
+    }
+
+    fn token(&self) -> Result<Option<String>> {
+        Ok(env::var(self.var).ok().filter(|v| !v.is_empty()))
+    }
+}
+
+/// Runs a shell command and uses its trimmed stdout as the token
+pub struct CommandProvider {
+    command: String,
+}
+
+impl CommandProvider {
+    pub fn new(command: String) -> CommandProvider {
+        CommandProvider { command }
+    }
+}
+
+impl CredentialProvider for CommandProvider {
+    fn name(&self) -> &'static str {
+        "token command"
+    }
+
+    fn token(&self) -> Result<Option<String>> {
+        let output = if cfg!(windows) {
+            Command::new("cmd").args(["/C", &self.command]).output()
+        } else {
+            Command::new("sh").args(["-c", &self.command]).output()
+        }
+        .context(format!("Failed to run token command: {}", self.command))?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Token command exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let token = String::from_utf8(output.stdout)
+            .context("Token command printed invalid UTF-8")?
+            .trim()
+            .to_string();
+
+        if token.is_empty() {
+            return Err(anyhow!("Token command printed an empty token"));
+        }
+
+        Ok(Some(token))
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+}
+
+/// Reads the token from the OS keyring (service `vectorize-iris`, user `api_token`)
+#[cfg(feature = "keyring")]
+pub struct KeyringProvider;
+
+#[cfg(feature = "keyring")]
+impl CredentialProvider for KeyringProvider {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn token(&self) -> Result<Option<String>> {
+        let entry = keyring::Entry::new("vectorize-iris", "api_token")
+            .context("Failed to open keyring entry")?;
+        match entry.get_password() {
+            Ok(token) => Ok(Some(token)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(anyhow!("Failed to read token from keyring: {}", e)),
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+}
+
+/// Fetches the token from an AWS Secrets Manager secret using the default AWS credential chain
+#[cfg(feature = "aws-secrets")]
+pub struct AwsSecretsProvider {
+    secret_id: String,
+}
+
+#[cfg(feature = "aws-secrets")]
+impl AwsSecretsProvider {
+    pub fn new(secret_id: String) -> AwsSecretsProvider {
+        AwsSecretsProvider { secret_id }
+    }
+}
+
+#[cfg(feature = "aws-secrets")]
+impl CredentialProvider for AwsSecretsProvider {
+    fn name(&self) -> &'static str {
+        "AWS Secrets Manager"
+    }
+
+    fn token(&self) -> Result<Option<String>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start async runtime")?;
+
+        runtime.block_on(async {
+            let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            let client = aws_sdk_secretsmanager::Client::new(&config);
+            let secret = client
+                .get_secret_value()
+                .secret_id(&self.secret_id)
+                .send()
+                .await
+                .map_err(|e| anyhow!("Failed to read secret '{}': {}", self.secret_id, e))?;
+            Ok(secret.secret_string().map(|s| s.trim().to_string()))
+        })
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+}
+
+struct CachedToken {
+    value: String,
+    fetched_at: Instant,
+    dynamic: bool,
+}
+
+/// Resolves the token through a provider chain and caches it for the rest of the run
+pub struct TokenSource {
+    providers: Vec<Box<dyn CredentialProvider>>,
+    ttl: Duration,
+    cache: Mutex<Option<CachedToken>>,
+}
+
+impl TokenSource {
+    pub fn new(providers: Vec<Box<dyn CredentialProvider>>, ttl: Duration) -> TokenSource {
+        TokenSource {
+            providers,
+            ttl,
+            cache: Mutex::new(None),
+        }
+    }
+}
+
+impl TokenProvider for TokenSource {
+    /// Current token; dynamic tokens are fetched again once older than the TTL
+    fn token(&self) -> Result<String> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(cached) = cache.as_ref() {
+            if !cached.dynamic || cached.fetched_at.elapsed() < self.ttl {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        for provider in &self.providers {
+            if let Some(value) = provider
+                .token()
+                .context(format!("Credential provider '{}' failed", provider.name()))?
+            {
+                *cache = Some(CachedToken {
+                    value: value.clone(),
+                    fetched_at: Instant::now(),
+                    dynamic: provider.is_dynamic(),
+                });
+                return Ok(value);
+            }
+        }
+
+        let tried: Vec<&str> = self.providers.iter().map(|p| p.name()).collect();
+        Err(anyhow!(
+            "Missing access token (tried: {}). Set with 'vectorize-iris config setup', VECTORIZE_TOKEN env var, --token-command, or --api-token flag",
+            tried.join(", ")
+        ))
+    }
+
+    /// Drop a rejected token; returns true if fetching again may yield a new one
+    fn invalidate(&self) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        let dynamic = cache.as_ref().map(|c| c.dynamic).unwrap_or(false);
+        if dynamic {
+            *cache = None;
+        }
+        dynamic
+    }
+}
@@ -0,0 +1,297 @@
+// Digest reports for long-running batches (--watch or --schedule): files
+// processed, failures with their reasons, and pages used since the last
+// report, sent to a webhook and/or by email on a cron schedule (daily by
+// default), so operations reads one report instead of scraping logs. The tally
+// is kept in the output directory, so a restart neither loses nor repeats it,
+// and a report missed while the process was down is sent on start.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use console::style;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::schedule::{self, Schedule};
+use crate::theme::Themed;
+
+const STATE_FILE: &str = ".vectorize-iris-digest.json";
+// Failures listed in a report; the rest are only counted
+const MAX_FAILURES: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where and when to send reports
+pub struct DigestConfig {
+    pub schedule: Schedule,
+    pub webhook: Option<String>,
+    /// Recipients, sent through `smtp_url`
+    pub email: Vec<String>,
+    #[cfg_attr(not(feature = "email-digest"), allow(dead_code))]
+    pub smtp_url: Option<String>,
+    #[cfg_attr(not(feature = "email-digest"), allow(dead_code))]
+    pub from: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Failure {
+    source: String,
+    error: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Tally {
+    since: DateTime<Local>,
+    successful: usize,
+    failed: usize,
+    pages: u64,
+    failures: Vec<Failure>,
+    failures_omitted: usize,
+}
+
+impl Tally {
+    fn new() -> Tally {
+        Tally { since: Local::now(), successful: 0, failed: 0, pages: 0, failures: Vec::new(), failures_omitted: 0 }
+    }
+
+    fn add_failures(&mut self, failures: impl IntoIterator<Item = Failure>) {
+        for failure in failures {
+            match self.failures.len() < MAX_FAILURES {
+                true => self.failures.push(failure),
+                false => self.failures_omitted += 1,
+            }
+        }
+    }
+
+    // Put back a report that couldn't be sent, so the next one covers it
+    fn restore(&mut self, unsent: Tally) {
+        self.since = unsent.since;
+        self.successful += unsent.successful;
+        self.failed += unsent.failed;
+        self.pages += unsent.pages;
+        self.failures_omitted += unsent.failures_omitted;
+        let recent = std::mem::replace(&mut self.failures, unsent.failures);
+        self.add_failures(recent);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct State {
+    schedule: String,
+    /// When the last report was sent, whether or not it was delivered
+    last_sent: Option<DateTime<Local>>,
+    tally: Tally,
+}
+
+struct Digest {
+    path: PathBuf,
+    state: State,
+}
+
+impl Digest {
+    fn save(&self) {
+        let written = serde_json::to_vec_pretty(&self.state)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| fs::write(&self.path, json).map_err(anyhow::Error::from));
+        if let Err(e) = written {
+            eprintln!("{} Failed to write {}: {}", style("[digest]").warning(), self.path.display(), e);
+        }
+    }
+}
+
+static DIGEST: Mutex<Option<Digest>> = Mutex::new(None);
+
+/// Count a finished batch toward the next report; does nothing unless
+/// reports were started
+pub fn record(successful: usize, failures: &[(String, String)], pages: u64) {
+    let mut digest = DIGEST.lock().unwrap();
+    let Some(digest) = digest.as_mut() else { return };
+    let tally = &mut digest.state.tally;
+    tally.successful += successful;
+    tally.failed += failures.len();
+    tally.pages += pages;
+    tally.add_failures(failures.iter().map(|(source, error)| Failure { source: source.clone(), error: error.clone() }));
+    digest.save();
+}
+
+/// Send a report of the batches run in `dir` whenever `config.schedule` comes
+/// due, from a background thread, for as long as the process runs
+pub fn start(config: DigestConfig, dir: &Path) -> Result<()> {
+    if config.webhook.is_none() && config.email.is_empty() {
+        return Err(anyhow!("Digest reports need --digest-webhook or --digest-email"));
+    }
+    if !config.email.is_empty() {
+        check_email(&config)?;
+    }
+    fs::create_dir_all(dir).context(format!("Failed to create output directory: {}", dir.display()))?;
+
+    let path = dir.join(STATE_FILE);
+    let state = fs::read(&path)
+        .ok()
+        .and_then(|json| serde_json::from_slice::<State>(&json).ok())
+        .filter(|state| state.schedule == config.schedule.as_str());
+    // A new schedule starts a new tally, and its first report isn't overdue
+    let state = state.unwrap_or_else(|| State {
+        schedule: config.schedule.as_str().to_string(),
+        last_sent: Some(Local::now()),
+        tally: Tally::new(),
+    });
+    let mut last_sent = state.last_sent;
+    *DIGEST.lock().unwrap() = Some(Digest { path, state });
+
+    let directory = dir.display().to_string();
+    thread::spawn(move || loop {
+        let now = Local::now();
+        let due = last_sent
+            .and_then(|last| config.schedule.next_after(last))
+            .filter(|due| *due <= now)
+            .or_else(|| config.schedule.next_after(now));
+        let Some(due) = due else { return };
+        schedule::sleep_until(due);
+
+        let sent = Local::now();
+        let tally = {
+            let mut digest = DIGEST.lock().unwrap();
+            let digest = digest.as_mut().unwrap();
+            digest.state.last_sent = Some(sent);
+            let tally = std::mem::replace(&mut digest.state.tally, Tally::new());
+            digest.save();
+            tally
+        };
+        let label = style("[digest]").dim();
+        match send(&config, &directory, &tally) {
+            Ok(()) => eprintln!(
+                "{} Sent the report for {} files since {}",
+                label,
+                tally.successful + tally.failed,
+                tally.since.format("%Y-%m-%d %H:%M")
+            ),
+            Err(e) => {
+                eprintln!("{} Failed to send the report, it will be included in the next one: {:#}", style("[digest]").warning(), e);
+                let mut digest = DIGEST.lock().unwrap();
+                let digest = digest.as_mut().unwrap();
+                digest.state.tally.restore(tally);
+                digest.save();
+            }
+        }
+        last_sent = Some(sent);
+    });
+    Ok(())
+}
+
+fn send(config: &DigestConfig, directory: &str, tally: &Tally) -> Result<()> {
+    let until = Local::now();
+    let mut errors = Vec::new();
+    if let Some(url) = &config.webhook {
+        let report = json!({
+            "event": "digest",
+            "directory": directory,
+            "since": tally.since,
+            "until": until,
+            "files": tally.successful + tally.failed,
+            "successful": tally.successful,
+            "failed": tally.failed,
+            "irisPages": tally.pages,
+            "failures": tally.failures,
+            "failuresOmitted": tally.failures_omitted,
+        });
+        if let Err(e) = post(url, &report) {
+            errors.push(format!("webhook: {:#}", e));
+        }
+    }
+    if !config.email.is_empty() {
+        let subject = format!(
+            "vectorize-iris digest: {} files, {} failed ({})",
+            tally.successful + tally.failed,
+            tally.failed,
+            directory
+        );
+        if let Err(e) = send_email(config, &subject, &text_report(directory, tally, until)) {
+            errors.push(format!("email: {:#}", e));
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!(errors.join("; "))),
+    }
+}
+
+fn post(url: &str, report: &serde_json::Value) -> Result<()> {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .post(url)
+        .json(report)
+        .send()
+        .context(format!("Failed to reach {}", url))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{} answered {}", url, response.status()));
+    }
+    Ok(())
+}
+
+fn text_report(directory: &str, tally: &Tally, until: DateTime<Local>) -> String {
+    let mut report = format!(
+        "vectorize-iris digest for {}\n{} to {}\n\nFiles processed: {}\nSuccessful: {}\nFailed: {}\nPages used: {}\n",
+        directory,
+        tally.since.format("%Y-%m-%d %H:%M"),
+        until.format("%Y-%m-%d %H:%M"),
+        tally.successful + tally.failed,
+        tally.successful,
+        tally.failed,
+        tally.pages
+    );
+    if !tally.failures.is_empty() {
+        report.push_str("\nFailures:\n");
+        for failure in &tally.failures {
+            report.push_str(&format!("  {}: {}\n", failure.source, failure.error));
+        }
+        if tally.failures_omitted > 0 {
+            report.push_str(&format!("  ... and {} more\n", tally.failures_omitted));
+        }
+    }
+    report
+}
+
+#[cfg(feature = "email-digest")]
+fn check_email(config: &DigestConfig) -> Result<()> {
+    let url = config.smtp_url.as_deref().context("--digest-email needs --smtp-url or VECTORIZE_SMTP_URL")?;
+    lettre::SmtpTransport::from_url(url).context("Invalid SMTP URL")?;
+    message(config, "", String::new()).map(|_| ())
+}
+
+#[cfg(feature = "email-digest")]
+fn message(config: &DigestConfig, subject: &str, body: String) -> Result<lettre::Message> {
+    let mut message = lettre::Message::builder()
+        .from(config.from.parse().context(format!("Invalid --digest-from address: {}", config.from))?)
+        .subject(subject);
+    for to in &config.email {
+        message = message.to(to.parse().context(format!("Invalid --digest-email address: {}", to))?);
+    }
+    Ok(message.body(body)?)
+}
+
+#[cfg(feature = "email-digest")]
+fn send_email(config: &DigestConfig, subject: &str, body: &str) -> Result<()> {
+    use lettre::Transport;
+    let url = config.smtp_url.as_deref().context("--digest-email needs --smtp-url")?;
+    let transport = lettre::SmtpTransport::from_url(url)?.timeout(Some(REQUEST_TIMEOUT)).build();
+    transport.send(&message(config, subject, body.to_string())?)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "email-digest"))]
+fn check_email(_config: &DigestConfig) -> Result<()> {
+    Err(anyhow!("This build doesn't include digest emails; rebuild with 'cargo build --features email-digest'"))
+}
+
+#[cfg(not(feature = "email-digest"))]
+fn send_email(_config: &DigestConfig, _subject: &str, _body: &str) -> Result<()> {
+    unreachable!("checked by start")
+}
@@ -0,0 +1,128 @@
+// Hierarchical output (-o doc-graph) for graph-RAG: the document's sections
+// nested by heading level, each holding the chunks that start in it. Every
+// node has an ID and its parent's ID, so the tree loads as a graph without
+// reconstructing the structure from a flat chunk list.
+
+use anyhow::Result;
+use crate::result::AnnotatedResult;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::outline::{self, Heading};
+
+const DOCUMENT_ID: &str = "document";
+
+#[derive(Serialize)]
+pub struct Graph {
+    id: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
+    /// Chunks before the first heading
+    chunks: Vec<Chunk>,
+    sections: Vec<Section>,
+}
+
+#[derive(Serialize)]
+struct Section {
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    parent: String,
+    title: String,
+    level: usize,
+    /// Titles from the outermost section down to this one
+    path: Vec<String>,
+    chunks: Vec<Chunk>,
+    sections: Vec<Section>,
+}
+
+#[derive(Serialize)]
+struct Chunk {
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    parent: String,
+    index: usize,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
+}
+
+/// The section tree of a result; `source` is the file or URL it came from
+pub fn graph(data: &AnnotatedResult, source: Option<&str>) -> Result<Graph> {
+    let text = outline::text(data)?;
+    let headings = outline::headings(&text);
+    let chunks = data.chunks.as_deref().unwrap_or_default();
+    let sections = outline::chunk_sections(&text, &headings, chunks);
+
+    let builder = Builder { data, headings: &headings, sections: &sections };
+    Ok(Graph {
+        id: DOCUMENT_ID,
+        kind: "document",
+        source: source.map(str::to_string),
+        metadata: data.metadata.as_deref().map(parse_metadata),
+        chunks: builder.chunks(None, DOCUMENT_ID),
+        sections: builder.children(None, DOCUMENT_ID, &[]),
+    })
+}
+
+// Metadata as JSON, or as a string when the service sent something else
+fn parse_metadata(metadata: &str) -> Value {
+    serde_json::from_str(metadata).unwrap_or_else(|_| Value::String(metadata.to_string()))
+}
+
+struct Builder<'a> {
+    data: &'a AnnotatedResult,
+    headings: &'a [Heading],
+    // The heading each chunk starts under
+    sections: &'a [Option<usize>],
+}
+
+impl Builder<'_> {
+    // The sections directly under heading `parent` (None: the document), numbered after `number`
+    fn children(&self, parent: Option<usize>, parent_id: &str, number: &[usize]) -> Vec<Section> {
+        let under: Vec<usize> = (0..self.headings.len()).filter(|i| self.headings[*i].parent == parent).collect();
+        under
+            .into_iter()
+            .enumerate()
+            .map(|(n, i)| {
+                let number = [number, &[n + 1]].concat();
+                let id = format!("section-{}", number.iter().map(usize::to_string).collect::<Vec<_>>().join("."));
+                let heading = &self.headings[i];
+                Section {
+                    kind: "section",
+                    parent: parent_id.to_string(),
+                    title: heading.title.clone(),
+                    level: heading.level,
+                    path: outline::breadcrumb(self.headings, i).into_iter().map(str::to_string).collect(),
+                    chunks: self.chunks(Some(i), &id),
+                    sections: self.children(Some(i), &id, &number),
+                    id,
+                }
+            })
+            .collect()
+    }
+
+    // The chunks that start under heading `section` (None: before the first heading)
+    fn chunks(&self, section: Option<usize>, parent_id: &str) -> Vec<Chunk> {
+        let texts = self.data.chunks.as_deref().unwrap_or_default();
+        let metadata = |i: usize| self.data.chunks_metadata.as_ref().and_then(|m| m.get(i)?.as_deref()).map(parse_metadata);
+        texts
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.sections[*i] == section)
+            .map(|(i, text)| Chunk {
+                id: format!("chunk-{}", i),
+                kind: "chunk",
+                parent: parent_id.to_string(),
+                index: i,
+                text: text.clone(),
+                metadata: metadata(i),
+            })
+            .collect()
+    }
+}
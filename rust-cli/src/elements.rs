@@ -0,0 +1,194 @@
+// Unstructured-compatible element JSON (-o unstructured-json), so tools built
+// around Unstructured's partitioners can read results of this CLI: the
+// Markdown text is split into Title, NarrativeText, ListItem, Table, and
+// CodeSnippet elements, each with Unstructured's metadata fields.
+
+use anyhow::{Context, Result};
+use crate::result::AnnotatedResult;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize)]
+pub struct Element {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    element_id: String,
+    text: String,
+    metadata: ElementMetadata,
+}
+
+#[derive(Serialize)]
+struct ElementMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filetype: Option<String>,
+    /// The Title element this one is under
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_id: Option<String>,
+    /// Heading level of Title elements, from 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_as_html: Option<String>,
+}
+
+/// The elements of a result's text; `source` is the file or URL it came from
+pub fn elements(data: &AnnotatedResult, source: Option<&str>) -> Result<Vec<Element>> {
+    let text = match (&data.text, &data.text_file) {
+        (Some(text), _) => text.clone(),
+        (None, Some(path)) => fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?,
+        (None, None) => data.chunks.iter().flatten().map(String::as_str).collect::<Vec<_>>().join("\n\n"),
+    };
+    let filename = source.and_then(|source| source.trim_end_matches('/').rsplit('/').next()).filter(|name| !name.is_empty());
+    let filetype = filename.map(|name| iris_client::content_type(Path::new(name), &[]));
+
+    let mut elements: Vec<Element> = Vec::new();
+    // Open Title elements, by depth, so each element gets the nearest as its parent
+    let mut titles: Vec<(usize, String)> = Vec::new();
+    for block in blocks(&text) {
+        if let Block::Title(depth, _) = block {
+            titles.retain(|(open, _)| *open < depth);
+        }
+        let (kind, text, category_depth, text_as_html) = match block {
+            Block::Title(depth, text) => ("Title", text, Some(depth), None),
+            Block::Narrative(text) => ("NarrativeText", text, None, None),
+            Block::ListItem(text) => ("ListItem", text, None, None),
+            Block::Code(text) => ("CodeSnippet", text, None, None),
+            Block::Table(rows) => {
+                let text = rows.iter().map(|row| row.join(" ")).collect::<Vec<_>>().join("\n");
+                ("Table", text, None, Some(table_html(&rows)))
+            }
+        };
+        let element_id = element_id(filename, elements.len(), &text);
+        let metadata = ElementMetadata {
+            filename: filename.map(str::to_string),
+            filetype: filetype.clone(),
+            parent_id: titles.last().map(|(_, id)| id.clone()),
+            category_depth,
+            text_as_html,
+        };
+        if let Some(depth) = category_depth {
+            titles.push((depth, element_id.clone()));
+        }
+        elements.push(Element { kind, element_id, text, metadata });
+    }
+    Ok(elements)
+}
+
+enum Block {
+    Title(usize, String),
+    Narrative(String),
+    ListItem(String),
+    Code(String),
+    Table(Vec<Vec<String>>),
+}
+
+// Split Markdown into blocks: headings, list items, and table rows are
+// recognized per line, and other consecutive lines form a paragraph
+fn blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut lines = text.lines().peekable();
+    let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Narrative(paragraph.join(" ")));
+            paragraph.clear();
+        }
+    };
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush(&mut paragraph, &mut blocks);
+            let fence = &trimmed[..3];
+            let mut code = Vec::new();
+            for line in lines.by_ref() {
+                if line.trim().starts_with(fence) {
+                    break;
+                }
+                code.push(line);
+            }
+            blocks.push(Block::Code(code.join("\n")));
+        } else if trimmed.starts_with('|') {
+            flush(&mut paragraph, &mut blocks);
+            let mut rows = vec![table_row(trimmed)];
+            while let Some(line) = lines.next_if(|line| line.trim().starts_with('|')) {
+                rows.push(table_row(line.trim()));
+            }
+            rows.retain(|row| !row.iter().all(|cell| !cell.is_empty() && cell.chars().all(|c| matches!(c, '-' | ':'))));
+            blocks.push(Block::Table(rows));
+        } else if let Some((level, title)) = heading(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Title(level - 1, title.to_string()));
+        } else if trimmed.is_empty() || is_rule(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+        } else if let Some(item) = list_item(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::ListItem(item.to_string()));
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+/// The level (1-6) and title of a Markdown heading line
+pub fn heading(line: &str) -> Option<(usize, &str)> {
+    let title = line.trim_start_matches('#');
+    let level = line.len() - title.len();
+    ((1..=6).contains(&level) && title.starts_with(' '))
+        .then(|| (level, title.trim().trim_end_matches('#').trim()))
+        .filter(|(_, title)| !title.is_empty())
+}
+
+// The text of a bulleted or numbered list item
+fn list_item(line: &str) -> Option<&str> {
+    let item = match line.strip_prefix(['-', '*', '+']) {
+        Some(item) => item,
+        None => {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            line[digits..].strip_prefix(['.', ')']).filter(|_| digits > 0)?
+        }
+    };
+    item.strip_prefix(' ').map(str::trim).filter(|item| !item.is_empty())
+}
+
+// A thematic break (---, ***, ___), which has no text
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && matches!(marks[0], '-' | '*' | '_') && marks.iter().all(|c| *c == marks[0])
+}
+
+fn table_row(line: &str) -> Vec<String> {
+    let inner = line.strip_prefix('|').unwrap_or(line);
+    let inner = inner.strip_suffix('|').unwrap_or(inner);
+    inner.split('|').map(|cell| cell.trim().to_string()).collect()
+}
+
+fn table_html(rows: &[Vec<String>]) -> String {
+    let mut html = String::from("<table>");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str("<td>");
+            html.push_str(&escape_html(cell));
+            html.push_str("</td>");
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Deterministic, like Unstructured's: the same document gives the same IDs
+fn element_id(filename: Option<&str>, index: usize, text: &str) -> String {
+    let digest = Sha256::digest(format!("{}\n{}\n{}", filename.unwrap_or_default(), index, text).as_bytes());
+    format!("{:x}", digest)[..32].to_string()
+}
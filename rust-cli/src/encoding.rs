@@ -0,0 +1,112 @@
+// Detection and transcoding of legacy-encoded text inputs (Windows-1252,
+// Shift-JIS, ...) so the service always receives UTF-8.
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+const TEXT_EXTENSIONS: &[&str] = &["txt", "csv", "tsv"];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum InputEncoding {
+    /// Keep UTF-8 as is and detect anything else
+    #[default]
+    Auto,
+    /// Upload the bytes unchanged
+    Raw,
+    Fixed(&'static Encoding),
+}
+
+impl InputEncoding {
+    /// `auto`, `raw`, or the encoding's WHATWG name, such as windows-1252
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputEncoding::Auto => "auto",
+            InputEncoding::Raw => "raw",
+            InputEncoding::Fixed(encoding) => encoding.name(),
+        }
+    }
+}
+
+/// Parse `auto`, `raw`, or an encoding label such as `windows-1252` or `shift_jis`
+pub fn parse_input_encoding(value: &str) -> Result<InputEncoding, String> {
+    match value.to_lowercase().as_str() {
+        "auto" => Ok(InputEncoding::Auto),
+        "raw" => Ok(InputEncoding::Raw),
+        label => Encoding::for_label(label.as_bytes())
+            .map(InputEncoding::Fixed)
+            .ok_or_else(|| format!("unknown encoding '{}'", value)),
+    }
+}
+
+pub fn is_text_input(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Convert text content to UTF-8; returns the source encoding name when it was transcoded
+pub fn to_utf8(content: Vec<u8>, encoding: InputEncoding) -> (Vec<u8>, Option<&'static str>) {
+    let source = match encoding {
+        InputEncoding::Raw => return (content, None),
+        InputEncoding::Fixed(encoding) => encoding,
+        InputEncoding::Auto => {
+            if std::str::from_utf8(&content).is_ok() {
+                return (content, None);
+            }
+            let mut detector = EncodingDetector::new();
+            detector.feed(&content, true);
+            detector.guess(None, false)
+        }
+    };
+
+    if source == UTF_8 {
+        return (content, None);
+    }
+
+    let (text, actual, _) = source.decode(&content);
+    (text.into_owned().into_bytes(), Some(actual.name()))
+}
+
+/// Read a text input that has to be converted to UTF-8, returning the
+/// converted content and its source encoding; `None` means the file can be
+/// uploaded as is, without reading it into memory
+pub fn transcode_file(path: &Path, encoding: InputEncoding) -> io::Result<Option<(Vec<u8>, &'static str)>> {
+    let needs_converting = match encoding {
+        InputEncoding::Raw => false,
+        InputEncoding::Fixed(encoding) => encoding != UTF_8,
+        InputEncoding::Auto => !is_utf8_file(path)?,
+    };
+    if !needs_converting {
+        return Ok(None);
+    }
+
+    let (content, source) = to_utf8(fs::read(path)?, encoding);
+    Ok(source.map(|source| (content, source)))
+}
+
+// Check UTF-8 a block at a time so large files are never read whole
+fn is_utf8_file(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    // Bytes of a character split across blocks, kept at the start of the buffer
+    let mut carry = 0;
+    loop {
+        let read = file.read(&mut buffer[carry..])?;
+        if read == 0 {
+            return Ok(carry == 0);
+        }
+        let filled = carry + read;
+        match std::str::from_utf8(&buffer[..filled]) {
+            Ok(_) => carry = 0,
+            Err(e) if e.error_len().is_none() => {
+                buffer.copy_within(e.valid_up_to()..filled, 0);
+                carry = filled - e.valid_up_to();
+            }
+            Err(_) => return Ok(false),
+        }
+    }
+}
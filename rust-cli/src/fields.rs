@@ -0,0 +1,249 @@
+// Normalizing extracted metadata values (--normalize-fields), so downstream
+// systems get dates as ISO-8601, amounts as a decimal and a currency code, and
+// phone numbers as E.164, whatever notation the document used.
+//
+//   rule  := path "=" kind
+//   path  := name ("." name)*        (arrays along the path are normalized element by element)
+//   kind  := "date" [":" ("mdy" | "dmy")]
+//          | "amount" [":" CURRENCY]   (currency of amounts that don't name one)
+//          | "phone" [":" "+" CODE]    (country code of numbers written without one)
+
+use chrono::{DateTime, NaiveDate};
+use crate::result::{AnnotatedResult, Warning};
+use serde_json::{json, Value};
+
+#[derive(Clone, Debug)]
+pub struct FieldRule {
+    path: Vec<String>,
+    kind: FieldKind,
+}
+
+#[derive(Clone, Debug)]
+enum FieldKind {
+    /// Whether numeric dates put the day first (18/06/2024) rather than the month (06/18/2024)
+    Date { day_first: bool },
+    Amount { currency: Option<String> },
+    Phone { country_code: Option<String> },
+}
+
+// Currency symbols, longest first so US$ isn't read as $
+const SYMBOLS: &[(&str, &str)] = &[
+    ("US$", "USD"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("R$", "BRL"),
+    ("$", "USD"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₹", "INR"),
+    ("₩", "KRW"),
+];
+
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d", "%Y%m%d",
+    "%B %d, %Y", "%B %d %Y", "%d %B %Y", "%d %B, %Y", "%d-%b-%Y", "%d. %B %Y",
+];
+const MONTH_FIRST: &[&str] = &["%m/%d/%Y", "%m-%d-%Y", "%m.%d.%Y", "%m/%d/%y"];
+const DAY_FIRST: &[&str] = &["%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y", "%d/%m/%y"];
+
+/// Parse a --normalize-fields rule, such as `invoice.date=date:dmy`
+pub fn parse_rule(value: &str) -> Result<FieldRule, String> {
+    let invalid = || format!("invalid field rule '{}': expected FIELD=date, FIELD=amount, or FIELD=phone", value);
+    let (path, kind) = value.split_once('=').ok_or_else(invalid)?;
+    let path: Vec<String> = path.trim().split('.').map(|name| name.trim().to_string()).collect();
+    if path.iter().any(String::is_empty) {
+        return Err(invalid());
+    }
+    let (kind, option) = match kind.trim().split_once(':') {
+        Some((kind, option)) => (kind, Some(option.trim())),
+        None => (kind.trim(), None),
+    };
+    let kind = match (kind, option) {
+        ("date", None | Some("mdy")) => FieldKind::Date { day_first: false },
+        ("date", Some("dmy")) => FieldKind::Date { day_first: true },
+        ("date", Some(order)) => return Err(format!("invalid date order '{}': expected mdy or dmy", order)),
+        ("amount", None) => FieldKind::Amount { currency: None },
+        ("amount", Some(code)) if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) => {
+            FieldKind::Amount { currency: Some(code.to_ascii_uppercase()) }
+        }
+        ("amount", Some(code)) => return Err(format!("invalid currency '{}': expected a code like EUR", code)),
+        ("phone", None) => FieldKind::Phone { country_code: None },
+        ("phone", Some(code)) => match code.trim_start_matches('+') {
+            digits if (1..=3).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit()) => {
+                FieldKind::Phone { country_code: Some(digits.to_string()) }
+            }
+            _ => return Err(format!("invalid country code '{}': expected one like +44", code)),
+        },
+        _ => return Err(invalid()),
+    };
+    Ok(FieldRule { path, kind })
+}
+
+impl FieldRule {
+    /// The rule in the syntax it's parsed from, with defaults left out
+    pub fn spec(&self) -> String {
+        let kind = match &self.kind {
+            FieldKind::Date { day_first: false } => "date".to_string(),
+            FieldKind::Date { day_first: true } => "date:dmy".to_string(),
+            FieldKind::Amount { currency: None } => "amount".to_string(),
+            FieldKind::Amount { currency: Some(code) } => format!("amount:{}", code),
+            FieldKind::Phone { country_code: None } => "phone".to_string(),
+            FieldKind::Phone { country_code: Some(code) } => format!("phone:+{}", code),
+        };
+        format!("{}={}", self.path.join("."), kind)
+    }
+}
+
+/// Normalize the fields of a result's metadata and chunk metadata in place.
+/// Values that can't be read are left as they are, with a warning
+pub fn normalize(data: &mut AnnotatedResult, rules: &[FieldRule]) {
+    let mut warnings = Vec::new();
+    for metadata in data.data.metadata.iter_mut().chain(data.data.chunks_metadata.iter_mut().flatten().flatten()) {
+        // Metadata that isn't JSON is reported by the metadata-invalid check
+        let Ok(mut value) = serde_json::from_str::<Value>(metadata) else {
+            continue;
+        };
+        for rule in rules {
+            visit(&mut value, &rule.path, &mut |field| {
+                if let Some(normalized) = normalize_value(field, &rule.kind) {
+                    *field = normalized;
+                } else {
+                    warnings.push(Warning::new("field-unnormalized", format!(
+                        "Can't read {} value {} as {}", rule.path.join("."), field, rule.kind.name()
+                    )));
+                }
+            });
+        }
+        *metadata = value.to_string();
+    }
+    warnings.dedup_by(|a, b| a.message == b.message);
+    data.warnings.extend(warnings);
+}
+
+impl FieldKind {
+    fn name(&self) -> &'static str {
+        match self {
+            FieldKind::Date { .. } => "a date",
+            FieldKind::Amount { .. } => "an amount",
+            FieldKind::Phone { .. } => "a phone number",
+        }
+    }
+}
+
+// Call `f` on each non-null value at `path`
+fn visit(value: &mut Value, path: &[String], f: &mut impl FnMut(&mut Value)) {
+    match (value, path.split_first()) {
+        (Value::Array(items), _) => items.iter_mut().for_each(|item| visit(item, path, f)),
+        (Value::Null, _) => {}
+        (value, None) => f(value),
+        (Value::Object(fields), Some((name, rest))) => {
+            if let Some(field) = fields.get_mut(name) {
+                visit(field, rest, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn normalize_value(value: &Value, kind: &FieldKind) -> Option<Value> {
+    match (kind, value) {
+        (FieldKind::Date { day_first }, Value::String(text)) => normalize_date(text, *day_first).map(Value::String),
+        (FieldKind::Amount { currency }, Value::Number(number)) => {
+            Some(json!({ "amount": number.to_string(), "currency": currency }))
+        }
+        // Already normalized, by an earlier run or the model
+        (FieldKind::Amount { .. }, Value::Object(fields)) if fields.contains_key("amount") => Some(value.clone()),
+        (FieldKind::Amount { currency }, Value::String(text)) => normalize_amount(text, currency.as_deref()),
+        (FieldKind::Phone { country_code }, Value::String(text)) => normalize_phone(text, country_code.as_deref()).map(Value::String),
+        _ => None,
+    }
+}
+
+fn normalize_date(text: &str, day_first: bool) -> Option<String> {
+    let text = text.trim().trim_end_matches('.');
+    // Dates with a time are kept when they're already ISO-8601
+    if DateTime::parse_from_rfc3339(text).is_ok() {
+        return Some(text.to_string());
+    }
+    let numeric = if day_first { DAY_FIRST } else { MONTH_FIRST };
+    // "June 1st, 2024" reads like "June 1, 2024"
+    let text = ["st,", "nd,", "rd,", "th,"].iter().fold(text.to_string(), |text, suffix| text.replace(suffix, ","));
+    DATE_FORMATS
+        .iter()
+        .chain(numeric)
+        .find_map(|format| NaiveDate::parse_from_str(&text, format).ok())
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+fn normalize_amount(text: &str, default_currency: Option<&str>) -> Option<Value> {
+    let mut rest = text.trim().to_string();
+    let mut currency = None;
+    for (symbol, code) in SYMBOLS {
+        if rest.contains(symbol) {
+            rest = rest.replace(symbol, "");
+            // A plain $ is the default currency's when it's a dollar
+            currency = Some(match (*symbol, default_currency) {
+                ("$", Some(default)) if default.ends_with('D') => default.to_string(),
+                _ => code.to_string(),
+            });
+            break;
+        }
+    }
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    if let Some(code) = words.iter().find(|word| word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase())) {
+        currency = Some(code.to_string());
+        rest = rest.replace(code, "");
+    }
+
+    let rest = rest.trim();
+    let negative = rest.starts_with('-') || rest.ends_with('-') || (rest.starts_with('(') && rest.ends_with(')'));
+    let number: String = rest.chars().filter(|c| !matches!(c, '-' | '(' | ')' | '\'' | ' ' | '\u{a0}')).collect();
+    let decimal = decimal_separator(&number);
+    let mut digits: String = number.chars().filter(|c| Some(*c) == decimal || c.is_ascii_digit()).collect();
+    if let Some(separator) = decimal {
+        digits = digits.replace(separator, ".");
+    }
+    let valid = !number.chars().any(|c| !c.is_ascii_digit() && c != ',' && c != '.')
+        && digits.split('.').all(|part| !part.is_empty())
+        && digits.matches('.').count() <= 1;
+    if !valid {
+        return None;
+    }
+    let amount = if negative { format!("-{}", digits) } else { digits };
+    Some(json!({ "amount": amount, "currency": currency.or(default_currency.map(str::to_string)) }))
+}
+
+// The decimal separator of a number written with , and . ("1,234.56",
+// "1.234,56"): the last one when both appear, and otherwise one that appears
+// once, unless it's a comma followed by three digits
+fn decimal_separator(number: &str) -> Option<char> {
+    match (number.rfind('.'), number.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) if number.matches('.').count() == 1 => Some('.'),
+        (None, Some(comma)) if number.matches(',').count() == 1 && number.len() - comma - 1 != 3 => Some(','),
+        _ => None,
+    }
+}
+
+fn normalize_phone(text: &str, country_code: Option<&str>) -> Option<String> {
+    // The (0) in "+44 (0)20 7946 0000" is only dialed within the country
+    let text = text.trim().replace("(0)", "");
+    let (international, rest) = match (text.strip_prefix('+'), text.strip_prefix("00")) {
+        (Some(rest), _) | (None, Some(rest)) => (true, rest),
+        (None, None) => (false, text.as_str()),
+    };
+    if !rest.chars().all(|c| c.is_ascii_digit() || " -.()/".contains(c)) {
+        return None;
+    }
+    let digits: String = rest.chars().filter(char::is_ascii_digit).collect();
+    let number = match (international, country_code) {
+        (true, _) => digits,
+        (false, None) => return None,
+        // 1-415-555-0100 already has the North American country code
+        (false, Some("1")) if digits.len() == 11 && digits.starts_with('1') => digits,
+        // The leading 0 of national numbers is a trunk prefix, not part of the number
+        (false, Some(code)) => format!("{}{}", code, digits.strip_prefix('0').unwrap_or(&digits)),
+    };
+    (8..=15).contains(&number.len()).then(|| format!("+{}", number))
+}
@@ -0,0 +1,42 @@
+// Glob filters that narrow which files of a directory are processed.
+
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Parse a glob such as `**/*.pdf` or `drafts/**`
+pub fn parse_glob(value: &str) -> Result<Pattern, String> {
+    Pattern::new(value).map_err(|e| format!("invalid glob '{}': {}", value, e))
+}
+
+pub struct FileFilter<'a> {
+    pub include: &'a [Pattern],
+    pub exclude: &'a [Pattern],
+}
+
+impl FileFilter<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether a file passes the filters; `relative` is its path under the
+    /// processed directory with forward slashes
+    pub fn matches(&self, relative: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|p| matches(p, relative));
+        included && !self.exclude.iter().any(|p| matches(p, relative))
+    }
+}
+
+// Patterns without a slash match the file name at any depth, as in .gitignore
+fn matches(pattern: &Pattern, relative: &str) -> bool {
+    if pattern.as_str().contains('/') {
+        return pattern.matches_with(relative, MATCH_OPTIONS);
+    }
+    let name = Path::new(relative).file_name().and_then(|n| n.to_str()).unwrap_or(relative);
+    pattern.matches_with(name, MATCH_OPTIONS)
+}
@@ -0,0 +1,181 @@
+// Pruning what the CLI leaves on disk (gc): old crash reports, temporary
+// files orphaned by runs that were killed before cleaning up, and, when asked
+// for, results in output directories that are past their retention period.
+// Only files the CLI wrote count as results; anything else in an output
+// directory is left alone however old it is.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+use crate::catalog::{self, DEFAULT_DATABASE};
+use crate::crash;
+use crate::layout::INDEX_FILE;
+use crate::snapshot::relative_key;
+use crate::spool::TEMP_PREFIX;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// Crash reports under ~/.vectorize-iris
+    CrashReport,
+    /// Temporary files and directories of earlier runs
+    Temp,
+    /// Results in an output directory
+    Output,
+}
+
+/// A file or directory that is old enough to remove
+#[derive(Serialize)]
+pub struct Entry {
+    pub kind: Kind,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// How old each kind of file must be to be removed; outputs are kept unless
+/// an age is given
+pub struct Ages {
+    pub crash_reports: Duration,
+    pub temp: Duration,
+    pub outputs: Option<Duration>,
+}
+
+/// Everything that `ages` says can go, crash reports first, then temporary
+/// files, then the results in each of `output_dirs`
+pub fn find(ages: &Ages, output_dirs: &[PathBuf]) -> Result<Vec<Entry>> {
+    let now = SystemTime::now();
+    let older_than = |age: Duration| move |modified: SystemTime| now.duration_since(modified).is_ok_and(|elapsed| elapsed > age);
+    let mut entries = Vec::new();
+
+    if let Some(dir) = crash::reports_dir().filter(|dir| dir.is_dir()) {
+        entries.extend(old_files(&dir, older_than(ages.crash_reports), |_| true).map(|(path, bytes)| Entry { kind: Kind::CrashReport, path, bytes }));
+    }
+
+    // Only the newest change inside a directory counts, so one still in use is left alone
+    let temp = std::env::temp_dir();
+    let listing = fs::read_dir(&temp).context(format!("Failed to read {}", temp.display()))?;
+    let mut temps: Vec<Entry> = listing
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX))
+        .filter_map(|entry| {
+            let (modified, bytes) = newest_change(&entry.path())?;
+            older_than(ages.temp)(modified).then(|| Entry { kind: Kind::Temp, path: entry.path(), bytes })
+        })
+        .collect();
+    temps.sort_by(|a, b| a.path.cmp(&b.path));
+    entries.extend(temps);
+
+    if let Some(age) = ages.outputs {
+        for dir in output_dirs {
+            if !dir.is_dir() {
+                anyhow::bail!("Not a directory: {}", dir.display());
+            }
+            let indexed = indexed_outputs(dir);
+            let results = old_files(dir, older_than(age), |path| {
+                !is_bookkeeping(dir, path) && (indexed.contains(&relative_key(dir, path)) || is_result_file(path))
+            });
+            entries.extend(results.map(|(path, bytes)| Entry { kind: Kind::Output, path, bytes }));
+        }
+    }
+    Ok(entries)
+}
+
+// Files under `dir` whose last change `expired` says is too old and that
+// `include` accepts, asked in that order since `include` may read the file
+fn old_files<'a>(
+    dir: &Path,
+    expired: impl Fn(SystemTime) -> bool + 'a,
+    include: impl Fn(&Path) -> bool + 'a,
+) -> impl Iterator<Item = (PathBuf, u64)> + 'a {
+    WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(move |entry| {
+            let metadata = entry.metadata().ok()?;
+            (expired(metadata.modified().ok()?) && include(entry.path())).then(|| (entry.into_path(), metadata.len()))
+        })
+}
+
+// Newest modification time and total size of a file, or of a directory's contents
+fn newest_change(path: &Path) -> Option<(SystemTime, u64)> {
+    let mut newest = fs::symlink_metadata(path).ok()?.modified().ok()?;
+    let mut bytes = 0;
+    for entry in WalkDir::new(path).into_iter().filter_map(|entry| entry.ok()) {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if let Ok(modified) = metadata.modified() {
+            newest = newest.max(modified);
+        }
+        if metadata.is_file() {
+            bytes += metadata.len();
+        }
+    }
+    Some((newest, bytes))
+}
+
+// Outputs that the layout index in `dir` lists, relative to `dir`
+fn indexed_outputs(dir: &Path) -> HashSet<String> {
+    fs::read(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_slice::<BTreeMap<String, String>>(&content).ok())
+        .map(|index| index.into_values().collect())
+        .unwrap_or_default()
+}
+
+// A JSON result written without --layout, which no index lists
+fn is_result_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "json")
+        && fs::read(path).ok().and_then(|content| serde_json::from_slice(&content).ok()).is_some_and(|content| catalog::is_result(&content))
+}
+
+// The layout index, the catalog, and hidden files such as the schedule state
+// describe the output directory rather than being results in it
+fn is_bookkeeping(dir: &Path, path: &Path) -> bool {
+    let hidden = path
+        .strip_prefix(dir)
+        .unwrap_or(path)
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+    hidden || path == dir.join(INDEX_FILE) || path == dir.join(DEFAULT_DATABASE)
+}
+
+/// Remove one entry found by `find`
+pub fn remove(entry: &Entry) -> Result<()> {
+    let result = match entry.path.is_dir() {
+        true => fs::remove_dir_all(&entry.path),
+        false => fs::remove_file(&entry.path),
+    };
+    result.context(format!("Failed to remove {}", entry.path.display()))
+}
+
+/// After results were removed from `dir`, drop the layout index entries of
+/// outputs that no longer exist and the directories left empty
+pub fn tidy_output_dir(dir: &Path) -> Result<()> {
+    let index_path = dir.join(INDEX_FILE);
+    if let Some(index) = fs::read(&index_path)
+        .ok()
+        .and_then(|content| serde_json::from_slice::<BTreeMap<String, String>>(&content).ok())
+    {
+        let kept: BTreeMap<&String, &String> = index.iter().filter(|(_, output)| dir.join(output).exists()).collect();
+        if kept.len() < index.len() {
+            fs::write(&index_path, serde_json::to_vec_pretty(&kept)?)
+                .context(format!("Failed to write {}", index_path.display()))?;
+        }
+    }
+
+    // Deepest first, so a directory holding only empty directories goes too
+    let dirs = WalkDir::new(dir).min_depth(1).contents_first(true).into_iter().filter_map(|entry| entry.ok());
+    for entry in dirs.filter(|entry| entry.file_type().is_dir()) {
+        // Fails for directories that aren't empty, which are kept
+        let _ = fs::remove_dir(entry.path());
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+// Converting results of other extraction tools (import) into this CLI's
+// result schema, so historical corpora extracted with AWS Textract, Google
+// Document AI, or Unstructured end up in the same format and sinks as new
+// extractions.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use iris_client::{ExtractionResultData, FormField, Region};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Tool {
+    /// AWS Textract responses (DetectDocumentText or AnalyzeDocument), with their Blocks
+    Textract,
+    /// Google Document AI Document objects, or processDocument responses holding one
+    Documentai,
+    /// Unstructured element lists (partitioned or chunked)
+    Unstructured,
+}
+
+impl Tool {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tool::Textract => "textract",
+            Tool::Documentai => "documentai",
+            Tool::Unstructured => "unstructured",
+        }
+    }
+}
+
+/// The result of one document, from the output `tool` wrote for it
+pub fn convert(tool: Tool, content: &Value) -> Result<ExtractionResultData> {
+    let pages = match tool {
+        Tool::Textract => textract(content)?,
+        Tool::Documentai => documentai(content)?,
+        Tool::Unstructured => unstructured(content)?,
+    };
+    let mut metadata = json!({ "importedFrom": tool.as_str() });
+    if let Some(count) = pages.chunks.iter().filter_map(|chunk| chunk.page).max() {
+        metadata["pages"] = json!(count);
+    }
+
+    let mut result = ExtractionResultData {
+        success: true,
+        text: Some(pages.text),
+        metadata: Some(metadata.to_string()),
+        form: (!pages.form.is_empty()).then_some(pages.form),
+        regions: (!pages.regions.is_empty()).then_some(pages.regions),
+        ..Default::default()
+    };
+    let chunks = pages.chunks.into_iter().filter(|chunk| !chunk.text.trim().is_empty());
+    let (chunks, metadata): (Vec<String>, Vec<Option<String>>) = chunks
+        .map(|chunk| (chunk.text, chunk.page.map(|page| json!({ "page": page }).to_string())))
+        .unzip();
+    result.chunks_metadata = metadata.iter().any(Option::is_some).then_some(metadata);
+    result.chunks = Some(chunks);
+    Ok(result)
+}
+
+// What a converter finds in a document
+#[derive(Default)]
+struct Converted {
+    text: String,
+    chunks: Vec<Chunk>,
+    form: Vec<FormField>,
+    regions: Vec<Region>,
+}
+
+struct Chunk {
+    text: String,
+    page: Option<u32>,
+}
+
+// LINE blocks in reading order, one chunk per page; responses split across
+// several pages of results can be given as an array
+fn textract(content: &Value) -> Result<Converted> {
+    let responses: Vec<&Value> = match content {
+        Value::Array(responses) => responses.iter().collect(),
+        response => vec![response],
+    };
+    let blocks: Vec<&Value> = responses.iter().filter_map(|r| r["Blocks"].as_array()).flatten().collect();
+    if blocks.is_empty() && responses.iter().all(|r| r.get("Blocks").is_none()) {
+        return Err(anyhow!("Not a Textract response: no Blocks"));
+    }
+
+    let mut converted = Converted::default();
+    let mut pages: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+    for block in blocks.iter().filter(|block| block["BlockType"] == "LINE") {
+        let page = block["Page"].as_u64().unwrap_or(1) as u32;
+        let text = block["Text"].as_str().unwrap_or_default();
+        pages.entry(page).or_default().push(text);
+        if let Some(confidence) = block["Confidence"].as_f64() {
+            converted.regions.push(Region {
+                page: Some(page),
+                text: text.to_string(),
+                confidence: confidence / 100.0,
+                handwritten: false,
+                needs_review: false,
+            });
+        }
+    }
+    converted.chunks = pages.into_iter().map(|(page, lines)| Chunk { text: lines.join("\n"), page: Some(page) }).collect();
+    converted.text = converted.chunks.iter().map(|chunk| chunk.text.as_str()).collect::<Vec<_>>().join("\n\n");
+
+    // Key-value pairs of AnalyzeDocument with FORMS
+    let by_id: BTreeMap<&str, &Value> = blocks.iter().filter_map(|b| Some((b["Id"].as_str()?, *b))).collect();
+    let related = |block: &Value, kind: &str| -> Vec<&Value> {
+        let relationships = block["Relationships"].as_array().into_iter().flatten();
+        let ids = relationships.filter(|r| r["Type"] == kind).filter_map(|r| r["Ids"].as_array()).flatten();
+        ids.filter_map(|id| by_id.get(id.as_str()?).copied()).collect()
+    };
+    let words = |block: &Value| -> String {
+        let children = related(block, "CHILD");
+        let words = children.iter().filter_map(|child| match child["BlockType"].as_str() {
+            Some("WORD") => child["Text"].as_str().map(str::to_string),
+            Some("SELECTION_ELEMENT") => Some(String::new()),
+            _ => None,
+        });
+        words.collect::<Vec<_>>().join(" ").trim().to_string()
+    };
+    let is_key = |block: &&&Value| block["BlockType"] == "KEY_VALUE_SET" && block["EntityTypes"][0] == "KEY";
+    for key in blocks.iter().filter(is_key) {
+        let Some(value) = related(key, "VALUE").into_iter().next() else {
+            continue;
+        };
+        let selection = related(value, "CHILD").into_iter().find(|child| child["BlockType"] == "SELECTION_ELEMENT");
+        let (value, kind) = match selection {
+            Some(selection) => (json!(selection["SelectionStatus"] == "SELECTED"), "checkbox"),
+            None => match words(value) {
+                text if text.is_empty() => (Value::Null, "text"),
+                text => (json!(text), "text"),
+            },
+        };
+        converted.form.push(FormField {
+            name: words(key).trim_end_matches(':').to_string(),
+            value,
+            kind: Some(kind.to_string()),
+            page: key["Page"].as_u64().map(|page| page as u32),
+        });
+    }
+    Ok(converted)
+}
+
+// The document text, one chunk per page from the page layouts, and form
+// fields; Document AI indexes the text by character
+fn documentai(content: &Value) -> Result<Converted> {
+    let document = content.get("document").unwrap_or(content);
+    let text = document["text"].as_str().ok_or_else(|| anyhow!("Not a Document AI document: no text"))?;
+    let characters: Vec<char> = text.chars().collect();
+    let anchored = |layout: &Value| -> String {
+        let segments = layout["textAnchor"]["textSegments"].as_array().into_iter().flatten();
+        let index = |value: &Value| value.as_u64().or_else(|| value.as_str()?.parse().ok()).unwrap_or(0) as usize;
+        segments
+            .map(|segment| {
+                let end = index(&segment["endIndex"]).min(characters.len());
+                let start = index(&segment["startIndex"]).min(end);
+                characters[start..end].iter().collect::<String>()
+            })
+            .collect()
+    };
+
+    let mut converted = Converted { text: text.to_string(), ..Default::default() };
+    for (i, page) in document["pages"].as_array().into_iter().flatten().enumerate() {
+        let number = page["pageNumber"].as_u64().map_or(i as u32 + 1, |n| n as u32);
+        converted.chunks.push(Chunk { text: anchored(&page["layout"]), page: Some(number) });
+        for field in page["formFields"].as_array().into_iter().flatten() {
+            let kind = field["valueType"].as_str().unwrap_or("text");
+            let value = anchored(&field["fieldValue"]);
+            let value = match kind {
+                "filled_checkbox" => json!(true),
+                "unfilled_checkbox" => json!(false),
+                _ if value.trim().is_empty() => Value::Null,
+                _ => json!(value.trim()),
+            };
+            converted.form.push(FormField {
+                name: anchored(&field["fieldName"]).trim().trim_end_matches(':').to_string(),
+                value,
+                kind: Some(if kind.ends_with("checkbox") { "checkbox" } else { "text" }.to_string()),
+                page: Some(number),
+            });
+        }
+    }
+    // Without page layouts, the whole text is one chunk
+    if converted.chunks.iter().all(|chunk| chunk.text.trim().is_empty()) {
+        converted.chunks = vec![Chunk { text: text.to_string(), page: None }];
+    }
+    Ok(converted)
+}
+
+// Elements joined into the text; chunked output keeps its chunks, and
+// partitioned output is chunked at each title, as Unstructured's by_title does
+fn unstructured(content: &Value) -> Result<Converted> {
+    let elements = content.as_array().ok_or_else(|| anyhow!("Not Unstructured output: expected a list of elements"))?;
+    let elements: Vec<(&str, &str, Option<u32>)> = elements
+        .iter()
+        .filter_map(|element| {
+            let page = element["metadata"]["page_number"].as_u64().map(|page| page as u32);
+            Some((element["type"].as_str().unwrap_or_default(), element["text"].as_str()?, page))
+        })
+        .collect();
+    let chunked = elements.iter().any(|(kind, _, _)| matches!(*kind, "CompositeElement" | "TableChunk"));
+
+    let mut converted = Converted {
+        text: elements.iter().map(|(_, text, _)| *text).collect::<Vec<_>>().join("\n\n"),
+        ..Default::default()
+    };
+    for (kind, text, page) in elements {
+        match converted.chunks.last_mut() {
+            Some(chunk) if !chunked && kind != "Title" => {
+                chunk.text.push_str("\n\n");
+                chunk.text.push_str(text);
+            }
+            _ => converted.chunks.push(Chunk { text: text.to_string(), page }),
+        }
+    }
+    Ok(converted)
+}
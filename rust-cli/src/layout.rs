@@ -0,0 +1,68 @@
+// How results are arranged in an output directory (--layout), so batches of
+// tens of thousands of documents don't end up in one unmanageable directory,
+// and the index.json that maps each source to its output.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Maps sources to their outputs, in the output directory
+pub const INDEX_FILE: &str = "index.json";
+// Where a document whose output would be named like the index goes instead
+const RENAMED_INDEX: &str = "index.document";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Layout {
+    /// Under a directory for the day of the run, then as in the source tree (2024-06-18/invoices/a.json)
+    ByDate,
+    /// Under a directory for the source's file type, then as in the source tree (pdf/invoices/a.json)
+    ByType,
+    /// At the same relative path as the source (invoices/a.json)
+    BySourceTree,
+    /// All in the output directory, with the source's directories in the name (invoices__a.json)
+    Flat,
+}
+
+impl Layout {
+    /// Output path of the source at `key` (a relative path with / separators),
+    /// relative to the output directory; `date` is the day of the run
+    pub fn output_path(&self, key: &str, date: &str, extension: &str) -> PathBuf {
+        let path = match self {
+            Layout::ByDate => Path::new(date).join(key),
+            Layout::ByType => Path::new(&file_type(key)).join(key),
+            Layout::BySourceTree => PathBuf::from(key),
+            Layout::Flat => PathBuf::from(key.replace('/', "__")),
+        };
+        let path = path.with_extension(extension);
+        if path == Path::new(INDEX_FILE) {
+            return PathBuf::from(format!("{}.{}", RENAMED_INDEX, extension));
+        }
+        path
+    }
+}
+
+// Lowercased extension of the source, or "other"
+fn file_type(key: &str) -> String {
+    Path::new(key)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "other".to_string())
+}
+
+/// Add `outputs` (source key and output path) to the index in `out_dir`,
+/// keeping the entries of earlier runs for other sources
+pub fn update_index(out_dir: &Path, outputs: &[(String, PathBuf)]) -> Result<()> {
+    let path = out_dir.join(INDEX_FILE);
+    let mut index: BTreeMap<String, String> = fs::read(&path)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default();
+    for (source, output) in outputs {
+        let relative = output.strip_prefix(out_dir).unwrap_or(output);
+        let relative = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        index.insert(source.clone(), relative);
+    }
+    fs::write(&path, serde_json::to_vec_pretty(&index)?).context(format!("Failed to write {}", path.display()))
+}
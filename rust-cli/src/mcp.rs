@@ -0,0 +1,157 @@
+// Model Context Protocol server over stdio (mcp), so LLM agents can extract
+// documents through this binary: one JSON-RPC message per line on stdin and
+// stdout, exposing an extract_text tool. Progress and logs go to stderr, since
+// stdout carries the protocol.
+
+use anyhow::{anyhow, Context, Result};
+use crate::result::AnnotatedResult;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+// Newest first; a client asking for another version gets the newest
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+const TOOL_NAME: &str = "extract_text";
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Arguments of one extract_text call
+pub struct ToolCall {
+    /// Local file path or http(s) URL
+    pub path: String,
+    pub chunk_size: Option<u32>,
+    pub parsing_instructions: Option<String>,
+    /// Answer with the whole result as JSON instead of the text
+    pub json: bool,
+}
+
+/// Answer requests on stdin until it's closed; `extract` runs each tool call
+pub fn serve(mut extract: impl FnMut(ToolCall) -> Result<AnnotatedResult>) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line.context("Failed to read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(&message, &mut extract),
+            Err(e) => Some(error(&Value::Null, PARSE_ERROR, format!("Invalid JSON: {}", e))),
+        };
+        if let Some(response) = response {
+            writeln!(stdout, "{}", response)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// The response to a request, or None for notifications
+fn handle(message: &Value, extract: &mut impl FnMut(ToolCall) -> Result<AnnotatedResult>) -> Option<Value> {
+    let Some(method) = message["method"].as_str() else {
+        // Responses need no answer, though this server sends no requests
+        if message.get("result").is_some() || message.get("error").is_some() {
+            return None;
+        }
+        let id = message.get("id").unwrap_or(&Value::Null);
+        return Some(error(id, INVALID_REQUEST, "Expected a JSON-RPC request".to_string()));
+    };
+    let id = message.get("id")?;
+    let params = &message["params"];
+
+    let result = match method {
+        "initialize" => {
+            let requested = params["protocolVersion"].as_str();
+            let version = PROTOCOL_VERSIONS.iter().find(|v| Some(**v) == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
+            json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": { "listChanged": false } },
+                "serverInfo": { "name": "vectorize-iris", "version": env!("CARGO_PKG_VERSION") },
+                "instructions": "Use extract_text to get the text of PDFs, Office documents, images, and other files, by local path or URL.",
+            })
+        }
+        "ping" => json!({}),
+        "tools/list" => json!({ "tools": [tool_definition()] }),
+        "tools/call" => match tool_call(params) {
+            Ok(call) => call_result(call, extract),
+            Err(e) => return Some(error(id, INVALID_PARAMS, e.to_string())),
+        },
+        _ => return Some(error(id, METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+fn tool_definition() -> Value {
+    json!({
+        "name": TOOL_NAME,
+        "title": "Extract text",
+        "description": "Extract the text of a document (PDF, Word, PowerPoint, Excel, image, HTML, and more) with Vectorize Iris. \
+            Returns the text, or with format \"json\" the full result with chunks and metadata.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of a local file (relative to the server's working directory), or an http(s) URL"
+                },
+                "chunk_size": { "type": "integer", "minimum": 1, "description": "Chunk size for the json format" },
+                "parsing_instructions": { "type": "string", "description": "Instructions for the AI model, such as what to ignore" },
+                "format": { "type": "string", "enum": ["text", "json"], "default": "text" }
+            },
+            "required": ["path"]
+        }
+    })
+}
+
+fn tool_call(params: &Value) -> Result<ToolCall> {
+    match params["name"].as_str() {
+        Some(TOOL_NAME) => {}
+        Some(name) => return Err(anyhow!("Unknown tool: {}", name)),
+        None => return Err(anyhow!("Missing tool name")),
+    }
+    let arguments = &params["arguments"];
+    let path = arguments["path"].as_str().filter(|p| !p.is_empty()).ok_or_else(|| anyhow!("Missing argument: path"))?;
+    let chunk_size = match &arguments["chunk_size"] {
+        Value::Null => None,
+        value => Some(value.as_u64().and_then(|n| u32::try_from(n).ok()).filter(|n| *n > 0).ok_or_else(|| anyhow!("chunk_size must be a positive integer"))?),
+    };
+    let json = match arguments["format"].as_str() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => return Err(anyhow!("Unknown format '{}' (use text or json)", other)),
+    };
+    Ok(ToolCall {
+        path: path.to_string(),
+        chunk_size,
+        parsing_instructions: arguments["parsing_instructions"].as_str().map(str::to_string),
+        json,
+    })
+}
+
+// Failed extractions are tool results with isError, so the model sees why
+fn call_result(call: ToolCall, extract: &mut impl FnMut(ToolCall) -> Result<AnnotatedResult>) -> Value {
+    let json = call.json;
+    let text = extract(call).and_then(|result| match json {
+        true => Ok(serde_json::to_string_pretty(&result)?),
+        false => result_text(&result),
+    });
+    match text {
+        Ok(text) => json!({ "content": [{ "type": "text", "text": text }], "isError": false }),
+        Err(e) => json!({ "content": [{ "type": "text", "text": format!("Extraction failed: {:#}", e) }], "isError": true }),
+    }
+}
+
+fn result_text(result: &AnnotatedResult) -> Result<String> {
+    match (&result.text, &result.text_file, &result.chunks) {
+        (Some(text), _, _) => Ok(text.clone()),
+        (None, Some(path), _) => fs::read_to_string(path).context(format!("Failed to read {}", path.display())),
+        (None, None, chunks) => Ok(chunks.iter().flatten().map(String::as_str).collect::<Vec<_>>().join("\n\n")),
+    }
+}
+
+fn error(id: &Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
@@ -0,0 +1,148 @@
+// Lightweight self-monitor for long batch runs: periodically logs resident
+// memory, open file descriptors, and the number of files still queued, and
+// asks the batch to restart its worker threads when a threshold is exceeded.
+
+use console::style;
+
+use crate::theme::Themed;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+pub struct MonitorConfig {
+    /// How often to sample and log; sampling is off when unset
+    pub interval: Option<Duration>,
+    pub max_rss_mb: Option<u64>,
+    pub max_open_files: Option<usize>,
+}
+
+pub struct Sample {
+    pub rss_bytes: Option<u64>,
+    pub open_fds: Option<usize>,
+}
+
+/// Resident set size in bytes (Linux only), from the VmRSS line of
+/// /proc/self/status, which is in kB whatever the page size
+fn rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kb: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Hand memory freed by finished files back to the system; glibc otherwise
+/// keeps it in the heaps of the threads that used it
+pub fn release_memory() {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    // SAFETY: malloc_trim only returns free pages of the allocator's heaps to the kernel
+    unsafe {
+        libc::malloc_trim(0);
+    }
+}
+
+fn open_fds() -> Option<usize> {
+    ["/proc/self/fd", "/dev/fd"]
+        .iter()
+        .find_map(|dir| fs::read_dir(dir).ok())
+        .map(|entries| entries.count())
+}
+
+pub fn sample() -> Sample {
+    Sample {
+        rss_bytes: rss_bytes(),
+        open_fds: open_fds(),
+    }
+}
+
+/// Shared between the batch worker and the monitor thread
+#[derive(Default)]
+pub struct MonitorState {
+    pub queue_depth: AtomicUsize,
+    recycle_requested: AtomicBool,
+}
+
+impl MonitorState {
+    /// True once per threshold breach; the batch should restart its workers
+    pub fn take_recycle_request(&self) -> bool {
+        self.recycle_requested.swap(false, Ordering::SeqCst)
+    }
+}
+
+pub struct Monitor {
+    pub state: Arc<MonitorState>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Start sampling on a background thread; a disabled config starts nothing
+    pub fn start(config: &MonitorConfig) -> Monitor {
+        let state = Arc::new(MonitorState::default());
+        let Some(interval) = config.interval else {
+            return Monitor { state, stop: None, handle: None };
+        };
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let shared = Arc::clone(&state);
+        let config = config.clone();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                check(&config, &shared);
+            }
+        });
+
+        Monitor {
+            state,
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn check(config: &MonitorConfig, state: &MonitorState) {
+    let sample = sample();
+    let rss_mb = sample.rss_bytes.map(|b| b / (1024 * 1024));
+    let show = |v: Option<String>| v.unwrap_or_else(|| "n/a".to_string());
+
+    eprintln!(
+        "{} rss={} fds={} queued={}",
+        style("[monitor]").dim(),
+        show(rss_mb.map(|mb| format!("{}MB", mb))),
+        show(sample.open_fds.map(|n| n.to_string())),
+        state.queue_depth.load(Ordering::SeqCst)
+    );
+
+    let mut exceeded = Vec::new();
+    if let (Some(max), Some(rss)) = (config.max_rss_mb, rss_mb) {
+        if rss > max {
+            exceeded.push(format!("rss {}MB > {}MB", rss, max));
+        }
+    }
+    if let (Some(max), Some(fds)) = (config.max_open_files, sample.open_fds) {
+        if fds > max {
+            exceeded.push(format!("open files {} > {}", fds, max));
+        }
+    }
+
+    // Log once per breach until the worker has picked up the request
+    if !exceeded.is_empty() && !state.recycle_requested.swap(true, Ordering::SeqCst) {
+        eprintln!(
+            "{} threshold exceeded ({}), restarting batch workers",
+            style("[monitor]").warning(),
+            exceeded.join(", ")
+        );
+    }
+}
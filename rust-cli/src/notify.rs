@@ -0,0 +1,169 @@
+// Chat notifications for batch runs (--notify-slack, --notify-teams): a
+// message when a batch starts and one when it finishes or fails, with the top
+// failure reasons and where the summary files are, posted to incoming
+// webhooks. Messages are posted quietly; the channel is only mentioned when at
+// least --notify-threshold files failed, so routine runs don't ping anyone.
+
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::theme::Themed;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Failure reasons listed in a message
+const TOP_REASONS: usize = 3;
+
+#[derive(Clone, Copy)]
+enum Service {
+    Slack,
+    Teams,
+}
+
+impl Service {
+    fn name(self) -> &'static str {
+        match self {
+            Service::Slack => "Slack",
+            Service::Teams => "Teams",
+        }
+    }
+
+    fn bold(self, text: &str) -> String {
+        match self {
+            Service::Slack => format!("*{}*", text),
+            Service::Teams => format!("**{}**", text),
+        }
+    }
+
+    // Teams incoming webhooks can't mention a channel, so the message is flagged instead
+    fn mention(self) -> &'static str {
+        match self {
+            Service::Slack => "<!here> ",
+            Service::Teams => "🚨 ",
+        }
+    }
+}
+
+/// How a batch ended, for the completion message
+pub struct Finished<'a> {
+    pub successful: usize,
+    pub failed: usize,
+    pub duplicates: usize,
+    pub warnings: usize,
+    /// Source and reason of each failed file
+    pub failures: &'a [(String, String)],
+    /// Index, run manifest, and progress files written by the batch
+    pub summary_files: Vec<PathBuf>,
+    pub correlation_id: &'a str,
+}
+
+pub struct Notifier {
+    targets: Vec<(Service, String)>,
+    threshold: usize,
+    client: reqwest::blocking::Client,
+}
+
+impl Notifier {
+    /// None without any webhook
+    pub fn new(slack: &[String], teams: &[String], threshold: usize) -> Result<Option<Notifier>> {
+        let targets: Vec<(Service, String)> = slack
+            .iter()
+            .map(|url| (Service::Slack, url.clone()))
+            .chain(teams.iter().map(|url| (Service::Teams, url.clone())))
+            .collect();
+        if targets.is_empty() {
+            return Ok(None);
+        }
+        let client = reqwest::blocking::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Some(Notifier { targets, threshold, client }))
+    }
+
+    pub fn started(&self, files: usize, out_dir: Option<&Path>) {
+        self.post(false, |service| {
+            let mut text = format!("{} {} files", service.bold("vectorize-iris batch started:"), files);
+            if let Some(out_dir) = out_dir {
+                text.push_str(&format!(", writing to `{}`", out_dir.display()));
+            }
+            text
+        });
+    }
+
+    pub fn finished(&self, finished: &Finished) {
+        let mention = finished.failed > 0 && finished.failed >= self.threshold;
+        let reasons = top_reasons(finished.failures);
+        self.post(mention, |service| {
+            let title = match finished.failed {
+                0 => "vectorize-iris batch finished:",
+                _ => "vectorize-iris batch finished with failures:",
+            };
+            let mut text = format!(
+                "{} {} succeeded, {} failed",
+                service.bold(title),
+                finished.successful,
+                finished.failed
+            );
+            if finished.duplicates > 0 || finished.warnings > 0 {
+                text.push_str(&format!(" ({} duplicates, {} warnings)", finished.duplicates, finished.warnings));
+            }
+            if !reasons.is_empty() {
+                text.push_str("\nTop errors:");
+                for (reason, count) in &reasons {
+                    text.push_str(&format!("\n• {} × {}", count, reason));
+                }
+            }
+            if !finished.summary_files.is_empty() {
+                let files: Vec<String> =
+                    finished.summary_files.iter().map(|file| format!("`{}`", file.display())).collect();
+                text.push_str(&format!("\nSummary files: {}", files.join(", ")));
+            }
+            text.push_str(&format!("\nCorrelation ID: `{}`", finished.correlation_id));
+            text
+        });
+    }
+
+    /// The batch stopped before finishing; always mentions the channel
+    pub fn failed(&self, error: &anyhow::Error) {
+        self.post(true, |service| format!("{} {:#}", service.bold("vectorize-iris batch failed:"), error));
+    }
+
+    // Notifications never fail the batch; delivery problems are only reported
+    fn post(&self, mention: bool, message: impl Fn(Service) -> String) {
+        for (service, url) in &self.targets {
+            let mut text = message(*service);
+            if mention {
+                text.insert_str(0, service.mention());
+            }
+            if let Err(e) = self.send(url, &text) {
+                eprintln!("{} Failed to notify {}: {:#}", style("[notify]").warning(), service.name(), e);
+            }
+        }
+    }
+
+    fn send(&self, url: &str, text: &str) -> Result<()> {
+        let response = self
+            .client
+            .post(url)
+            .json(&json!({ "text": text }))
+            .send()
+            .context("Failed to reach the webhook")?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Webhook answered {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+// The most common failure reasons, most frequent first
+fn top_reasons(failures: &[(String, String)]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (_, reason) in failures {
+        *counts.entry(reason.as_str()).or_default() += 1;
+    }
+    let mut reasons: Vec<(String, usize)> = counts.into_iter().map(|(reason, count)| (reason.to_string(), count)).collect();
+    reasons.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    reasons.truncate(TOP_REASONS);
+    reasons
+}
@@ -0,0 +1,144 @@
+// The heading structure of an extracted document: its Markdown headings
+// (outside code blocks) and which section each chunk starts in, used for
+// chunk titles and -o doc-graph. Chunks are found in the text by their first
+// line, in order, since chunk overlap means they can't simply be laid end to
+// end.
+
+use anyhow::{Context, Result};
+use crate::result::AnnotatedResult;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::fs;
+
+use crate::elements;
+
+// Characters of a chunk's first line used to find it in the text
+const PROBE_CHARS: usize = 80;
+
+/// A Markdown heading of the text
+pub struct Heading {
+    /// 1 for #, up to 6
+    pub level: usize,
+    pub title: String,
+    /// Byte offset of the heading line in the text
+    pub offset: usize,
+    /// Index of the enclosing heading, the nearest before it with a lower level
+    pub parent: Option<usize>,
+}
+
+/// The text of a result: its text, spooled text file, or else its chunks
+pub fn text(data: &AnnotatedResult) -> Result<Cow<'_, str>> {
+    Ok(match (&data.text, &data.text_file) {
+        (Some(text), _) => text.as_str().into(),
+        (None, Some(path)) => fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?.into(),
+        (None, None) => data.chunks.iter().flatten().map(String::as_str).collect::<Vec<_>>().join("\n\n").into(),
+    })
+}
+
+/// The headings of `text`, in order
+pub fn headings(text: &str) -> Vec<Heading> {
+    let mut headings: Vec<Heading> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let start = offset;
+        offset += line.len();
+        if let Some(open) = fence {
+            if trimmed.starts_with(open) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        let Some((level, title)) = elements::heading(trimmed) else { continue };
+        // The enclosing heading is the nearest open one of a lower level
+        let mut parent = headings.len().checked_sub(1);
+        while let Some(i) = parent.filter(|i| headings[*i].level >= level) {
+            parent = headings[i].parent;
+        }
+        headings.push(Heading { level, title: title.to_string(), offset: start, parent });
+    }
+    headings
+}
+
+/// The titles from the outermost heading down to `index`
+pub fn breadcrumb(headings: &[Heading], index: usize) -> Vec<&str> {
+    let mut titles = Vec::new();
+    let mut current = Some(index);
+    while let Some(i) = current {
+        titles.push(headings[i].title.as_str());
+        current = headings[i].parent;
+    }
+    titles.reverse();
+    titles
+}
+
+/// For each chunk, the heading whose section it starts in; None before the
+/// first heading. A chunk that can't be found in the text stays in the
+/// section of the chunk before it.
+pub fn chunk_sections(text: &str, headings: &[Heading], chunks: &[String]) -> Vec<Option<usize>> {
+    let mut sections = Vec::with_capacity(chunks.len());
+    let mut from = 0;
+    let mut previous: Option<usize> = None;
+    for chunk in chunks {
+        let section = match locate(text, chunk, from) {
+            Some(at) => {
+                from = at + 1;
+                // The last heading at or before the chunk's start
+                headings.iter().rposition(|heading| heading.offset <= at)
+            }
+            None => previous,
+        };
+        sections.push(section);
+        previous = section;
+    }
+    sections
+}
+
+// Where a chunk starts in the text, at or after `from`
+fn locate(text: &str, chunk: &str, from: usize) -> Option<usize> {
+    let first_line = chunk.trim_start().lines().next()?.trim_end();
+    let end = first_line.char_indices().nth(PROBE_CHARS).map_or(first_line.len(), |(i, _)| i);
+    let probe = &first_line[..end];
+    if probe.is_empty() {
+        return None;
+    }
+    let from = (from..=text.len()).find(|i| text.is_char_boundary(*i))?;
+    text[from..].find(probe).map(|at| from + at)
+}
+
+/// Add the `title` of the section each chunk starts in, and the `breadcrumb`
+/// of titles down to it, to the chunk's metadata; a title the service set is
+/// kept. Chunks before the first heading are left as they are.
+pub fn add_chunk_titles(data: &mut AnnotatedResult) -> Result<()> {
+    let Some(chunks) = data.chunks.as_deref().filter(|chunks| !chunks.is_empty()) else {
+        return Ok(());
+    };
+    let text = text(data)?.into_owned();
+    let headings = headings(&text);
+    if headings.is_empty() {
+        return Ok(());
+    }
+    let sections = chunk_sections(&text, &headings, chunks);
+    let count = chunks.len();
+
+    let metadata = data.chunks_metadata.get_or_insert_with(Vec::new);
+    metadata.resize(metadata.len().max(count), None);
+    for (i, section) in sections.into_iter().enumerate() {
+        let Some(section) = section else { continue };
+        let mut fields = match metadata[i].as_deref().map(serde_json::from_str::<Value>) {
+            None => Map::new(),
+            Some(Ok(Value::Object(fields))) => fields,
+            // Metadata that isn't an object is left alone
+            Some(_) => continue,
+        };
+        fields.entry("title").or_insert_with(|| headings[section].title.clone().into());
+        fields.insert("breadcrumb".to_string(), breadcrumb(&headings, section).into());
+        metadata[i] = Some(Value::Object(fields).to_string());
+    }
+    Ok(())
+}
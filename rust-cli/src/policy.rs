@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Context, Result};
+use iris_client::is_under;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Admin-managed restrictions, read from /etc/vectorize-iris/policy.toml
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(skip)]
+    pub source: PathBuf,
+    /// API base URLs the CLI may talk to; an entry ending in `*` also allows the URLs below it
+    pub allowed_base_urls: Option<Vec<String>>,
+    /// Allow plain-HTTP (http://) API endpoints and downloads
+    #[serde(default = "default_true")]
+    pub allow_insecure: bool,
+    /// Upper bound for the number of files processed in parallel
+    pub max_concurrency: Option<usize>,
+    /// Refuse to write results, reports, snapshots, caches, or sinks (the CLI cannot encrypt outputs)
+    #[serde(default)]
+    pub forbid_unencrypted_outputs: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What a run is about to do, checked against the policy before any work starts
+#[derive(Default)]
+pub struct Usage<'a> {
+    pub base_url: Option<&'a str>,
+    pub download_urls: Vec<&'a str>,
+    pub outputs: Vec<String>,
+    pub concurrency: Option<usize>,
+}
+
+fn policy_path() -> PathBuf {
+    // Only debug builds let the tests point at a fixture; a released binary
+    // always reads the system path, so users can't opt out of the policy
+    if cfg!(debug_assertions) {
+        if let Ok(path) = env::var("VECTORIZE_IRIS_POLICY") {
+            return PathBuf::from(path);
+        }
+    }
+    if cfg!(windows) {
+        let program_data = env::var("ProgramData").unwrap_or_else(|_| "C:\\ProgramData".to_string());
+        PathBuf::from(program_data).join("vectorize-iris").join("policy.toml")
+    } else {
+        PathBuf::from("/etc/vectorize-iris/policy.toml")
+    }
+}
+
+// A pattern ending in `*` allows the URL before it and the URLs below it, but
+// not ones that merely start with the same characters (api.example.com.evil)
+fn url_allowed(url: &str, allowed: &[String]) -> bool {
+    let url = url.trim_end_matches('/');
+    allowed.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => is_under(url, prefix),
+        None => url == pattern.trim_end_matches('/'),
+    })
+}
+
+impl Policy {
+    /// Load the policy if one is installed
+    pub fn load() -> Result<Option<Policy>> {
+        let path = policy_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)
+            .context(format!("Failed to read policy file: {}", path.display()))?;
+        let mut policy: Policy = toml::from_str(&content)
+            .context(format!("Invalid policy file: {}", path.display()))?;
+        policy.source = path;
+        Ok(Some(policy))
+    }
+
+    pub fn violations(&self, usage: &Usage) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if let Some(base_url) = usage.base_url {
+            if let Some(allowed) = &self.allowed_base_urls {
+                if !url_allowed(base_url, allowed) {
+                    violations.push(format!(
+                        "API URL '{}' is not allowed (allowed: {})",
+                        base_url,
+                        allowed.join(", ")
+                    ));
+                }
+            }
+            if !self.allow_insecure && base_url.starts_with("http://") {
+                violations.push(format!("Insecure API URL '{}' is not allowed; use https", base_url));
+            }
+        }
+
+        if !self.allow_insecure {
+            for url in usage.download_urls.iter().filter(|u| u.starts_with("http://")) {
+                violations.push(format!("Insecure download '{}' is not allowed; use https", url));
+            }
+        }
+
+        if let (Some(max), Some(requested)) = (self.max_concurrency, usage.concurrency) {
+            if requested > max {
+                violations.push(format!("Concurrency {} exceeds the maximum of {}", requested, max));
+            }
+        }
+
+        if self.forbid_unencrypted_outputs {
+            for output in &usage.outputs {
+                violations.push(format!("Writing unencrypted output to {} is not allowed", output));
+            }
+        }
+
+        violations
+    }
+
+    pub fn enforce(&self, usage: &Usage) -> Result<()> {
+        let violations = self.violations(usage);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "Policy violation ({}):\n{}",
+            self.source.display(),
+            violations
+                .iter()
+                .map(|v| format!("  - {}", v))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))
+    }
+}
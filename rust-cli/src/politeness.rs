@@ -0,0 +1,249 @@
+// Politeness toward the sites of a URL list: each site's robots.txt is read
+// once and URLs it disallows are skipped (RFC 9309, with the * and $ wildcards
+// and Crawl-delay), requests to one site wait --crawl-delay after each other,
+// and only --max-per-domain downloads from one site run at a time. A site
+// without a robots.txt (4xx) may be crawled; one whose robots.txt can't be
+// reached (5xx, network errors) is treated as disallowing everything.
+
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const ROBOTS_TIMEOUT: Duration = Duration::from_secs(30);
+// Robots.txt content past this is ignored, as RFC 9309 allows
+const MAX_ROBOTS_SIZE: usize = 500 * 1024;
+
+/// The rules of one robots.txt group for our user agent
+#[derive(Default)]
+pub struct Robots {
+    // (allow, path pattern)
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+    // Why everything is disallowed, when the file couldn't be read
+    unreachable: Option<String>,
+}
+
+impl Robots {
+    /// Rules of the group naming `token` (the user agent's product token), or else of the `*` group
+    pub fn parse(text: &str, token: &str) -> Robots {
+        let token = token.to_lowercase();
+        let mut named = Robots::default();
+        let mut any = Robots::default();
+        let (mut found_named, mut found_any) = (false, false);
+        // Agents of the group being read, and whether its rules started
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_lowercase());
+                continue;
+            }
+            in_rules = true;
+            // Groups for the same agent are combined
+            let mut targets = Vec::new();
+            if agents.contains(&token) {
+                found_named = true;
+                targets.push(&mut named);
+            }
+            if agents.iter().any(|agent| agent == "*") {
+                found_any = true;
+                targets.push(&mut any);
+            }
+            for robots in targets {
+                match key.as_str() {
+                    "allow" | "disallow" if !value.is_empty() => robots.rules.push((key == "allow", value.to_string())),
+                    "crawl-delay" => {
+                        if let Ok(seconds) = value.parse::<f64>() {
+                            robots.crawl_delay = Duration::try_from_secs_f64(seconds).ok();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        match (found_named, found_any) {
+            (true, _) => named,
+            (false, true) => any,
+            _ => Robots::default(),
+        }
+    }
+
+    /// Whether the path (with its query) of a URL may be fetched: the longest
+    /// matching rule decides, and Allow wins a tie
+    pub fn allows(&self, path: &str) -> bool {
+        if self.unreachable.is_some() {
+            return false;
+        }
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if matches(pattern, path) && best.is_none_or(|(len, allowed)| pattern.len() > len || (pattern.len() == len && *allow && !allowed)) {
+                best = Some((pattern.len(), *allow));
+            }
+        }
+        best.is_none_or(|(_, allow)| allow)
+    }
+}
+
+// Whether a robots.txt path pattern matches a path: * matches any characters
+// and a trailing $ anchors the pattern at the end
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return !anchored || rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    match anchored {
+        true => rest.ends_with(last),
+        false => rest.contains(last),
+    }
+}
+
+#[derive(Default)]
+struct Site {
+    active: usize,
+    // When the next request may start
+    next: Option<Instant>,
+}
+
+/// Holds a download slot of a site until dropped
+pub struct Slot<'a> {
+    politeness: &'a Politeness,
+    site: String,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut sites = self.politeness.sites.lock().unwrap();
+        if let Some(site) = sites.get_mut(&self.site) {
+            site.active -= 1;
+        }
+        self.politeness.freed.notify_all();
+    }
+}
+
+pub struct Politeness {
+    user_agent: String,
+    delay: Option<Duration>,
+    per_domain: usize,
+    respect_robots: bool,
+    client: reqwest::blocking::Client,
+    robots: Mutex<HashMap<String, Arc<OnceLock<Robots>>>>,
+    sites: Mutex<HashMap<String, Site>>,
+    freed: Condvar,
+}
+
+impl Politeness {
+    pub fn new(user_agent: String, delay: Option<Duration>, per_domain: usize, respect_robots: bool) -> Result<Politeness> {
+        let client = reqwest::blocking::Client::builder().timeout(ROBOTS_TIMEOUT).build()?;
+        Ok(Politeness {
+            user_agent,
+            delay,
+            per_domain: per_domain.max(1),
+            respect_robots,
+            client,
+            robots: Mutex::new(HashMap::new()),
+            sites: Mutex::new(HashMap::new()),
+            freed: Condvar::new(),
+        })
+    }
+
+    /// The User-Agent sent with downloads and matched against robots.txt
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// Wait until `url` may be downloaded: its site has a free slot and the
+    /// crawl delay since the last request has passed. Fails when robots.txt
+    /// disallows the URL.
+    pub fn wait(&self, url: &str) -> Result<Slot<'_>> {
+        let parsed = Url::parse(url)?;
+        let site = match (parsed.host_str(), parsed.port_or_known_default()) {
+            (Some(host), Some(port)) => format!("{}://{}:{}", parsed.scheme(), host, port),
+            _ => return Err(anyhow!("URL has no host: {}", url)),
+        };
+        let mut delay = self.delay;
+        if self.respect_robots {
+            // Fetched once per site, however many threads ask
+            let robots = self.robots.lock().unwrap().entry(site.clone()).or_default().clone();
+            let robots = robots.get_or_init(|| self.fetch_robots(&parsed, &site));
+            if let Some(reason) = &robots.unreachable {
+                return Err(anyhow!("{} (--ignore-robots to download anyway)", reason));
+            }
+            let path = match parsed.query() {
+                Some(query) => format!("{}?{}", parsed.path(), query),
+                None => parsed.path().to_string(),
+            };
+            if !robots.allows(&path) {
+                return Err(anyhow!("Disallowed by robots.txt (--ignore-robots to download anyway)"));
+            }
+            delay = delay.max(robots.crawl_delay);
+        }
+
+        let mut sites = self.sites.lock().unwrap();
+        loop {
+            let entry = sites.entry(site.clone()).or_default();
+            let now = Instant::now();
+            match entry.next.filter(|next| *next > now) {
+                _ if entry.active >= self.per_domain => sites = self.freed.wait(sites).unwrap(),
+                Some(next) => sites = self.freed.wait_timeout(sites, next - now).unwrap().0,
+                None => {
+                    entry.active += 1;
+                    entry.next = delay.map(|delay| now + delay);
+                    return Ok(Slot { politeness: self, site });
+                }
+            }
+        }
+    }
+
+    fn fetch_robots(&self, url: &Url, site: &str) -> Robots {
+        let unreachable = |reason: String| Robots {
+            unreachable: Some(format!("robots.txt of {} couldn't be read: {}", site, reason)),
+            ..Robots::default()
+        };
+        let Ok(robots_url) = url.join("/robots.txt") else { return Robots::default() };
+        let response = match self.client.get(robots_url).header("User-Agent", &self.user_agent).send() {
+            Ok(response) => response,
+            Err(e) => return unreachable(e.to_string()),
+        };
+        let status = response.status();
+        if status.is_client_error() {
+            return Robots::default();
+        }
+        if !status.is_success() {
+            return unreachable(format!("HTTP {}", status));
+        }
+        match response.bytes() {
+            Ok(body) => {
+                let body = &body[..body.len().min(MAX_ROBOTS_SIZE)];
+                Robots::parse(&String::from_utf8_lossy(body), product_token(&self.user_agent))
+            }
+            Err(e) => unreachable(e.to_string()),
+        }
+    }
+}
+
+// The name a robots.txt group addresses a crawler by, as in
+// "vectorize-iris-cli/1.0 (linux; x86_64)" -> "vectorize-iris-cli"
+fn product_token(user_agent: &str) -> &str {
+    user_agent.split(['/', ' ']).next().unwrap_or_default()
+}
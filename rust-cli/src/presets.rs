@@ -0,0 +1,250 @@
+// Presets (--preset) for common document types: curated parsing instructions
+// and a metadata schema, so good results don't take prompt engineering. Users
+// can add their own as ~/.config/vectorize/presets/NAME.toml, which also take
+// precedence over a built-in preset of the same name. Explicit flags replace
+// the matching part of a preset.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{config, schemas};
+use crate::{ChunkUnit, ChunkingStrategy, ExtractionType};
+
+/// Parsing instructions, chunking settings, and metadata schemas for one kind
+/// of document
+#[derive(Clone, Debug, Default)]
+pub struct Preset {
+    pub parsing_instructions: Option<String>,
+    pub chunk_size: Option<u32>,
+    pub chunk_overlap: Option<u32>,
+    pub chunk_unit: Option<ChunkUnit>,
+    pub chunking_strategy: Option<ChunkingStrategy>,
+    pub extraction_type: Option<ExtractionType>,
+    /// In the ID:JSON form of --metadata-schema
+    pub metadata_schemas: Vec<String>,
+}
+
+pub const BUILT_IN: [&str; 4] = ["invoice", "resume", "contract", "research-paper"];
+
+/// A presets/NAME.toml file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PresetFile {
+    parsing_instructions: Option<String>,
+    chunk_size: Option<u32>,
+    chunk_overlap: Option<u32>,
+    chunk_unit: Option<ChunkUnit>,
+    chunking_strategy: Option<ChunkingStrategy>,
+    extraction_type: Option<ExtractionType>,
+    /// By ID: a table holding the schema, "@NAME" for a saved schema, or the
+    /// path of a JSON file, relative to the presets directory
+    #[serde(default)]
+    schemas: BTreeMap<String, toml::Value>,
+}
+
+pub fn dir() -> Result<PathBuf> {
+    Ok(config::config_dir().context("Could not determine home directory")?.join("presets"))
+}
+
+/// The names of the presets in the presets directory
+pub fn user_presets() -> Vec<String> {
+    let Ok(entries) = dir().and_then(|dir| Ok(fs::read_dir(dir)?)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn parse(value: &str) -> Result<Preset, String> {
+    let valid_name = !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid_name {
+        if let Some(path) = dir().ok().map(|dir| dir.join(format!("{}.toml", value))).filter(|path| path.exists()) {
+            return load(&path).map_err(|e| format!("{:#}", e));
+        }
+    }
+    let (instructions, schema) = match value {
+        "invoice" => (INVOICE_INSTRUCTIONS, invoice_schema()),
+        "resume" => (RESUME_INSTRUCTIONS, resume_schema()),
+        "contract" => (CONTRACT_INSTRUCTIONS, contract_schema()),
+        "research-paper" => (RESEARCH_PAPER_INSTRUCTIONS, research_paper_schema()),
+        _ => {
+            let available: Vec<String> = BUILT_IN.iter().map(|name| name.to_string()).chain(user_presets()).collect();
+            return Err(format!("unknown preset '{}' (available: {})", value, available.join(", ")));
+        }
+    };
+    Ok(Preset {
+        parsing_instructions: Some(instructions.to_string()),
+        metadata_schemas: vec![format!("{}:{}", value, schema)],
+        ..Preset::default()
+    })
+}
+
+fn load(path: &Path) -> Result<Preset> {
+    let content = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    let file: PresetFile = toml::from_str(&content).context(format!("Invalid preset {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let metadata_schemas = file
+        .schemas
+        .into_iter()
+        .map(|(id, schema)| {
+            let schema = schema_json(base, &schema).context(format!("Invalid schema '{}' in preset {}", id, path.display()))?;
+            Ok(format!("{}:{}", id, schema))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Preset {
+        parsing_instructions: file.parsing_instructions,
+        chunk_size: file.chunk_size,
+        chunk_overlap: file.chunk_overlap,
+        chunk_unit: file.chunk_unit,
+        chunking_strategy: file.chunking_strategy,
+        extraction_type: file.extraction_type,
+        metadata_schemas,
+    })
+}
+
+// A schema of a preset file as JSON
+fn schema_json(base: &Path, schema: &toml::Value) -> Result<Value> {
+    match schema {
+        toml::Value::Table(_) => Ok(serde_json::to_value(schema)?),
+        toml::Value::String(name) if name.starts_with('@') => Ok(serde_json::from_str(&schemas::load(&name[1..])?)?),
+        toml::Value::String(file) => {
+            let path = base.join(file);
+            let content = fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+            serde_json::from_str(&content).context(format!("Invalid JSON in {}", path.display()))
+        }
+        _ => Err(anyhow!("expected a table, \"@NAME\" of a saved schema, or a JSON file path")),
+    }
+}
+
+const INVOICE_INSTRUCTIONS: &str = "This is an invoice or bill. Keep line item tables as tables, one row per item, \
+with their quantity, unit price, and amount columns. Keep the vendor and customer address blocks together. \
+Report amounts exactly as printed, with their currency, and dates as printed.";
+
+const RESUME_INSTRUCTIONS: &str = "This is a resume or CV. Keep each position together with its employer, title, \
+dates, and the bullet points under it, in the order given. Keep section headings such as Experience, Education, \
+and Skills. Don't merge the columns of two-column layouts into one another.";
+
+const CONTRACT_INSTRUCTIONS: &str = "This is a contract or legal agreement. Preserve the numbering of sections, \
+clauses, and sub-clauses exactly, and keep each clause with its heading. Keep defined terms as written, \
+including capitalization. Include signature blocks, dates, and schedules or exhibits.";
+
+const RESEARCH_PAPER_INSTRUCTIONS: &str = "This is a research paper. Keep the title, authors, affiliations, and \
+abstract at the start, and section headings with their numbering. Render equations as LaTeX, keep tables as \
+tables with their captions, and keep figure captions. Keep citation markers in the text and the reference list \
+as one entry per reference.";
+
+fn string(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn invoice_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "invoice_number": string("Invoice number or ID"),
+            "invoice_date": string("Date the invoice was issued, as printed"),
+            "due_date": string("Payment due date, as printed"),
+            "vendor": string("Name of the company issuing the invoice"),
+            "customer": string("Name of the billed company or person"),
+            "currency": string("ISO 4217 currency code, e.g. USD"),
+            "subtotal": { "type": "number", "description": "Total before tax" },
+            "tax": { "type": "number", "description": "Total tax" },
+            "total_amount": { "type": "number", "description": "Amount due" },
+            "line_items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "description": string("What was sold"),
+                        "quantity": { "type": "number" },
+                        "unit_price": { "type": "number" },
+                        "amount": { "type": "number" }
+                    }
+                }
+            }
+        },
+        "required": ["invoice_number", "invoice_date", "vendor", "total_amount"]
+    })
+}
+
+fn resume_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "name": string("Full name of the candidate"),
+            "email": string("Email address"),
+            "phone": string("Phone number"),
+            "location": string("City and country"),
+            "summary": string("Professional summary or objective, if any"),
+            "skills": { "type": "array", "items": { "type": "string" } },
+            "experience": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "employer": { "type": "string" },
+                        "title": { "type": "string" },
+                        "start_date": { "type": "string" },
+                        "end_date": string("End date, or 'present'")
+                    }
+                }
+            },
+            "education": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "institution": { "type": "string" },
+                        "degree": { "type": "string" },
+                        "year": { "type": "string" }
+                    }
+                }
+            }
+        },
+        "required": ["name", "experience"]
+    })
+}
+
+fn contract_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "title": string("Title of the agreement, e.g. Master Services Agreement"),
+            "parties": { "type": "array", "items": { "type": "string" }, "description": "Names of the contracting parties" },
+            "effective_date": string("Date the agreement takes effect, as written"),
+            "term": string("Duration of the agreement and renewal terms"),
+            "termination": string("How and when either party may terminate"),
+            "governing_law": string("Jurisdiction whose law governs the agreement"),
+            "payment_terms": string("Fees, payment schedule, and late payment terms"),
+            "confidentiality": { "type": "boolean", "description": "Whether there is a confidentiality clause" }
+        },
+        "required": ["parties", "effective_date"]
+    })
+}
+
+fn research_paper_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "title": string("Title of the paper"),
+            "authors": { "type": "array", "items": { "type": "string" } },
+            "affiliations": { "type": "array", "items": { "type": "string" } },
+            "abstract": string("The abstract, in full"),
+            "keywords": { "type": "array", "items": { "type": "string" } },
+            "publication_date": string("Date of publication or submission, as printed"),
+            "venue": string("Journal, conference, or preprint server"),
+            "doi": string("DOI, if printed"),
+            "reference_count": { "type": "integer", "description": "Number of entries in the reference list" }
+        },
+        "required": ["title", "authors", "abstract"]
+    })
+}
@@ -0,0 +1,64 @@
+// Privacy mode (--privacy-mode): no document content (text, chunks, metadata
+// values, or snippets quoted in warnings) reaches logs, verbose dumps, or crash
+// reports, only hashes and counts. Everything that logs content passes it
+// through these functions, so the mode is enforced in one place.
+
+use crate::result::Warning;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// `text` as it may be logged: as is, or in privacy mode its hash and length
+pub fn redact(text: &str) -> Cow<'_, str> {
+    if !enabled() {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(fingerprint(text))
+}
+
+/// A request or response body as it may be logged: in privacy mode, JSON
+/// keeps its structure, numbers, and booleans, and every string is replaced
+/// by its hash and length
+pub fn redact_body(body: &str) -> Cow<'_, str> {
+    if !enabled() {
+        return Cow::Borrowed(body);
+    }
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_strings(&mut value);
+            Cow::Owned(serde_json::to_string_pretty(&value).unwrap_or_default())
+        }
+        Err(_) => Cow::Owned(fingerprint(body)),
+    }
+}
+
+/// A warning as it may be logged, `[code] message`; its code is kept in
+/// privacy mode, since codes never hold content
+pub fn warning(warning: &Warning) -> String {
+    format!("[{}] {}", warning.code, redact(&warning.message))
+}
+
+fn redact_strings(value: &mut Value) {
+    match value {
+        Value::String(text) => *text = fingerprint(text),
+        Value::Array(items) => items.iter_mut().for_each(redact_strings),
+        Value::Object(fields) => fields.values_mut().for_each(redact_strings),
+        _ => {}
+    }
+}
+
+fn fingerprint(text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    format!("<sha256:{}, {} chars>", &format!("{:x}", digest)[..12], text.chars().count())
+}
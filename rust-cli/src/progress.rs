@@ -0,0 +1,147 @@
+// Machine-readable batch progress for schedulers that can't read a terminal:
+// a JSON file rewritten every second (and when the batch ends) with counts,
+// percent, ETA, and the files currently being extracted.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report<'a> {
+    status: &'a str,
+    total: usize,
+    completed: usize,
+    successful: usize,
+    failed: usize,
+    percent: f64,
+    elapsed_secs: u64,
+    eta_secs: Option<u64>,
+    active_files: &'a [String],
+    updated_at: u64,
+}
+
+struct State {
+    total: usize,
+    successful: usize,
+    failed: usize,
+    active: Vec<String>,
+    started: Instant,
+}
+
+impl State {
+    fn write(&self, path: &Path, status: &str) -> Result<()> {
+        let completed = self.successful + self.failed;
+        let elapsed = self.started.elapsed();
+        // Average time per completed file so far, applied to the files left
+        let eta_secs = (completed > 0 && completed < self.total).then(|| {
+            (elapsed.as_secs_f64() / completed as f64 * (self.total - completed) as f64).round() as u64
+        });
+        let report = Report {
+            status,
+            total: self.total,
+            completed,
+            successful: self.successful,
+            failed: self.failed,
+            percent: if self.total == 0 { 100.0 } else { (completed as f64 * 1000.0 / self.total as f64).round() / 10.0 },
+            elapsed_secs: elapsed.as_secs(),
+            eta_secs: if completed == self.total { Some(0) } else { eta_secs },
+            active_files: &self.active,
+            updated_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        };
+
+        // Write then rename so readers never see a partial file
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&report)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Batch progress shared by the workers; does nothing without a path
+pub struct ProgressFile {
+    path: Option<PathBuf>,
+    state: Arc<Mutex<State>>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ProgressFile {
+    pub fn start(path: Option<&Path>, total: usize) -> Result<ProgressFile> {
+        let state = Arc::new(Mutex::new(State {
+            total,
+            successful: 0,
+            failed: 0,
+            active: Vec::new(),
+            started: Instant::now(),
+        }));
+        let Some(path) = path else {
+            return Ok(ProgressFile { path: None, state, stop: None, handle: None });
+        };
+
+        state.lock().unwrap().write(path, "running")
+            .context(format!("Failed to write progress file {}", path.display()))?;
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let (shared, file) = (Arc::clone(&state), path.to_path_buf());
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(WRITE_INTERVAL) {
+                let _ = shared.lock().unwrap().write(&file, "running");
+            }
+        });
+
+        Ok(ProgressFile {
+            path: Some(path.to_path_buf()),
+            state,
+            stop: Some(stop),
+            handle: Some(handle),
+        })
+    }
+
+    pub fn file_started(&self, name: &str) {
+        self.state.lock().unwrap().active.push(name.to_string());
+    }
+
+    pub fn file_finished(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(pos) = state.active.iter().position(|n| n == name) {
+            state.active.remove(pos);
+        }
+    }
+
+    pub fn set_counts(&self, successful: usize, failed: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.successful = successful;
+        state.failed = failed;
+    }
+
+    /// Stop the periodic writer and write the final state
+    pub fn finish(mut self) -> Result<()> {
+        self.stop_writer();
+        if let Some(path) = &self.path {
+            self.state.lock().unwrap().write(path, "finished")
+                .context(format!("Failed to write progress file {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn stop_writer(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for ProgressFile {
+    fn drop(&mut self) {
+        self.stop_writer();
+    }
+}
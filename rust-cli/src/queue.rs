@@ -0,0 +1,288 @@
+// Distributed batch runs: 'coordinator' serves the documents of a manifest as
+// a job queue, and 'worker' processes on any number of machines pull jobs from
+// it until the queue is empty, so a large corpus scales out without external
+// orchestration. Idle workers take the next pending job, so fast workers do
+// more of the work. Jobs are leased rather than handed out for good: a job
+// whose lease runs out (its worker died or hung) goes to the next worker that
+// asks, and a failed job is retried, up to --max-attempts.
+//
+//   POST /lease     {"worker": NAME, "max": N} -> {"jobs": [{"id", "source", "lease"}], "done": BOOL}
+//   POST /complete  {"id", "lease", "ok", "error"} -> {}
+//   GET  /status    -> {"total", "pending", "leased", "done", "failed"}
+//   GET  /health    -> {"status": "ok"}
+
+use crate::serve::{self, Rejection};
+use crate::theme::Themed;
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long a worker waits before asking again when every job is leased
+pub const WAIT: Duration = Duration::from_secs(2);
+// After the last job, how long workers have to learn the queue is done
+const LINGER: Duration = Duration::from_secs(30);
+// A worker started before its coordinator keeps trying this long
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A document leased to a worker
+#[derive(Serialize, Deserialize)]
+pub struct Job {
+    pub id: usize,
+    /// Path or URL, as the manifest lists it
+    pub source: String,
+    pub lease: String,
+}
+
+#[derive(Deserialize)]
+pub struct Lease {
+    pub jobs: Vec<Job>,
+    /// Every job is done or failed; the worker can stop
+    pub done: bool,
+}
+
+#[derive(Deserialize)]
+struct LeaseRequest {
+    worker: String,
+    max: usize,
+}
+
+#[derive(Deserialize)]
+struct Completion {
+    id: usize,
+    lease: String,
+    ok: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+enum State {
+    Pending,
+    Leased { lease: String, worker: String, expires: Instant },
+    Done,
+    Failed(String),
+}
+
+struct Entry {
+    source: String,
+    state: State,
+    attempts: u32,
+}
+
+pub struct Coordinator {
+    jobs: Vec<Entry>,
+    lease_timeout: Duration,
+    max_attempts: u32,
+    // Workers that asked for jobs, and those told the queue is done
+    workers: HashSet<String>,
+    dismissed: HashSet<String>,
+}
+
+impl Coordinator {
+    pub fn new(sources: Vec<String>, lease_timeout: Duration, max_attempts: u32) -> Coordinator {
+        Coordinator {
+            jobs: sources.into_iter().map(|source| Entry { source, state: State::Pending, attempts: 0 }).collect(),
+            lease_timeout,
+            max_attempts: max_attempts.max(1),
+            workers: HashSet::new(),
+            dismissed: HashSet::new(),
+        }
+    }
+
+    /// Serve the queue on `addr` until every job is done or failed and the
+    /// workers were told so; the sources that failed and why
+    pub fn run(mut self, addr: SocketAddr) -> Result<Vec<(String, String)>> {
+        let listener = TcpListener::bind(addr).context(format!("Failed to listen on {}", addr))?;
+        eprintln!(
+            "{} Serving {} jobs on {} (POST /lease, POST /complete, GET /status)",
+            style("[coordinator]").dim(),
+            self.jobs.len(),
+            style(format!("http://{}", listener.local_addr()?)).accent()
+        );
+        // Polled, so the queue can stop once it's finished
+        listener.set_nonblocking(true)?;
+        let mut finished_at = None;
+        loop {
+            if self.finished() {
+                let finished_at = *finished_at.get_or_insert_with(Instant::now);
+                if self.workers.is_subset(&self.dismissed) || finished_at.elapsed() > LINGER {
+                    break;
+                }
+            }
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    serve::answer_json(stream, |method, path, body| self.route(method, path, body));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(50)),
+                Err(e) => eprintln!("{} {}", style("[coordinator]").warning(), e),
+            }
+        }
+
+        Ok(self
+            .jobs
+            .into_iter()
+            .filter_map(|job| match job.state {
+                State::Failed(error) => Some((job.source, error)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn finished(&self) -> bool {
+        self.jobs.iter().all(|job| matches!(job.state, State::Done | State::Failed(_)))
+    }
+
+    fn route(&mut self, method: &str, path: &str, body: Value) -> Result<Value, Rejection> {
+        match (method, path) {
+            ("GET", "/health") => Ok(json!({ "status": "ok" })),
+            ("GET", "/status") => Ok(self.status()),
+            ("POST", "/lease") => Ok(self.lease(parse(body)?)),
+            ("POST", "/complete") => self.complete(parse(body)?).map(|()| json!({})),
+            (_, "/health" | "/status" | "/lease" | "/complete") => Err(Rejection::not_allowed(method)),
+            _ => Err(Rejection::not_found(path)),
+        }
+    }
+
+    fn status(&self) -> Value {
+        let count = |state: fn(&State) -> bool| self.jobs.iter().filter(|job| state(&job.state)).count();
+        json!({
+            "total": self.jobs.len(),
+            "pending": count(|s| matches!(s, State::Pending)),
+            "leased": count(|s| matches!(s, State::Leased { .. })),
+            "done": count(|s| matches!(s, State::Done)),
+            "failed": count(|s| matches!(s, State::Failed(_))),
+        })
+    }
+
+    fn lease(&mut self, request: LeaseRequest) -> Value {
+        self.workers.insert(request.worker.clone());
+        let now = Instant::now();
+        let mut jobs = Vec::new();
+        for (id, job) in self.jobs.iter_mut().enumerate() {
+            if jobs.len() >= request.max.max(1) {
+                break;
+            }
+            match &job.state {
+                State::Pending => {}
+                State::Leased { worker, expires, .. } if *expires <= now => {
+                    eprintln!(
+                        "{} {} took too long on {}",
+                        style("[coordinator]").warning(),
+                        style(worker).highlight(),
+                        job.source
+                    );
+                    if job.attempts >= self.max_attempts {
+                        job.state = State::Failed(format!("No worker finished it in {} attempts", job.attempts));
+                        continue;
+                    }
+                }
+                _ => continue,
+            }
+            let lease = Uuid::new_v4().to_string();
+            job.attempts += 1;
+            job.state = State::Leased { lease: lease.clone(), worker: request.worker.clone(), expires: now + self.lease_timeout };
+            jobs.push(Job { id, source: job.source.clone(), lease });
+        }
+
+        let done = jobs.is_empty() && self.finished();
+        if done {
+            self.dismissed.insert(request.worker);
+        }
+        json!({ "jobs": jobs, "done": done })
+    }
+
+    fn complete(&mut self, completion: Completion) -> Result<(), Rejection> {
+        let max_attempts = self.max_attempts;
+        let job = self
+            .jobs
+            .get_mut(completion.id)
+            .ok_or_else(|| Rejection::bad_request(format!("No such job: {}", completion.id)))?;
+        let State::Leased { lease, worker, .. } = &job.state else {
+            // Finished by another worker after its lease ran out
+            if completion.ok && matches!(job.state, State::Pending) {
+                job.state = State::Done;
+            }
+            return Ok(());
+        };
+        let worker = worker.clone();
+        if completion.ok {
+            eprintln!("{} {} {} ({})", style("[coordinator]").dim(), style("done").success(), job.source, worker);
+            job.state = State::Done;
+        } else if *lease == completion.lease {
+            let error = completion.error.unwrap_or_else(|| "Extraction failed".to_string());
+            eprintln!("{} {} {} ({}): {}", style("[coordinator]").warning(), style("failed").error(), job.source, worker, error);
+            job.state = match job.attempts >= max_attempts {
+                true => State::Failed(error),
+                false => State::Pending,
+            };
+        }
+        Ok(())
+    }
+}
+
+/// A worker's connection to its coordinator
+pub struct CoordinatorClient {
+    client: reqwest::blocking::Client,
+    url: String,
+    worker: String,
+}
+
+impl CoordinatorClient {
+    pub fn new(url: &str, worker: String) -> Result<CoordinatorClient> {
+        let client = reqwest::blocking::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(CoordinatorClient { client, url: url.trim_end_matches('/').to_string(), worker })
+    }
+
+    pub fn worker(&self) -> &str {
+        &self.worker
+    }
+
+    /// Up to `max` jobs; none, without `done`, while other workers hold the rest
+    pub fn lease(&self, max: usize) -> Result<Lease> {
+        let started = Instant::now();
+        loop {
+            match self.post("/lease", json!({ "worker": self.worker, "max": max })) {
+                Ok(response) => return Ok(serde_json::from_value(response)?),
+                Err(e) if started.elapsed() < CONNECT_TIMEOUT && is_connect_error(&e) => thread::sleep(WAIT),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Report how a job went; failed jobs are retried on another worker
+    pub fn complete(&self, job: &Job, outcome: Result<(), String>) -> Result<()> {
+        let body = match outcome {
+            Ok(()) => json!({ "id": job.id, "lease": job.lease, "ok": true }),
+            Err(error) => json!({ "id": job.id, "lease": job.lease, "ok": false, "error": error }),
+        };
+        self.post("/complete", body).map(|_| ())
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<Value> {
+        let url = format!("{}{}", self.url, path);
+        let response = self.client.post(&url).json(&body).send().context(format!("Failed to reach the coordinator at {}", self.url))?;
+        let status = response.status();
+        let body: Value = response.json().unwrap_or_default();
+        if !status.is_success() {
+            return Err(anyhow!("Coordinator answered {}: {}", status, body["error"].as_str().unwrap_or_default()));
+        }
+        Ok(body)
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: Value) -> Result<T, Rejection> {
+    serde_json::from_value(body).map_err(|e| Rejection::bad_request(format!("Invalid request: {}", e)))
+}
+
+fn is_connect_error(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<reqwest::Error>().is_some_and(reqwest::Error::is_connect)
+}
@@ -0,0 +1,707 @@
+// Boilerplate removal for downloaded web pages (--readability): the page is
+// parsed leniently, navigation, sidebars, ads, share bars, and footers are
+// dropped, and the element holding the article (an <article> or <main>, or
+// else the one with the most paragraph text) is written as Markdown or clean
+// HTML, which is uploaded instead of the raw page.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use reqwest::Url;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::coverage::decode_entities;
+use crate::encoding::{self, InputEncoding};
+
+/// What the article is uploaded as
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Format {
+    /// Markdown with headings, lists, links, and tables
+    #[default]
+    Markdown,
+    /// A minimal HTML document with only the article's structure
+    Html,
+}
+
+// Elements that are never part of an article
+const DROPPED: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "iframe", "form", "nav", "aside", "footer", "button",
+    "input", "select", "textarea", "canvas", "object", "embed", "dialog", "menu",
+];
+// Elements whose content is kept as-is
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title"];
+const VOID: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+const BLOCKS: &[&str] = &[
+    "address", "article", "blockquote", "div", "dl", "dd", "dt", "figure", "figcaption", "h1", "h2", "h3", "h4", "h5",
+    "h6", "header", "hr", "li", "main", "ol", "p", "pre", "section", "table", "tr", "ul",
+];
+// ARIA roles of page furniture
+const DROPPED_ROLES: &[&str] = &["navigation", "banner", "contentinfo", "complementary", "search", "dialog", "alert"];
+// Words of class and id names that mark boilerplate, unless a content word is also present
+const UNLIKELY: &[&str] = &[
+    "ad", "ads", "advert", "advertisement", "banner", "breadcrumb", "breadcrumbs", "comment", "comments", "cookie",
+    "cookies", "footer", "masthead", "menu", "nav", "navbar", "newsletter", "popup", "promo", "related", "share",
+    "sharing", "sidebar", "skip", "social", "sponsor", "sponsored", "subscribe", "widget",
+];
+const LIKELY: &[&str] = &["article", "body", "content", "entry", "main", "post", "story", "text"];
+// Paragraphs shorter than this don't count toward their container's score
+const MIN_PARAGRAPH: usize = 25;
+// Deepest nesting kept by the parser; elements opened below it are flattened
+// into their ancestor at this depth, which bounds the recursion of cleaning,
+// rendering, and dropping the tree on hostile pages
+const MAX_DEPTH: usize = 256;
+
+enum Node {
+    Text(String),
+    Element(Element),
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn elements(&self) -> impl DoubleEndedIterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+        self.collect_text(&mut text);
+        text
+    }
+
+    fn collect_text(&self, text: &mut String) {
+        let mut pending: Vec<&Node> = self.children.iter().rev().collect();
+        while let Some(node) = pending.pop() {
+            match node {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(element) => pending.extend(element.children.iter().rev()),
+            }
+        }
+    }
+
+    // Characters of visible text, ignoring whitespace runs
+    fn text_len(&self) -> usize {
+        self.text().split_whitespace().map(|word| word.chars().count() + 1).sum()
+    }
+
+    // Share of the text inside links
+    fn link_density(&self) -> f64 {
+        let total = self.text_len();
+        if total == 0 {
+            return 0.0;
+        }
+        let mut links = 0;
+        self.walk(&mut |element| {
+            if element.name == "a" {
+                links += element.text_len();
+            }
+        });
+        links as f64 / total as f64
+    }
+
+    // Visit the element and its descendants in document order
+    fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Element)) {
+        let mut pending = vec![self];
+        while let Some(element) = pending.pop() {
+            visit(element);
+            pending.extend(element.elements().rev());
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&Element> {
+        let mut found = None;
+        self.walk(&mut |element| {
+            if found.is_none() && element.name == name {
+                found = Some(element);
+            }
+        });
+        found
+    }
+}
+
+/// Whether a downloaded file is an HTML page, by its name or its first bytes
+pub fn is_html(path: &Path, content: &[u8]) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    if matches!(extension.as_deref(), Some("html" | "htm" | "xhtml")) {
+        return true;
+    }
+    let start = String::from_utf8_lossy(&content[..content.len().min(1024)]).trim_start().to_ascii_lowercase();
+    start.starts_with("<!doctype html") || start.contains("<html")
+}
+
+/// Replace a downloaded page with its article, written next to it as
+/// Markdown or HTML; other files, and pages without any text, are kept as
+/// they are. `url` resolves relative links.
+pub fn convert_file(path: &Path, url: &str, format: Format, encoding: InputEncoding) -> Result<PathBuf> {
+    let content = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    if !is_html(path, &content) {
+        return Ok(path.to_path_buf());
+    }
+    let (content, _) = encoding::to_utf8(content, encoding);
+    let Some(article) = article(&String::from_utf8_lossy(&content), Url::parse(url).ok().as_ref(), format) else {
+        return Ok(path.to_path_buf());
+    };
+    let converted = path.with_extension(match format {
+        Format::Markdown => "md",
+        Format::Html => "html",
+    });
+    fs::write(&converted, article).context(format!("Failed to write {}", converted.display()))?;
+    if converted != path {
+        let _ = fs::remove_file(path);
+    }
+    Ok(converted)
+}
+
+/// The article of an HTML page, or None when it has no text
+pub fn article(html: &str, base: Option<&Url>, format: Format) -> Option<String> {
+    let mut document = parse(html);
+    let title = document
+        .find("title")
+        .map(|title| collapse(&title.text()))
+        .filter(|title| !title.is_empty());
+    clean(&mut document);
+    let root = content_root(&document);
+    if root.text_len() == 0 {
+        return None;
+    }
+    // The page title heads an article without a headline of its own
+    let heading = title.as_ref().filter(|_| root.find("h1").is_none());
+    Some(match format {
+        Format::Markdown => {
+            let mut markdown = Markdown { base, out: String::new() };
+            if let Some(title) = heading {
+                markdown.out.push_str(&format!("# {}\n\n", title));
+            }
+            markdown.children(root);
+            tidy(&markdown.out)
+        }
+        Format::Html => {
+            let mut body = String::new();
+            if let Some(title) = heading {
+                body.push_str(&format!("<h1>{}</h1>\n", escape(title)));
+            }
+            html_children(root, base, &mut body);
+            format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head>\n<body><article>\n{}\n</article></body></html>\n",
+                escape(title.as_deref().unwrap_or_default()),
+                tidy(&body)
+            )
+        }
+    })
+}
+
+// A lenient parse into a tree: unknown and mismatched closing tags are
+// ignored, an open <p> or <li> ends where a sibling starts, and nesting is
+// cut off at MAX_DEPTH
+fn parse(html: &str) -> Element {
+    // ASCII lowercasing keeps byte offsets, so positions in one apply to the other
+    let lower = html.to_ascii_lowercase();
+    let mut stack = vec![Element { name: "#document".to_string(), attrs: Vec::new(), children: Vec::new() }];
+    // Names of the open elements that were flattened for being too deep
+    let mut flattened: Vec<String> = Vec::new();
+    let mut pos = 0;
+
+    // Attach the top of the stack to its parent
+    fn close(stack: &mut Vec<Element>) {
+        if stack.len() > 1 {
+            let element = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(Node::Element(element));
+        }
+    }
+    fn push_text(stack: &mut [Element], text: &str) {
+        if !text.is_empty() {
+            stack.last_mut().unwrap().children.push(Node::Text(decode_entities(text)));
+        }
+    }
+
+    while pos < html.len() {
+        let Some(lt) = html[pos..].find('<') else {
+            push_text(&mut stack, &html[pos..]);
+            break;
+        };
+        push_text(&mut stack, &html[pos..pos + lt]);
+        let start = pos + lt;
+        if lower[start..].starts_with("<!--") {
+            pos = lower[start..].find("-->").map_or(html.len(), |end| start + end + 3);
+            continue;
+        }
+        if !lower[start + 1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?') {
+            push_text(&mut stack, "<");
+            pos = start + 1;
+            continue;
+        }
+        let Some(end) = tag_end(&html[start..]) else { break };
+        let tag = &html[start + 1..start + end];
+        pos = start + end + 1;
+        if tag.starts_with(['!', '?']) {
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            if let Some(open) = flattened.iter().rposition(|open| *open == name) {
+                flattened.truncate(open);
+            } else if let Some(open) = stack.iter().rposition(|element| element.name == name) {
+                while stack.len() > open.max(1) {
+                    close(&mut stack);
+                }
+            }
+            continue;
+        }
+
+        let (name, attrs, self_closing) = parse_tag(tag);
+        let top = stack.last().map(|element| element.name.as_str()).unwrap_or_default();
+        if (top == "p" && BLOCKS.contains(&name.as_str())) || (top == "li" && name == "li") {
+            close(&mut stack);
+        }
+        if RAW_TEXT.contains(&name.as_str()) {
+            let close_at = lower[pos..].find(&format!("</{}", name)).map_or(html.len(), |end| pos + end);
+            let text = &html[pos..close_at];
+            pos = lower[close_at..].find('>').map_or(html.len(), |end| close_at + end + 1);
+            let children = match name.as_str() {
+                "title" => vec![Node::Text(decode_entities(text))],
+                _ => Vec::new(),
+            };
+            stack.last_mut().unwrap().children.push(Node::Element(Element { name, attrs, children }));
+            continue;
+        }
+        let element = Element { name, attrs, children: Vec::new() };
+        if self_closing || VOID.contains(&element.name.as_str()) {
+            stack.last_mut().unwrap().children.push(Node::Element(element));
+        } else if stack.len() >= MAX_DEPTH {
+            flattened.push(element.name);
+        } else {
+            stack.push(element);
+        }
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().unwrap()
+}
+
+// Offset of the > that ends the tag at the start of `html`, skipping quoted attribute values
+fn tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// The name, attributes, and whether it ends in />, of a start tag's content
+fn parse_tag(tag: &str) -> (String, Vec<(String, String)>, bool) {
+    let self_closing = tag.trim_end().ends_with('/');
+    let tag = tag.trim_end().trim_end_matches('/');
+    let split = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+    let name = tag[..split].to_ascii_lowercase();
+    let mut attrs = Vec::new();
+    let mut rest = tag[split..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let close = after[1..].find(q).map_or(after.len(), |i| i + 1);
+                    (&after[1..close], after.get(close + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = remaining.trim_start();
+        }
+        if !key.is_empty() {
+            attrs.push((key, value));
+        }
+    }
+    (name, attrs, self_closing)
+}
+
+// Remove page furniture everywhere in the tree
+fn clean(element: &mut Element) {
+    element.children.retain(|node| match node {
+        Node::Element(child) => !is_boilerplate(child),
+        Node::Text(_) => true,
+    });
+    for child in &mut element.children {
+        if let Node::Element(child) = child {
+            clean(child);
+        }
+    }
+}
+
+fn is_boilerplate(element: &Element) -> bool {
+    let name = element.name.as_str();
+    if DROPPED.contains(&name) {
+        return true;
+    }
+    // A page header is furniture; an article's header holds its headline
+    if name == "header" && element.find("h1").is_none() && element.find("h2").is_none() {
+        return true;
+    }
+    if element.attr("hidden").is_some() || element.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    if element.attr("style").is_some_and(|style| style.replace(' ', "").to_lowercase().contains("display:none")) {
+        return true;
+    }
+    if element.attr("role").is_some_and(|role| DROPPED_ROLES.contains(&role.to_lowercase().as_str())) {
+        return true;
+    }
+    if matches!(name, "html" | "body" | "article" | "main") {
+        return false;
+    }
+    let names = format!("{} {}", element.attr("class").unwrap_or_default(), element.attr("id").unwrap_or_default()).to_lowercase();
+    let words: Vec<&str> = names.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    words.iter().any(|word| UNLIKELY.contains(word)) && !words.iter().any(|word| LIKELY.contains(word))
+}
+
+// The element holding the article: the longest <article>, a <main>, or else
+// the container whose paragraphs score highest
+fn content_root(document: &Element) -> &Element {
+    let mut articles = Vec::new();
+    let mut main = None;
+    document.walk(&mut |element| {
+        if element.name == "article" || element.attr("itemprop") == Some("articleBody") {
+            articles.push(element);
+        } else if main.is_none() && (element.name == "main" || element.attr("role") == Some("main")) {
+            main = Some(element);
+        }
+    });
+    if let Some(article) = articles.into_iter().max_by_key(|article| article.text_len()).filter(|article| article.text_len() > 0) {
+        return article;
+    }
+    if let Some(main) = main.filter(|main| main.text_len() > 0) {
+        return main;
+    }
+
+    let mut best: Option<(&Element, f64)> = None;
+    document.walk(&mut |element| {
+        let mut score = 0.0;
+        for child in element.elements() {
+            score += paragraph_score(child);
+            for grandchild in child.elements() {
+                score += paragraph_score(grandchild) / 2.0;
+            }
+        }
+        let score = score * (1.0 - element.link_density());
+        if score > 0.0 && best.is_none_or(|(_, top)| score > top) {
+            best = Some((element, score));
+        }
+    });
+    best.map(|(element, _)| element)
+        .or_else(|| document.find("body"))
+        .unwrap_or(document)
+}
+
+fn paragraph_score(element: &Element) -> f64 {
+    if !matches!(element.name.as_str(), "p" | "pre" | "blockquote" | "td") {
+        return 0.0;
+    }
+    let text = element.text();
+    let len = text.split_whitespace().map(str::len).sum::<usize>();
+    if len < MIN_PARAGRAPH {
+        return 0.0;
+    }
+    1.0 + text.matches(',').count() as f64 + (len / 100).min(3) as f64
+}
+
+// Blocks inside the article that are mostly links, like lists of related
+// stories, which both renderers skip
+fn is_link_list(element: &Element) -> bool {
+    matches!(element.name.as_str(), "ul" | "ol" | "div" | "section" | "table")
+        && element.text_len() < 200
+        && element.link_density() > 0.5
+}
+
+// Renders the article as Markdown
+struct Markdown<'a> {
+    base: Option<&'a Url>,
+    out: String,
+}
+
+impl Markdown<'_> {
+    fn children(&mut self, element: &Element) {
+        for node in &element.children {
+            match node {
+                Node::Text(text) => self.text(text),
+                Node::Element(child) => self.element(child),
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let mut text = collapse_spaces(text);
+        if self.out.is_empty() || self.out.ends_with(['\n', ' ']) {
+            text = text.trim_start().to_string();
+        }
+        self.out.push_str(&text);
+    }
+
+    fn block(&mut self) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push_str(if self.out.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+    }
+
+    // Markdown of an element's content on its own
+    fn render(&self, element: &Element) -> String {
+        let mut inner = Markdown { base: self.base, out: String::new() };
+        inner.children(element);
+        tidy(&inner.out)
+    }
+
+    fn element(&mut self, element: &Element) {
+        if is_link_list(element) {
+            return;
+        }
+        match element.name.as_str() {
+            heading @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                let text = collapse(&element.text());
+                if !text.is_empty() {
+                    self.block();
+                    let level: usize = heading[1..].parse().unwrap_or(1);
+                    self.out.push_str(&format!("{} {}", "#".repeat(level), text));
+                    self.block();
+                }
+            }
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.block();
+                self.out.push_str("---");
+                self.block();
+            }
+            "pre" => {
+                self.block();
+                self.out.push_str(&format!("```\n{}\n```", element.text().trim_end()));
+                self.block();
+            }
+            "blockquote" => {
+                let inner = self.render(element);
+                if !inner.is_empty() {
+                    self.block();
+                    let quoted: Vec<String> = inner.lines().map(|line| format!("> {}", line).trim_end().to_string()).collect();
+                    self.out.push_str(&quoted.join("\n"));
+                    self.block();
+                }
+            }
+            list @ ("ul" | "ol") => {
+                self.block();
+                let mut number = 0;
+                for item in element.elements().filter(|child| child.name == "li") {
+                    number += 1;
+                    let marker = match list {
+                        "ol" => format!("{}. ", number),
+                        _ => "- ".to_string(),
+                    };
+                    let inner = self.render(item);
+                    let indent = " ".repeat(marker.len());
+                    for (i, line) in inner.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+                        let prefix = if i == 0 { marker.as_str() } else { indent.as_str() };
+                        self.out.push_str(&format!("{}{}\n", prefix, line));
+                    }
+                }
+                self.block();
+            }
+            "table" => {
+                let mut rows = Vec::new();
+                element.walk(&mut |row| {
+                    if row.name == "tr" {
+                        let cells: Vec<String> = row
+                            .elements()
+                            .filter(|cell| matches!(cell.name.as_str(), "td" | "th"))
+                            .map(|cell| collapse(&cell.text()).replace('|', "\\|"))
+                            .collect();
+                        if !cells.is_empty() {
+                            rows.push(cells);
+                        }
+                    }
+                });
+                if let Some(columns) = rows.iter().map(Vec::len).max() {
+                    self.block();
+                    for (i, row) in rows.iter().enumerate() {
+                        let mut cells = row.clone();
+                        cells.resize(columns, String::new());
+                        self.out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                        if i == 0 {
+                            self.out.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+                        }
+                    }
+                    self.block();
+                }
+            }
+            "a" => {
+                let text = collapse(&self.render(element));
+                match element.attr("href").and_then(|href| self.link(href)) {
+                    Some(href) if !text.is_empty() => self.out.push_str(&format!("[{}]({})", text, href)),
+                    _ => self.text(&text),
+                }
+            }
+            "img" => {
+                if let Some(src) = element.attr("src").and_then(|src| self.link(src)) {
+                    self.out.push_str(&format!("![{}]({})", element.attr("alt").unwrap_or_default(), src));
+                }
+            }
+            "strong" | "b" => self.inline(element, "**"),
+            "em" | "i" => self.inline(element, "*"),
+            "code" => self.inline(element, "`"),
+            name if BLOCKS.contains(&name) => {
+                self.block();
+                self.children(element);
+                self.block();
+            }
+            _ => self.children(element),
+        }
+    }
+
+    fn inline(&mut self, element: &Element, mark: &str) {
+        let text = collapse(&self.render(element));
+        if !text.is_empty() {
+            if !self.out.is_empty() && !self.out.ends_with([' ', '\n', '(']) && element.children.first().is_some_and(|n| matches!(n, Node::Text(t) if t.starts_with(char::is_whitespace))) {
+                self.out.push(' ');
+            }
+            self.out.push_str(&format!("{}{}{}", mark, text, mark));
+        }
+    }
+
+    fn link(&self, href: &str) -> Option<String> {
+        resolve(self.base, href)
+    }
+}
+
+// An absolute link, or None for in-page anchors and scripts
+fn resolve(base: Option<&Url>, href: &str) -> Option<String> {
+    let href = href.trim();
+    if href.is_empty() || href.starts_with('#') || href.to_lowercase().starts_with("javascript:") {
+        return None;
+    }
+    Some(match base.and_then(|base| base.join(href).ok()) {
+        Some(url) => url.to_string(),
+        None => href.to_string(),
+    })
+}
+
+// Elements kept in HTML output; others are replaced by their content
+const HTML_KEPT: &[&str] = &[
+    "h1", "h2", "h3", "h4", "h5", "h6", "p", "ul", "ol", "li", "a", "img", "strong", "b", "em", "i", "code", "pre",
+    "blockquote", "table", "thead", "tbody", "tr", "th", "td", "br", "hr", "figure", "figcaption", "dl", "dt", "dd",
+];
+
+fn html_children(element: &Element, base: Option<&Url>, out: &mut String) {
+    for node in &element.children {
+        match node {
+            Node::Text(text) if element.name == "pre" => out.push_str(&escape(text)),
+            Node::Text(text) => out.push_str(&escape(&collapse_spaces(text))),
+            Node::Element(child) => html_element(child, base, out),
+        }
+    }
+}
+
+fn html_element(element: &Element, base: Option<&Url>, out: &mut String) {
+    if is_link_list(element) {
+        return;
+    }
+    let name = element.name.as_str();
+    if !HTML_KEPT.contains(&name) {
+        let block = BLOCKS.contains(&name);
+        if block {
+            out.push('\n');
+        }
+        html_children(element, base, out);
+        if block {
+            out.push('\n');
+        }
+        return;
+    }
+    out.push('<');
+    out.push_str(name);
+    for attr in ["href", "src", "alt"] {
+        let value = match attr {
+            "alt" => element.attr(attr).map(str::to_string),
+            _ => element.attr(attr).and_then(|value| resolve(base, value)),
+        };
+        if let Some(value) = value {
+            out.push_str(&format!(" {}=\"{}\"", attr, escape(&value).replace('"', "&quot;")));
+        }
+    }
+    out.push('>');
+    if VOID.contains(&name) {
+        return;
+    }
+    html_children(element, base, out);
+    out.push_str(&format!("</{}>", name));
+    if BLOCKS.contains(&name) {
+        out.push('\n');
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Whitespace runs as single spaces, keeping the ends
+fn collapse_spaces(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !space {
+                out.push(' ');
+            }
+            space = true;
+        } else {
+            out.push(c);
+            space = false;
+        }
+    }
+    out
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Lines without trailing spaces and at most one blank line between blocks
+fn tidy(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank = 0;
+    for line in text.trim().lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank += 1;
+            if blank > 1 {
+                continue;
+            }
+        } else {
+            blank = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
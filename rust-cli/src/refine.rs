@@ -0,0 +1,92 @@
+// Targeted re-extraction of metadata fields the first pass left empty
+// (--refine-missing): the same upload is extracted again with a schema of only
+// the missing fields and instructions that name them, and whatever is found is
+// merged into the result, instead of re-running the whole document.
+
+use serde_json::{Map, Value};
+
+/// Fields of `schema` (a wrapped metadata schema) that `metadata` has no value
+/// for; JSON Schemas name their required fields, plain schemas require them all
+pub fn missing_fields(schema: &str, metadata: Option<&str>) -> Vec<String> {
+    let Ok(schema) = serde_json::from_str::<Value>(schema) else {
+        return Vec::new();
+    };
+    let metadata = metadata.and_then(|m| serde_json::from_str::<Value>(m).ok()).unwrap_or_default();
+    required_fields(&schema)
+        .into_iter()
+        .filter(|field| is_empty(metadata.get(field)))
+        .collect()
+}
+
+fn required_fields(schema: &Value) -> Vec<String> {
+    let document = schema.get("document").unwrap_or(schema);
+    let names = |fields: &Map<String, Value>| fields.keys().cloned().collect();
+    match (&document["required"], &document["properties"]) {
+        (Value::Array(required), _) => required.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        (_, Value::Object(properties)) => names(properties),
+        _ => document.as_object().map(names).unwrap_or_default(),
+    }
+}
+
+fn is_empty(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => true,
+        Some(Value::String(text)) => text.trim().is_empty(),
+        Some(Value::Array(items)) => items.is_empty(),
+        Some(Value::Object(fields)) => fields.is_empty(),
+        Some(_) => false,
+    }
+}
+
+/// `schema` cut down to `fields`, still wrapped in a 'document' key
+pub fn subset_schema(schema: &str, fields: &[String]) -> String {
+    let schema: Value = serde_json::from_str(schema).unwrap_or_default();
+    let document = schema.get("document").unwrap_or(&schema);
+    let pick = |all: &Value| -> Map<String, Value> {
+        fields.iter().filter_map(|field| Some((field.clone(), all.get(field)?.clone()))).collect()
+    };
+    let subset = match &document["properties"] {
+        Value::Object(_) => {
+            let mut subset = document.as_object().cloned().unwrap_or_default();
+            subset.insert("properties".to_string(), Value::Object(pick(&document["properties"])));
+            subset.insert("required".to_string(), fields.into());
+            Value::Object(subset)
+        }
+        _ => Value::Object(pick(document)),
+    };
+    serde_json::json!({ "document": subset }).to_string()
+}
+
+/// The caller's parsing instructions, with a request to look for `fields`
+pub fn instructions(base: Option<&str>, fields: &[String]) -> String {
+    let request = format!(
+        "A first pass over this document didn't find these fields: {}. Look for them carefully, including in tables, headers, footers, and small print, and fill in only values the document states.",
+        fields.join(", ")
+    );
+    match base {
+        Some(base) => format!("{}\n\n{}", base, request),
+        None => request,
+    }
+}
+
+/// Copy the values `refined` found for `fields` into `metadata`; the names of
+/// the fields that were filled
+pub fn merge(metadata: &mut Option<String>, refined: Option<&str>, fields: &[String]) -> Vec<String> {
+    let Some(Value::Object(refined)) = refined.and_then(|r| serde_json::from_str(r).ok()) else {
+        return Vec::new();
+    };
+    let mut merged = match metadata.as_deref().map(serde_json::from_str::<Value>) {
+        None => Map::new(),
+        Some(Ok(Value::Object(fields))) => fields,
+        // Metadata that isn't an object is reported by the metadata-invalid check
+        Some(_) => return Vec::new(),
+    };
+    let filled: Vec<String> = fields.iter().filter(|field| !is_empty(refined.get(*field))).cloned().collect();
+    for field in &filled {
+        merged.insert(field.clone(), refined[field].clone());
+    }
+    if !filled.is_empty() {
+        *metadata = Some(Value::Object(merged).to_string());
+    }
+    filled
+}
@@ -0,0 +1,220 @@
+// Comparing fresh extraction results with stored baselines, so a new version
+// of the service can be checked against a pinned corpus before it's adopted.
+
+use crate::coverage::words;
+use clap::Args;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// How far a result may drift from its baseline and still pass
+#[derive(Args, Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Tolerances {
+    /// Largest share of a document's words that may be added or removed, in percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 2.0)]
+    pub max_text_change: f64,
+
+    /// Largest change in a document's number of chunks, in percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 10.0)]
+    pub max_chunk_change: f64,
+
+    /// Number of metadata fields that may differ from the baseline
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub max_metadata_changes: usize,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// There is no baseline to compare with yet
+    New,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Pass => "PASS",
+            Outcome::Fail => "FAIL",
+            Outcome::New => "NEW",
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentReport {
+    pub source: String,
+    pub outcome: Outcome,
+    /// Share of words added or removed, in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_change: Option<f64>,
+    /// Change in the number of chunks, in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_change: Option<f64>,
+    /// Paths of the metadata fields that differ, such as `invoice.total`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub metadata_changes: Vec<String>,
+    /// Why the document failed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub tolerances: Tolerances,
+    pub passed: usize,
+    pub failed: usize,
+    pub new: usize,
+    pub documents: Vec<DocumentReport>,
+}
+
+impl Report {
+    pub fn new(tolerances: Tolerances, documents: Vec<DocumentReport>) -> Report {
+        let count = |outcome| documents.iter().filter(|d| d.outcome == outcome).count();
+        Report {
+            tolerances,
+            passed: count(Outcome::Pass),
+            failed: count(Outcome::Fail),
+            new: count(Outcome::New),
+            documents,
+        }
+    }
+}
+
+/// Compare the fresh result of `source` with its baseline; a missing fresh
+/// result means the extraction failed
+pub fn compare_document(source: &str, baseline: &Path, fresh: &Path, tolerances: &Tolerances) -> DocumentReport {
+    let mut report = DocumentReport {
+        source: source.to_string(),
+        outcome: Outcome::Fail,
+        text_change: None,
+        chunk_change: None,
+        metadata_changes: Vec::new(),
+        problems: Vec::new(),
+    };
+    if !fresh.exists() {
+        report.problems.push("extraction failed".to_string());
+        return report;
+    }
+    if !baseline.exists() {
+        report.outcome = Outcome::New;
+        report.problems.push("no baseline yet".to_string());
+        return report;
+    }
+    let (baseline, fresh) = match (read_result(baseline), read_result(fresh)) {
+        (Ok(baseline), Ok(fresh)) => (baseline, fresh),
+        (Err(e), _) | (_, Err(e)) => {
+            report.problems.push(e);
+            return report;
+        }
+    };
+
+    let text_change = text_change(text(&baseline), text(&fresh));
+    if text_change > tolerances.max_text_change {
+        report.problems.push(format!("text changed {:.2}% (max {}%)", text_change, tolerances.max_text_change));
+    }
+    report.text_change = Some(text_change);
+
+    let (before, after) = (chunk_count(&baseline), chunk_count(&fresh));
+    let chunk_change = before.abs_diff(after) as f64 * 100.0 / before.max(1) as f64;
+    if chunk_change > tolerances.max_chunk_change {
+        report.problems.push(format!(
+            "chunks changed {:.1}% ({} to {}, max {}%)",
+            chunk_change, before, after, tolerances.max_chunk_change
+        ));
+    }
+    report.chunk_change = Some(chunk_change);
+
+    report.metadata_changes = metadata_changes(&baseline, &fresh);
+    if report.metadata_changes.len() > tolerances.max_metadata_changes {
+        report.problems.push(format!(
+            "{} metadata fields changed (max {}): {}",
+            report.metadata_changes.len(),
+            tolerances.max_metadata_changes,
+            report.metadata_changes.join(", ")
+        ));
+    }
+
+    if report.problems.is_empty() {
+        report.outcome = Outcome::Pass;
+    }
+    report
+}
+
+fn read_result(path: &Path) -> Result<Value, String> {
+    let content = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    serde_json::from_slice(&content).map_err(|e| format!("{} is not a JSON result: {}", path.display(), e))
+}
+
+fn text(result: &Value) -> &str {
+    result["text"].as_str().unwrap_or_default()
+}
+
+fn chunk_count(result: &Value) -> usize {
+    result["chunks"].as_array().map_or(0, Vec::len)
+}
+
+// Words added or removed, as a share of the words of both texts; word order
+// isn't compared, so reflowed text doesn't count as a change
+pub fn text_change(before: &str, after: &str) -> f64 {
+    let (before, after) = (words(before), words(after));
+    let total = before.len() + after.len();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut counts: HashMap<String, i64> = HashMap::new();
+    for word in before {
+        *counts.entry(word).or_default() += 1;
+    }
+    for word in after {
+        *counts.entry(word).or_default() -= 1;
+    }
+    let changed: u64 = counts.values().map(|count| count.unsigned_abs()).sum();
+    (changed as f64 * 10000.0 / total as f64).round() / 100.0
+}
+
+// Paths of the metadata fields whose values differ; metadata that isn't JSON
+// is compared as a whole
+fn metadata_changes(baseline: &Value, fresh: &Value) -> Vec<String> {
+    let (before, after) = (metadata_fields(baseline), metadata_fields(fresh));
+    let mut paths: Vec<String> = before.keys().chain(after.keys()).cloned().collect();
+    paths.sort();
+    paths.dedup();
+    paths.retain(|path| before.get(path) != after.get(path));
+    paths
+}
+
+fn metadata_fields(result: &Value) -> BTreeMap<String, Value> {
+    let mut fields = BTreeMap::new();
+    // Results hold metadata as a JSON string, but baselines written by other tools may not
+    match &result["metadata"] {
+        Value::Null => {}
+        Value::String(metadata) => match serde_json::from_str::<Value>(metadata) {
+            Ok(metadata) => flatten("", &metadata, &mut fields),
+            Err(_) => {
+                fields.insert("metadata".to_string(), Value::String(metadata.clone()));
+            }
+        },
+        metadata => flatten("", metadata, &mut fields),
+    }
+    fields
+}
+
+fn flatten(prefix: &str, value: &Value, fields: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&path, value, fields);
+            }
+        }
+        value => {
+            fields.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
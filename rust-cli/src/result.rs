@@ -0,0 +1,65 @@
+// Extraction results as the CLI writes them: the service's data plus what the
+// CLI learned while producing it, such as where the text was spilled or the
+// warnings raised along the way. Serialized flat, so the annotations appear
+// next to the service's fields.
+
+use iris_client::ExtractionResultData;
+use serde::{Deserialize, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+use crate::coverage::Coverage;
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct AnnotatedResult {
+    #[serde(flatten)]
+    pub data: ExtractionResultData,
+    /// So outputs can be matched with platform-side logs
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "correlationId")]
+    pub correlation_id: Option<String>,
+    /// The extraction the result came from
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "extractionId")]
+    pub extraction_id: Option<String>,
+    /// Set when an oversized text was moved out of memory into a file
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "textFile")]
+    pub text_file: Option<PathBuf>,
+    /// How much of a text source file the text covers, with --verify-coverage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<Coverage>,
+    /// Non-fatal issues noticed while producing this result
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+}
+
+impl AnnotatedResult {
+    pub fn new(data: ExtractionResultData) -> AnnotatedResult {
+        AnnotatedResult { data, ..Default::default() }
+    }
+}
+
+impl Deref for AnnotatedResult {
+    type Target = ExtractionResultData;
+
+    fn deref(&self) -> &ExtractionResultData {
+        &self.data
+    }
+}
+
+impl DerefMut for AnnotatedResult {
+    fn deref_mut(&mut self) -> &mut ExtractionResultData {
+        &mut self.data
+    }
+}
+
+/// A non-fatal issue, such as a retried request, with a stable `code` for automation
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Warning {
+    pub code: String,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(code: &str, message: impl Into<String>) -> Warning {
+        Warning { code: code.to_string(), message: message.into() }
+    }
+}
@@ -0,0 +1,160 @@
+// Resolving tracked changes in .docx inputs before upload, so the service
+// extracts one version of the text instead of insertions and deletions mixed
+// together.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Revisions {
+    /// The text with every tracked change accepted
+    Accept,
+    /// The text as it was before the tracked changes
+    Reject,
+    /// Inserted and deleted text, both as plain text
+    Both,
+}
+
+impl Revisions {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Revisions::Accept => "accepted",
+            Revisions::Reject => "rejected",
+            Revisions::Both => "kept as text",
+        }
+    }
+}
+
+pub fn is_docx(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| matches!(e.to_lowercase().as_str(), "docx" | "docm"))
+}
+
+/// Rewrite a .docx with its tracked changes resolved; returns None when it has none
+pub fn resolve_file(path: &Path, mode: Revisions) -> Result<Option<Vec<u8>>> {
+    let file = File::open(path).context(format!("Failed to read file: {}", path.display()))?;
+    let mut archive = ZipArchive::new(file).context(format!("Not a valid .docx file: {}", path.display()))?;
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut changed = false;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        // The body, headers, footers, footnotes, and comments can all hold tracked changes
+        if !(entry.name().starts_with("word/") && entry.name().ends_with(".xml")) {
+            drop(entry);
+            writer.raw_copy_file(archive.by_index_raw(i)?)?;
+            continue;
+        }
+
+        let mut xml = Vec::new();
+        entry.read_to_end(&mut xml)?;
+        let (xml, part_changed) = resolve_xml(&xml, mode)
+            .context(format!("Failed to read {} in {}", entry.name(), path.display()))?;
+        changed |= part_changed;
+        writer.start_file(entry.name(), SimpleFileOptions::default().compression_method(CompressionMethod::Deflated))?;
+        writer.write_all(&xml)?;
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+    Ok(Some(writer.finish()?.into_inner()))
+}
+
+// What to do with an element of a document part
+#[derive(Clone, Copy)]
+enum Action {
+    Keep,
+    // Drop the element and everything in it
+    Drop,
+    // Drop the element's tags but keep what's in it
+    Unwrap,
+    Rename(&'static str),
+}
+
+fn action(name: &str, mode: Revisions) -> Action {
+    let Some(name) = name.strip_prefix("w:") else {
+        return Action::Keep;
+    };
+    match (name, mode) {
+        ("ins" | "moveTo", Revisions::Reject) => Action::Drop,
+        ("ins" | "moveTo", _) => Action::Unwrap,
+        ("del" | "moveFrom", Revisions::Accept) => Action::Drop,
+        ("del" | "moveFrom", _) => Action::Unwrap,
+        // Left over once the surrounding w:del is unwrapped
+        ("delText", _) => Action::Rename("w:t"),
+        ("delInstrText", _) => Action::Rename("w:instrText"),
+        // Earlier formatting and move markers don't affect the text
+        (
+            "rPrChange" | "pPrChange" | "sectPrChange" | "tblPrChange" | "tblPrExChange" | "trPrChange"
+            | "tcPrChange" | "tblGridChange" | "numberingChange" | "moveFromRangeStart" | "moveFromRangeEnd"
+            | "moveToRangeStart" | "moveToRangeEnd",
+            _,
+        ) => Action::Drop,
+        _ => Action::Keep,
+    }
+}
+
+/// Resolve the tracked changes of one document part; also returns whether it had any
+fn resolve_xml(xml: &[u8], mode: Revisions) -> Result<(Vec<u8>, bool)> {
+    let mut reader = Reader::from_reader(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+    // What was done with each open element, and how deep we are inside a dropped one
+    let mut open: Vec<Action> = Vec::new();
+    let mut skipping = 0;
+    let mut changed = false;
+
+    loop {
+        let event = reader.read_event()?;
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(start) => {
+                let action = action(start.name().into_inner(), mode);
+                match action {
+                    Action::Keep => writer.write_event(Event::Start(start))?,
+                    Action::Drop => skipping = 1,
+                    Action::Unwrap => {}
+                    Action::Rename(name) => writer.write_event(Event::Start(renamed(&start, name)))?,
+                }
+                changed |= matches!(action, Action::Drop | Action::Unwrap);
+                if !matches!(action, Action::Drop) {
+                    open.push(action);
+                }
+            }
+            Event::End(end) => match open.pop() {
+                Some(Action::Unwrap) => {}
+                Some(Action::Rename(name)) => writer.write_event(Event::End(BytesEnd::new(name)))?,
+                _ => writer.write_event(Event::End(end))?,
+            },
+            Event::Empty(empty) => match action(empty.name().into_inner(), mode) {
+                Action::Keep => writer.write_event(Event::Empty(empty))?,
+                Action::Drop | Action::Unwrap => changed = true,
+                Action::Rename(name) => writer.write_event(Event::Empty(renamed(&empty, name)))?,
+            },
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok((writer.into_inner(), changed))
+}
+
+fn renamed(element: &BytesStart, name: &'static str) -> BytesStart<'static> {
+    BytesStart::new(name).with_attributes(element.attributes().flatten())
+}
@@ -0,0 +1,243 @@
+// Cron-style scheduling for batch runs, so a recurring corpus refresh lives in
+// one long-running process instead of a crontab entry wrapped in a lockfile:
+// runs never overlap, a second process on the same output directory is
+// refused, and a run missed while the process was down is made up on start.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Datelike, Duration as TimeDelta, Local, NaiveDate, TimeZone, Timelike};
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, TryLockError};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::theme::Themed;
+
+// Kept in the output directory, next to the results they describe
+const STATE_FILE: &str = ".vectorize-iris-schedule.json";
+const LOCK_FILE: &str = ".vectorize-iris-schedule.lock";
+
+// Long enough to find February 29 across the gap between leap years at a century
+const SEARCH_DAYS: u32 = 366 * 8 + 1;
+// Sleep in short steps, so a suspended machine or a clock change is noticed
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// The values one field matches, as a bit set
+#[derive(Clone, Copy, Debug)]
+struct Field {
+    bits: u64,
+    // False for fields starting with *, which matters for day of month and weekday
+    restricted: bool,
+}
+
+impl Field {
+    fn has(&self, value: u32) -> bool {
+        self.bits & (1u64 << value) != 0
+    }
+}
+
+/// A five-field cron expression (minute, hour, day of month, month, day of
+/// week), evaluated in local time
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    expression: String,
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+pub fn parse_schedule(value: &str) -> Result<Schedule, String> {
+    let expression = value.trim();
+    let expanded = match expression {
+        "@yearly" | "@annually" => "0 0 1 1 *",
+        "@monthly" => "0 0 1 * *",
+        "@weekly" => "0 0 * * 0",
+        "@daily" | "@midnight" => "0 0 * * *",
+        "@hourly" => "0 * * * *",
+        _ => expression,
+    };
+    let invalid = |reason: String| {
+        format!("invalid schedule '{}': {} (expected cron fields like '0 2 * * *', or @daily)", value, reason)
+    };
+
+    let fields: Vec<&str> = expanded.split_whitespace().collect();
+    let [minutes, hours, days, months, weekdays] = fields[..] else {
+        return Err(invalid(format!("expected 5 fields, found {}", fields.len())));
+    };
+    let mut weekdays = parse_field(weekdays, 0, 7, WEEKDAYS).map_err(invalid)?;
+    // Both 0 and 7 are Sunday
+    if weekdays.has(7) {
+        weekdays.bits = (weekdays.bits | 1) & !(1 << 7);
+    }
+    let schedule = Schedule {
+        expression: expression.to_string(),
+        minutes: parse_field(minutes, 0, 59, &[]).map_err(invalid)?,
+        hours: parse_field(hours, 0, 23, &[]).map_err(invalid)?,
+        days: parse_field(days, 1, 31, &[]).map_err(invalid)?,
+        months: parse_field(months, 1, 12, MONTHS).map_err(invalid)?,
+        weekdays,
+    };
+    if schedule.next_after(Local::now()).is_none() {
+        return Err(invalid("it never matches a date".to_string()));
+    }
+    Ok(schedule)
+}
+
+// A comma-separated list of *, values, and ranges, each with an optional /step
+fn parse_field(spec: &str, min: u32, max: u32, names: &[&str]) -> Result<Field, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let named = names.iter().position(|name| name.eq_ignore_ascii_case(text)).map(|i| min + i as u32);
+        match named.or_else(|| text.parse().ok()) {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            _ => Err(format!("'{}' is not a value from {} to {}", text, min, max)),
+        }
+    };
+
+    let mut bits = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("'{}' is not a valid step", step)),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // A single value with a step runs to the end of the range, as in 5/15
+            None if part.contains('/') => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if first > last {
+            return Err(format!("range '{}' runs backwards", range));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1u64 << value;
+        }
+    }
+    Ok(Field { bits, restricted: !spec.starts_with('*') })
+}
+
+impl Schedule {
+    pub fn as_str(&self) -> &str {
+        &self.expression
+    }
+
+    /// The first time the schedule matches after `after`
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let mut date = start.date();
+        for _ in 0..SEARCH_DAYS {
+            if self.matches_day(date) {
+                for (hour, minute) in (0..24).flat_map(|h| (0..60).map(move |m| (h, m))) {
+                    if !self.hours.has(hour) || !self.minutes.has(minute) {
+                        continue;
+                    }
+                    let time = date.and_hms_opt(hour, minute, 0)?;
+                    // Times skipped by a daylight saving change don't run; repeated ones run once
+                    match Local.from_local_datetime(&time).earliest() {
+                        Some(time) if time > after => return Some(time),
+                        _ => {}
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    // As in cron, a date matches either day field when both are restricted
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days.has(date.day());
+        let weekday = self.weekdays.has(date.weekday().num_days_from_sunday());
+        self.months.has(date.month())
+            && match (self.days.restricted, self.weekdays.restricted) {
+                (true, true) => day || weekday,
+                _ => day && weekday,
+            }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct State {
+    schedule: String,
+    /// When the last run finished, whether or not it succeeded
+    last_run: DateTime<Local>,
+}
+
+fn read_state(path: &Path) -> Option<State> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+/// Call `run` whenever `schedule` comes due, until the process is stopped.
+/// Runs that come due while one is in progress are skipped; when a run was
+/// missed since the last one recorded in `dir`, it starts right away.
+pub fn run_scheduled(schedule: &Schedule, dir: &Path, mut run: impl FnMut() -> Result<()>) -> Result<()> {
+    fs::create_dir_all(dir).context(format!("Failed to create output directory: {}", dir.display()))?;
+    let lock_path = dir.join(LOCK_FILE);
+    let lock = File::create(&lock_path).context(format!("Failed to create {}", lock_path.display()))?;
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Err(anyhow!("Another scheduled batch is already writing to {}", dir.display()));
+        }
+        Err(TryLockError::Error(e)) => return Err(e).context(format!("Failed to lock {}", lock_path.display())),
+    }
+
+    let state_path = dir.join(STATE_FILE);
+    let mut last_run = read_state(&state_path).map(|state| state.last_run);
+    let label = style("[schedule]").dim();
+    loop {
+        let now = Local::now();
+        let due = last_run
+            .and_then(|last| schedule.next_after(last))
+            .filter(|due| *due <= now)
+            .or_else(|| schedule.next_after(now))
+            .context("The schedule no longer matches any date")?;
+        if due <= now {
+            eprintln!("{} Catching up on the run missed at {}", label, due.format("%Y-%m-%d %H:%M"));
+        } else {
+            eprintln!("{} Next run at {} ({})", label, due.format("%Y-%m-%d %H:%M"), schedule.as_str());
+            sleep_until(due);
+        }
+
+        let started = Local::now();
+        if let Err(e) = run() {
+            eprintln!("{} Run failed: {:#}", style("[schedule]").error(), e);
+        }
+        let finished = Local::now();
+        let skipped = skipped_runs(schedule, started, finished);
+        if skipped > 0 {
+            eprintln!("{} Skipped {} run(s) that came due while this one was in progress", style("[schedule]").warning(), skipped);
+        }
+
+        let state = State { schedule: schedule.as_str().to_string(), last_run: finished };
+        fs::write(&state_path, serde_json::to_vec_pretty(&state)?)
+            .context(format!("Failed to write {}", state_path.display()))?;
+        last_run = Some(finished);
+    }
+}
+
+/// Sleep until `time`, in steps short enough to notice clock changes
+pub fn sleep_until(time: DateTime<Local>) {
+    while let Ok(remaining) = (time - Local::now()).to_std() {
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(MAX_SLEEP));
+    }
+}
+
+fn skipped_runs(schedule: &Schedule, started: DateTime<Local>, finished: DateTime<Local>) -> usize {
+    std::iter::successors(schedule.next_after(started), |time| schedule.next_after(*time))
+        .take_while(|time| *time <= finished)
+        .count()
+}
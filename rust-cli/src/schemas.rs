@@ -0,0 +1,80 @@
+// Named metadata schemas saved under the config directory ('schema save',
+// 'schema list'), referenced as --metadata-schema @NAME. The directory holds
+// one NAME.json per schema, so a team can keep it in a shared repository or
+// sync it between machines.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config;
+
+/// A saved schema
+pub struct Saved {
+    pub name: String,
+    pub path: PathBuf,
+    /// Top-level fields of the schema, under its `document` key if it has one
+    pub fields: Vec<String>,
+}
+
+pub fn dir() -> Result<PathBuf> {
+    Ok(config::config_dir().context("Could not determine home directory")?.join("schemas"))
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow!("Invalid schema name '{}': use letters, digits, - and _", name));
+    }
+    Ok(())
+}
+
+/// Save the JSON schema in `file` as NAME, replacing any schema of that name;
+/// true when one was replaced
+pub fn save(name: &str, file: &PathBuf) -> Result<(PathBuf, bool)> {
+    validate_name(name)?;
+    let content = fs::read_to_string(file).context(format!("Failed to read {}", file.display()))?;
+    let schema: serde_json::Value =
+        serde_json::from_str(&content).context(format!("Invalid JSON in {}", file.display()))?;
+    let dir = dir()?;
+    fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.json", name));
+    let replaced = path.exists();
+    fs::write(&path, serde_json::to_string_pretty(&schema)? + "\n").context(format!("Failed to write {}", path.display()))?;
+    Ok((path, replaced))
+}
+
+/// The saved schemas, by name
+pub fn list() -> Result<Vec<Saved>> {
+    let dir = dir()?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut saved: Vec<Saved> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_string();
+            let schema: Option<serde_json::Value> = fs::read_to_string(&path).ok().and_then(|json| serde_json::from_str(&json).ok());
+            let fields = schema
+                .as_ref()
+                .map(|schema| schema.get("document").unwrap_or(schema))
+                .and_then(|document| document.as_object())
+                .map(|fields| fields.keys().cloned().collect())
+                .unwrap_or_default();
+            Some(Saved { name, path, fields })
+        })
+        .collect();
+    saved.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(saved)
+}
+
+/// The JSON of the schema saved as NAME
+pub fn load(name: &str) -> Result<String> {
+    validate_name(name)?;
+    let path = dir()?.join(format!("{}.json", name));
+    let content = fs::read_to_string(&path)
+        .map_err(|_| anyhow!("No saved schema named '{}' (see 'vectorize-iris schema list')", name))?;
+    let schema: serde_json::Value =
+        serde_json::from_str(&content).context(format!("Invalid JSON in {}", path.display()))?;
+    Ok(schema.to_string())
+}
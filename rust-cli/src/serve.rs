@@ -0,0 +1,339 @@
+// HTTP server mode (serve): a small REST API in front of the extraction
+// pipeline, so teams can run the CLI as an internal extraction service.
+// POST /extract takes a file as multipart/form-data (field "file") or as the
+// raw request body, and answers with the same JSON as -o json; GET /health
+// answers {"status": "ok"} for load balancer checks.
+
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::theme::Themed;
+
+// Long enough for slow uploads, short enough that idle connections don't tie up a worker
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// A file posted to /extract
+pub struct Upload {
+    /// From the multipart filename or the file_name parameter
+    pub file_name: Option<String>,
+    pub content: Vec<u8>,
+    /// Query parameters and multipart text fields
+    pub params: HashMap<String, String>,
+}
+
+/// Why a request wasn't answered with a result
+pub struct Rejection {
+    status: u16,
+    message: String,
+}
+
+impl Rejection {
+    pub fn bad_request(message: impl Display) -> Rejection {
+        Rejection { status: 400, message: message.to_string() }
+    }
+
+    /// The extraction itself failed
+    pub fn failed(error: anyhow::Error) -> Rejection {
+        Rejection { status: 502, message: format!("{:#}", error) }
+    }
+
+    pub fn not_found(path: &str) -> Rejection {
+        Rejection { status: 404, message: format!("No such endpoint: {}", path) }
+    }
+
+    pub fn not_allowed(method: &str) -> Rejection {
+        Rejection { status: 405, message: format!("{} is not allowed here", method) }
+    }
+}
+
+/// Answer requests on `addr` with `workers` threads until the process is
+/// stopped; `extract` turns each upload into the JSON result
+pub fn serve(
+    addr: SocketAddr,
+    workers: usize,
+    max_upload: u64,
+    extract: impl Fn(Upload) -> Result<Value, Rejection> + Sync,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).context(format!("Failed to listen on {}", addr))?;
+    let local = listener.local_addr()?;
+    eprintln!(
+        "{} Listening on {} with {} workers (POST /extract, GET /health)",
+        style("[serve]").dim(),
+        style(format!("http://{}", local)).accent(),
+        workers
+    );
+
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(|| loop {
+                match listener.accept() {
+                    Ok((stream, peer)) => handle(stream, peer, max_upload, &extract),
+                    Err(e) => eprintln!("{} {}", style("[serve]").warning(), e),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    params: HashMap<String, String>,
+    headers: HashMap<String, String>,
+}
+
+fn handle(stream: TcpStream, peer: SocketAddr, max_upload: u64, extract: &impl Fn(Upload) -> Result<Value, Rejection>) {
+    let started = Instant::now();
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let mut reader = BufReader::new(&stream);
+    let (summary, response) = match read_request(&mut reader) {
+        Ok(request) => {
+            let summary = format!("{} {}", request.method, request.path);
+            (summary, route(request, &mut reader, max_upload, extract))
+        }
+        Err(e) => ("?".to_string(), Err(Rejection::bad_request(e))),
+    };
+    let (status, body) = match response {
+        Ok(body) => (200, body),
+        Err(rejection) => (rejection.status, json!({ "error": rejection.message })),
+    };
+
+    let label = match status {
+        200 => style("[serve]").dim(),
+        400..=499 => style("[serve]").warning(),
+        _ => style("[serve]").error(),
+    };
+    eprintln!("{} {} {} {} ({:.1}s)", label, peer.ip(), summary, status, started.elapsed().as_secs_f64());
+    let _ = write_response(reader.into_inner(), status, &body);
+}
+
+fn route(
+    request: Request,
+    reader: &mut BufReader<&TcpStream>,
+    max_upload: u64,
+    extract: &impl Fn(Upload) -> Result<Value, Rejection>,
+) -> Result<Value, Rejection> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Ok(json!({ "status": "ok" })),
+        ("POST", "/extract") => extract(read_upload(request, reader, max_upload)?),
+        (_, "/health" | "/extract") => Err(Rejection::not_allowed(&request.method)),
+        _ => Err(Rejection::not_found(&request.path)),
+    }
+}
+
+/// Answer one request of a small JSON API (the coordinator's) with `route`,
+/// which gets the method, path, and JSON body (null when there is none)
+pub fn answer_json(stream: TcpStream, route: impl FnOnce(&str, &str, Value) -> Result<Value, Rejection>) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let mut reader = BufReader::new(&stream);
+    let response = read_request(&mut reader).map_err(Rejection::bad_request).and_then(|request| {
+        let length: usize = request.headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+        if length > MAX_HEADER_BYTES {
+            return Err(Rejection { status: 413, message: format!("Request body of {} bytes is too large", length) });
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).map_err(Rejection::bad_request)?;
+        let body = match body.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_slice(&body).map_err(|e| Rejection::bad_request(format!("Invalid JSON: {}", e)))?,
+        };
+        route(&request.method, &request.path, body)
+    });
+    let (status, body) = match response {
+        Ok(body) => (200, body),
+        Err(rejection) => (rejection.status, json!({ "error": rejection.message })),
+    };
+    let _ = write_response(reader.into_inner(), status, &body);
+}
+
+fn read_request(reader: &mut BufReader<&TcpStream>) -> Result<Request> {
+    // The limit applies while reading, so one endless line can't fill memory
+    let mut head = reader.take(MAX_HEADER_BYTES as u64);
+    let mut line = String::new();
+    read_head_line(&mut head, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow!("Malformed request line: {}", line.trim()));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        params: parse_params(query),
+        headers: HashMap::new(),
+    };
+
+    loop {
+        line.clear();
+        read_head_line(&mut head, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            request.headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok(request)
+}
+
+// One line of the request line and headers; a line cut short by the limit
+// means they're too large
+fn read_head_line(head: &mut io::Take<&mut BufReader<&TcpStream>>, line: &mut String) -> Result<()> {
+    head.read_line(line)?;
+    if !line.ends_with('\n') && head.limit() == 0 {
+        return Err(anyhow!("Request headers are too large"));
+    }
+    Ok(())
+}
+
+fn read_upload(mut request: Request, reader: &mut BufReader<&TcpStream>, max_upload: u64) -> Result<Upload, Rejection> {
+    let Some(length) = request.headers.get("content-length") else {
+        return Err(Rejection { status: 411, message: "Content-Length is required".to_string() });
+    };
+    let length: u64 = length.parse().map_err(|_| Rejection::bad_request("Invalid Content-Length header"))?;
+    if length > max_upload {
+        return Err(Rejection {
+            status: 413,
+            message: format!("Upload of {} bytes is larger than the limit of {} bytes", length, max_upload),
+        });
+    }
+    // Clients such as curl wait for this before sending larger bodies
+    if request.headers.get("expect").is_some_and(|e| e.eq_ignore_ascii_case("100-continue")) {
+        let mut stream = *reader.get_ref();
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").map_err(Rejection::bad_request)?;
+    }
+    let mut body = vec![0; length as usize];
+    reader.read_exact(&mut body).map_err(|e| Rejection::bad_request(format!("Failed to read the upload: {}", e)))?;
+
+    let content_type = request.headers.get("content-type").map(String::as_str).unwrap_or_default();
+    let Some(boundary) = boundary(content_type) else {
+        let file_name = request.params.get("file_name").cloned();
+        return Ok(Upload { file_name, content: body, params: request.params });
+    };
+
+    let mut file = None;
+    for part in multipart_parts(&body, &boundary) {
+        match (part.name.as_deref(), part.file_name) {
+            (Some("file"), file_name) => file = Some((file_name, part.content.to_vec())),
+            (Some(name), None) => {
+                let value = String::from_utf8_lossy(part.content).into_owned();
+                request.params.insert(name.to_string(), value);
+            }
+            _ => {}
+        }
+    }
+    let (file_name, content) = file.ok_or_else(|| Rejection::bad_request("The form has no 'file' field"))?;
+    let file_name = file_name.or_else(|| request.params.get("file_name").cloned());
+    Ok(Upload { file_name, content, params: request.params })
+}
+
+// Query strings and urlencoded values, where + is a space
+fn parse_params(query: &str) -> HashMap<String, String> {
+    let decode = |s: &str| {
+        let s = s.replace('+', " ");
+        urlencoding::decode(&s).map(|d| d.into_owned()).unwrap_or(s)
+    };
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+// The boundary of a multipart/form-data content type
+fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("boundary").then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+struct Part<'a> {
+    name: Option<String>,
+    file_name: Option<String>,
+    content: &'a [u8],
+}
+
+fn multipart_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<Part<'a>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let Some(mut rest) = find(body, &delimiter).map(|start| &body[start + delimiter.len()..]) else {
+        return parts;
+    };
+    // After each delimiter: "--" at the end of the form, otherwise headers and content
+    while let Some(after) = rest.strip_prefix(b"\r\n") {
+        let Some(headers_end) = find(after, b"\r\n\r\n") else {
+            break;
+        };
+        let content_start = headers_end + 4;
+        let closing = [b"\r\n".as_slice(), &delimiter].concat();
+        let Some(content_end) = find(&after[content_start..], &closing).map(|end| content_start + end) else {
+            break;
+        };
+
+        let headers = String::from_utf8_lossy(&after[..headers_end]);
+        let disposition = headers
+            .lines()
+            .find_map(|line| line.split_once(':').filter(|(name, _)| name.trim().eq_ignore_ascii_case("content-disposition")))
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_default();
+        parts.push(Part {
+            name: disposition_param(&disposition, "name"),
+            file_name: disposition_param(&disposition, "filename"),
+            content: &after[content_start..content_end],
+        });
+        rest = &after[content_end + closing.len()..];
+    }
+    parts
+}
+
+// A parameter of a Content-Disposition header, such as name="file"
+fn disposition_param(disposition: &str, param: &str) -> Option<String> {
+    disposition.split(';').skip(1).find_map(|part| {
+        let (name, value) = part.split_once('=')?;
+        (name.trim() == param).then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, body: &Value) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Bad Gateway",
+    };
+    let body = serde_json::to_vec_pretty(body)?;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(stream.flush()?)
+}
@@ -0,0 +1,111 @@
+// Detached signatures of output files (--sign-output), for audit trails: each
+// result gets a minisign signature (Ed25519) next to it, and directory runs
+// keep a signed manifest with the SHA-256 of every output, so a compliance
+// process can verify results weren't altered after extraction.
+
+use crate::snapshot::hash_file;
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use minisign::{SecretKey, SecretKeyBox};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Written to the output directory of signed directory runs
+pub const MANIFEST_FILE: &str = "run-manifest.json";
+const SIGNATURE_EXTENSION: &str = "minisig";
+/// Password of an encrypted key; without it, the password is prompted for
+const PASSWORD_VAR: &str = "VECTORIZE_SIGNING_KEY_PASSWORD";
+
+pub struct Signer {
+    key: SecretKey,
+    // Hex key ID, as minisign prints it
+    key_id: String,
+}
+
+/// An output file and its signature
+pub struct Signed {
+    pub source: String,
+    pub output: PathBuf,
+    pub sha256: String,
+    pub signature: PathBuf,
+}
+
+// Entries of the manifest, keyed by output path relative to the output directory
+type Manifest = BTreeMap<String, ManifestEntry>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestEntry {
+    source: String,
+    sha256: String,
+    signature: String,
+    key_id: String,
+    signed_at: String,
+}
+
+impl Signer {
+    /// Load a minisign secret key, as written by 'minisign -G'
+    pub fn load(path: &Path) -> Result<Signer> {
+        let content = fs::read_to_string(path).context(format!("Failed to read signing key: {}", path.display()))?;
+        let invalid = |e: minisign::PError| anyhow!("Invalid signing key {}: {}", path.display(), e);
+        let key = match SecretKeyBox::from_string(&content).and_then(SecretKeyBox::into_unencrypted_secret_key) {
+            Ok(key) => key,
+            // Encrypted keys are the default of 'minisign -G'
+            Err(_) => SecretKeyBox::from_string(&content)
+                .and_then(|key| key.into_secret_key(env::var(PASSWORD_VAR).ok()))
+                .map_err(invalid)?,
+        };
+        let key_id = key.keynum().iter().rev().map(|byte| format!("{:02X}", byte)).collect();
+        Ok(Signer { key, key_id })
+    }
+
+    /// Sign the output file of `source`, writing FILE.minisig next to it
+    pub fn sign(&self, source: &str, output: &Path) -> Result<Signed> {
+        // Named pipes can't be read back
+        if !output.is_file() {
+            return Err(anyhow!("Can't sign {}: not a regular file", output.display()));
+        }
+        let sha256 = hash_file(output)?;
+        let file_name = output.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let trusted_comment = format!("timestamp:{}\tfile:{}\tsha256:{}", Utc::now().timestamp(), file_name, sha256);
+        let content = fs::File::open(output).context(format!("Failed to open {}", output.display()))?;
+        let signature = minisign::sign(None, &self.key, content, Some(&trusted_comment), Some("signature from vectorize-iris"))
+            .map_err(|e| anyhow!("Failed to sign {}: {}", output.display(), e))?;
+        let mut signature_path = output.as_os_str().to_owned();
+        signature_path.push(format!(".{}", SIGNATURE_EXTENSION));
+        let signature_path = PathBuf::from(signature_path);
+        fs::write(&signature_path, signature.into_string())
+            .context(format!("Failed to write signature: {}", signature_path.display()))?;
+        Ok(Signed { source: source.to_string(), output: output.to_path_buf(), sha256, signature: signature_path })
+    }
+
+    /// Add `signed` to the manifest in `out_dir`, keeping the entries of
+    /// earlier runs for other outputs, and sign it again
+    pub fn update_manifest(&self, out_dir: &Path, signed: &[Signed]) -> Result<()> {
+        let path = out_dir.join(MANIFEST_FILE);
+        let mut manifest: Manifest = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        let relative = |path: &Path| {
+            let path = path.strip_prefix(out_dir).unwrap_or(path);
+            path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+        };
+        let signed_at = Utc::now().to_rfc3339();
+        for file in signed {
+            manifest.insert(relative(&file.output), ManifestEntry {
+                source: file.source.clone(),
+                sha256: file.sha256.clone(),
+                signature: relative(&file.signature),
+                key_id: self.key_id.clone(),
+                signed_at: signed_at.clone(),
+            });
+        }
+        fs::write(&path, serde_json::to_vec_pretty(&manifest)?).context(format!("Failed to write {}", path.display()))?;
+        self.sign(MANIFEST_FILE, &path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,569 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use rusqlite::blob::ZeroBlob;
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::result::AnnotatedResult;
+use iris_client::{Entity, EntityKind};
+
+/// A destination that mirrors extracted chunks, such as a vector index feed
+pub trait Sink {
+    /// Record a document before its chunks are upserted; only sinks that keep
+    /// documents apart from their chunks need it
+    fn begin_document(&mut self, _document: &DocumentRecord) -> Result<()> {
+        Ok(())
+    }
+    fn upsert(&mut self, record: &ChunkRecord) -> Result<()>;
+    fn delete_prefix(&mut self, source: &str, prefix: &str) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+}
+
+/// Text of a record: in memory, or the file of a text over --max-result-size,
+/// which sinks copy from instead of loading
+#[derive(Clone, Copy)]
+pub enum Text<'a> {
+    Inline(&'a str),
+    File(&'a Path),
+}
+
+pub struct ChunkRecord<'a> {
+    pub id: String,
+    pub source: &'a str,
+    pub index: usize,
+    pub text: Text<'a>,
+    pub metadata: Option<&'a str>,
+    pub entities: Option<&'a [Entity]>,
+}
+
+pub struct DocumentRecord<'a> {
+    pub source: &'a str,
+    pub extraction_id: Option<&'a str>,
+    pub text: Option<Text<'a>>,
+    pub metadata: Option<&'a str>,
+    pub chunks: usize,
+}
+
+// Pass a text file to `write` in blocks
+fn copy_file(path: &Path, mut write: impl FnMut(&[u8]) -> io::Result<()>) -> Result<()> {
+    let mut file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).context(format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            return Ok(());
+        }
+        write(&buffer[..read])?;
+    }
+}
+
+// A text as a JSON string; bytes of multi-byte characters never need escaping,
+// so a file is escaped a block at a time
+fn write_json_string(writer: &mut impl Write, text: Text) -> Result<()> {
+    let path = match text {
+        Text::Inline(text) => return Ok(serde_json::to_writer(writer, text)?),
+        Text::File(path) => path,
+    };
+    writer.write_all(b"\"")?;
+    copy_file(path, |block| {
+        let mut start = 0;
+        for (i, &byte) in block.iter().enumerate() {
+            let escaped = match byte {
+                b'"' => "\\\"".to_string(),
+                b'\\' => "\\\\".to_string(),
+                b'\n' => "\\n".to_string(),
+                b'\r' => "\\r".to_string(),
+                b'\t' => "\\t".to_string(),
+                0x08 => "\\b".to_string(),
+                0x0c => "\\f".to_string(),
+                0..=0x1f => format!("\\u{:04x}", byte),
+                _ => continue,
+            };
+            writer.write_all(&block[start..i])?;
+            writer.write_all(escaped.as_bytes())?;
+            start = i + 1;
+        }
+        writer.write_all(&block[start..])
+    })?;
+    writer.write_all(b"\"")?;
+    Ok(())
+}
+
+/// Stable identifier for a source document, derived from its path within the corpus
+pub fn document_id(source: &str) -> String {
+    let digest = Sha256::digest(source.as_bytes());
+    format!("{:x}", digest)[..16].to_string()
+}
+
+/// Prefix shared by every chunk ID of a document
+pub fn chunk_id_prefix(source: &str) -> String {
+    format!("{}-", document_id(source))
+}
+
+/// Appends upsert/delete operations as JSON lines
+pub struct JsonlSink {
+    writer: BufWriter<File>,
+}
+
+impl JsonlSink {
+    pub fn open(path: PathBuf) -> Result<JsonlSink> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Failed to open sink file: {}", path.display()))?;
+        Ok(JsonlSink { writer: BufWriter::new(file) })
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl Sink for JsonlSink {
+    fn upsert(&mut self, record: &ChunkRecord) -> Result<()> {
+        // Field by field, so a text in a file is copied into the line
+        let writer = &mut self.writer;
+        writer.write_all(b"{\"op\":\"upsert\",\"id\":")?;
+        serde_json::to_writer(&mut *writer, &record.id)?;
+        writer.write_all(b",\"source\":")?;
+        serde_json::to_writer(&mut *writer, record.source)?;
+        write!(writer, ",\"index\":{},\"text\":", record.index)?;
+        write_json_string(writer, record.text)?;
+        if let Some(metadata) = record.metadata {
+            writer.write_all(b",\"metadata\":")?;
+            serde_json::to_writer(&mut *writer, metadata)?;
+        }
+        if let Some(entities) = record.entities {
+            writer.write_all(b",\"entities\":")?;
+            serde_json::to_writer(&mut *writer, entities)?;
+        }
+        writer.write_all(b"}\n")?;
+        Ok(())
+    }
+
+    fn delete_prefix(&mut self, source: &str, prefix: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct Delete<'a> {
+            op: &'static str,
+            source: &'a str,
+            #[serde(rename = "idPrefix")]
+            id_prefix: &'a str,
+        }
+        self.write_line(&Delete { op: "delete", source, id_prefix: prefix })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush sink")
+    }
+}
+
+/// Appends upsert/delete operations as CSV rows (op,id,source,index,text,metadata,
+/// then one column of entities per entity type)
+pub struct CsvSink {
+    writer: BufWriter<File>,
+}
+
+impl CsvSink {
+    pub fn open(path: PathBuf) -> Result<CsvSink> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Failed to open sink file: {}", path.display()))?;
+        let is_new = file.metadata().map(|m| m.len() == 0).unwrap_or(true);
+
+        let mut sink = CsvSink { writer: BufWriter::new(file) };
+        if is_new {
+            let mut header = vec!["op", "id", "source", "index", "text", "metadata"];
+            header.extend(entity_columns());
+            sink.write_row(&header)?;
+        }
+        Ok(sink)
+    }
+
+    fn write_row(&mut self, fields: &[&str]) -> Result<()> {
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        self.writer.write_all(row.join(",").as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+
+    // A text field; a file is always quoted, since it isn't read ahead to see if it needs to be
+    fn write_text(&mut self, text: Text) -> Result<()> {
+        let path = match text {
+            Text::Inline(text) => return Ok(self.writer.write_all(csv_field(text).as_bytes())?),
+            Text::File(path) => path,
+        };
+        let writer = &mut self.writer;
+        writer.write_all(b"\"")?;
+        copy_file(path, |block| {
+            for (i, part) in block.split(|byte| *byte == b'"').enumerate() {
+                if i > 0 {
+                    writer.write_all(b"\"\"")?;
+                }
+                writer.write_all(part)?;
+            }
+            Ok(())
+        })?;
+        writer.write_all(b"\"")?;
+        Ok(())
+    }
+}
+
+/// The entity columns of a CSV row, one per entity type
+pub fn entity_columns() -> [&'static str; EntityKind::ALL.len()] {
+    EntityKind::ALL.map(entity_column)
+}
+
+/// The texts of the entities of each type joined with "; ", in the order of
+/// `entity_columns`
+pub fn entity_fields(entities: &[Entity]) -> [String; EntityKind::ALL.len()] {
+    EntityKind::ALL.map(|kind| {
+        let texts: Vec<&str> = entities.iter().filter(|e| e.kind == kind).map(|e| e.text.as_str()).collect();
+        texts.join("; ")
+    })
+}
+
+fn entity_column(kind: EntityKind) -> &'static str {
+    match kind {
+        EntityKind::Person => "people",
+        EntityKind::Organization => "organizations",
+        EntityKind::Date => "dates",
+        EntityKind::Amount => "amounts",
+        EntityKind::Other => "other_entities",
+    }
+}
+
+/// Quote a field per RFC 4180 when it contains separators, quotes, or line breaks
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Sink for CsvSink {
+    fn upsert(&mut self, record: &ChunkRecord) -> Result<()> {
+        let index = record.index.to_string();
+        let entities = entity_fields(record.entities.unwrap_or_default());
+        let before: Vec<String> = ["upsert", &record.id, record.source, &index].iter().map(|f| csv_field(f)).collect();
+        self.writer.write_all(before.join(",").as_bytes())?;
+        self.writer.write_all(b",")?;
+        self.write_text(record.text)?;
+        let mut after = vec![record.metadata.unwrap_or("")];
+        after.extend(entities.iter().map(String::as_str));
+        for field in after {
+            self.writer.write_all(b",")?;
+            self.writer.write_all(csv_field(field).as_bytes())?;
+        }
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+
+    fn delete_prefix(&mut self, source: &str, prefix: &str) -> Result<()> {
+        // A delete row carries the ID prefix in the id column
+        let mut row = vec!["delete", prefix, source, "", "", ""];
+        row.extend(EntityKind::ALL.map(|_| ""));
+        self.write_row(&row)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush sink")
+    }
+}
+
+const SQLITE_SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE IF NOT EXISTS documents (
+        id INTEGER PRIMARY KEY,
+        source TEXT NOT NULL UNIQUE,
+        extraction_id TEXT,
+        text TEXT,
+        metadata TEXT,
+        chunks INTEGER NOT NULL,
+        extracted_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS chunks (
+        id TEXT PRIMARY KEY,
+        document INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+        chunk_index INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        metadata TEXT
+    );
+    CREATE TABLE IF NOT EXISTS metadata (
+        document INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+        chunk TEXT REFERENCES chunks(id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        value TEXT
+    );
+    CREATE INDEX IF NOT EXISTS chunks_document ON chunks (document, chunk_index);
+    CREATE INDEX IF NOT EXISTS metadata_key_value ON metadata (key, value);
+";
+
+/// Appends documents into a SQLite database with documents, chunks, and
+/// metadata tables; a re-extracted document replaces its earlier rows.
+/// Metadata rows are the top-level fields of the document's metadata (with no
+/// chunk) and of each chunk's own metadata. A text over --max-result-size is
+/// written straight from its file as a BLOB of UTF-8; read it with
+/// CAST(text AS TEXT)
+pub struct SqliteSink {
+    connection: Connection,
+    // Row ID and metadata of the document whose chunks are being upserted
+    document: Option<(i64, Option<String>)>,
+}
+
+impl SqliteSink {
+    pub fn open(path: PathBuf) -> Result<SqliteSink> {
+        let connection = Connection::open(&path)
+            .context(format!("Failed to open sink database: {}", path.display()))?;
+        connection.execute_batch(SQLITE_SCHEMA)
+            .context(format!("Failed to create tables in {}", path.display()))?;
+        Ok(SqliteSink { connection, document: None })
+    }
+
+    // Writes between flushes are one transaction, which SQLite needs to write quickly
+    fn begin(&self) -> Result<()> {
+        if self.connection.is_autocommit() {
+            self.connection.execute_batch("BEGIN")?;
+        }
+        Ok(())
+    }
+
+    // A text parameter: a file is inserted as zeros of its size, filled in by fill_text
+    fn text_param(text: Option<Text>) -> Result<Box<dyn ToSql + '_>> {
+        Ok(match text {
+            None => Box::new(None::<&str>),
+            Some(Text::Inline(text)) => Box::new(text),
+            Some(Text::File(path)) => {
+                let size = fs::metadata(path).context(format!("Failed to read {}", path.display()))?.len();
+                Box::new(ZeroBlob(i32::try_from(size).context(format!("{} is too large for SQLite", path.display()))?))
+            }
+        })
+    }
+
+    fn fill_text(&self, table: &str, row: i64, text: Option<Text>) -> Result<()> {
+        let Some(Text::File(path)) = text else {
+            return Ok(());
+        };
+        let mut blob = self.connection.blob_open("main", table, "text", row, false)?;
+        copy_file(path, |block| blob.write_all(block))
+    }
+
+    fn insert_metadata(&self, document: i64, chunk: Option<&str>, metadata: &str) -> Result<()> {
+        // Metadata that isn't a JSON object is only kept whole, in its table's metadata column
+        let Ok(Value::Object(fields)) = serde_json::from_str(metadata) else {
+            return Ok(());
+        };
+        for (key, value) in fields {
+            let value = match value {
+                Value::Null => None,
+                Value::String(text) => Some(text),
+                value => Some(value.to_string()),
+            };
+            self.connection.execute(
+                "INSERT INTO metadata (document, chunk, key, value) VALUES (?1, ?2, ?3, ?4)",
+                params![document, chunk, key, value],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn begin_document(&mut self, document: &DocumentRecord) -> Result<()> {
+        self.begin()?;
+        self.connection.execute(
+            "INSERT INTO documents (source, extraction_id, text, metadata, chunks, extracted_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                document.source,
+                document.extraction_id,
+                Self::text_param(document.text)?,
+                document.metadata,
+                document.chunks as i64,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+        let id = self.connection.last_insert_rowid();
+        self.fill_text("documents", id, document.text)?;
+        if let Some(metadata) = document.metadata {
+            self.insert_metadata(id, None, metadata)?;
+        }
+        self.document = Some((id, document.metadata.map(str::to_string)));
+        Ok(())
+    }
+
+    fn upsert(&mut self, record: &ChunkRecord) -> Result<()> {
+        let Some((document, document_metadata)) = &self.document else {
+            return Err(anyhow!("Chunk {} has no document", record.id));
+        };
+        // Chunks without metadata of their own are given the document's, which is already recorded
+        let metadata = record.metadata.filter(|metadata| Some(*metadata) != document_metadata.as_deref());
+        self.connection.execute(
+            "INSERT INTO chunks (id, document, chunk_index, text, metadata) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![record.id, document, record.index as i64, Self::text_param(Some(record.text))?, metadata],
+        )?;
+        self.fill_text("chunks", self.connection.last_insert_rowid(), Some(record.text))?;
+        if let Some(metadata) = metadata {
+            self.insert_metadata(*document, Some(&record.id), metadata)?;
+        }
+        Ok(())
+    }
+
+    fn delete_prefix(&mut self, source: &str, _prefix: &str) -> Result<()> {
+        self.begin()?;
+        // Chunks and metadata go with their document
+        self.connection.execute("DELETE FROM documents WHERE source = ?1", params![source])?;
+        self.document = None;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT").context("Failed to commit sink database")?;
+        }
+        Ok(())
+    }
+}
+
+/// All sinks configured for a run
+pub struct SinkSet {
+    sinks: Vec<Box<dyn Sink>>,
+    // Documents written since the last flush, and how many to buffer before flushing
+    pending: usize,
+    flush_every: usize,
+}
+
+impl SinkSet {
+    /// Build sinks from `TYPE:PATH` specs, flushing them every `flush_every` documents
+    pub fn from_specs(specs: &[String], flush_every: usize) -> Result<SinkSet> {
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        for spec in specs {
+            let (kind, target) = spec
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid sink '{}'. Expected TYPE:PATH", spec))?;
+            match kind {
+                "jsonl" => sinks.push(Box::new(JsonlSink::open(PathBuf::from(target))?)),
+                "csv" => sinks.push(Box::new(CsvSink::open(PathBuf::from(target))?)),
+                "sqlite" => sinks.push(Box::new(SqliteSink::open(PathBuf::from(target))?)),
+                #[cfg(feature = "parquet")]
+                "parquet" => sinks.push(Box::new(crate::columnar::ParquetSink::open(PathBuf::from(target))?)),
+                #[cfg(not(feature = "parquet"))]
+                "parquet" => {
+                    return Err(anyhow!("This build doesn't include the parquet sink; rebuild with 'cargo build --features parquet'"))
+                }
+                _ => return Err(anyhow!("Unknown sink type '{}'. Supported: jsonl, csv, sqlite, parquet", kind)),
+            }
+        }
+        Ok(SinkSet {
+            sinks,
+            pending: 0,
+            flush_every: flush_every.max(1),
+        })
+    }
+
+    /// Write buffered records to disk
+    pub fn flush(&mut self) -> Result<()> {
+        for sink in &mut self.sinks {
+            sink.flush()?;
+        }
+        self.pending = 0;
+        Ok(())
+    }
+
+    // Records are streamed as each document completes; flushing periodically
+    // bounds how much is lost if the run is killed
+    fn document_written(&mut self) -> Result<()> {
+        self.pending += 1;
+        if self.pending >= self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Replace all chunks of a document with the chunks of a new extraction
+    pub fn write_document(&mut self, source: &str, data: &AnnotatedResult) -> Result<()> {
+        if self.sinks.is_empty() {
+            return Ok(());
+        }
+
+        let prefix = chunk_id_prefix(source);
+        // Text over --max-result-size lives in a file, which sinks copy from
+        let text = match (&data.text, &data.text_file) {
+            (Some(text), _) => Some(Text::Inline(text)),
+            (None, Some(path)) => Some(Text::File(path)),
+            (None, None) => None,
+        };
+        let texts: Vec<Text> = match (&data.chunks, text) {
+            (Some(chunks), _) if !chunks.is_empty() => chunks.iter().map(|chunk| Text::Inline(chunk)).collect(),
+            (_, Some(text)) => vec![text],
+            _ => Vec::new(),
+        };
+
+        let document = DocumentRecord {
+            source,
+            extraction_id: data.extraction_id.as_deref(),
+            text,
+            metadata: data.metadata.as_deref(),
+            chunks: texts.len(),
+        };
+
+        for sink in &mut self.sinks {
+            // Drop chunks from a previous extraction so shrinking documents leave no stale IDs
+            sink.delete_prefix(source, &prefix)?;
+            sink.begin_document(&document)?;
+            for (index, text) in texts.iter().enumerate() {
+                let metadata = data
+                    .chunks_metadata
+                    .as_ref()
+                    .and_then(|m| m.get(index))
+                    .and_then(|m| m.as_deref())
+                    .or(data.metadata.as_deref());
+                // Like metadata, a chunk without entities of its own gets the document's
+                let entities = data
+                    .chunks_entities
+                    .as_ref()
+                    .and_then(|e| e.get(index))
+                    .or(data.entities.as_ref())
+                    .map(Vec::as_slice);
+                sink.upsert(&ChunkRecord {
+                    id: format!("{}{}", prefix, index),
+                    source,
+                    index,
+                    text: *text,
+                    metadata,
+                    entities,
+                })?;
+            }
+        }
+        self.document_written()
+    }
+
+    /// Remove every chunk that came from a document no longer in the source
+    pub fn delete_document(&mut self, source: &str) -> Result<()> {
+        let prefix = chunk_id_prefix(source);
+        for sink in &mut self.sinks {
+            sink.delete_prefix(source, &prefix)?;
+        }
+        self.document_written()
+    }
+}
+
+impl Drop for SinkSet {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
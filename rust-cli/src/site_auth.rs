@@ -0,0 +1,149 @@
+// Credentials for URL inputs behind login walls: the [sites."HOST"] sections
+// of the config file, the cookies of a Netscape cookies.txt file
+// (--cookie-jar) as exported by browsers and curl, and sessions saved with
+// 'login-web'. Each download only gets the credentials of its own host, so
+// public URLs in the same batch are fetched anonymously, and reqwest drops
+// them when a redirect leaves the host.
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::RequestBuilder;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::SiteCredentials;
+
+/// One cookie of a cookies.txt file
+struct Cookie {
+    domain: String,
+    include_subdomains: bool,
+    path: String,
+    secure: bool,
+    // Unix time; 0 for a session cookie
+    expires: i64,
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    fn matches(&self, url: &Url, host: &str, now: i64) -> bool {
+        let domain_matches = host == self.domain
+            || (self.include_subdomains && host.strip_suffix(&self.domain).is_some_and(|sub| sub.ends_with('.')));
+        domain_matches
+            && url.path().starts_with(&self.path)
+            && (!self.secure || url.scheme() == "https")
+            && (self.expires == 0 || self.expires > now)
+    }
+}
+
+#[derive(Default)]
+pub struct SiteAuth {
+    sites: BTreeMap<String, SiteCredentials>,
+    jar: Vec<Cookie>,
+}
+
+impl SiteAuth {
+    pub fn new(sites: BTreeMap<String, SiteCredentials>) -> SiteAuth {
+        SiteAuth { sites, jar: Vec::new() }
+    }
+
+    /// Add the cookies of a Netscape cookies.txt file
+    pub fn load_cookie_jar(&mut self, path: &Path) -> Result<()> {
+        let content = fs::read_to_string(path).context(format!("Failed to read cookie jar {}", path.display()))?;
+        for (i, line) in content.lines().enumerate() {
+            // Browsers mark HttpOnly cookies with a prefix on an otherwise commented-out line
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line).trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, include_subdomains, path, secure, expires, name, value] = fields[..] else {
+                return Err(anyhow!("Invalid cookie jar {} line {}: expected 7 tab-separated fields", path.display(), i + 1));
+            };
+            self.jar.push(Cookie {
+                // A leading dot is the old way of marking a cookie for subdomains
+                include_subdomains: include_subdomains.eq_ignore_ascii_case("TRUE") || domain.starts_with('.'),
+                domain: domain.trim_start_matches('.').to_lowercase(),
+                path: path.to_string(),
+                secure: secure.eq_ignore_ascii_case("TRUE"),
+                expires: expires.parse().unwrap_or(0),
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Send the cookies of a browser session (name=value; ...) to `domain` and its subdomains
+    pub fn add_session(&mut self, domain: &str, cookies: &str) {
+        for (name, value) in cookies.split("; ").filter_map(|pair| pair.split_once('=')) {
+            self.jar.push(Cookie {
+                domain: domain.to_lowercase(),
+                include_subdomains: true,
+                path: "/".to_string(),
+                secure: false,
+                expires: 0,
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+    }
+
+    /// Whether there are any credentials at all
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty() && self.jar.is_empty()
+    }
+
+    // The config section for a host: HOST:PORT, then HOST, then the closest *.DOMAIN
+    fn site(&self, url: &Url, host: &str) -> Option<&SiteCredentials> {
+        let with_port = url.port().map(|port| format!("{}:{}", host, port));
+        if let Some(site) = with_port.and_then(|key| self.sites.get(&key)) {
+            return Some(site);
+        }
+        if let Some(site) = self.sites.get(host) {
+            return Some(site);
+        }
+        let mut parent = host;
+        while let Some((_, rest)) = parent.split_once('.') {
+            if let Some(site) = self.sites.get(&format!("*.{}", rest)) {
+                return Some(site);
+            }
+            parent = rest;
+        }
+        None
+    }
+
+    /// Add the credentials for the host of `url` to a request for it
+    pub fn authorize(&self, url: &str, mut request: RequestBuilder) -> RequestBuilder {
+        if self.is_empty() {
+            return request;
+        }
+        let Ok(parsed) = Url::parse(url) else { return request };
+        let Some(host) = parsed.host_str().map(str::to_lowercase) else { return request };
+
+        let mut cookies: Vec<String> = Vec::new();
+        if let Some(site) = self.site(&parsed, &host) {
+            if let Some(token) = &site.bearer_token {
+                request = request.bearer_auth(token);
+            } else if let Some(username) = &site.username {
+                request = request.basic_auth(username, site.password.as_ref());
+            }
+            for (name, value) in &site.headers {
+                request = request.header(name, value);
+            }
+            cookies.extend(site.cookies.iter().map(|(name, value)| format!("{}={}", name, value)));
+        }
+        let now = chrono::Utc::now().timestamp();
+        cookies.extend(
+            self.jar
+                .iter()
+                .filter(|cookie| cookie.matches(&parsed, &host, now))
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value)),
+        );
+        if !cookies.is_empty() {
+            request = request.header(reqwest::header::COOKIE, cookies.join("; "));
+        }
+        request
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .context(format!("Failed to open file: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .context(format!("Failed to hash file: {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash many files in parallel, advancing `progress` as each one finishes
+pub fn hash_files(paths: &[PathBuf], progress: &ProgressBar) -> Vec<Result<String>> {
+    paths
+        .par_iter()
+        .map(|path| {
+            let hash = hash_file(path);
+            progress.inc(1);
+            hash
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileEntry {
+    pub hash: String,
+    pub size: u64,
+}
+
+/// Content hashes of every file under a directory, keyed by relative path
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub root: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+/// Differences between a stored snapshot and the current state of a directory
+pub struct ChangeSet {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Relative path with forward slashes so snapshots are portable across platforms
+pub fn relative_key(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Snapshot {
+    /// Hash `paths`, the files found under `root`
+    pub fn capture(root: &Path, paths: &[PathBuf], progress: &ProgressBar) -> Result<Snapshot> {
+        progress.set_length(paths.len() as u64);
+        let hashes = hash_files(paths, progress);
+
+        let mut files = BTreeMap::new();
+        for (path, hash) in paths.iter().zip(hashes) {
+            let size = fs::metadata(path)?.len();
+            files.insert(relative_key(root, path), FileEntry { hash: hash?, size });
+        }
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            root: root.display().to_string(),
+            created_at,
+            files,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read snapshot: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .context(format!("Invalid snapshot file: {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .context(format!("Failed to write snapshot: {}", path.display()))
+    }
+
+    /// Compare `self` (the older snapshot) against `current`
+    pub fn diff(&self, current: &Snapshot) -> ChangeSet {
+        let mut added = Vec::new();
+        let mut modified = Vec::new();
+        let mut removed = Vec::new();
+
+        for (path, entry) in &current.files {
+            match self.files.get(path) {
+                None => added.push(path.clone()),
+                Some(previous) if previous.hash != entry.hash => modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+
+        for path in self.files.keys() {
+            if !current.files.contains_key(path) {
+                removed.push(path.clone());
+            }
+        }
+
+        ChangeSet { added, modified, removed }
+    }
+}
+
+/// Delete record for a document that no longer exists in the source directory
+#[derive(Serialize)]
+struct Tombstone<'a> {
+    op: &'static str,
+    source: &'a str,
+    hash: Option<&'a str>,
+}
+
+/// Write one JSON line per removed document so downstream sinks can drop its chunks
+pub fn write_tombstones(path: &Path, previous: &Snapshot, removed: &[String]) -> Result<()> {
+    let mut content = String::new();
+    for source in removed {
+        let record = Tombstone {
+            op: "delete",
+            source,
+            hash: previous.files.get(source).map(|e| e.hash.as_str()),
+        };
+        content.push_str(&serde_json::to_string(&record)?);
+        content.push('\n');
+    }
+    fs::write(path, content)
+        .context(format!("Failed to write tombstones: {}", path.display()))
+}
@@ -0,0 +1,65 @@
+// Size guards for large results: oversized extracted text is moved out of
+// memory into its own file, which iris-client streams it into straight from
+// the response. Large response bodies are spooled by iris-client.
+
+use anyhow::{Context, Result};
+use iris_client::TextSpill;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Start of the names of every temporary file and directory, so ones left
+/// behind by a killed run can be found by 'vectorize-iris gc'
+pub const TEMP_PREFIX: &str = "vectorize-iris-";
+
+/// A temporary directory, removed when dropped
+pub fn temp_dir() -> Result<TempDir> {
+    tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .tempdir()
+        .context("Failed to create temporary directory")
+}
+
+/// Directory of texts over --max-result-size, which results point to; not a
+/// temporary directory, since the results outlive the run
+pub fn texts_dir() -> Result<PathBuf> {
+    Ok(crate::get_config_dir()?.join("texts"))
+}
+
+/// Where status checks stream texts over `max_size` bytes
+pub fn text_spill(max_size: u64) -> Result<TextSpill> {
+    Ok(TextSpill { max_size, dir: texts_dir()? })
+}
+
+/// Write oversized extracted text that is already in memory, such as a
+/// cached result's, to NAME.txt in the texts directory
+pub fn spill_text(text: &str, name: &str) -> Result<PathBuf> {
+    let dir = texts_dir()?;
+    fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.txt", name));
+    fs::write(&path, text).context(format!("Failed to write oversized text to {}", path.display()))?;
+    Ok(path)
+}
+
+/// Copy a spilled text file to `writer` without loading it into memory
+pub fn copy_text_file(path: &Path, writer: &mut (impl Write + ?Sized)) -> Result<u64> {
+    let mut file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    Ok(io::copy(&mut file, writer)?)
+}
+
+/// Parse sizes like `1048576`, `512KB`, `50MB`, or `2GB`
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid size unit in '{}' (use B, KB, MB, or GB)", value)),
+    };
+    number.checked_mul(multiplier).ok_or_else(|| "size too large".to_string())
+}
@@ -0,0 +1,37 @@
+// The single `RESULT ...` line printed at exit with --summary-line, so wrapper
+// scripts can grep one line instead of parsing the summary block.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::telemetry;
+
+static FILES: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// Count processed files (including failures) toward the summary line
+pub fn record(files: usize, failed: usize) {
+    FILES.fetch_add(files, Ordering::SeqCst);
+    FAILED.fetch_add(failed, Ordering::SeqCst);
+}
+
+/// `status` is ok, partial (some files failed), or error (the run itself failed)
+pub fn line(result: &anyhow::Result<()>, duration: Duration) -> String {
+    let failed = FAILED.load(Ordering::SeqCst);
+    let status = match result {
+        Err(_) => "error",
+        Ok(()) if failed > 0 => "partial",
+        Ok(()) => "ok",
+    };
+    let mut line = format!(
+        "RESULT status={} files={} failed={} duration={}s",
+        status,
+        FILES.load(Ordering::SeqCst),
+        failed,
+        duration.as_secs()
+    );
+    if let Err(e) = result {
+        line.push_str(&format!(" error={}", telemetry::categorize_error(e)));
+    }
+    line
+}
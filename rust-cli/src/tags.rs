@@ -0,0 +1,187 @@
+// Tags added to the metadata of every chunk (--tag, --tags-from-path), for
+// routing information such as tenant or date that isn't in the document.
+
+use crate::result::{AnnotatedResult, Warning};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::path::{Component, Path};
+
+/// Parse a `key=value` tag
+pub fn parse_tag(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("invalid tag '{}': expected KEY=VALUE", value)),
+    }
+}
+
+/// A path pattern such as `clients/{client}/{year}/` whose placeholders
+/// capture tags from the directories (and, without a trailing slash, the file
+/// name) of each input
+#[derive(Clone)]
+pub struct PathPattern {
+    segments: Vec<Vec<Part>>,
+    // Whether the last segment matches the file name rather than a directory
+    matches_file: bool,
+}
+
+#[derive(Clone)]
+enum Part {
+    Literal(String),
+    Capture(String),
+}
+
+/// Parse a --tags-from-path pattern
+pub fn parse_path_pattern(value: &str) -> Result<PathPattern, String> {
+    let segments: Vec<&str> = value.trim_matches('/').split('/').collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        return Err(format!("invalid path pattern '{}': empty segment", value));
+    }
+
+    let mut parsed = Vec::new();
+    let mut captures = 0;
+    for segment in segments {
+        let mut parts = Vec::new();
+        let mut rest = segment;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..].find('}').map(|i| open + i)
+                .ok_or_else(|| format!("invalid path pattern '{}': missing '}}'", value))?;
+            let name = &rest[open + 1..close];
+            if name.is_empty() {
+                return Err(format!("invalid path pattern '{}': empty {{}}", value));
+            }
+            if matches!(parts.last(), Some(Part::Capture(_))) && open == 0 {
+                return Err(format!("invalid path pattern '{}': placeholders must be separated by text", value));
+            }
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            parts.push(Part::Capture(name.to_string()));
+            captures += 1;
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        parsed.push(parts);
+    }
+    if captures == 0 {
+        return Err(format!("invalid path pattern '{}': no {{name}} placeholders", value));
+    }
+
+    Ok(PathPattern { segments: parsed, matches_file: !value.ends_with('/') })
+}
+
+impl PathPattern {
+    /// Tags captured from the first place in `path` the pattern matches
+    fn captures(&self, path: &Path) -> Option<Vec<(String, String)>> {
+        let components: Vec<String> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let (file_name, dirs) = components.split_last()?;
+
+        // The pattern may start at any directory, so absolute and relative inputs both match
+        (0..=dirs.len()).find_map(|start| {
+            let candidates: Vec<&String> = match self.matches_file {
+                true if dirs.len() - start + 1 == self.segments.len() => dirs[start..].iter().chain([file_name]).collect(),
+                false if dirs.len() - start >= self.segments.len() => dirs[start..start + self.segments.len()].iter().collect(),
+                _ => return None,
+            };
+            let mut tags = Vec::new();
+            for (parts, component) in self.segments.iter().zip(candidates) {
+                tags.extend(match_segment(parts, component)?);
+            }
+            Some(tags)
+        })
+    }
+}
+
+// Match one path component against literals and placeholders; each
+// placeholder takes text up to the next literal
+fn match_segment(parts: &[Part], mut text: &str) -> Option<Vec<(String, String)>> {
+    let mut tags = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Literal(literal) => text = text.strip_prefix(literal.as_str())?,
+            Part::Capture(name) => {
+                let end = match parts.get(i + 1) {
+                    Some(Part::Literal(next)) => text.find(next.as_str())?,
+                    _ => text.len(),
+                };
+                if end == 0 {
+                    return None;
+                }
+                tags.push((name.clone(), text[..end].to_string()));
+                text = &text[end..];
+            }
+        }
+    }
+    text.is_empty().then_some(tags)
+}
+
+pub struct Tags<'a> {
+    pub fixed: &'a [(String, String)],
+    pub from_path: Option<&'a PathPattern>,
+}
+
+impl Tags<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.from_path.is_none()
+    }
+
+    /// The result with this input's tags merged into the metadata of every
+    /// chunk (or of the document when there are no chunks); --tag values win
+    /// over captured ones, and both win over extracted metadata
+    pub fn apply<'r>(&self, result: &'r AnnotatedResult, path: &Path) -> Cow<'r, AnnotatedResult> {
+        if self.is_empty() {
+            return Cow::Borrowed(result);
+        }
+
+        let mut result = result.clone();
+        let mut tags = Map::new();
+        if let Some(pattern) = self.from_path {
+            match pattern.captures(path) {
+                Some(captured) => tags.extend(captured.into_iter().map(|(k, v)| (k, Value::String(v)))),
+                None => result.warnings.push(Warning::new(
+                    "tags-unmatched",
+                    format!("{} doesn't match --tags-from-path", path.display()),
+                )),
+            }
+        }
+        tags.extend(self.fixed.iter().map(|(k, v)| (k.clone(), Value::String(v.clone()))));
+        if tags.is_empty() {
+            return Cow::Owned(result);
+        }
+
+        match result.chunks.as_ref().map(Vec::len).filter(|&n| n > 0) {
+            Some(count) => {
+                // Chunks without metadata of their own fall back to the document's
+                let document = result.data.metadata.as_deref();
+                let metadata = result.data.chunks_metadata.get_or_insert_with(Vec::new);
+                metadata.resize(count, None);
+                for entry in metadata.iter_mut() {
+                    *entry = Some(merge(entry.as_deref().or(document), &tags));
+                }
+            }
+            None => result.metadata = Some(merge(result.metadata.as_deref(), &tags)),
+        }
+        Cow::Owned(result)
+    }
+}
+
+// Add tags to a metadata JSON object; metadata that isn't an object is kept under "metadata"
+fn merge(metadata: Option<&str>, tags: &Map<String, Value>) -> String {
+    let mut object = match metadata {
+        None => Map::new(),
+        Some(raw) => match serde_json::from_str::<Value>(raw) {
+            Ok(Value::Object(object)) => object,
+            Ok(other) => Map::from_iter([("metadata".to_string(), other)]),
+            Err(_) => Map::from_iter([("metadata".to_string(), Value::String(raw.to_string()))]),
+        },
+    };
+    object.extend(tags.clone());
+    Value::Object(object).to_string()
+}
@@ -0,0 +1,226 @@
+// Strictly opt-in usage statistics. Nothing is recorded until the user runs
+// `vectorize-iris telemetry enable`; events only contain flag names, the
+// command, the outcome, and a coarse error category - never file names,
+// URLs, credentials, or document content. Events are queued at the end of a
+// run and sent in the background while the next run works, so no run waits
+// on the telemetry endpoint.
+
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const DEFAULT_ENDPOINT: &str = "https://telemetry.vectorize.io/v1/cli-events";
+// Older events are dropped once the local queue grows beyond this
+const MAX_QUEUED_EVENTS: usize = 500;
+// A batch being sent that hasn't changed in this long was left by a run that
+// exited mid-send, and the next run sends it instead
+const ABANDONED_AFTER: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+struct TelemetryState {
+    enabled: bool,
+    #[serde(rename = "installId")]
+    install_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Event {
+    #[serde(rename = "installId")]
+    install_id: String,
+    version: String,
+    os: String,
+    arch: String,
+    command: String,
+    features: Vec<String>,
+    outcome: String,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "errorCategory")]
+    error_category: Option<String>,
+}
+
+fn state_path() -> Result<PathBuf> {
+    Ok(crate::get_config_dir()?.join("telemetry.json"))
+}
+
+fn queue_path() -> Result<PathBuf> {
+    Ok(crate::get_config_dir()?.join("telemetry-queue.jsonl"))
+}
+
+// Events taken off the queue by the run sending them
+fn sending_path() -> Result<PathBuf> {
+    Ok(crate::get_config_dir()?.join("telemetry-sending.jsonl"))
+}
+
+// Environment overrides win over the stored opt-in
+fn disabled_by_env() -> bool {
+    let off = |var: &str| {
+        env::var(var)
+            .map(|v| matches!(v.to_lowercase().as_str(), "0" | "false" | "off" | "no"))
+            .unwrap_or(false)
+    };
+    off("VECTORIZE_TELEMETRY") || env::var("DO_NOT_TRACK").map(|v| v == "1").unwrap_or(false)
+}
+
+fn read_state() -> Option<TelemetryState> {
+    let content = fs::read_to_string(state_path().ok()?).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_state(state: &TelemetryState) -> Result<()> {
+    fs::create_dir_all(crate::get_config_dir()?).context("Failed to create config directory")?;
+    fs::write(state_path()?, serde_json::to_string_pretty(state)?)
+        .context("Failed to write telemetry settings")
+}
+
+pub fn is_enabled() -> bool {
+    !disabled_by_env() && read_state().map(|s| s.enabled).unwrap_or(false)
+}
+
+pub fn enable() -> Result<()> {
+    let install_id = read_state()
+        .map(|s| s.install_id)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    write_state(&TelemetryState { enabled: true, install_id })
+}
+
+/// Turn telemetry off and delete anything still queued
+pub fn disable() -> Result<()> {
+    let install_id = read_state().map(|s| s.install_id).unwrap_or_default();
+    write_state(&TelemetryState { enabled: false, install_id })?;
+    for queue in [queue_path()?, sending_path()?] {
+        if queue.exists() {
+            fs::remove_file(&queue).context("Failed to delete telemetry queue")?;
+        }
+    }
+    Ok(())
+}
+
+pub struct Status {
+    pub enabled: bool,
+    pub disabled_by_env: bool,
+    pub queued: usize,
+    pub queue_path: Option<PathBuf>,
+}
+
+pub fn status() -> Status {
+    let queue = queue_path().ok();
+    let queued = queue
+        .as_ref()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .map(|c| c.lines().count())
+        .unwrap_or(0);
+    Status {
+        enabled: read_state().map(|s| s.enabled).unwrap_or(false),
+        disabled_by_env: disabled_by_env(),
+        queued,
+        queue_path: queue,
+    }
+}
+
+/// Coarse error category, derived without recording the error message itself
+pub fn categorize_error(error: &anyhow::Error) -> &'static str {
+    if error.chain().any(|e| e.downcast_ref::<reqwest::Error>().is_some()) {
+        return "network";
+    }
+    let message = format!("{:#}", error).to_lowercase();
+    if message.contains("policy violation") {
+        "policy"
+    } else if message.contains("access token") || message.contains("401") || message.contains("org id") {
+        "auth"
+    } else if message.contains("timed out") {
+        "timeout"
+    } else if message.contains("not found") || message.contains("invalid") {
+        "input"
+    } else {
+        "other"
+    }
+}
+
+/// Queue an event locally, to be sent by the next run; never fails the run
+pub fn record(command: &str, features: Vec<String>, error: Option<&anyhow::Error>) {
+    if !is_enabled() {
+        return;
+    }
+    let Some(state) = read_state() else {
+        return;
+    };
+
+    let event = Event {
+        install_id: state.install_id,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        os: env::consts::OS.to_string(),
+        arch: env::consts::ARCH.to_string(),
+        command: command.to_string(),
+        features,
+        outcome: if error.is_some() { "error" } else { "ok" }.to_string(),
+        error_category: error.map(|e| categorize_error(e).to_string()),
+    };
+
+    if let Ok(line) = serde_json::to_string(&event) {
+        let _ = enqueue(&[line]);
+    }
+}
+
+fn enqueue(lines: &[String]) -> Result<()> {
+    let path = queue_path()?;
+    fs::create_dir_all(crate::get_config_dir()?)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    lines.iter().try_for_each(|line| writeln!(file, "{}", line))?;
+    Ok(())
+}
+
+/// Send the events queued by earlier runs on a background thread, which the
+/// run doesn't wait for; whatever isn't delivered is queued again
+pub fn send_queued() {
+    if is_enabled() {
+        thread::spawn(|| {
+            let _ = flush();
+        });
+    }
+}
+
+fn flush() -> Result<()> {
+    // Moving the queue aside claims its events, so events this run queues
+    // meanwhile aren't lost, and concurrent runs don't send them twice
+    let path = sending_path()?;
+    match fs::metadata(&path).and_then(|m| m.modified()) {
+        Ok(modified) if SystemTime::now().duration_since(modified).unwrap_or_default() < ABANDONED_AFTER => return Ok(()),
+        Ok(_) => {}
+        Err(_) => fs::rename(queue_path()?, &path)?,
+    }
+    let content = fs::read_to_string(&path)?;
+    let mut events: Vec<serde_json::Value> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    if events.is_empty() {
+        return Ok(fs::remove_file(&path)?);
+    }
+    if events.len() > MAX_QUEUED_EVENTS {
+        events.drain(..events.len() - MAX_QUEUED_EVENTS);
+    }
+
+    let endpoint = env::var("VECTORIZE_TELEMETRY_URL").unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string());
+    let sent = Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()?
+        .post(&endpoint)
+        .json(&serde_json::json!({ "events": events }))
+        .send()
+        .map(|r| r.status().is_success())
+        .unwrap_or(false);
+
+    if !sent {
+        // Keep undelivered events for the next run, bounded in size
+        let kept: Vec<String> = events.iter().map(|e| e.to_string()).collect();
+        enqueue(&kept)?;
+    }
+    fs::remove_file(&path)?;
+    Ok(())
+}
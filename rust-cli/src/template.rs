@@ -0,0 +1,87 @@
+// User-defined output templates (--template FILE): results are rendered with a
+// Tera template instead of an --output format, so custom layouts (front matter
+// plus text, a report per document, ...) need no post-processing script. The
+// context is the result as -o json writes it, with `metadata` and
+// `chunksMetadata` parsed into objects, the full `text` even when it spilled
+// to a file, and the document's `source`.
+
+use anyhow::{anyhow, Context, Result};
+use crate::result::AnnotatedResult;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tera::Tera;
+
+const NAME: &str = "output";
+// Template extensions that don't say what the template renders
+const TEMPLATE_EXTENSIONS: &[&str] = &["tera", "j2", "jinja", "tmpl", "tpl"];
+
+pub struct OutputTemplate {
+    tera: Tera,
+    extension: String,
+}
+
+impl OutputTemplate {
+    /// Load and compile a template, so syntax errors fail the run before
+    /// anything is extracted
+    pub fn load(path: &Path) -> Result<OutputTemplate> {
+        let source = fs::read_to_string(path).context(format!("Failed to read template: {}", path.display()))?;
+        let mut tera = Tera::default();
+        tera.autoescape_on(Vec::new());
+        tera.add_raw_template(NAME, &source)
+            .map_err(|e| anyhow!("Invalid template {}: {}", path.display(), error_chain(&e)))?;
+        Ok(OutputTemplate { tera, extension: extension(path) })
+    }
+
+    /// Extension of result files rendered with this template: its own, or the
+    /// one before .tera in names like note.md.tera
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    pub fn render(&self, data: &AnnotatedResult, source: Option<&str>) -> Result<String> {
+        let mut result = serde_json::to_value(data)?;
+        if let Some(text_file) = &data.text_file {
+            let text = fs::read_to_string(text_file).context(format!("Failed to read {}", text_file.display()))?;
+            result["text"] = text.into();
+        }
+        result["metadata"] = parse_json(data.metadata.as_deref());
+        if let Some(chunks_metadata) = &data.chunks_metadata {
+            result["chunksMetadata"] = chunks_metadata.iter().map(|m| parse_json(m.as_deref())).collect();
+        }
+        result["source"] = source.into();
+
+        let context = tera::Context::from_value(result)?;
+        self.tera
+            .render(NAME, &context)
+            .map_err(|e| anyhow!("Failed to render template: {}", error_chain(&e)))
+    }
+}
+
+// Metadata that isn't JSON is kept as the string it is
+fn parse_json(value: Option<&str>) -> Value {
+    match value {
+        Some(text) => serde_json::from_str(text).unwrap_or_else(|_| text.into()),
+        None => Value::Null,
+    }
+}
+
+fn extension(path: &Path) -> String {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut parts: Vec<&str> = name.split('.').skip(1).collect();
+    if parts.last().is_some_and(|ext| TEMPLATE_EXTENSIONS.contains(ext)) {
+        parts.pop();
+    }
+    parts.last().map(|ext| ext.to_string()).unwrap_or_else(|| "txt".to_string())
+}
+
+// Tera puts the line and cause of an error in its sources
+fn error_chain(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
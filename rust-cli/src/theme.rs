@@ -0,0 +1,236 @@
+// Output styling: a named preset (--theme) refined by the [theme] section of
+// the config file, so the palette can be made readable on light terminals.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use console::{style, Color, Emoji, StyledObject};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, ValueEnum, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    /// Colors and emoji (the default)
+    #[default]
+    Classic,
+    /// No emoji, color only for success, warnings, and errors; suits light backgrounds
+    Minimal,
+    /// No color and ASCII-only output
+    Plain,
+}
+
+/// The `[theme]` section of the config file; every field overrides the preset
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub name: Option<ThemeName>,
+    /// Colors are names (`blue`, `magenta`, ...), 0-255 for the 256-color palette, or `none`
+    pub accent: Option<String>,
+    pub highlight: Option<String>,
+    pub success: Option<String>,
+    pub warning: Option<String>,
+    pub error: Option<String>,
+    pub emoji: Option<bool>,
+    pub divider: Option<String>,
+    /// Same as --ascii
+    pub ascii: Option<bool>,
+}
+
+pub struct Theme {
+    accent: Option<Color>,
+    highlight: Option<Color>,
+    success: Option<Color>,
+    warning: Option<Color>,
+    error: Option<Color>,
+    emoji: bool,
+    divider: String,
+    ascii: bool,
+}
+
+impl Theme {
+    fn preset(name: ThemeName) -> Theme {
+        match name {
+            ThemeName::Classic => Theme {
+                accent: Some(Color::Cyan),
+                highlight: Some(Color::Yellow),
+                success: Some(Color::Green),
+                warning: Some(Color::Yellow),
+                error: Some(Color::Red),
+                emoji: true,
+                divider: "─".to_string(),
+                ascii: false,
+            },
+            ThemeName::Minimal => Theme {
+                accent: None,
+                highlight: None,
+                success: Some(Color::Green),
+                warning: Some(Color::Magenta),
+                error: Some(Color::Red),
+                emoji: false,
+                divider: "─".to_string(),
+                ascii: false,
+            },
+            ThemeName::Plain => Theme {
+                accent: None,
+                highlight: None,
+                success: None,
+                warning: None,
+                error: None,
+                emoji: false,
+                divider: "-".to_string(),
+                ascii: true,
+            },
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Result<Option<Color>> {
+    let color = match value.trim().to_lowercase().as_str() {
+        "none" => return Ok(None),
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        other => Color::Color256(other.parse().map_err(|_| {
+            anyhow!("Invalid theme color '{}' (use a color name, 0-255, or none)", value)
+        })?),
+    };
+    Ok(Some(color))
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Select the theme for this run; `--theme` picks the preset over the config file,
+/// and `ascii` (from --ascii) restricts all output to ASCII on top of any theme
+pub fn init(name: Option<ThemeName>, ascii: bool, config: &ThemeConfig) -> Result<()> {
+    let name = name.or(config.name).unwrap_or_default();
+    let mut theme = Theme::preset(name);
+
+    for (slot, value) in [
+        (&mut theme.accent, &config.accent),
+        (&mut theme.highlight, &config.highlight),
+        (&mut theme.success, &config.success),
+        (&mut theme.warning, &config.warning),
+        (&mut theme.error, &config.error),
+    ] {
+        if let Some(value) = value {
+            *slot = parse_color(value)?;
+        }
+    }
+    if let Some(emoji) = config.emoji {
+        theme.emoji = emoji;
+    }
+    if let Some(divider) = config.divider.as_ref().filter(|d| !d.is_empty()) {
+        theme.divider = divider.clone();
+    }
+    if ascii || config.ascii == Some(true) {
+        theme.ascii = true;
+    }
+    if theme.ascii {
+        theme.emoji = false;
+        if !theme.divider.is_ascii() {
+            theme.divider = "-".to_string();
+        }
+    }
+
+    if matches!(name, ThemeName::Plain) {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
+fn current() -> &'static Theme {
+    THEME.get_or_init(|| Theme::preset(ThemeName::Classic))
+}
+
+fn paint<D>(object: StyledObject<D>, color: Option<Color>) -> StyledObject<D> {
+    match color {
+        Some(color) => object.fg(color),
+        None => object,
+    }
+}
+
+/// Theme colors for styled output, used in place of fixed `.cyan()`, `.red()`, ...
+pub trait Themed {
+    fn accent(self) -> Self;
+    fn highlight(self) -> Self;
+    fn success(self) -> Self;
+    fn warning(self) -> Self;
+    fn error(self) -> Self;
+}
+
+impl<D> Themed for StyledObject<D> {
+    fn accent(self) -> Self {
+        paint(self, current().accent)
+    }
+    fn highlight(self) -> Self {
+        paint(self, current().highlight)
+    }
+    fn success(self) -> Self {
+        paint(self, current().success)
+    }
+    fn warning(self) -> Self {
+        paint(self, current().warning)
+    }
+    fn error(self) -> Self {
+        paint(self, current().error)
+    }
+}
+
+/// A horizontal rule `width` characters wide
+pub fn divider(width: usize) -> StyledObject<String> {
+    let divider = &current().divider;
+    let rule: String = divider.chars().cycle().take(width).collect();
+    style(rule).dim()
+}
+
+/// `unicode`, or `ascii` when output is restricted to ASCII
+pub fn glyph(unicode: &'static str, ascii: &'static str) -> &'static str {
+    if current().ascii {
+        ascii
+    } else {
+        unicode
+    }
+}
+
+pub fn spinner_ticks() -> &'static [&'static str] {
+    if current().ascii {
+        &["|", "/", "-", "\\"]
+    } else {
+        &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]
+    }
+}
+
+/// Filled, current, and empty characters for progress bars
+pub fn progress_chars() -> &'static str {
+    glyph("━╸─", "=>-")
+}
+
+/// Color spec for indicatif templates, e.g. `.cyan` in `{spinner:.cyan}`
+pub fn template_color() -> String {
+    match current().accent {
+        Some(Color::Color256(n)) => format!(".{}", n),
+        Some(color) => format!(".{}", format!("{:?}", color).to_lowercase()),
+        None => String::new(),
+    }
+}
+
+/// An emoji with its text fallback, printed as the fallback when the theme turns emoji off
+pub struct Icon(pub Emoji<'static, 'static>);
+
+impl fmt::Display for Icon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if current().emoji {
+            self.0.fmt(f)
+        } else {
+            f.write_str(self.0 .1)
+        }
+    }
+}
@@ -0,0 +1,41 @@
+// TLS backend selection. Build with exactly one of the `rustls` (default),
+// `native-tls`, or `fips` features.
+
+use anyhow::Result;
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls", feature = "fips")))]
+compile_error!("enable one of the `rustls`, `native-tls`, or `fips` features to get a TLS backend");
+
+#[cfg(all(feature = "fips", feature = "native-tls"))]
+compile_error!("the `fips` feature uses rustls and cannot be combined with `native-tls`");
+
+#[cfg(all(feature = "fips", feature = "rustls"))]
+compile_error!("the `fips` feature replaces the ring provider of `rustls`; build it with --no-default-features");
+
+/// Install the process-wide crypto provider before any HTTP client is built
+pub fn init() -> Result<()> {
+    #[cfg(feature = "fips")]
+    {
+        use anyhow::anyhow;
+
+        let provider = rustls::crypto::default_fips_provider();
+        if !provider.fips() {
+            return Err(anyhow!("The FIPS crypto provider is not operating in FIPS mode"));
+        }
+        provider
+            .install_default()
+            .map_err(|_| anyhow!("Failed to install the FIPS crypto provider"))?;
+    }
+    Ok(())
+}
+
+/// Human-readable name of the TLS stack compiled into this binary
+pub fn backend() -> &'static str {
+    if cfg!(feature = "fips") {
+        "rustls (FIPS, aws-lc-rs)"
+    } else if cfg!(feature = "native-tls") {
+        "native-tls"
+    } else {
+        "rustls (ring)"
+    }
+}
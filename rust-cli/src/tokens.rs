@@ -0,0 +1,153 @@
+// Token-based chunk sizing (--chunk-unit tokens): --chunk-size and
+// --chunk-overlap count tokens of a local tiktoken encoding instead of
+// characters, so chunks fit an embedding model's input budget. The service
+// sizes chunks in characters, so it's asked for about CHARS_PER_TOKEN
+// characters per token, and chunks still over the budget are split here on
+// token boundaries.
+
+use iris_client::{Entity, ExtractionResultData};
+use serde::{Deserialize, Deserializer};
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as Encoding};
+use tiktoken_rs::CoreBPE;
+
+/// Characters per token of English text in the OpenAI encodings
+pub const CHARS_PER_TOKEN: u32 = 4;
+
+const ENCODINGS: &[(&str, Encoding)] = &[
+    ("o200k_base", Encoding::O200kBase),
+    ("cl100k_base", Encoding::Cl100kBase),
+    ("p50k_base", Encoding::P50kBase),
+    ("r50k_base", Encoding::R50kBase),
+    ("gpt2", Encoding::Gpt2),
+];
+
+/// A tiktoken encoding, named directly or by a model that uses it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tokenizer(Encoding);
+
+impl Default for Tokenizer {
+    // The encoding of OpenAI's text-embedding-3 and ada-002 models
+    fn default() -> Tokenizer {
+        Tokenizer(Encoding::Cl100kBase)
+    }
+}
+
+pub fn parse_tokenizer(value: &str) -> Result<Tokenizer, String> {
+    let name = value.trim();
+    ENCODINGS
+        .iter()
+        .find(|(encoding, _)| encoding.eq_ignore_ascii_case(name))
+        .map(|(_, encoding)| *encoding)
+        .or_else(|| get_tokenizer(name))
+        .map(Tokenizer)
+        .ok_or_else(|| {
+            let names: Vec<&str> = ENCODINGS.iter().map(|(name, _)| *name).collect();
+            format!("unknown tokenizer '{}' (expected {}, or a model name like text-embedding-3-small)", value, names.join(", "))
+        })
+}
+
+impl<'de> Deserialize<'de> for Tokenizer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tokenizer, D::Error> {
+        parse_tokenizer(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Tokenizer {
+    fn bpe(&self) -> &'static CoreBPE {
+        match self.0 {
+            Encoding::O200kBase => tiktoken_rs::o200k_base_singleton(),
+            Encoding::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+            Encoding::P50kBase => tiktoken_rs::p50k_base_singleton(),
+            Encoding::P50kEdit => tiktoken_rs::p50k_edit_singleton(),
+            Encoding::R50kBase | Encoding::Gpt2 => tiktoken_rs::r50k_base_singleton(),
+        }
+    }
+
+    /// The encoding's tiktoken name, such as cl100k_base
+    pub fn name(&self) -> &'static str {
+        match self.0 {
+            Encoding::O200kBase => "o200k_base",
+            Encoding::Cl100kBase => "cl100k_base",
+            Encoding::P50kBase => "p50k_base",
+            Encoding::P50kEdit => "p50k_edit",
+            Encoding::R50kBase => "r50k_base",
+            Encoding::Gpt2 => "gpt2",
+        }
+    }
+
+    /// Number of tokens in `text`
+    pub fn count(&self, text: &str) -> usize {
+        self.bpe().encode_ordinary(text).len()
+    }
+
+    /// Split every chunk longer than `size` tokens into pieces of at most
+    /// `size` tokens, each repeating the last `overlap` tokens of the one
+    /// before. Pieces keep their chunk's metadata and schema, and the entities
+    /// found in their text.
+    pub fn split_chunks(&self, data: &mut ExtractionResultData, size: u32, overlap: u32) {
+        let Some(chunks) = data.chunks.take() else { return };
+        let mut split = Vec::with_capacity(chunks.len());
+        let mut metadata = data.chunks_metadata.as_ref().map(|_| Vec::new());
+        let mut schema = data.chunks_schema.as_ref().map(|_| Vec::new());
+        let mut entities = data.chunks_entities.as_ref().map(|_| Vec::new());
+        for (i, chunk) in chunks.iter().enumerate() {
+            for piece in self.split(chunk, size as usize, overlap as usize) {
+                if let (Some(all), Some(split)) = (&data.chunks_metadata, &mut metadata) {
+                    split.push(all.get(i).cloned().flatten());
+                }
+                if let (Some(all), Some(split)) = (&data.chunks_schema, &mut schema) {
+                    split.push(all.get(i).cloned().flatten());
+                }
+                if let (Some(all), Some(split)) = (&data.chunks_entities, &mut entities) {
+                    let found: Vec<Entity> = all.get(i).into_iter().flatten().filter(|e| piece.contains(&e.text)).cloned().collect();
+                    split.push(found);
+                }
+                split.push(piece.to_string());
+            }
+        }
+        data.chunks = Some(split);
+        data.chunks_metadata = metadata;
+        data.chunks_schema = schema;
+        data.chunks_entities = entities;
+    }
+
+    fn split<'a>(&self, text: &'a str, size: usize, overlap: usize) -> Vec<&'a str> {
+        let bpe = self.bpe();
+        let tokens = bpe.encode_ordinary(text);
+        if tokens.len() <= size {
+            return vec![text];
+        }
+        // Byte offset where each token starts; the tokens spell out the text exactly
+        let mut offsets = Vec::with_capacity(tokens.len() + 1);
+        offsets.push(0);
+        for bytes in bpe._decode_native_and_split(tokens.clone()) {
+            offsets.push(offsets.last().unwrap() + bytes.len());
+        }
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = (start + size).min(tokens.len());
+            // A token can end inside a multi-byte character; cut before it
+            let from = char_boundary(text, offsets[start]);
+            let mut to = char_boundary(text, offsets[end]);
+            if to <= from {
+                to = text[from..].chars().next().map_or(text.len(), |c| from + c.len_utf8());
+            }
+            pieces.push(&text[from..to]);
+            if end == tokens.len() {
+                return pieces;
+            }
+            start = end.saturating_sub(overlap).max(start + 1);
+        }
+    }
+}
+
+// The start of the character at or before byte `index`
+fn char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
@@ -0,0 +1,177 @@
+// Client-side transformations applied to extracted text before it is written
+// to outputs or sinks.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use unicode_normalization::{is_nfc_quick, is_nfkc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::tokens::Tokenizer;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum UnicodeForm {
+    /// Canonical composition (é stays é, ﬁ stays ﬁ)
+    Nfc,
+    /// Compatibility composition (ﬁ becomes fi, full-width letters become ASCII)
+    Nfkc,
+}
+
+/// Normalize `text` in place; text that is already normalized is left untouched
+pub fn normalize_unicode(text: &mut String, form: UnicodeForm) {
+    let already = match form {
+        UnicodeForm::Nfc => is_nfc_quick(text.chars()),
+        UnicodeForm::Nfkc => is_nfkc_quick(text.chars()),
+    };
+    if already == IsNormalized::Yes {
+        return;
+    }
+
+    *text = match form {
+        UnicodeForm::Nfc => text.nfc().collect(),
+        UnicodeForm::Nfkc => text.nfkc().collect(),
+    };
+}
+
+/// Normalize a text file in place, a line at a time, for texts too large to
+/// load; a line break never combines with the characters around it
+pub fn normalize_unicode_file(path: &Path, form: UnicodeForm) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut normalized = tempfile::NamedTempFile::new_in(dir).context(format!("Failed to create a file in {}", dir.display()))?;
+    {
+        let mut reader = BufReader::new(File::open(path).context(format!("Failed to open {}", path.display()))?);
+        let mut writer = BufWriter::new(normalized.as_file_mut());
+        let mut line = String::new();
+        while reader.read_line(&mut line).context(format!("Failed to read {}", path.display()))? > 0 {
+            normalize_unicode(&mut line, form);
+            writer.write_all(line.as_bytes())?;
+            line.clear();
+        }
+        writer.flush()?;
+    }
+    normalized.persist(path).context(format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+#[derive(Clone, Copy, ValueEnum, Default, PartialEq)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    /// Keep line endings exactly as extracted
+    #[default]
+    Preserve,
+}
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Rewrites line endings and drops a leading byte order mark while streaming
+/// text into `inner`, so large outputs are never copied as a whole
+pub struct TextWriter<W: Write> {
+    inner: W,
+    line_endings: LineEndings,
+    // Leading bytes held back until we know whether they are a BOM
+    head: Option<Vec<u8>>,
+    pending_cr: bool,
+    last: Option<u8>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> TextWriter<W> {
+    pub fn new(inner: W, strip_bom: bool, line_endings: LineEndings) -> TextWriter<W> {
+        TextWriter {
+            inner,
+            line_endings,
+            head: strip_bom.then(Vec::new),
+            pending_cr: false,
+            last: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn convert(&mut self, input: &[u8]) -> io::Result<()> {
+        if self.line_endings == LineEndings::Preserve {
+            return self.inner.write_all(input);
+        }
+
+        self.buffer.clear();
+        for &byte in input {
+            match (self.line_endings, byte) {
+                (LineEndings::Lf, b'\r') => {
+                    if self.pending_cr {
+                        self.buffer.push(b'\r');
+                    }
+                    self.pending_cr = true;
+                }
+                (LineEndings::Lf, _) => {
+                    // A CR directly before LF is dropped; a lone CR is kept
+                    if self.pending_cr && byte != b'\n' {
+                        self.buffer.push(b'\r');
+                    }
+                    self.pending_cr = false;
+                    self.buffer.push(byte);
+                }
+                (_, b'\n') if self.last != Some(b'\r') => self.buffer.extend_from_slice(b"\r\n"),
+                _ => self.buffer.push(byte),
+            }
+            self.last = Some(byte);
+        }
+        self.inner.write_all(&self.buffer)
+    }
+
+    fn release_head(&mut self) -> io::Result<()> {
+        if let Some(head) = self.head.take() {
+            let rest = head.strip_prefix(BOM).unwrap_or(&head).to_vec();
+            self.convert(&rest)?;
+        }
+        Ok(())
+    }
+
+    /// Write out anything held back and flush the inner writer
+    pub fn finish(mut self) -> io::Result<()> {
+        self.release_head()?;
+        if self.pending_cr {
+            self.inner.write_all(b"\r")?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for TextWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(head) = self.head.as_mut() {
+            head.extend_from_slice(buf);
+            if head.len() >= BOM.len() {
+                self.release_head()?;
+            }
+            return Ok(buf.len());
+        }
+        for piece in buf.chunks(64 * 1024) {
+            self.convert(piece)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// How extracted text is written for `-o text` and shown in pretty output
+#[derive(Clone, Copy, Default)]
+pub struct TextOptions {
+    pub strip_bom: bool,
+    pub line_endings: LineEndings,
+    /// Pretty output only: don't cut off very long lines
+    pub show_full_lines: bool,
+    /// JSON and YAML output only: one object per chunk instead of parallel arrays
+    pub merge_chunk_metadata: bool,
+    /// Pretty, JSON, and YAML output: counts the tokens of each chunk
+    pub tokenizer: Tokenizer,
+}
+
+impl TextOptions {
+    pub fn writer<W: Write>(&self, inner: W) -> TextWriter<W> {
+        TextWriter::new(inner, self.strip_bom, self.line_endings)
+    }
+}
@@ -0,0 +1,164 @@
+// Client-side check of returned metadata against the submitted schema
+// (--validate-metadata). Both schema styles are understood: JSON Schema,
+// validated as draft 2020-12 by the jsonschema crate, and the plain
+// {"field": "type"} form, where every field is expected and nested objects or
+// one-element arrays describe their values.
+
+use anyhow::{anyhow, Result};
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::paths::{Location, LocationSegment};
+use serde_json::Value;
+
+use crate::privacy;
+
+/// One way the metadata differs from the schema
+pub struct Violation {
+    /// Where in the metadata, as in items[2].price; empty for the whole document
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Violation {
+    fn new(path: &str, expected: impl std::fmt::Display, actual: impl Into<String>) -> Violation {
+        Violation { path: path.to_string(), expected: expected.to_string(), actual: actual.into() }
+    }
+}
+
+/// Check `metadata` (JSON) against a wrapped metadata schema; fails when the
+/// schema isn't a valid JSON Schema
+pub fn check(schema: &Value, metadata: &Value) -> Result<Vec<Violation>> {
+    let document = schema.get("document").unwrap_or(schema);
+    let mut violations = Vec::new();
+    if is_json_schema(document) {
+        check_schema(document, metadata, &mut violations)?;
+    } else {
+        check_plain(document, metadata, "", &mut violations);
+    }
+    Ok(violations)
+}
+
+/// The violations as a diff: - what the schema expects, + what came back
+pub fn report(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|violation| {
+            let path = if violation.path.is_empty() { "(document)" } else { violation.path.as_str() };
+            format!("  {}\n    - {}\n    + {}", path, violation.expected, violation.actual)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_json_schema(document: &Value) -> bool {
+    ["$schema", "$ref", "type", "properties", "required", "items", "enum", "const", "anyOf", "oneOf", "allOf", "not", "if"]
+        .iter()
+        .any(|keyword| document.get(keyword).is_some())
+}
+
+fn check_schema(schema: &Value, metadata: &Value, violations: &mut Vec<Violation>) -> Result<()> {
+    let validator = jsonschema::draft202012::new(schema).map_err(|e| anyhow!("Invalid JSON Schema: {}", e.masked()))?;
+    for error in validator.iter_errors(metadata) {
+        let path = dotted(error.instance_path());
+        // The error messages leave the value out, so privacy mode only has to redact `actual`
+        match error.kind() {
+            ValidationErrorKind::Required { property } => {
+                let name = property.as_str().map(str::to_string).unwrap_or_else(|| property.to_string());
+                violations.push(Violation::new(&join(&path, &name), "a value (required)", "missing"));
+            }
+            _ => violations.push(Violation::new(&path, error.masked(), describe(error.instance()))),
+        }
+    }
+    Ok(())
+}
+
+// A JSON Pointer location as items[2].price
+fn dotted(location: &Location) -> String {
+    location.into_iter().fold(String::new(), |path, segment| match segment {
+        LocationSegment::Property(name) => join(&path, &name),
+        LocationSegment::Index(i) => format!("{}[{}]", path, i),
+    })
+}
+
+fn join(path: &str, field: &str) -> String {
+    match path {
+        "" => field.to_string(),
+        _ => format!("{}.{}", path, field),
+    }
+}
+
+// A value's type and JSON, or in privacy mode its type and hash
+fn describe(value: &Value) -> String {
+    let json = value.to_string();
+    let json = match json.chars().count() > 60 {
+        true => format!("{}...", json.chars().take(57).collect::<String>()),
+        false => json,
+    };
+    format!("{} {}", type_name(value), privacy::redact(&json))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        name => type_name(value) == name,
+    }
+}
+
+// The plain form: {"field": "string", "address": {"city": "string"}, "items": [{"sku": "string"}]}
+fn check_plain(schema: &Value, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+    let mut push = |expected: &str| violations.push(Violation::new(path, expected, describe(value)));
+    match schema {
+        Value::String(name) => {
+            let known = ["string", "number", "integer", "boolean", "array", "object", "null"];
+            if known.contains(&name.as_str()) && !is_type(value, name) {
+                push(name);
+            }
+        }
+        Value::Object(fields) => {
+            let Some(values) = value.as_object() else {
+                push("object");
+                return;
+            };
+            for (name, field) in fields {
+                match values.get(name) {
+                    Some(value) => check_plain(field, value, &join(path, name), violations),
+                    None => violations.push(Violation::new(&join(path, name), field_type(field), "missing")),
+                }
+            }
+        }
+        Value::Array(item) => {
+            let Some(items) = value.as_array() else {
+                push("array");
+                return;
+            };
+            if let Some(item) = item.first() {
+                for (i, value) in items.iter().enumerate() {
+                    check_plain(item, value, &format!("{}[{}]", path, i), violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// What a field of the plain form holds, for reporting it missing
+fn field_type(schema: &Value) -> &str {
+    match schema {
+        Value::String(name) => name,
+        Value::Array(_) => "array",
+        _ => "object",
+    }
+}
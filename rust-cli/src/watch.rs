@@ -0,0 +1,82 @@
+// Watching a drop folder (batch --watch): files that are created, modified,
+// or moved into it are extracted once they've stopped changing, so a file
+// that is still being copied isn't uploaded half-written.
+
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+use crate::theme::Themed;
+
+// How long a file must go without changes before it's extracted
+const SETTLE: Duration = Duration::from_secs(2);
+const TICK: Duration = Duration::from_millis(250);
+
+/// Call `process` with the files under `dir` that are created or modified,
+/// until the process is stopped; changes under `ignore` (the output
+/// directory, when it's inside `dir`) and hidden files are left alone
+pub fn watch(dir: &Path, recursive: bool, ignore: &Path, mut process: impl FnMut(Vec<PathBuf>) -> Result<()>) -> Result<()> {
+    let dir = std::path::absolute(dir).context(format!("Invalid path: {}", dir.display()))?;
+    let ignore = std::path::absolute(ignore).context(format!("Invalid path: {}", ignore.display()))?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start watching for file changes")?;
+    let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(&dir, mode).context(format!("Failed to watch {}", dir.display()))?;
+
+    let label = style("[watch]").dim();
+    eprintln!("{} Watching {} for new and modified files", label, style(dir.display()).accent());
+
+    // Last change of each file that hasn't settled yet
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match rx.recv_timeout(TICK) {
+            Ok(Ok(event)) => {
+                let relevant = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+                );
+                let paths = event.paths.into_iter().filter(|_| relevant);
+                // Files can land in a new directory before it's watched, so it's scanned instead
+                let paths = paths.flat_map(|path| match recursive && path.is_dir() {
+                    true => WalkDir::new(path).into_iter().filter_map(|e| e.ok()).map(|e| e.into_path()).collect(),
+                    false => vec![path],
+                });
+                for path in paths {
+                    if !path.starts_with(&ignore) && !is_hidden(&path) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("{} {}", style("[watch]").warning(), e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("Stopped receiving file changes")),
+        }
+
+        let mut settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        pending.retain(|_, changed| changed.elapsed() < SETTLE);
+        // Deleted or renamed away in the meantime, or a new directory
+        settled.retain(|path| path.is_file());
+        settled.sort();
+        if !settled.is_empty() {
+            if let Err(e) = process(settled) {
+                eprintln!("{} {:#}", style("[watch]").error(), e);
+            }
+            eprintln!("{} Waiting for changes in {}", label, style(dir.display()).accent());
+        }
+    }
+}
+
+// Dot files, such as the partial files of uploaders that rename on completion
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'))
+}
@@ -0,0 +1,181 @@
+// Browser sessions captured with 'vectorize-iris login-web DOMAIN': the
+// cookies of a site the user logged into, sent with later URL inputs from that
+// domain and its subdomains. Each session is a DOMAIN.session file under the
+// config directory, sealed with ChaCha20-Poly1305 (AES-256-GCM from aws-lc-rs
+// in FIPS builds, which can't use ring or ChaCha20). The key is kept in the OS
+// keyring when the build has it, and otherwise in a session.key file readable
+// only by the user. That file sits next to the sessions directory, so a backup
+// or sync of the config directory carries both; only the keyring keeps the
+// key out of those copies.
+
+use anyhow::{anyhow, Context, Result};
+#[cfg(feature = "fips")]
+use aws_lc_rs as crypto;
+#[cfg(not(feature = "fips"))]
+use ring as crypto;
+
+use crypto::aead::{Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use crypto::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+const KEY_LEN: usize = 32;
+
+#[cfg(feature = "fips")]
+const ALGORITHM: &crypto::aead::Algorithm = &crypto::aead::AES_256_GCM;
+#[cfg(not(feature = "fips"))]
+const ALGORITHM: &crypto::aead::Algorithm = &crypto::aead::CHACHA20_POLY1305;
+
+/// The cookies of one domain
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub domain: String,
+    /// As in a Cookie header: name=value; name=value
+    pub cookies: String,
+    pub saved: chrono::DateTime<chrono::Utc>,
+}
+
+pub fn dir() -> Result<PathBuf> {
+    Ok(config::config_dir().context("Could not determine home directory")?.join("sessions"))
+}
+
+/// Check that `domain` is a bare host name, as in wiki.acme.internal
+pub fn validate_domain(domain: &str) -> Result<()> {
+    let valid = !domain.is_empty()
+        && !domain.starts_with('.')
+        && domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    match valid {
+        true => Ok(()),
+        false => Err(anyhow!("Invalid domain '{}': give a host name like wiki.example.com, without scheme or path", domain)),
+    }
+}
+
+/// Turn a pasted Cookie header (with or without the "Cookie:" name) into
+/// name=value pairs joined by "; "
+pub fn parse_cookies(text: &str) -> Result<String> {
+    let text = text.trim();
+    let text = match text.split_once(':') {
+        Some((name, rest)) if name.trim().eq_ignore_ascii_case("cookie") => rest,
+        _ => text,
+    };
+    let pairs: Vec<&str> = text.split(';').map(str::trim).filter(|pair| !pair.is_empty()).collect();
+    if pairs.is_empty() || pairs.iter().any(|pair| !pair.contains('=')) {
+        return Err(anyhow!("Expected cookies as name=value pairs separated by ';'"));
+    }
+    Ok(pairs.join("; "))
+}
+
+/// Encrypt and save the cookies of `domain`, replacing an earlier session
+pub fn save(domain: &str, cookies: &str) -> Result<PathBuf> {
+    validate_domain(domain)?;
+    let session = Session { domain: domain.to_string(), cookies: cookies.to_string(), saved: chrono::Utc::now() };
+    let dir = dir()?;
+    fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+    let key = key()?;
+
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| anyhow!("Failed to generate a nonce"))?;
+    let mut sealed = serde_json::to_vec(&session)?;
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(domain.as_bytes()), &mut sealed)
+        .map_err(|_| anyhow!("Failed to encrypt the session"))?;
+
+    let path = dir.join(format!("{}.session", domain));
+    write_private(&path, &[&nonce[..], &sealed].concat())?;
+    Ok(path)
+}
+
+/// Every saved session; none when nothing was ever saved
+pub fn load_all() -> Result<Vec<Session>> {
+    let dir = dir()?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "session"))
+        .collect();
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let key = key()?;
+    paths.iter().map(|path| open(&key, path)).collect()
+}
+
+fn open(key: &LessSafeKey, path: &Path) -> Result<Session> {
+    let domain = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    let data = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    let (nonce, sealed) = data.split_at_checked(NONCE_LEN).context(format!("Invalid session file {}", path.display()))?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("Invalid session file {}", path.display()))?;
+    let mut sealed = sealed.to_vec();
+    let json = key.open_in_place(nonce, Aad::from(domain.as_bytes()), &mut sealed).map_err(|_| {
+        anyhow!("Can't decrypt {}: the session key changed (log in again with 'vectorize-iris login-web {}')", path.display(), domain)
+    })?;
+    serde_json::from_slice(json).context(format!("Invalid session file {}", path.display()))
+}
+
+// The session key, created on first use
+fn key() -> Result<LessSafeKey> {
+    let bytes = stored_key()?;
+    let key = UnboundKey::new(ALGORITHM, &bytes).map_err(|_| anyhow!("Invalid session key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn new_key() -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; KEY_LEN];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| anyhow!("Failed to generate a session key"))?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "keyring")]
+fn stored_key() -> Result<Vec<u8>> {
+    let entry = keyring::Entry::new("vectorize-iris", "web_session_key").context("Failed to open keyring entry")?;
+    match entry.get_secret() {
+        Ok(bytes) if bytes.len() == KEY_LEN => Ok(bytes),
+        Ok(_) => Err(anyhow!("Invalid session key in the keyring")),
+        Err(keyring::Error::NoEntry) => {
+            let bytes = new_key()?;
+            entry.set_secret(&bytes).map_err(|e| anyhow!("Failed to save the session key to the keyring: {}", e))?;
+            Ok(bytes)
+        }
+        Err(e) => Err(anyhow!("Failed to read the session key from the keyring: {}", e)),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn stored_key() -> Result<Vec<u8>> {
+    let path = config::config_dir().context("Could not determine home directory")?.join("session.key");
+    match fs::read(&path) {
+        Ok(bytes) if bytes.len() == KEY_LEN => Ok(bytes),
+        Ok(_) => Err(anyhow!("Invalid session key {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let bytes = new_key()?;
+            write_private(&path, &bytes)?;
+            Ok(bytes)
+        }
+        Err(e) => Err(anyhow!("Failed to read the session key {}: {}", path.display(), e)),
+    }
+}
+
+// Write a file only the user can read. A new file is created with those
+// permissions, and an existing one is narrowed to them before the data is
+// written, since the mode only applies when the file is created
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).context(format!("Failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600)).context(format!("Failed to restrict {}", path.display()))?;
+    }
+    file.write_all(data).context(format!("Failed to write {}", path.display()))
+}
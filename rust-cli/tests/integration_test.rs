@@ -1,5 +1,8 @@
-use std::process::Command;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 fn get_test_file() -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -13,9 +16,216 @@ fn get_binary_path() -> PathBuf {
     path
 }
 
+// The CLI with `home` as its home directory, telemetry off, and no system
+// policy, so a test never reads or writes the developer's ~/.vectorize-iris,
+// ~/.config/vectorize, or /etc/vectorize-iris/policy.toml
+fn iris_cmd(home: &Path) -> Command {
+    let mut command = Command::new(get_binary_path());
+    command
+        .env("HOME", home)
+        .env("VECTORIZE_TELEMETRY", "0")
+        .env("VECTORIZE_IRIS_POLICY", home.join("no-policy.toml"));
+    command
+}
+
+// Like `iris_cmd`, signed in to the stub API at `api_url`
+fn iris_api_cmd(home: &Path, api_url: &str) -> Command {
+    let mut command = iris_cmd(home);
+    command
+        .env("VECTORIZE_API_URL", api_url)
+        .env("VECTORIZE_TOKEN", "test-token")
+        .env("VECTORIZE_ORG_ID", "test-org");
+    command
+}
+
+// Minimal stand-in for the Iris API: every extraction completes on the first
+// status check with `text`, split into chunks at blank lines. Returns the API URL.
+fn start_stub_api(text: &'static str) -> String {
+    start_flaky_stub_api(text, 0, "503 Service Unavailable")
+}
+
+// Like `start_stub_api`, but the first `failures` requests get `failure_status`
+fn start_flaky_stub_api(text: &'static str, failures: usize, failure_status: &'static str) -> String {
+    serve_stub_api(text, failures, failure_status, Arc::default())
+}
+
+// What the stub API was sent
+#[derive(Default)]
+struct Received {
+    // Content of the last upload
+    upload: Vec<u8>,
+    // Name and content type the last upload was started with
+    name: String,
+    content_type: String,
+    // The last extraction request
+    extraction: serde_json::Value,
+}
+
+// Like `start_stub_api`, and also returns what it was sent
+fn start_recording_stub_api(text: &'static str) -> (String, Arc<Mutex<Received>>) {
+    let received = Arc::new(Mutex::new(Received::default()));
+    (serve_stub_api(text, 0, "503 Service Unavailable", received.clone()), received)
+}
+
+// A webhook endpoint; returns its URL and the JSON bodies POSTed to it
+fn start_webhook_receiver() -> (String, std::sync::mpsc::Receiver<serde_json::Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind webhook receiver");
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, received) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            if sender.send(serde_json::from_slice(&body).unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    (url, received)
+}
+
+fn serve_stub_api(
+    text: &'static str,
+    failures: usize,
+    failure_status: &'static str,
+    received: Arc<Mutex<Received>>,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind stub API");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let upload_url = format!("{}/upload", url);
+
+    std::thread::spawn(move || {
+        let mut failures = failures;
+        // Options of the last extraction, for the optional parts of the result
+        let mut options = serde_json::Value::Null;
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            if failures > 0 {
+                failures -= 1;
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    failure_status
+                );
+                continue;
+            }
+
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let response = if request_line.starts_with("PUT") {
+                received.lock().unwrap().upload = body;
+                serde_json::Value::Null
+            } else if path.ends_with("/files") {
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+                let mut received = received.lock().unwrap();
+                received.name = request["name"].as_str().unwrap_or_default().to_string();
+                received.content_type = request["contentType"].as_str().unwrap_or_default().to_string();
+                serde_json::json!({ "fileId": "file-1", "uploadUrl": upload_url })
+            } else if path.ends_with("/extraction") {
+                options = serde_json::from_slice(&body).unwrap_or_default();
+                received.lock().unwrap().extraction = options.clone();
+                serde_json::json!({ "extractionId": "extraction-1" })
+            } else if path.contains("/extraction/") {
+                let chunks: Vec<&str> = text.split("\n\n").collect();
+                let mut response = serde_json::json!({
+                    "ready": true,
+                    "data": {
+                        "success": true,
+                        "text": text,
+                        "chunks": chunks,
+                        "metadata": "{\"title\": \"Sample\"}",
+                        "usage": { "irisPages": 1 }
+                    }
+                });
+                if options["entities"] == true {
+                    // One person per chunk, named by the chunk's first word
+                    let people: Vec<serde_json::Value> = chunks
+                        .iter()
+                        .map(|c| serde_json::json!([{ "type": "person", "text": c.split_whitespace().next() }]))
+                        .collect();
+                    response["data"]["entities"] = serde_json::json!([{ "type": "amount", "text": "$10" }]);
+                    response["data"]["chunksEntities"] = serde_json::Value::Array(people);
+                }
+                let instructions = options["parsingInstructions"].as_str().unwrap_or_default();
+                if instructions.contains("didn't find these fields") {
+                    // A --refine-missing pass finds the total, but never the vendor
+                    response["data"]["metadata"] = serde_json::json!({ "total": "$10", "vendor": null }).to_string().into();
+                } else if options["metadata"]["schemas"].is_array() {
+                    // Fields as an invoice writes them
+                    let metadata = serde_json::json!({
+                        "title": "Sample",
+                        "invoice": { "date": "March 5th, 2024", "due": "05/04/2024", "total": "1.234,50 €", "paid": "sometime" },
+                        "contacts": [{ "phone": "(415) 555-0100" }, { "phone": "+44 (0)20 7946 0000" }]
+                    });
+                    response["data"]["metadata"] = metadata.to_string().into();
+                }
+                if options["handwriting"] == true {
+                    response["data"]["regions"] = serde_json::json!([
+                        { "page": 1, "text": "Jane Doe", "confidence": 0.97, "handwritten": true },
+                        { "page": 2, "text": "illegible scrawl", "confidence": 0.41, "handwritten": true }
+                    ]);
+                }
+                if options["formFields"] == true {
+                    response["data"]["form"] = serde_json::json!([
+                        { "name": "applicant.name", "value": "Jane Doe", "type": "text", "page": 1 },
+                        { "name": "applicant.over18", "value": true, "type": "checkbox", "page": 1 },
+                        { "name": "signature", "value": null, "type": "signature", "page": 2 }
+                    ]);
+                }
+                response
+            } else {
+                serde_json::Value::Null
+            };
+            let body = if response.is_null() { String::new() } else { response.to_string() };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    url
+}
+
 #[test]
 fn test_cli_help() {
-    let output = Command::new(get_binary_path())
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = iris_cmd(dir.path())
         .arg("--help")
         .output()
         .expect("Failed to execute command");
@@ -27,12 +237,36 @@ fn test_cli_help() {
 
 #[test]
 fn test_cli_version() {
-    let output = Command::new(get_binary_path())
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = iris_cmd(dir.path())
+        .arg("--version")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_cli_version_verbose_json() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = iris_cmd(dir.path())
         .arg("--version")
+        .arg("--verbose")
+        .arg("-o")
+        .arg("json")
         .output()
         .expect("Failed to execute command");
 
     assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: serde_json::Value = serde_json::from_str(&stdout)
+        .expect("Output should be valid JSON");
+
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert!(json["features"].is_array());
+    assert!(json["lockedDependencies"].is_array());
+    assert!(json.get("gitSha").is_some());
+    assert!(json["buildDate"].is_string());
 }
 
 #[test]
@@ -180,7 +414,8 @@ fn test_cli_with_parsing_instructions() {
 
 #[test]
 fn test_cli_missing_file() {
-    let output = Command::new(get_binary_path())
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = iris_cmd(dir.path())
         .arg("nonexistent.pdf")
         .output()
         .expect("Failed to execute command");
@@ -191,7 +426,8 @@ fn test_cli_missing_file() {
 
 #[test]
 fn test_cli_invalid_output_format() {
-    let output = Command::new(get_binary_path())
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = iris_cmd(dir.path())
         .arg(get_test_file())
         .arg("-o")
         .arg("invalid")
@@ -200,3 +436,3550 @@ fn test_cli_invalid_output_format() {
 
     assert!(!output.status.success());
 }
+
+// A request the scripted stub API received
+struct ScriptedRequest {
+    // Method and path, e.g. "GET /v1/org/test-org/extraction/extraction-1"
+    line: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl ScriptedRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+// Status line, extra headers, and body of a scripted stub API response
+type ScriptedReply = (&'static str, Vec<(&'static str, String)>, String);
+
+// A stub API that answers each request with `route`, given the stub's URL and
+// the request; returns the URL and every request received
+fn start_scripted_stub_api(
+    route: impl Fn(&str, &ScriptedRequest) -> ScriptedReply + Send + 'static,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<ScriptedRequest>>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind stub API");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (base, requests) = (url.clone(), received.clone());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let line = request_line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+            let mut headers = Vec::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            let mut request = ScriptedRequest { line, headers, body: Vec::new() };
+            let length = request.header("content-length").map_or(0, |v| v.parse().unwrap());
+            request.body = vec![0; length];
+            reader.read_exact(&mut request.body).unwrap();
+
+            let (status, extra, body) = route(&base, &request);
+            requests.lock().unwrap().push(request);
+            let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+            for (name, value) in extra {
+                response.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            let _ = write!(stream, "{}\r\n{}", response, body);
+        }
+    });
+    (url, received)
+}
+
+// Answers of a scripted stub API for registering and uploading a file and
+// starting its extraction
+fn upload_reply(base: &str, request: &ScriptedRequest) -> Option<ScriptedReply> {
+    match request.line.split_once(' ')? {
+        ("POST", path) if path.ends_with("/files") => {
+            Some(("200 OK", vec![], serde_json::json!({ "fileId": "file-1", "uploadUrl": format!("{}/upload", base) }).to_string()))
+        }
+        ("PUT", "/upload") => Some(("200 OK", vec![], String::new())),
+        ("POST", path) if path.ends_with("/extraction") => Some(("200 OK", vec![], r#"{"extractionId":"extraction-1"}"#.to_string())),
+        _ => None,
+    }
+}
+
+// A completed extraction with `data`
+fn ready_reply(data: serde_json::Value) -> ScriptedReply {
+    ("200 OK", vec![], serde_json::json!({ "ready": true, "data": data }).to_string())
+}
+
+#[test]
+fn test_cli_timings_not_modified() {
+    let polls = std::sync::Mutex::new(0);
+    let (api_url, received) = start_scripted_stub_api(move |base, request| {
+        if let Some(reply) = upload_reply(base, request) {
+            return reply;
+        }
+        let mut polls = polls.lock().unwrap();
+        *polls += 1;
+        match *polls {
+            1 => ("200 OK", vec![("ETag", "\"v1\"".to_string())], r#"{"ready":false}"#.to_string()),
+            // Nothing changed since the first check, then still nothing
+            2 | 3 => ("304 Not Modified", vec![("ETag", "\"v1\"".to_string())], String::new()),
+            _ => ready_reply(serde_json::json!({ "success": true, "text": "Polled text" })),
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .arg(get_test_file())
+        .args(["--timings", "--poll-interval", "0", "-o", "text"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Polled text"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let checks = stderr.lines().find(|line| line.contains("Status checks")).expect("No status check timings");
+    assert!(checks.trim_end().ends_with("4 (2 not modified)"), "{}", checks);
+
+    // Every check after the first sends the ETag it was given
+    let received = received.lock().unwrap();
+    let sent: Vec<Option<&str>> = received
+        .iter()
+        .filter(|request| request.line.starts_with("GET"))
+        .map(|request| request.header("if-none-match"))
+        .collect();
+    assert_eq!(sent, [None, Some("\"v1\""), Some("\"v1\""), Some("\"v1\"")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_token_command_refresh() {
+    // The service only accepts the second token the command prints
+    let (api_url, received) = start_scripted_stub_api(|base, request| {
+        if request.line.contains("/v1/") && request.header("authorization") != Some("Bearer token-2") {
+            return ("401 Unauthorized", vec![], r#"{"error":"expired token"}"#.to_string());
+        }
+        upload_reply(base, request).unwrap_or_else(|| ready_reply(serde_json::json!({ "success": true, "text": "Refreshed text" })))
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let counter = dir.path().join("fetches");
+    // Prints token-1, token-2, ... and counts how often it ran
+    let command = format!("echo x >> {0}; echo token-$(wc -l < {0} | tr -d ' ')", counter.display());
+    let run = |args: &[&str]| {
+        iris_cmd(dir.path())
+            .arg(get_test_file())
+            .args(["--poll-interval", "0", "-o", "text"])
+            .args(args)
+            .env("VECTORIZE_API_URL", &api_url)
+            .env("VECTORIZE_TOKEN_COMMAND", &command)
+            .env("VECTORIZE_ORG_ID", "test-org")
+            .env_remove("VECTORIZE_TOKEN")
+            .output()
+            .expect("Failed to execute command")
+    };
+    let fetches = || std::fs::read_to_string(&counter).unwrap_or_default().lines().count();
+
+    // The rejected token is fetched again once, and the new one kept for the rest of the run
+    let output = run(&["--verbose"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Refreshed text"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Token rejected, fetching a new one"));
+    assert_eq!(fetches(), 2);
+    let tokens: Vec<String> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|request| request.line.contains("/v1/"))
+        .filter_map(|request| request.header("authorization").map(str::to_string))
+        .collect();
+    assert_eq!(tokens[..2], ["Bearer token-1", "Bearer token-2"]);
+    assert!(tokens[2..].iter().all(|token| token == "Bearer token-2"), "{:?}", tokens);
+
+    // A token that is rejected again after the refresh fails the run instead of looping
+    std::fs::write(&counter, "x\nx\n").unwrap();
+    let output = run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("401"), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fetches(), 4);
+}
+
+#[test]
+fn test_cli_normalize_unicode() {
+    // A decomposed é and an fi ligature, the way some PDFs and DOCX files differ
+    let (api_url, _) = start_scripted_stub_api(|base, request| {
+        upload_reply(base, request).unwrap_or_else(|| {
+            ready_reply(serde_json::json!({
+                "success": true,
+                "text": "Cafe\u{301} \u{FB01}le\n\nsecond chunk cafe\u{301}",
+                "chunks": ["Cafe\u{301} \u{FB01}le", "second chunk cafe\u{301}"],
+            }))
+        })
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let run = |args: &[&str]| -> serde_json::Value {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .arg(get_test_file())
+            .args(["--poll-interval", "0", "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice(&output.stdout).expect("Output should be JSON")
+    };
+
+    let result = run(&[]);
+    assert_eq!(result["text"], "Cafe\u{301} \u{FB01}le\n\nsecond chunk cafe\u{301}");
+
+    // NFC composes the é but keeps the ligature, in the text and in every chunk
+    let result = run(&["--normalize-unicode", "nfc"]);
+    assert_eq!(result["text"], "Caf\u{E9} \u{FB01}le\n\nsecond chunk caf\u{E9}");
+    assert_eq!(result["chunks"], serde_json::json!(["Caf\u{E9} \u{FB01}le", "second chunk caf\u{E9}"]));
+
+    // NFKC also replaces compatibility characters such as the ligature
+    let result = run(&["--normalize-unicode", "nfkc"]);
+    assert_eq!(result["text"], "Caf\u{E9} file\n\nsecond chunk caf\u{E9}");
+    assert_eq!(result["chunks"][0], "Caf\u{E9} file");
+}
+
+#[test]
+fn test_cli_input_encoding() {
+    let (api_url, received) = start_scripted_stub_api(|base, request| {
+        upload_reply(base, request).unwrap_or_else(|| ready_reply(serde_json::json!({ "success": true, "text": "Extracted text" })))
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    // Windows-1252: curly quotes, an é, and a euro sign
+    let cp1252 = dir.path().join("legacy.txt");
+    std::fs::write(&cp1252, b"\x93Caf\xe9 cr\xe8me br\xfbl\xe9e\x94 costs \x805 at the caf\xe9 on the corner.\n").unwrap();
+    // Shift-JIS for 日本語
+    let shift_jis = dir.path().join("japanese.txt");
+    std::fs::write(&shift_jis, b"\x93\xfa\x96\x7b\x8c\xea").unwrap();
+    let upload = |path: &std::path::Path, args: &[&str]| -> Vec<u8> {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .arg(path)
+            .args(["--poll-interval", "0", "-o", "text"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let received = received.lock().unwrap();
+        received.iter().rev().find(|request| request.line == "PUT /upload").expect("No upload").body.clone()
+    };
+    let converted = "\u{201C}Caf\u{E9} cr\u{E8}me br\u{FB}l\u{E9}e\u{201D} costs \u{20AC}5 at the caf\u{E9} on the corner.\n";
+
+    // Detected by default and uploaded as UTF-8
+    assert_eq!(String::from_utf8(upload(&cp1252, &[])).unwrap(), converted);
+    // An explicit encoding is used as given
+    assert_eq!(String::from_utf8(upload(&cp1252, &["--input-encoding", "windows-1252"])).unwrap(), converted);
+    assert_eq!(String::from_utf8(upload(&shift_jis, &["--input-encoding", "shift_jis"])).unwrap(), "\u{65E5}\u{672C}\u{8A9E}");
+    // raw uploads the bytes unchanged
+    assert_eq!(upload(&cp1252, &["--input-encoding", "raw"]), std::fs::read(&cp1252).unwrap());
+    // UTF-8 files are never touched
+    let utf8 = dir.path().join("utf8.txt");
+    std::fs::write(&utf8, converted).unwrap();
+    assert_eq!(upload(&utf8, &[]), converted.as_bytes());
+
+    let output = iris_cmd(dir.path())
+        .arg(&cp1252)
+        .args(["--input-encoding", "klingon"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown encoding 'klingon'"));
+}
+
+#[test]
+fn test_cli_line_endings_and_bom() {
+    let (api_url, _) = start_scripted_stub_api(|base, request| {
+        upload_reply(base, request)
+            .unwrap_or_else(|| ready_reply(serde_json::json!({ "success": true, "text": "\u{FEFF}one\r\ntwo\nthree\rfour" })))
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let run = |args: &[&str]| -> Vec<u8> {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .arg(get_test_file())
+            .args(["--poll-interval", "0", "-o", "text"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output.stdout
+    };
+
+    // Text is written exactly as extracted unless asked otherwise
+    assert_eq!(run(&[]), "\u{FEFF}one\r\ntwo\nthree\rfour".as_bytes());
+    assert_eq!(run(&["--strip-bom"]), b"one\r\ntwo\nthree\rfour");
+    // A lone CR isn't a line ending either way
+    assert_eq!(run(&["--line-endings", "lf"]), "\u{FEFF}one\ntwo\nthree\rfour".as_bytes());
+    assert_eq!(run(&["--line-endings", "crlf"]), "\u{FEFF}one\r\ntwo\r\nthree\rfour".as_bytes());
+    assert_eq!(run(&["--strip-bom", "--line-endings", "crlf"]), b"one\r\ntwo\r\nthree\rfour");
+}
+
+#[test]
+fn test_cli_long_lines() {
+    // A base64-like blob on one line, between two ordinary ones
+    let text = format!("Before the blob\n{}\nAfter the blob", "A".repeat(5000));
+    let (api_url, _) = start_scripted_stub_api(move |base, request| {
+        upload_reply(base, request)
+            .unwrap_or_else(|| ready_reply(serde_json::json!({ "success": true, "text": text, "chunks": [text] })))
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let run = |args: &[&str]| -> String {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .arg(get_test_file())
+            .args(["--poll-interval", "0", "-o", "pretty"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    // Characters of the blob shown, wherever it is printed (text and chunk)
+    let blob_chars = |stdout: &str| stdout.matches('A').count() - stdout.matches("After").count();
+
+    // The blob is cut off after MAX_LINE_CHARS, saying how much is hidden
+    let stdout = run(&[]);
+    let cut = stdout.matches("3000 more chars on this line (use --show-full-lines to see them)").count();
+    assert!(cut > 0, "{}", stdout);
+    assert_eq!(blob_chars(&stdout), 2000 * cut);
+    assert!(stdout.contains("Before the blob") && stdout.contains("After the blob"));
+
+    let stdout = run(&["--show-full-lines"]);
+    assert!(!stdout.contains("more chars on this line"));
+    assert_eq!(blob_chars(&stdout), 5000 * cut);
+}
+
+#[test]
+fn test_cli_heartbeat() {
+    let polls = std::sync::Mutex::new(0);
+    let (api_url, _) = start_scripted_stub_api(move |base, request| {
+        if let Some(reply) = upload_reply(base, request) {
+            return reply;
+        }
+        let mut polls = polls.lock().unwrap();
+        *polls += 1;
+        match *polls {
+            1..=3 => ("200 OK", vec![], r#"{"ready":false}"#.to_string()),
+            _ => ready_reply(serde_json::json!({ "success": true, "text": "Slow text" })),
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let run = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", get_test_file().to_str().unwrap(), "--poll-interval", "1", "-o", "text"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // A status line about once a second while the extraction is pending,
+    // written even though stderr isn't a terminal
+    let output = run(&["--heartbeat", "1s"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Slow text");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let heartbeats: Vec<&str> = stderr.lines().filter(|line| line.contains("Still processing")).collect();
+    assert!((2..=3).contains(&heartbeats.len()), "{}", stderr);
+    assert!(heartbeats[0].contains("status checks)"), "{}", heartbeats[0]);
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Still processing"));
+}
+
+#[test]
+fn test_cli_streamed_upload() {
+    let (api_url, received) = start_scripted_stub_api(|base, request| {
+        upload_reply(base, request).unwrap_or_else(|| ready_reply(serde_json::json!({ "success": true, "text": "Extracted text" })))
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    // Larger than any read buffer, so it takes many reads from disk
+    let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i: u32| (i % 251) as u8).collect();
+    let file = dir.path().join("large.bin");
+    std::fs::write(&file, &content).unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "--poll-interval", "0", "-o", "text"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // The stub reads exactly Content-Length bytes, so a chunked or truncated
+    // body wouldn't match
+    let received = received.lock().unwrap();
+    let upload = &received.iter().find(|request| request.line == "PUT /upload").expect("No upload").body;
+    assert_eq!(upload.len(), content.len());
+    assert!(*upload == content, "The uploaded content differs from the file");
+}
+
+#[test]
+fn test_cli_snapshot_and_changed() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("nested")).unwrap();
+    std::fs::write(corpus.join("kept.md"), "unchanged").unwrap();
+    std::fs::write(corpus.join("edited.md"), "before").unwrap();
+    std::fs::write(corpus.join("nested/gone.md"), "removed").unwrap();
+
+    let snapshot = dir.path().join("snapshot.json");
+    let output = iris_cmd(dir.path())
+        .arg("snapshot")
+        .arg(&corpus)
+        .arg("-f")
+        .arg(&snapshot)
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    std::fs::write(corpus.join("edited.md"), "after").unwrap();
+    std::fs::remove_file(corpus.join("nested/gone.md")).unwrap();
+    std::fs::write(corpus.join("new.md"), "added").unwrap();
+
+    let output = iris_cmd(dir.path())
+        .arg("-o")
+        .arg("json")
+        .arg("changed")
+        .arg(&corpus)
+        .arg("--against")
+        .arg(&snapshot)
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: serde_json::Value = serde_json::from_str(&stdout)
+        .expect("Output should be valid JSON");
+
+    assert_eq!(json["added"], serde_json::json!(["new.md"]));
+    assert_eq!(json["modified"], serde_json::json!(["edited.md"]));
+    assert_eq!(json["removed"], serde_json::json!(["nested/gone.md"]));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_snapshot_walks_like_batches() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("nested")).unwrap();
+    std::fs::write(corpus.join("kept.md"), "kept").unwrap();
+    std::fs::write(corpus.join("nested/draft.md"), "draft").unwrap();
+    // A link back to the corpus would recurse forever if it were followed blindly
+    std::os::unix::fs::symlink(&corpus, corpus.join("nested/loop")).unwrap();
+
+    let output = iris_cmd(dir.path())
+        .args(["snapshot", corpus.to_str().unwrap(), "--exclude", "nested/**"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let snapshot: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be valid JSON");
+    let files: Vec<&String> = snapshot["files"].as_object().unwrap().keys().collect();
+    assert_eq!(files, ["kept.md"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping nested/loop"));
+}
+
+#[test]
+fn test_cli_changed_sends_deletes_to_sink() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("gone.md"), "removed").unwrap();
+
+    let snapshot = dir.path().join("snapshot.json");
+    let output = iris_cmd(dir.path())
+        .arg("snapshot")
+        .arg(&corpus)
+        .arg("-f")
+        .arg(&snapshot)
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    std::fs::remove_file(corpus.join("gone.md")).unwrap();
+
+    let sink = dir.path().join("sink.jsonl");
+    let output = iris_cmd(dir.path())
+        .arg("changed")
+        .arg(&corpus)
+        .arg("--against")
+        .arg(&snapshot)
+        .arg("--sink")
+        .arg(format!("jsonl:{}", sink.display()))
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let content = std::fs::read_to_string(&sink).expect("Sink file should exist");
+    let record: serde_json::Value = serde_json::from_str(content.lines().next().unwrap())
+        .expect("Sink record should be valid JSON");
+
+    assert_eq!(record["op"], "delete");
+    assert_eq!(record["source"], "gone.md");
+    assert!(record["idPrefix"].as_str().unwrap().ends_with('-'));
+}
+
+#[test]
+fn test_cli_policy_violation() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let policy = dir.path().join("policy.toml");
+    std::fs::write(&policy, "allowed_base_urls = [\"https://api.vectorize.io\"]\n").unwrap();
+
+    let output = iris_api_cmd(dir.path(), "https://staging.example.com")
+        .arg(get_test_file())
+        .env("VECTORIZE_IRIS_POLICY", &policy)
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Policy violation"));
+    assert!(stderr.contains("https://staging.example.com"));
+
+    // A wildcard covers the paths below it, not hosts that share its prefix
+    std::fs::write(&policy, "allowed_base_urls = [\"https://api.vectorize.io*\"]\n").unwrap();
+    let output = iris_api_cmd(dir.path(), "https://api.vectorize.io.evil.com")
+        .arg(get_test_file())
+        .env("VECTORIZE_IRIS_POLICY", &policy)
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("https://api.vectorize.io.evil.com' is not allowed"));
+}
+
+#[test]
+fn test_cli_policy_forbids_output_files() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let policy = dir.path().join("policy.toml");
+    std::fs::write(&policy, "forbid_unencrypted_outputs = true\n").unwrap();
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("kept.md"), "unchanged").unwrap();
+    let run = |args: &[&str]| {
+        iris_cmd(dir.path())
+            .args(args)
+            .env("VECTORIZE_IRIS_POLICY", &policy)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // Snapshots and catalogs are outputs too, refused before they're written
+    let snapshot = dir.path().join("snapshot.json");
+    let output = run(&["snapshot", corpus.to_str().unwrap(), "-f", snapshot.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Writing unencrypted output to file"));
+    assert!(!snapshot.exists());
+    let output = run(&["index-outputs", corpus.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Policy violation"));
+
+    // Printing to stdout is allowed, but tombstones aren't written
+    let output = run(&["snapshot", corpus.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    std::fs::write(&snapshot, &output.stdout).unwrap();
+    let tombstones = dir.path().join("tombstones.jsonl");
+    let output = run(&["changed", corpus.to_str().unwrap(), "--against", snapshot.to_str().unwrap(), "--tombstones", tombstones.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("file {}", tombstones.display())));
+    assert!(!tombstones.exists());
+}
+
+#[test]
+fn test_cli_telemetry_opt_in() {
+    let home = tempfile::tempdir().expect("Failed to create temp dir");
+    let run = |args: &[&str]| {
+        let output = iris_cmd(home.path())
+            .args(args)
+            .env("VECTORIZE_TELEMETRY_URL", "http://127.0.0.1:9")
+            .env_remove("VECTORIZE_TELEMETRY")
+            .env_remove("DO_NOT_TRACK")
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    assert!(run(&["telemetry", "status"]).contains("Telemetry: disabled"));
+
+    run(&["telemetry", "enable"]);
+    assert!(run(&["telemetry", "status"]).contains("Telemetry: enabled"));
+
+    run(&["snapshot", home.path().to_str().unwrap(), "-f", home.path().join("snap.json").to_str().unwrap()]);
+    let queue = std::fs::read_to_string(home.path().join(".vectorize-iris/telemetry-queue.jsonl")).unwrap();
+    assert!(queue.contains("\"command\":\"snapshot\""));
+    assert!(!queue.contains(home.path().to_str().unwrap()));
+
+    // The next run sends the queue in the background while it works
+    let telemetry = TcpListener::bind("127.0.0.1:0").unwrap();
+    let listen = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let mut server = iris_cmd(home.path())
+        .args(["serve", "--listen", &listen])
+        .env("VECTORIZE_TELEMETRY_URL", format!("http://{}", telemetry.local_addr().unwrap()))
+        .env("VECTORIZE_TOKEN", "test-token")
+        .env("VECTORIZE_ORG_ID", "test-org")
+        .env_remove("VECTORIZE_TELEMETRY")
+        .env_remove("DO_NOT_TRACK")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    let (mut stream, _) = telemetry.accept().unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+    drop(stream);
+    assert!(String::from_utf8_lossy(&body).contains("\"command\":\"snapshot\""));
+    let sent = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let config_dir = home.path().join(".vectorize-iris");
+    while config_dir.join("telemetry-sending.jsonl").exists() && std::time::Instant::now() < sent {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(!config_dir.join("telemetry-sending.jsonl").exists());
+    assert!(!config_dir.join("telemetry-queue.jsonl").exists());
+
+    run(&["telemetry", "disable"]);
+    assert!(run(&["telemetry", "status"]).contains("Queued events: 0"));
+}
+
+#[test]
+fn test_cli_subcommand_options_placement() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    // Extraction options belong after the subcommand name
+    let output = iris_cmd(dir.path())
+        .args(["--chunk-size", "512", "extract"])
+        .arg(get_test_file())
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'--chunk-size' must come after the subcommand"));
+
+    let output = iris_cmd(dir.path())
+        .arg("batch")
+        .arg(get_test_file())
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Not a directory"));
+}
+
+#[test]
+fn test_cli_config_setup_and_show() {
+    let home = tempfile::tempdir().expect("Failed to create temp dir");
+    let run = |args: &[&str]| {
+        let output = iris_cmd(home.path())
+            .args(args)
+            .env_remove("VECTORIZE_TOKEN")
+            .env_remove("VECTORIZE_TOKEN_COMMAND")
+            .env_remove("VECTORIZE_ORG_ID")
+            .env_remove("VECTORIZE_API_URL")
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    run(&["config", "setup", "--api-token", "secret-token-value", "--org-id", "test-org"]);
+    let shown = run(&["config", "show"]);
+    assert!(shown.contains("test-org"));
+    assert!(shown.contains("secr…"));
+    assert!(!shown.contains("secret-token-value"));
+}
+
+#[test]
+fn test_cli_ascii_output() {
+    // A line long enough to be cut off, and several chunks to get separators
+    let text: &'static str = Box::leak(format!("First paragraph.\n\nSecond paragraph.\n\n{}", "x".repeat(3000)).into_boxed_str());
+    let api_url = start_stub_api(text);
+    let home = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = home.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("a.txt"), "alpha").unwrap();
+    std::fs::write(corpus.join("b.txt"), "beta").unwrap();
+
+    let run = |args: &[&str]| {
+        let output = iris_api_cmd(home.path(), &api_url)
+            .args(args)
+            .env_remove("VECTORIZE_TOKEN_COMMAND")
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        [output.stdout, output.stderr].concat()
+    };
+    let corpus = corpus.to_str().unwrap();
+    let sample = format!("{}/a.txt", corpus);
+
+    // Without --ascii these paths print box drawing and other Unicode
+    assert!(!run(&["extract", &sample]).is_ascii());
+
+    for args in [
+        vec!["--ascii", "extract", &sample],
+        vec!["--ascii", "--verbose", "--timings", "extract", &sample],
+        vec!["--ascii", "batch", corpus, "--no-dedupe"],
+        vec!["--ascii", "config", "show"],
+        vec!["--theme", "plain", "extract", &sample],
+    ] {
+        let output = run(&args);
+        let non_ascii: String = String::from_utf8_lossy(&output).chars().filter(|c| !c.is_ascii()).collect();
+        assert!(non_ascii.is_empty(), "{:?} printed non-ASCII characters: {}", args, non_ascii);
+    }
+}
+
+#[test]
+fn test_cli_batch_concurrency() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+        std::fs::write(corpus.join(name), name).unwrap();
+    }
+    std::fs::write(corpus.join("copy-of-a.txt"), "a.txt").unwrap();
+    let out_dir = dir.path().join("out");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "--concurrency", "3", "-o", "json", "-f", out_dir.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 5"));
+    assert!(stderr.contains("Duplicates: 1"));
+    for name in ["a", "b", "c", "d", "copy-of-a"] {
+        let json = std::fs::read_to_string(out_dir.join(format!("{}.json", name))).unwrap();
+        assert!(json.contains("Extracted text"));
+    }
+}
+
+#[test]
+fn test_cli_batch_monitor_restarts_workers() {
+    // Rate-limited requests wait a second each, so the monitor samples mid-batch
+    let api_url = start_flaky_stub_api("Extracted text", 4, "429 Too Many Requests");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    for i in 0..6 {
+        std::fs::write(corpus.join(format!("{}.txt", i)), i.to_string()).unwrap();
+    }
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "--concurrency", "2", "-o", "json"])
+        .args(["--monitor-interval", "1", "--max-rss", "1"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 6"), "{}", stderr);
+    if cfg!(target_os = "linux") {
+        let rss = stderr.split("rss=").nth(1).unwrap_or_default();
+        assert!(rss.starts_with(|c: char| c.is_ascii_digit()), "{}", stderr);
+    }
+    assert!(stderr.contains("restarting batch workers"), "{}", stderr);
+    assert!(stderr.contains("Restarting 2 workers once their files in flight finish"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_batch_progress_file() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(corpus.join(name), name).unwrap();
+    }
+    let progress_file = dir.path().join("progress.json");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "--concurrency", "2", "-o", "json"])
+        .args(["--progress-file", progress_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let progress: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&progress_file).unwrap()).unwrap();
+    assert_eq!(progress["status"], "finished");
+    assert_eq!(progress["total"], 3);
+    assert_eq!(progress["completed"], 3);
+    assert_eq!(progress["percent"], 100.0);
+    assert_eq!(progress["activeFiles"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn test_cli_batch_recursive() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("2024/q1")).unwrap();
+    std::fs::write(corpus.join("top.txt"), "top").unwrap();
+    std::fs::write(corpus.join("2024/report.txt"), "report").unwrap();
+    std::fs::write(corpus.join("2024/q1/notes.txt"), "notes").unwrap();
+
+    let run = |extra: &[&str], out_dir: &std::path::Path| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["batch", corpus.to_str().unwrap(), "--no-dedupe", "-o", "json", "-f", out_dir.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // Without --recursive only the top level is processed
+    let flat = dir.path().join("flat");
+    let output = run(&[], &flat);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(flat.join("top.json").exists());
+    assert!(!flat.join("2024").exists());
+
+    let nested = dir.path().join("nested");
+    let output = run(&["--recursive"], &nested);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Successful: 3"));
+    for path in ["top.json", "2024/report.json", "2024/q1/notes.json"] {
+        assert!(nested.join(path).exists(), "missing {}", path);
+    }
+}
+
+#[test]
+fn test_cli_batch_tags() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("clients");
+    std::fs::create_dir_all(corpus.join("acme/2024")).unwrap();
+    std::fs::write(corpus.join("acme/2024/report.txt"), "report").unwrap();
+    std::fs::write(corpus.join("loose.txt"), "loose").unwrap();
+    let sink = dir.path().join("chunks.jsonl");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "--recursive", "--no-dedupe", "-o", "text"])
+        .args(["--sink", &format!("jsonl:{}", sink.display())])
+        .args(["--tags-from-path", "clients/{client}/{year}/", "--tag", "env=prod"])
+        .output()
+        .expect("Failed to execute command");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("[tags-unmatched]"), "{}", stderr);
+
+    let upserts: Vec<serde_json::Value> = std::fs::read_to_string(&sink).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|record: &serde_json::Value| record["op"] == "upsert")
+        .collect();
+    let metadata = |source: &str| -> Vec<serde_json::Value> {
+        upserts.iter()
+            .filter(|record| record["source"] == source)
+            .map(|record| serde_json::from_str(record["metadata"].as_str().unwrap()).unwrap())
+            .collect()
+    };
+
+    let tagged = metadata("acme/2024/report.txt");
+    assert_eq!(tagged.len(), 2);
+    for chunk in tagged {
+        assert_eq!(chunk, serde_json::json!({ "title": "Sample", "client": "acme", "year": "2024", "env": "prod" }));
+    }
+    // Files outside the pattern still get --tag values
+    assert_eq!(metadata("loose.txt")[0], serde_json::json!({ "title": "Sample", "env": "prod" }));
+}
+
+#[test]
+fn test_cli_batch_include_exclude() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("drafts")).unwrap();
+    std::fs::create_dir_all(corpus.join("final")).unwrap();
+    for path in ["a.pdf", "b.docx", "drafts/c.pdf", "final/d.pdf", "final/e.txt"] {
+        std::fs::write(corpus.join(path), path).unwrap();
+    }
+    let out_dir = dir.path().join("out");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "--recursive", "-o", "json", "-f", out_dir.to_str().unwrap()])
+        .args(["--include", "**/*.pdf", "--exclude", "drafts/**"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("3 skipped by filters"), "{}", stderr);
+    assert!(out_dir.join("a.json").exists());
+    assert!(out_dir.join("final/d.json").exists());
+    assert!(!out_dir.join("b.json").exists());
+    assert!(!out_dir.join("drafts").exists());
+    assert!(!out_dir.join("final/e.json").exists());
+
+    let output = iris_cmd(dir.path())
+        .args(["batch", corpus.to_str().unwrap(), "--include", "[pdf"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid glob"));
+}
+
+#[test]
+fn test_cli_batch_layout() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("invoices")).unwrap();
+    for path in ["invoices/a.pdf", "notes.md", "index.txt"] {
+        std::fs::write(corpus.join(path), path).unwrap();
+    }
+    let batch = |layout: &str| {
+        let out_dir = dir.path().join(layout);
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["batch", corpus.to_str().unwrap(), "--recursive", "--no-dedupe", "-o", "json"])
+            .args(["--layout", layout, "-f", out_dir.to_str().unwrap()])
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let index: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(out_dir.join("index.json")).unwrap()).expect("index should be JSON");
+        for (source, output) in index.as_object().unwrap() {
+            assert!(out_dir.join(output.as_str().unwrap()).exists(), "{} -> {}", source, output);
+        }
+        index
+    };
+
+    let index = batch("by-type");
+    assert_eq!(index["invoices/a.pdf"], "pdf/invoices/a.json");
+    assert_eq!(index["notes.md"], "md/notes.json");
+    let index = batch("flat");
+    assert_eq!(index["invoices/a.pdf"], "invoices__a.json");
+    // A document's output doesn't replace the index
+    assert_eq!(index["index.txt"], "index.document.json");
+    let index = batch("by-date");
+    assert!(index["invoices/a.pdf"].as_str().unwrap().ends_with("/invoices/a.json"), "{}", index);
+    assert_eq!(index["invoices/a.pdf"].as_str().unwrap().len(), "2024-06-18/invoices/a.json".len());
+}
+
+#[test]
+fn test_cli_batch_sign_output() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("a.pdf"), "a").unwrap();
+    std::fs::write(corpus.join("b.pdf"), "b").unwrap();
+    let keys = minisign::KeyPair::generate_unencrypted_keypair().unwrap();
+    let key = dir.path().join("iris.key");
+    std::fs::write(&key, keys.sk.to_box(None).unwrap().to_string()).unwrap();
+    let out_dir = dir.path().join("out");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "--no-dedupe", "-o", "json", "-f", out_dir.to_str().unwrap()])
+        .args(["--sign-output", "--key", key.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let verify = |file: &str| {
+        let signature = minisign::SignatureBox::from_file(out_dir.join(format!("{}.minisig", file))).unwrap();
+        let content = std::fs::File::open(out_dir.join(file)).unwrap();
+        minisign::verify(&keys.pk, &signature, content, true, false, false)
+    };
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(out_dir.join("run-manifest.json")).unwrap()).expect("manifest should be JSON");
+    for file in ["a.json", "b.json", "run-manifest.json"] {
+        assert!(verify(file).is_ok(), "{} should verify", file);
+    }
+    assert_eq!(manifest["a.json"]["source"], "a.pdf");
+    assert_eq!(manifest["a.json"]["signature"], "a.json.minisig");
+    assert_eq!(manifest["a.json"]["sha256"].as_str().unwrap().len(), 64);
+
+    // Altered results no longer verify
+    std::fs::write(out_dir.join("a.json"), "{}").unwrap();
+    assert!(verify("a.json").is_err());
+}
+
+#[test]
+fn test_cli_output_sqlite() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("a.pdf"), "a").unwrap();
+    std::fs::write(corpus.join("b.pdf"), "b").unwrap();
+    let database = dir.path().join("results.db");
+
+    // Extracting the corpus again replaces its rows instead of adding to them
+    for _ in 0..2 {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["batch", corpus.to_str().unwrap(), "--no-dedupe", "-o", "json", "--tag", "client=acme"])
+            .args(["--output-sqlite", database.to_str().unwrap()])
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    let count = |sql: &str| connection.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+    assert_eq!(count("SELECT count(*) FROM documents"), 2);
+    assert_eq!(count("SELECT count(*) FROM chunks"), 4);
+    assert_eq!(count("SELECT count(*) FROM metadata WHERE key = 'client' AND value = 'acme'"), 4);
+    let text: String = connection
+        .query_row(
+            "SELECT chunks.text FROM chunks JOIN documents ON documents.id = chunks.document \
+             WHERE documents.source = 'b.pdf' AND chunk_index = 1",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(text, "Second chunk");
+}
+
+#[test]
+fn test_cli_index_outputs_and_query() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("invoices")).unwrap();
+    for path in ["invoices/a.pdf", "notes.md"] {
+        std::fs::write(corpus.join(path), path).unwrap();
+    }
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let run = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let out = out_dir.to_str().unwrap();
+
+    let output = run(&["query", out]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("index-outputs"));
+
+    let sink = format!("jsonl:{}", out_dir.join("feed.jsonl").display());
+    let output = run(&[
+        "batch", corpus.to_str().unwrap(), "-r", "--no-dedupe", "-o", "json", "-f", out,
+        "--layout", "by-type", "--tag", "client=acme", "--sink", &sink,
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = run(&["index-outputs", out]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(out_dir.join("catalog.sqlite").exists());
+
+    // Results and the sink feed both list each document
+    let output = run(&["query", out, "-o", "json", "--source", "invoices/*", "--tag", "client=acme", "--min-chunks", "2"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let documents: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    let documents = documents.as_array().unwrap();
+    assert_eq!(documents.len(), 2, "{:?}", documents);
+    assert_eq!(documents[0]["output"], "feed.jsonl");
+    assert_eq!(documents[1]["output"], "pdf/invoices/a.json");
+    assert_eq!(documents[1]["source"], "invoices/a.pdf");
+    assert_eq!(documents[1]["extractionId"], "extraction-1");
+    assert_eq!(documents[1]["chunks"], 2);
+    assert_eq!(documents[1]["tags"]["client"], "acme");
+
+    let output = run(&["query", out, "-o", "text", "--extraction-id", "extraction-1", "--source", "*.md"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), [out_dir.join("md/notes.json").to_str().unwrap()]);
+    let output = run(&["query", out, "-o", "json", "--tag", "client=other"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "[]");
+}
+
+#[test]
+fn test_cli_batch_watch() {
+    let api_url = start_stub_api("Dropped text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let drop_dir = dir.path().join("drop");
+    std::fs::create_dir_all(&drop_dir).unwrap();
+    std::fs::write(drop_dir.join("existing.txt"), "existing").unwrap();
+    // The output directory is inside the watched one; writing results mustn't trigger extractions
+    let out_dir = drop_dir.join("out");
+
+    let mut watcher = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", drop_dir.to_str().unwrap(), "--watch", "-r", "-o", "json", "-f", out_dir.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    let wait_for = |path: PathBuf| {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+        while !path.exists() {
+            assert!(std::time::Instant::now() < deadline, "{} was not written", path.display());
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+    };
+
+    wait_for(out_dir.join("existing.json"));
+    std::fs::create_dir_all(drop_dir.join("inbox")).unwrap();
+    std::fs::write(drop_dir.join("inbox/new.txt"), "new").unwrap();
+    std::fs::write(drop_dir.join(".partial"), "partial").unwrap();
+    wait_for(out_dir.join("inbox/new.json"));
+    watcher.kill().unwrap();
+    watcher.wait().unwrap();
+    assert!(!out_dir.join(".partial.json").exists());
+    assert!(!out_dir.join("out").exists());
+
+    let output = iris_cmd(dir.path())
+        .args(["batch", drop_dir.to_str().unwrap(), "--watch"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_duration_and_size_overflow() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let output = iris_cmd(dir.path())
+        .args(["extract", "x.pdf", "--heartbeat", "99999999999999999d"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("duration too large"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+
+    let output = iris_cmd(dir.path())
+        .args(["extract", "x.pdf", "--retry-backoff", "99999999999999999h"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("duration too large"));
+
+    // The config file goes through the same parser
+    let config_dir = dir.path().join(".config/vectorize");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), "[retention]\ncache = \"99999999999999999h\"\n").unwrap();
+    let output = iris_cmd(dir.path())
+        .args(["gc"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid retention.cache in the config file: duration too large"), "{}", stderr);
+
+    for args in [&["extract", "x.pdf", "--max-result-size", "99999999999999GB"][..], &["serve", "--max-upload-size", "99999999999999GB"]] {
+        let output = iris_cmd(dir.path())
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("size too large"), "{}", stderr);
+    }
+}
+
+#[test]
+fn test_cli_gc() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let home = dir.path().join("home");
+    let temp = dir.path().join("tmp");
+    let out_dir = dir.path().join("out");
+    let reports = home.join(".vectorize-iris/crash-reports");
+    for path in [&reports, &temp.join("vectorize-iris-abc"), &out_dir.join("old")] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let age = |path: &std::path::Path, days: u64| {
+        let file = std::fs::File::open(path).unwrap();
+        file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(days * 24 * 60 * 60)).unwrap();
+    };
+    let files = [
+        (reports.join("crash-1.json"), 40),
+        (reports.join("crash-2.json"), 1),
+        (temp.join("vectorize-iris-abc/download.pdf"), 2),
+        (temp.join("unrelated.txt"), 40),
+        (out_dir.join("old/a.json"), 100),
+        (out_dir.join("b.json"), 1),
+        (out_dir.join("c.json"), 100),
+        (out_dir.join("notes.txt"), 100),
+        (out_dir.join("settings.json"), 100),
+    ];
+    for (path, days) in &files {
+        std::fs::write(path, "x").unwrap();
+        age(path, *days);
+    }
+    // An unindexed result is the CLI's too, but other files in the output directory aren't
+    std::fs::write(out_dir.join("c.json"), r#"{"success":true}"#).unwrap();
+    age(&out_dir.join("c.json"), 100);
+    std::fs::write(out_dir.join("settings.json"), r#"{"theme":"dark"}"#).unwrap();
+    age(&out_dir.join("settings.json"), 100);
+    std::fs::write(out_dir.join("index.json"), r#"{"old/a.pdf": "old/a.json", "b.pdf": "b.json"}"#).unwrap();
+    age(&out_dir.join("index.json"), 100);
+    age(&temp.join("vectorize-iris-abc"), 2);
+    std::fs::create_dir_all(home.join(".config/vectorize")).unwrap();
+    let config = format!("[retention]\noutputs = \"90d\"\noutput_dirs = [{:?}]\n", out_dir.to_str().unwrap());
+    std::fs::write(home.join(".config/vectorize/config.toml"), config).unwrap();
+    let run = |args: &[&str]| {
+        iris_cmd(&home)
+            .args(args)
+            .env("TMPDIR", &temp)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // A dry run lists what would go without removing it
+    let output = run(&["gc", "--dry-run", "-o", "text"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let listed: Vec<String> = String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect();
+    let expected = [reports.join("crash-1.json"), temp.join("vectorize-iris-abc"), out_dir.join("c.json"), out_dir.join("old/a.json")];
+    assert_eq!(listed, expected.iter().map(|p| p.display().to_string()).collect::<Vec<_>>());
+    assert!(files.iter().all(|(path, _)| path.exists()));
+
+    let output = run(&["gc", "-o", "json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(report["reclaimedBytes"], 19);
+    assert_eq!(report["removed"][0]["kind"], "crash-report");
+    assert!(expected.iter().all(|path| !path.exists()));
+    assert!(!out_dir.join("old").exists());
+    for kept in [reports.join("crash-2.json"), temp.join("unrelated.txt"), out_dir.join("b.json"), out_dir.join("notes.txt"), out_dir.join("settings.json")] {
+        assert!(kept.exists(), "{} was removed", kept.display());
+    }
+    let index: serde_json::Value = serde_json::from_slice(&std::fs::read(out_dir.join("index.json")).unwrap()).unwrap();
+    assert_eq!(index, serde_json::json!({"b.pdf": "b.json"}));
+
+    // Output directories need an age, which the flag or the config gives
+    let output = run(&["--config", "/dev/null", "gc", out_dir.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--outputs-older-than"));
+}
+
+#[test]
+fn test_cli_serve() {
+    let (api_url, received) = start_recording_stub_api("Served text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let listen = format!("127.0.0.1:{}", port);
+    let mut server = iris_api_cmd(dir.path(), &api_url)
+        .args(["serve", "--listen", &listen, "--max-upload-size", "1KB"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    // Status code and JSON body of one request
+    let request = |head: &str, body: &[u8]| -> (u16, serde_json::Value) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let mut stream = loop {
+            match std::net::TcpStream::connect(&listen) {
+                Ok(stream) => break stream,
+                Err(_) if std::time::Instant::now() < deadline => std::thread::sleep(std::time::Duration::from_millis(50)),
+                Err(e) => panic!("Server didn't start: {}", e),
+            }
+        };
+        write!(stream, "{}\r\nContent-Length: {}\r\n\r\n", head, body.len()).unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (headers, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = headers.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).expect("Response should be JSON"))
+    };
+
+    let (status, body) = request("GET /health HTTP/1.1", b"");
+    assert_eq!((status, body), (200, serde_json::json!({"status": "ok"})));
+
+    let form = b"--XYZ\r\nContent-Disposition: form-data; name=\"chunk_size\"\r\n\r\n128\r\n--XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"report.txt\"\r\nContent-Type: text/plain\r\n\r\nquarterly report\r\n--XYZ--\r\n";
+    let (status, body) = request("POST /extract HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XYZ", form);
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["text"], "Served text");
+    {
+        let received = received.lock().unwrap();
+        assert_eq!(received.upload, b"quarterly report");
+        assert_eq!(received.name, "report.txt");
+    }
+
+    // Raw bodies are named by the file_name parameter
+    let (status, _) = request("POST /extract?file_name=notes.md HTTP/1.1", b"# Notes");
+    assert_eq!(status, 200);
+    assert_eq!(received.lock().unwrap().name, "notes.md");
+
+    let (status, body) = request("POST /extract?chunk_size=lots HTTP/1.1", b"text");
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("chunk_size"));
+    assert_eq!(request("POST /extract HTTP/1.1", &[b'x'; 2048]).0, 413);
+    assert_eq!(request("GET /extract HTTP/1.1", b"").0, 405);
+    assert_eq!(request("GET /other HTTP/1.1", b"").0, 404);
+
+    // A request line that never ends is refused once it reaches the header limit
+    let mut stream = std::net::TcpStream::connect(&listen).unwrap();
+    stream.write_all(format!("GET /{}", "a".repeat(64 * 1024 - 5)).as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    assert!(response.contains("Request headers are too large"), "{}", response);
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+}
+
+#[test]
+fn test_cli_import() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let textract = dir.path().join("textract");
+    let unstructured = dir.path().join("unstructured");
+    std::fs::create_dir_all(textract.join("invoices")).unwrap();
+    std::fs::create_dir_all(&unstructured).unwrap();
+    let blocks = serde_json::json!({"Blocks": [
+        {"BlockType": "PAGE", "Page": 1},
+        {"BlockType": "LINE", "Page": 1, "Text": "Invoice 42", "Confidence": 99.0},
+        {"BlockType": "LINE", "Page": 2, "Text": "Total due", "Confidence": 55.0},
+    ]});
+    std::fs::write(textract.join("invoices/a.pdf.json"), blocks.to_string()).unwrap();
+    let elements = serde_json::json!([
+        {"type": "Title", "text": "Intro", "metadata": {"page_number": 1}},
+        {"type": "NarrativeText", "text": "Some text.", "metadata": {"page_number": 1}},
+        {"type": "Title", "text": "Terms", "metadata": {"page_number": 2}},
+    ]);
+    std::fs::write(unstructured.join("b.docx.json"), elements.to_string()).unwrap();
+    let out_dir = dir.path().join("out");
+    let run = |args: &[&str]| {
+        iris_cmd(dir.path())
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let read = |path: PathBuf| -> serde_json::Value { serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap() };
+
+    let output = run(&["import", "--from", "textract", textract.to_str().unwrap(), "-o", "json", "-f", out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let result = read(out_dir.join("invoices/a.json"));
+    assert_eq!(result["success"], true);
+    assert_eq!(result["text"], "Invoice 42\n\nTotal due");
+    assert_eq!(result["chunks"], serde_json::json!(["Invoice 42", "Total due"]));
+    assert_eq!(result["chunksMetadata"][1], r#"{"page":2}"#);
+    assert_eq!(result["regions"][1]["confidence"], 0.55);
+    assert_eq!(result["metadata"], r#"{"importedFrom":"textract","pages":2}"#);
+
+    // Sinks get the converted chunks, keyed by the original document
+    let sink = format!("jsonl:{}", dir.path().join("feed.jsonl").display());
+    let output = run(&["import", "--from", "unstructured", unstructured.to_str().unwrap(), "-o", "json", "--sink", &sink]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(result["chunks"], serde_json::json!(["Intro\n\nSome text.", "Terms"]));
+    let feed = std::fs::read_to_string(dir.path().join("feed.jsonl")).unwrap();
+    let upserts: Vec<serde_json::Value> = feed.lines().map(|l| serde_json::from_str(l).unwrap()).filter(|r: &serde_json::Value| r["op"] == "upsert").collect();
+    assert_eq!(upserts.len(), 2);
+    assert_eq!(upserts[0]["source"], "b.docx");
+
+    // Results of another tool aren't recognized
+    let output = run(&["import", "--from", "unstructured", textract.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expected a list of elements"));
+}
+
+#[test]
+fn test_cli_mcp() {
+    let (api_url, received) = start_recording_stub_api("Agent text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("memo.txt");
+    std::fs::write(&file, "memo").unwrap();
+    let mut server = iris_api_cmd(dir.path(), &api_url)
+        .arg("mcp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    // The response to one request, or null for notifications
+    let mut request = |message: serde_json::Value| -> serde_json::Value {
+        writeln!(stdin, "{}", message).unwrap();
+        if message.get("id").is_none() {
+            return serde_json::Value::Null;
+        }
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).expect("Response should be JSON")
+    };
+
+    let response = request(serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2025-03-26"}}));
+    assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+    assert_eq!(response["result"]["serverInfo"]["name"], "vectorize-iris");
+    request(serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}));
+
+    let response = request(serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}));
+    assert_eq!(response["result"]["tools"][0]["name"], "extract_text");
+
+    let response = request(serde_json::json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+        "params": {"name": "extract_text", "arguments": {"path": file}}}));
+    assert_eq!(response["id"], 3);
+    assert_eq!(response["result"]["isError"], false, "{}", response);
+    assert_eq!(response["result"]["content"][0]["text"], "Agent text");
+    assert_eq!(received.lock().unwrap().name, "memo.txt");
+
+    // Failed extractions are tool errors; bad arguments are protocol errors
+    let response = request(serde_json::json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call",
+        "params": {"name": "extract_text", "arguments": {"path": dir.path().join("missing.pdf")}}}));
+    assert_eq!(response["result"]["isError"], true);
+    let response = request(serde_json::json!({"jsonrpc": "2.0", "id": 5, "method": "tools/call",
+        "params": {"name": "extract_text", "arguments": {"path": file, "chunk_size": 0}}}));
+    assert_eq!(response["error"]["code"], -32602);
+    let response = request(serde_json::json!({"jsonrpc": "2.0", "id": 6, "method": "resources/list"}));
+    assert_eq!(response["error"]["code"], -32601);
+
+    drop(stdin);
+    assert!(server.wait().unwrap().success());
+}
+
+#[test]
+fn test_cli_summary_line() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    for name in ["a.txt", "b.txt"] {
+        std::fs::write(corpus.join(name), name).unwrap();
+    }
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", corpus.to_str().unwrap(), "-o", "json", "--summary-line"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let last = stderr.lines().last().unwrap();
+    assert!(last.starts_with("RESULT status=ok files=2 failed=0 duration="), "{}", last);
+
+    // A run that fails before extracting anything still ends with the line
+    let output = iris_cmd(dir.path())
+        .args(["extract", "nonexistent.pdf", "--summary-line"])
+        .env_remove("VECTORIZE_TOKEN")
+        .env_remove("VECTORIZE_ORG_ID")
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let last = stderr.lines().last().unwrap();
+    assert!(last.starts_with("RESULT status=error files=0 failed=0 "), "{}", last);
+}
+
+#[test]
+fn test_cli_callback_listen() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |listen: &str| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "text", "--callback-listen", listen])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run("127.0.0.1:0");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Waiting for completion callbacks on http://127.0.0.1:"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Extracted text"));
+
+    // The service can't reach a wildcard address, so it needs an explicit URL
+    let output = run("0.0.0.0:0");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("needs --callback-url"));
+}
+
+#[test]
+fn test_cli_retries_transient_errors() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |api_url: &str, max_retries: &str| {
+        iris_api_cmd(dir.path(), api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "text", "--retry-backoff", "10ms", "--max-retries", max_retries])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&start_flaky_stub_api("Extracted text", 2, "503 Service Unavailable"), "2");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Extracted text"));
+
+    let output = run(&start_flaky_stub_api("Extracted text", 2, "503 Service Unavailable"), "1");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("503"));
+}
+
+#[test]
+fn test_cli_result_cache() {
+    let api_url = start_stub_api("Cached text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let cache = dir.path().join("cache");
+    let extract = |api_url: &str, extra: &[&str]| {
+        iris_api_cmd(dir.path(), api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--max-retries", "0", "--result-cache", cache.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = extract(&api_url, &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // The second run never reaches the API
+    let output = extract("http://127.0.0.1:9", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(json["text"], "Cached text");
+    // Other options need another extraction
+    let output = extract("http://127.0.0.1:9", &["--chunk-size", "64"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_output_template() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let docs = dir.path().join("docs");
+    std::fs::create_dir(&docs).unwrap();
+    std::fs::write(docs.join("report.txt"), "doc").unwrap();
+    let template = dir.path().join("note.md.tera");
+    std::fs::write(&template, "---\nsource: {{ source }}\ntitle: {{ metadata.title }}\nchunks: {{ chunks | length }}\n---\n{{ text }}\n").unwrap();
+    let out = dir.path().join("out");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", docs.to_str().unwrap(), "-f", out.to_str().unwrap(), "--template", template.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // The template's inner extension names the output files
+    let rendered = std::fs::read_to_string(out.join("report.md")).expect("Rendered output should exist");
+    assert!(rendered.starts_with("---\nsource: report.txt\ntitle: Sample\nchunks: 2\n---\n"), "{}", rendered);
+    assert!(rendered.contains("Second chunk"));
+
+    // Syntax errors fail before anything is extracted
+    std::fs::write(&template, "{{ text").unwrap();
+    let output = iris_api_cmd(dir.path(), "http://127.0.0.1:9")
+        .args(["extract", docs.to_str().unwrap(), "-f", out.to_str().unwrap(), "--template", template.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid template"));
+}
+
+#[test]
+fn test_cli_merge_chunk_metadata() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    // --tag gives every chunk metadata
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "json", "--tag", "env=prod", "--merge-chunk-metadata"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert!(json.get("chunksMetadata").is_none());
+    assert_eq!(json["chunks"][1], serde_json::json!({
+        "index": 1,
+        "text": "Second chunk",
+        "chars": 12,
+        "tokens": 2,
+        "metadata": { "title": "Sample", "env": "prod" },
+        "schema": null
+    }));
+}
+
+#[test]
+fn test_cli_chunk_token_counts() {
+    let api_url = start_stub_api("Second chunk\n\nnaïve café");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |args: &[&str]| {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap()])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&run(&["-o", "json"])).expect("Output should be JSON");
+    assert_eq!(json["chunksChars"], serde_json::json!([12, 10]));
+    let tokens = json["chunksTokens"].as_array().expect("chunksTokens should be an array");
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0], 2);
+
+    // The counts follow --tokenizer
+    let json: serde_json::Value = serde_json::from_str(&run(&["-o", "json", "--tokenizer", "gpt2"])).unwrap();
+    assert_eq!(json["chunksTokens"][0], 2);
+    assert!(json["chunksTokens"][1].as_u64().unwrap() > 0);
+
+    let pretty = run(&["-o", "pretty"]);
+    assert!(pretty.contains("(12 chars, ~2 tokens)"), "{}", pretty);
+}
+
+#[test]
+fn test_cli_coordinator_and_workers() {
+    let api_url = start_stub_api("Distributed text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let docs = dir.path().join("docs");
+    std::fs::create_dir_all(docs.join("sub")).unwrap();
+    let mut manifest = String::from("# corpus\n");
+    for name in ["a.txt", "b.txt", "c.txt", "sub/d.txt"] {
+        std::fs::write(docs.join(name), name).unwrap();
+        manifest.push_str(&format!("docs/{}\n", name));
+    }
+    manifest.push_str("docs/missing.txt\n");
+    std::fs::write(dir.path().join("manifest.txt"), manifest).unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let coordinator_url = format!("http://127.0.0.1:{}", port);
+
+    let coordinator = iris_cmd(dir.path())
+        .args(["coordinator", "manifest.txt", "--listen", &format!("127.0.0.1:{}", port), "--max-attempts", "2"])
+        .current_dir(dir.path())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start coordinator");
+    let workers: Vec<_> = (0..2)
+        .map(|i| {
+            iris_api_cmd(dir.path(), &api_url)
+                .args(["worker", "--coordinator", &coordinator_url, "-f", "out", "-o", "json", "--name", &format!("worker-{}", i)])
+                .current_dir(dir.path())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .expect("Failed to start worker")
+        })
+        .collect();
+    let workers: Vec<_> = workers.into_iter().map(|w| w.wait_with_output().expect("Worker should exit")).collect();
+    let coordinator = coordinator.wait_with_output().expect("Coordinator should exit");
+
+    // Every document was extracted once, mirroring the manifest's paths
+    for name in ["a", "b", "c", "sub/d"] {
+        assert!(dir.path().join(format!("out/docs/{}.json", name)).exists(), "{} missing", name);
+    }
+    for worker in &workers {
+        assert!(worker.status.success(), "{}", String::from_utf8_lossy(&worker.stderr));
+    }
+    // The missing file failed on every attempt and fails the coordinator
+    let stderr = String::from_utf8_lossy(&coordinator.stderr);
+    assert!(!coordinator.status.success());
+    assert!(stderr.contains("4 of 5 documents extracted"), "{}", stderr);
+    assert!(stderr.contains("docs/missing.txt"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_chunk_overlap() {
+    let (api_url, received) = start_recording_stub_api("Overlapping text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let extract = |extra: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = extract(&["--chunk-size", "512", "--chunk-overlap", "64"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let request = received.lock().unwrap().extraction.clone();
+    assert_eq!(request["chunkSize"], 512);
+    assert_eq!(request["chunkOverlap"], 64);
+
+    // The overlap has to fit in the default chunk size of 256
+    let output = extract(&["--chunk-overlap", "256"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--chunk-overlap must be smaller than the chunk size (256)"));
+
+    // A batch fails once, before any file is uploaded
+    let docs = dir.path().join("docs");
+    std::fs::create_dir_all(&docs).unwrap();
+    std::fs::write(docs.join("a.txt"), "a").unwrap();
+    std::fs::write(docs.join("b.txt"), "b").unwrap();
+    received.lock().unwrap().upload.clear();
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", docs.to_str().unwrap(), "-o", "json", "-f", dir.path().join("out").to_str().unwrap(), "--chunk-overlap", "256"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("--chunk-overlap must be smaller").count(), 1, "{}", stderr);
+    assert!(received.lock().unwrap().upload.is_empty());
+}
+
+#[test]
+fn test_cli_chunk_unit_tokens() {
+    const LONG: &str = "The quarterly report covers revenue, operating costs, and the outlook for every region in detail.";
+    let (api_url, received) = start_recording_stub_api(
+        "The quarterly report covers revenue, operating costs, and the outlook for every region in detail.\n\nShort note.",
+    );
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let extract = |extra: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--chunk-unit", "tokens"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = extract(&["--chunk-size", "8", "--tokenizer", "text-embedding-3-small"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // The service is asked for characters
+    assert_eq!(received.lock().unwrap().extraction["chunkSize"], 32);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let chunks: Vec<&str> = json["chunks"].as_array().unwrap().iter().map(|c| c.as_str().unwrap()).collect();
+    // The long chunk is split on token boundaries without losing text; the short one is kept
+    assert!(chunks.len() > 3, "{:?}", chunks);
+    assert_eq!(chunks[..chunks.len() - 1].concat(), LONG);
+    assert_eq!(chunks.last(), Some(&"Short note."));
+
+    let output = extract(&["--tokenizer", "no-such-model"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown tokenizer 'no-such-model'"));
+}
+
+#[test]
+fn test_cli_chunking_strategy() {
+    let (api_url, received) = start_recording_stub_api("Strategy text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.md");
+    std::fs::write(&file, "# doc").unwrap();
+    let extract = |strategy: &str| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--chunking-strategy", strategy])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = extract("markdown");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(received.lock().unwrap().extraction["chunkingStrategy"], "markdown");
+
+    let output = extract("paragraph");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("possible values: fixed, markdown, sentence, semantic"));
+}
+
+#[test]
+fn test_cli_extraction_type() {
+    let (api_url, received) = start_recording_stub_api("Type text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let extract = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = extract(&[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(received.lock().unwrap().extraction["type"], "iris");
+
+    let output = extract(&["--extraction-type", "vectorize"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(received.lock().unwrap().extraction["type"], "vectorize");
+
+    let output = extract(&["--extraction-type", "ocr"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("possible values: iris, vectorize"));
+}
+
+#[test]
+fn test_cli_privacy_mode() {
+    let api_url = start_stub_api("Confidential settlement terms");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let extract = |privacy: bool| {
+        let mut command = iris_api_cmd(dir.path(), &api_url);
+        command
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--verbose"]);
+        if privacy {
+            command.arg("--privacy-mode");
+        }
+        let output = command.output().expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        (String::from_utf8_lossy(&output.stdout).into_owned(), String::from_utf8_lossy(&output.stderr).into_owned())
+    };
+
+    let (_, stderr) = extract(false);
+    assert!(stderr.contains("Confidential settlement terms"));
+    // The result still has the content; only the verbose dump is redacted
+    let (stdout, stderr) = extract(true);
+    assert!(stdout.contains("Confidential settlement terms"));
+    assert!(!stderr.contains("Confidential"), "{}", stderr);
+    assert!(stderr.contains("<sha256:"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_reports_warnings() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |extra: &[&str]| {
+        iris_api_cmd(dir.path(), &start_flaky_stub_api("Extracted text", 1, "503 Service Unavailable"))
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--retry-backoff", "10ms"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["--max-result-size", "4"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    let codes: Vec<&str> = json["warnings"].as_array().expect("warnings should be an array")
+        .iter()
+        .map(|w| w["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, ["retry", "text-spilled"]);
+
+    // --strict turns the retry into a failure
+    let output = run(&["--strict"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).trim().is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 warnings with --strict: [retry]"));
+}
+
+#[test]
+fn test_cli_max_result_size_spills_text() {
+    let text = "Opening \"quoted\" line\twith a tab\u{1}\n\nZürich 😀 closing chunk";
+    let api_url = start_stub_api(text);
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let temp = dir.path().join("tmp");
+    std::fs::create_dir_all(&temp).unwrap();
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("doc.txt"), "doc").unwrap();
+    let database = dir.path().join("chunks.sqlite");
+    let run = |args: &[&str]| {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(args)
+            .env("TMPDIR", &temp)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output
+    };
+
+    // The text goes from the response into a file that isn't temporary
+    let output = run(&["extract", corpus.join("doc.txt").to_str().unwrap(), "-o", "json", "--max-result-size", "10", "--normalize-unicode", "nfc"]);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert!(json["text"].is_null());
+    let text_file = PathBuf::from(json["textFile"].as_str().unwrap());
+    assert!(text_file.starts_with(dir.path().join(".vectorize-iris/texts")), "{}", text_file.display());
+    assert_eq!(std::fs::read_to_string(&text_file).unwrap(), text);
+    run(&["gc"]);
+    assert!(text_file.exists());
+
+    // Sinks copy it from the file
+    run(&["batch", corpus.to_str().unwrap(), "-o", "text", "--max-result-size", "10", "--sink", &format!("sqlite:{}", database.display())]);
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    let stored: String = connection.query_row("SELECT CAST(text AS TEXT) FROM documents", [], |row| row.get(0)).unwrap();
+    assert_eq!(stored, text);
+}
+
+#[test]
+fn test_cli_filter_chunks() {
+    let api_url = start_stub_api("Overview\n\nRisk factors\n\nOutlook");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |filter: &str| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--filter-chunks", filter])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(r#"text contains "Risk" or (index >= 2 and metadata.title == 'Sample')"#);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(json["chunks"], serde_json::json!(["Risk factors", "Outlook"]));
+    assert_eq!(json["text"], "Risk factors\n\nOutlook");
+
+    let output = run("metadata.section = 'Risk'");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unexpected '='"));
+}
+
+#[test]
+fn test_cli_metadata_schema_file() {
+    let (api_url, received) = start_recording_stub_api("Invoice");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("invoice.pdf");
+    std::fs::write(&file, "invoice").unwrap();
+    let schema = dir.path().join("invoice.json");
+    std::fs::write(&schema, "{\n  \"total\": \"number\",\n  \"vendor\": \"string\"\n}\n").unwrap();
+    let wrapped = dir.path().join("contract.json");
+    std::fs::write(&wrapped, r#"{"document": {"parties": "string"}}"#).unwrap();
+    let broken = dir.path().join("broken.json");
+    std::fs::write(&broken, "{\"total\": ").unwrap();
+    let run = |schema_args: &[String]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(schema_args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&[
+        "--metadata-schema-file".to_string(),
+        format!("invoice:{}", schema.display()),
+        "--metadata-schema-file".to_string(),
+        format!("contract:{}", wrapped.display()),
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let metadata = received.lock().unwrap().extraction["metadata"].clone();
+    assert_eq!(metadata["inferSchema"], false);
+    let schemas = metadata["schemas"].as_array().unwrap();
+    assert_eq!(schemas[0]["id"], "invoice");
+    let invoice: serde_json::Value = serde_json::from_str(schemas[0]["schema"].as_str().unwrap()).unwrap();
+    assert_eq!(invoice, serde_json::json!({ "document": { "total": "number", "vendor": "string" } }));
+    let contract: serde_json::Value = serde_json::from_str(schemas[1]["schema"].as_str().unwrap()).unwrap();
+    assert_eq!(contract, serde_json::json!({ "document": { "parties": "string" } }));
+
+    let output = run(&["--metadata-schema-file".to_string(), format!("invoice:{}", broken.display())]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid JSON in"));
+    let output = run(&["--metadata-schema-file".to_string(), schema.display().to_string()]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_validate_metadata() {
+    // The stub returns {"title": "Sample"}
+    let api_url = start_stub_api("Report");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("report.pdf");
+    std::fs::write(&file, "report").unwrap();
+    let run = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--validate-metadata"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["--metadata-schema", r#"doc:{"title":"string"}"#]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = run(&["--metadata-schema", r#"doc:{"type":"object","properties":{"title":{"type":"string","minLength":3}},"required":["title"]}"#]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run(&["--metadata-schema", r#"doc:{"title":"number","author":"string"}"#]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Metadata doesn't conform to schema 'doc' (2 differences)"), "{}", stderr);
+    assert!(stderr.contains("  title\n    - number\n    + string \"Sample\""), "{}", stderr);
+    assert!(stderr.contains("  author\n    - string\n    + missing"), "{}", stderr);
+
+    let schema = r#"doc:{"type":"object","properties":{"title":{"enum":["Annual Report","Invoice"]},"pages":{"type":"integer"}},"required":["pages"],"additionalProperties":false}"#;
+    let stderr = String::from_utf8_lossy(&run(&["--metadata-schema", schema]).stderr).to_string();
+    assert!(stderr.contains("  title\n    - value is not one of \"Annual Report\" or \"Invoice\"\n    + string \"Sample\""), "{}", stderr);
+    assert!(stderr.contains("  pages\n    - a value (required)\n    + missing"), "{}", stderr);
+
+    // Patterns are ECMA-262 regular expressions, as on the service
+    let output = run(&["--metadata-schema", r#"doc:{"type":"object","properties":{"title":{"type":"string","pattern":"^[A-Z]{3}-\\d+$"}}}"#]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("  title\n    - value does not match \"^[A-Z]{3}-\\d+$\"\n    + string \"Sample\""), "{}", stderr);
+
+    // $ref, not, and if/then/else are checked like any other keyword
+    let schema = r##"doc:{"type":"object","properties":{"title":{"$ref":"#/$defs/code"}},"$defs":{"code":{"not":{"const":"Sample"}}},"if":{"required":["title"]},"then":{"required":["pages"]}}"##;
+    let output = run(&["--metadata-schema", schema]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("(2 differences)"), "{}", stderr);
+    assert!(stderr.contains("  title\n    - "), "{}", stderr);
+    assert!(stderr.contains("  pages\n    - a value (required)\n    + missing"), "{}", stderr);
+    let schema = r##"doc:{"type":"object","properties":{"title":{"$ref":"#/$defs/title","format":"date"}},"$defs":{"title":{"type":"string"}}}"##;
+    let output = run(&["--metadata-schema", schema]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Nothing to check against
+    let output = run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--validate-metadata needs a schema"));
+}
+
+#[test]
+fn test_cli_preset() {
+    let (api_url, received) = start_recording_stub_api("Invoice");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("invoice.pdf");
+    std::fs::write(&file, "invoice").unwrap();
+    let run = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["--preset", "invoice"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let extraction = received.lock().unwrap().extraction.clone();
+    assert!(extraction["parsingInstructions"].as_str().unwrap().contains("line item tables"));
+    assert_eq!(extraction["metadata"]["inferSchema"], false);
+    let schemas = extraction["metadata"]["schemas"].as_array().unwrap();
+    assert_eq!(schemas.len(), 1);
+    assert_eq!(schemas[0]["id"], "invoice");
+    let schema: serde_json::Value = serde_json::from_str(schemas[0]["schema"].as_str().unwrap()).unwrap();
+    assert_eq!(schema["document"]["properties"]["total_amount"]["type"], "number");
+
+    // Explicit flags replace the preset's parts
+    let output = run(&["--preset", "contract", "--parsing-instructions", "Only the first page", "--metadata-schema", r#"nda:{"parties":"array"}"#]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let extraction = received.lock().unwrap().extraction.clone();
+    assert_eq!(extraction["parsingInstructions"], "Only the first page");
+    let schemas = extraction["metadata"]["schemas"].as_array().unwrap();
+    assert_eq!(schemas.iter().map(|schema| schema["id"].as_str().unwrap()).collect::<Vec<_>>(), ["nda"]);
+
+    let output = run(&["--preset", "receipt"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("available: invoice, resume, contract, research-paper"));
+}
+
+#[test]
+fn test_cli_user_preset() {
+    let (api_url, received) = start_recording_stub_api("Filing");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("10-k.pdf");
+    std::fs::write(&file, "filing").unwrap();
+    let presets = dir.path().join(".config/vectorize/presets");
+    std::fs::create_dir_all(&presets).unwrap();
+    std::fs::write(presets.join("risk.json"), r#"{"risks": "array"}"#).unwrap();
+    std::fs::write(
+        presets.join("filing.toml"),
+        r#"
+parsing_instructions = "Keep the financial statements as tables"
+chunk_size = 800
+chunking_strategy = "markdown"
+
+[schemas]
+risk = "risk.json"
+
+[schemas.filing]
+type = "object"
+required = ["company"]
+properties.company = { type = "string" }
+"#,
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["--preset", "filing"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let extraction = received.lock().unwrap().extraction.clone();
+    assert_eq!(extraction["parsingInstructions"], "Keep the financial statements as tables");
+    assert_eq!(extraction["chunkSize"], 800);
+    assert_eq!(extraction["chunkingStrategy"], "markdown");
+    let schemas = extraction["metadata"]["schemas"].as_array().unwrap();
+    assert_eq!(schemas.iter().map(|schema| schema["id"].as_str().unwrap()).collect::<Vec<_>>(), ["filing", "risk"]);
+    let schema: serde_json::Value = serde_json::from_str(schemas[0]["schema"].as_str().unwrap()).unwrap();
+    assert_eq!(schema["document"]["properties"]["company"]["type"], "string");
+
+    // Explicit flags win over the preset
+    let output = run(&["--preset", "filing", "--chunk-size", "300"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let extraction = received.lock().unwrap().extraction.clone();
+    assert_eq!(extraction["chunkSize"], 300);
+    assert_eq!(extraction["parsingInstructions"], "Keep the financial statements as tables");
+
+    let output = run(&["--preset", "memo"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("research-paper, filing"));
+
+    std::fs::write(presets.join("broken.toml"), "chunk_sise = 800\n").unwrap();
+    let output = run(&["--preset", "broken"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("chunk_sise"));
+}
+
+#[test]
+fn test_cli_schema_registry() {
+    let (api_url, received) = start_recording_stub_api("Invoice");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("invoice.pdf");
+    std::fs::write(&file, "invoice").unwrap();
+    let schema = dir.path().join("invoice.json");
+    std::fs::write(&schema, r#"{"total": "number", "vendor": "string"}"#).unwrap();
+    let run = |args: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["schema", "save", "invoice", schema.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.path().join(".config/vectorize/schemas/invoice.json").exists());
+
+    let output = run(&["schema", "list", "-o", "json"]);
+    let list: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(list[0]["name"], "invoice");
+    assert_eq!(list[0]["fields"], serde_json::json!(["total", "vendor"]));
+
+    let output = run(&["extract", file.to_str().unwrap(), "-o", "json", "--metadata-schema", "@invoice"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let metadata = received.lock().unwrap().extraction["metadata"].clone();
+    assert_eq!(metadata["schemas"][0]["id"], "invoice");
+    let sent: serde_json::Value = serde_json::from_str(metadata["schemas"][0]["schema"].as_str().unwrap()).unwrap();
+    assert_eq!(sent, serde_json::json!({ "document": { "total": "number", "vendor": "string" } }));
+
+    let output = run(&["extract", file.to_str().unwrap(), "--metadata-schema", "@receipt"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No saved schema named 'receipt'"));
+}
+
+#[test]
+fn test_cli_normalize_fields() {
+    let api_url = start_stub_api("Invoice");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("invoice.pdf");
+    std::fs::write(&file, "invoice").unwrap();
+
+    let run = |rules: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json", "--metadata-schema", r#"invoice:{"type": "object"}"#])
+            .args(rules.iter().flat_map(|rule| ["--normalize-fields", rule]))
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["invoice.date=date,invoice.due=date:dmy,invoice.paid=date", "invoice.total=amount", "contacts.phone=phone:+1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    let metadata: serde_json::Value = serde_json::from_str(json["metadata"].as_str().unwrap()).unwrap();
+    assert_eq!(metadata["invoice"]["date"], "2024-03-05");
+    assert_eq!(metadata["invoice"]["due"], "2024-04-05");
+    assert_eq!(metadata["invoice"]["total"], serde_json::json!({ "amount": "1234.50", "currency": "EUR" }));
+    assert_eq!(metadata["contacts"][0]["phone"], "+14155550100");
+    assert_eq!(metadata["contacts"][1]["phone"], "+442079460000");
+    // Values that can't be read are kept, with a warning
+    assert_eq!(metadata["invoice"]["paid"], "sometime");
+    let warnings = json["warnings"].as_array().unwrap();
+    assert!(warnings.iter().any(|w| w["code"] == "field-unnormalized" && w["message"].as_str().unwrap().contains("invoice.paid")));
+
+    let output = run(&["invoice.date=timestamp"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid field rule"));
+}
+
+#[test]
+fn test_cli_refine_missing() {
+    let api_url = start_stub_api("Invoice");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("invoice.pdf");
+    std::fs::write(&file, "invoice").unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "json", "--refine-missing", "2"])
+        .args(["--metadata-schema", r#"invoice:{"title":"string","total":"string","vendor":"string"}"#])
+        .args(["--normalize-fields", "total=amount"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    let metadata: serde_json::Value = serde_json::from_str(json["metadata"].as_str().unwrap()).unwrap();
+    assert_eq!(metadata["title"], "Sample");
+    assert_eq!(metadata["total"], serde_json::json!({ "amount": "10", "currency": "USD" }));
+    assert!(metadata.get("vendor").is_none());
+    let warnings = json["warnings"].as_array().unwrap();
+    assert_eq!(warnings.len(), 1, "{}", json);
+    assert_eq!(warnings[0]["code"], "fields-missing");
+    assert!(warnings[0]["message"].as_str().unwrap().contains("vendor"));
+}
+
+#[test]
+fn test_cli_summarize() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |command: &str| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args([command, file.to_str().unwrap(), "-o", "json", "--summarize"])
+            .args(["--metadata-schema", r#"invoice:{"total":"number"}"#])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // The summary extraction runs alongside the schema one and is merged into its metadata
+    let output = run("extract");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    let metadata: serde_json::Value = serde_json::from_str(json["metadata"].as_str().unwrap()).unwrap();
+    assert_eq!(metadata["title"], "Sample");
+    assert!(json.get("warnings").is_none_or(|w| w.as_array().unwrap().is_empty()), "{}", json);
+
+    let output = run("upload");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--summarize can't be combined"));
+}
+
+#[test]
+fn test_cli_entities() {
+    let api_url = start_stub_api("Alice wrote\n\nBob signed");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let sink = dir.path().join("chunks.csv");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "json", "--entities"])
+        .args(["--sink", &format!("csv:{}", sink.display())])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(json["entities"], serde_json::json!([{ "type": "amount", "text": "$10" }]));
+    assert_eq!(json["chunksEntities"][1], serde_json::json!([{ "type": "person", "text": "Bob" }]));
+
+    let csv = std::fs::read_to_string(&sink).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "op,id,source,index,text,metadata,people,organizations,dates,amounts,other_entities");
+    assert!(lines.iter().any(|l| l.starts_with("upsert,") && l.ends_with(",Alice,,,,")), "{}", csv);
+
+    // -o csv has the same entity columns
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "csv", "--entities"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<&str> = stdout.split("\r\n").filter(|row| !row.is_empty()).collect();
+    assert_eq!(rows[0], "index,text,char_count,metadata,people,organizations,dates,amounts,other_entities");
+    assert_eq!(rows[2], "1,Bob signed,10,,Bob,,,,");
+}
+
+#[test]
+fn test_cli_reads_stdin() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+
+    let run = |args: &[&str], input: &[u8]| {
+        let mut child = iris_api_cmd(dir.path(), &api_url)
+            .args(args)
+            .args(["-o", "json", "--verbose"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to execute command");
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = run(&["-", "--file-name", "report.txt"], b"piped content");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(json["text"], "Extracted text");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(r#""name": "report.txt""#), "{}", stderr);
+    assert!(stderr.contains("<binary data: 13 bytes>"), "{}", stderr);
+
+    let output = run(&["upload", "--stdin"], b"piped content");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(r#""name": "stdin""#));
+
+    let output = run(&["-"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No input on stdin"));
+
+    let output = run(&["extract", "doc.pdf", "--file-name", "report.pdf"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--file-name only applies"));
+}
+
+#[test]
+fn test_cli_names_url_uploads() {
+    // The stub API also serves the downloads, with an empty body
+    let (api_url, received) = start_recording_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let upload_name = |args: &[&str]| {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(args)
+            .args(["-o", "json"])
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        received.lock().unwrap().name.clone()
+    };
+
+    let url = format!("{}/docs/Annual%20Report.pdf?download=1", api_url);
+    assert_eq!(upload_name(&[&url]), "Annual Report.pdf");
+    assert_eq!(upload_name(&["upload", &url, "--file-name", "report-2024.pdf"]), "report-2024.pdf");
+    assert_eq!(upload_name(&[&format!("{}/docs/", api_url)]), "index");
+}
+
+#[test]
+fn test_cli_detects_content_type() {
+    let (api_url, received) = start_recording_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let content_type = |name: &str, content: &[u8], args: &[&str]| {
+        let file = dir.path().join(name);
+        std::fs::write(&file, content).unwrap();
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        received.lock().unwrap().content_type.clone()
+    };
+
+    assert_eq!(content_type("scan.pdf", b"%PDF-1.7\n", &[]), "application/pdf");
+    // The content wins over a misleading extension
+    assert_eq!(content_type("scan.bin", b"%PDF-1.7\n", &[]), "application/pdf");
+    assert_eq!(content_type("notes.md", b"# Notes\n", &[]), "text/markdown");
+    assert_eq!(content_type("blob", b"\x01\x02\x03", &[]), "application/octet-stream");
+    // --content-type overrides detection
+    assert_eq!(content_type("scan.tmp", b"\x01\x02\x03", &["--content-type", "application/pdf"]), "application/pdf");
+
+    let output = iris_cmd(dir.path())
+        .args(["extract", "scan.tmp", "--content-type", "pdf"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid content type 'pdf'"));
+}
+
+#[test]
+fn test_cli_resolves_tracked_changes() {
+    let (api_url, received) = start_recording_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("contract.docx");
+    let mut docx = zip::ZipWriter::new(std::fs::File::create(&file).unwrap());
+    docx.start_file("[Content_Types].xml", zip::write::SimpleFileOptions::default()).unwrap();
+    docx.write_all(b"<Types/>").unwrap();
+    docx.start_file("word/document.xml", zip::write::SimpleFileOptions::default()).unwrap();
+    docx.write_all(concat!(
+        r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body><w:p>"#,
+        r#"<w:r><w:t xml:space="preserve">The fee is </w:t></w:r>"#,
+        r#"<w:del w:id="1" w:author="Legal"><w:r><w:delText>$100</w:delText></w:r></w:del>"#,
+        r#"<w:ins w:id="2" w:author="Legal"><w:r><w:t>$200</w:t></w:r></w:ins>"#,
+        r#"</w:p></w:body></w:document>"#,
+    ).as_bytes()).unwrap();
+    docx.finish().unwrap();
+
+    let document = |mode: &str| {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "text", "--revisions", mode])
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = received.lock().unwrap().upload.clone();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(content)).expect("Upload should be a .docx");
+        assert!(archive.by_name("[Content_Types].xml").is_ok());
+        let mut xml = String::new();
+        archive.by_name("word/document.xml").unwrap().read_to_string(&mut xml).unwrap();
+        xml
+    };
+
+    let accepted = document("accept");
+    assert!(accepted.contains("<w:t>$200</w:t>") && !accepted.contains("$100"), "{}", accepted);
+    assert!(!accepted.contains("w:ins"));
+    let rejected = document("reject");
+    assert!(rejected.contains("<w:t>$100</w:t>") && !rejected.contains("$200"), "{}", rejected);
+    let both = document("both");
+    assert!(both.contains("<w:r><w:t>$100</w:t></w:r><w:r><w:t>$200</w:t></w:r>"), "{}", both);
+    // The rewritten document keeps the type of the original
+    assert_eq!(
+        received.lock().unwrap().content_type,
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+    );
+}
+
+#[test]
+fn test_cli_multiple_files() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    for name in ["a/report.pdf", "b/report.pdf", "notes.md"] {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, name).unwrap();
+    }
+    let out_dir = dir.path().join("out");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .current_dir(dir.path())
+        .args(["a/report.pdf", "b/report.pdf", "notes.md", "-o", "json", "-f", "out"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Successful: 3"), "{}", stderr);
+    // Same-named files keep their directories apart
+    for name in ["a/report.json", "b/report.json", "notes.json"] {
+        assert!(out_dir.join(name).exists(), "missing {}", name);
+    }
+}
+
+#[test]
+fn test_cli_files_from() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    std::fs::create_dir_all(dir.path().join("notes")).unwrap();
+    std::fs::write(dir.path().join("notes/a.md"), "a").unwrap();
+    // The stub answers any other GET with an empty 200, which stands in for a download
+    let url = format!("{}/docs/guide.txt?v=2", api_url);
+    std::fs::write(dir.path().join("list.txt"), format!("# this week's files\nnotes/a.md\n\n{}\n", url)).unwrap();
+
+    let mut child = iris_api_cmd(dir.path(), &api_url)
+        .current_dir(dir.path())
+        .args(["--files-from", "-", "-o", "json", "-f", "out"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    child.stdin.take().unwrap().write_all(&std::fs::read(dir.path().join("list.txt")).unwrap()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 2"), "{}", stderr);
+    assert!(dir.path().join("out/a.json").exists());
+    // URLs are written under their host and path
+    let host = api_url.trim_start_matches("http://");
+    assert!(dir.path().join("out").join(host).join("docs/guide.json").exists(), "{}", stderr);
+}
+
+#[test]
+fn test_cli_extract_files_from() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    std::fs::write(dir.path().join("a.md"), "a").unwrap();
+    std::fs::write(dir.path().join("b.md"), "b").unwrap();
+    std::fs::write(dir.path().join("list.txt"), "a.md\n").unwrap();
+    std::fs::write(dir.path().join("list0.txt"), "b.md\0").unwrap();
+
+    for (flag, list, expected) in [("--files-from", "list.txt", "out/a.json"), ("--files-from0", "list0.txt", "out/b.json")] {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .current_dir(dir.path())
+            .args(["extract", flag, list, "-o", "json", "-f", "out"])
+            .output()
+            .expect("Failed to execute command");
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}: {}", flag, stderr);
+        assert!(dir.path().join(expected).exists(), "{}: {}", flag, stderr);
+    }
+}
+
+#[test]
+fn test_cli_url_list_crawl_state() {
+    let api_url = start_stub_api("Page text");
+    // Serves a page with an ETag and one without validators; counts full responses
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let site = format!("http://{}", listener.local_addr().unwrap());
+    let full_responses = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let served = full_responses.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut if_none_match = String::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = header.to_ascii_lowercase().strip_prefix("if-none-match:") {
+                    if_none_match = value.trim().to_string();
+                }
+            }
+            let response = if request_line.contains("/robots.txt") {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            } else if request_line.contains("/guide.html") && if_none_match == "\"v1\"" {
+                "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            } else {
+                served.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let etag = if request_line.contains("/guide.html") { "ETag: \"v1\"\r\n" } else { "" };
+                format!("HTTP/1.1 200 OK\r\n{}Content-Length: 4\r\nConnection: close\r\n\r\npage", etag)
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let list = dir.path().join("urls.txt");
+    std::fs::write(&list, format!("{}/docs/guide.html\n{}/docs/notes.txt\n", site, site)).unwrap();
+    let out_dir = dir.path().join("out");
+    let crawl = |extra: &[&str]| {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["--files-from", list.to_str().unwrap(), "-o", "json", "-f", out_dir.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stderr).to_string()
+    };
+
+    let stderr = crawl(&[]);
+    assert!(stderr.contains("Successful: 2"), "{}", stderr);
+    assert_eq!(full_responses.load(std::sync::atomic::Ordering::SeqCst), 2);
+    let state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(out_dir.join(".vectorize-iris-crawl.json")).unwrap()).unwrap();
+    assert_eq!(state[format!("{}/docs/guide.html", site)]["etag"], "\"v1\"");
+
+    // The guide answers 304; the notes are downloaded again but have the same content
+    let stderr = crawl(&[]);
+    assert!(stderr.contains("2 unchanged since the last run"), "{}", stderr);
+    assert!(!stderr.contains("Batch Processing Complete"), "{}", stderr);
+    assert_eq!(full_responses.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+    let stderr = crawl(&["--refetch-all"]);
+    assert!(stderr.contains("Successful: 2"), "{}", stderr);
+    assert_eq!(full_responses.load(std::sync::atomic::Ordering::SeqCst), 5);
+}
+
+
+#[test]
+fn test_cli_url_list_robots() {
+    let api_url = start_stub_api("Page text");
+    // Disallows /private/ to everyone and /docs/ to polite-bot; records the User-Agent of page requests
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let site = format!("http://{}", listener.local_addr().unwrap());
+    let agents = Arc::new(Mutex::new(Vec::new()));
+    let seen = agents.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut agent = String::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = header.to_ascii_lowercase().strip_prefix("user-agent:") {
+                    agent = value.trim().to_string();
+                }
+            }
+            let body = if request_line.contains("/robots.txt") {
+                "User-agent: *\nDisallow: /private/\n\nUser-agent: polite-bot\nDisallow: /docs/\n".to_string()
+            } else {
+                seen.lock().unwrap().push(agent);
+                "page".to_string()
+            };
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let list = dir.path().join("urls.txt");
+    std::fs::write(&list, format!("{}/docs/guide.txt\n{}/private/notes.txt\n", site, site)).unwrap();
+    let crawl = |extra: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["--files-from", list.to_str().unwrap(), "-o", "json", "-f", dir.path().join("out").to_str().unwrap()])
+            .args(["--refetch-all", "--crawl-delay", "10ms"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = crawl(&[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 1"), "{}", stderr);
+    assert!(stderr.contains("Disallowed by robots.txt"), "{}", stderr);
+    assert!(agents.lock().unwrap().iter().all(|agent| agent.starts_with("vectorize-iris-cli/")));
+
+    // polite-bot has its own group, which disallows the guide instead of the notes
+    agents.lock().unwrap().clear();
+    let output = crawl(&["--crawl-user-agent", "polite-bot/1.0", "--max-per-domain", "1"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 1"), "{}", stderr);
+    assert!(dir.path().join("out").join(site.trim_start_matches("http://")).join("private/notes.json").exists());
+    assert_eq!(*agents.lock().unwrap(), vec!["polite-bot/1.0".to_string()]);
+
+    agents.lock().unwrap().clear();
+    let output = crawl(&["--crawl-user-agent", "polite-bot/1.0", "--ignore-robots"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 2"), "{}", stderr);
+    assert_eq!(*agents.lock().unwrap(), vec!["polite-bot/1.0".to_string(); 2]);
+}
+
+#[test]
+fn test_cli_readability() {
+    let (api_url, received) = start_recording_stub_api("Article text");
+    let page = r#"<!DOCTYPE html><html><head><title>Rates rise again</title><script>var ads = 1;</script></head>
+<body>
+  <header><a href="/">Daily News</a> <nav><a href="/world">World</a> <a href="/sport">Sport</a></nav></header>
+  <div class="ad-banner">Buy now &amp; save</div>
+  <article>
+    <h1>Rates rise again</h1>
+    <p>The central bank raised rates by a quarter point on Tuesday, citing <em>persistent</em> inflation.</p>
+    <p>Markets had expected the move, and <a href="/markets/bonds">bond yields</a> barely changed.</p>
+    <ul class="related"><li><a href="/a">Related story one</a></li><li><a href="/b">Related story two</a></li></ul>
+  </article>
+  <footer>Copyright Daily News</footer>
+</body></html>"#;
+    // Nesting far deeper than any real page
+    let deep = format!("<html><body>{}<p>Deep paragraph text, well past the nesting limit.</p>{}</body></html>", "<div>".repeat(200_000), "</div>".repeat(200_000));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let site = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let page = if request_line.contains("/deep") { deep.as_str() } else { page };
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", page.len(), page);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let extract_url = |url: &str, args: &[&str]| {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", url, "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let received = received.lock().unwrap();
+        (received.name.clone(), String::from_utf8_lossy(&received.upload).to_string())
+    };
+
+    let extract = |args: &[&str]| extract_url(&format!("{}/news/rates", site), args);
+
+    let (name, upload) = extract(&["--readability"]);
+    assert_eq!(name, "rates.md");
+    assert!(upload.starts_with("# Rates rise again\n\nThe central bank raised rates"), "{}", upload);
+    assert!(upload.contains("*persistent*"), "{}", upload);
+    assert!(upload.contains(&format!("[bond yields]({}/markets/bonds)", site)), "{}", upload);
+    for boilerplate in ["Sport", "Buy now", "Related story", "Copyright", "var ads"] {
+        assert!(!upload.contains(boilerplate), "{} in {}", boilerplate, upload);
+    }
+
+    let (name, upload) = extract(&["--readability", "html"]);
+    assert_eq!(name, "rates.html");
+    assert!(upload.contains("<title>Rates rise again</title>"), "{}", upload);
+    assert!(upload.contains("<h1>Rates rise again</h1>"), "{}", upload);
+    assert!(!upload.contains("Copyright"), "{}", upload);
+
+    // Without the flag the raw page is uploaded
+    let (_, upload) = extract(&[]);
+    assert!(upload.contains("Copyright Daily News"));
+
+    // Deeply nested pages are flattened rather than overflowing the stack
+    let (_, upload) = extract_url(&format!("{}/deep.html", site), &["--readability"]);
+    assert!(upload.contains("Deep paragraph text"), "{}", upload);
+}
+
+#[test]
+fn test_cli_site_credentials() {
+    let api_url = start_stub_api("Page text");
+    // (Host, path, Cookie, Authorization) of each request
+    let requests = Arc::new(Mutex::new(Vec::<(String, String, String, String)>::new()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let recorded = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let path = line.split_whitespace().nth(1).unwrap_or_default().to_string();
+            let (mut host, mut cookie, mut auth) = (String::new(), String::new(), String::new());
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).unwrap() <= 2 {
+                    break;
+                }
+                let (name, value) = line.trim_end().split_once(": ").unwrap_or_default();
+                match name.to_lowercase().as_str() {
+                    "host" => host = value.split(':').next().unwrap_or_default().to_string(),
+                    "cookie" => cookie = value.to_string(),
+                    "authorization" => auth = value.to_string(),
+                    _ => {}
+                }
+            }
+            let status = if path == "/robots.txt" { "404 Not Found" } else { "200 OK" };
+            recorded.lock().unwrap().push((host, path, cookie, auth));
+            let _ = stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 4\r\nConnection: close\r\n\r\npage", status).as_bytes());
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    // 127.0.0.1 is the wiki behind a login, localhost a public site on the same server
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "[sites.\"127.0.0.1\"]\nusername = \"analyst\"\npassword = \"secret\"\n\n[sites.\"127.0.0.1\".cookies]\nwiki_session = \"abc\"\n",
+    )
+    .unwrap();
+    let jar = dir.path().join("cookies.txt");
+    std::fs::write(
+        &jar,
+        "# Netscape HTTP Cookie File\n127.0.0.1\tFALSE\t/portal\tFALSE\t0\tportal\txyz\n#HttpOnly_127.0.0.1\tFALSE\t/\tFALSE\t1\texpired\told\n",
+    )
+    .unwrap();
+    let list = dir.path().join("urls.txt");
+    std::fs::write(
+        &list,
+        format!("http://127.0.0.1:{port}/wiki/page\nhttp://127.0.0.1:{port}/portal/report\nhttp://localhost:{port}/public/page\n"),
+    )
+    .unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["--files-from", list.to_str().unwrap(), "-o", "json", "-f", dir.path().join("out").to_str().unwrap()])
+        .args(["--config", config.to_str().unwrap(), "--cookie-jar", jar.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let requests = requests.lock().unwrap();
+    let request = |path: &str| requests.iter().find(|request| request.1 == path).cloned().unwrap_or_else(|| panic!("no request for {}", path));
+    // Basic auth for "analyst:secret"
+    let basic = "Basic YW5hbHlzdDpzZWNyZXQ=";
+    let (_, _, cookie, auth) = request("/wiki/page");
+    assert_eq!((cookie.as_str(), auth.as_str()), ("wiki_session=abc", basic));
+    let (_, _, cookie, auth) = request("/portal/report");
+    assert_eq!((cookie.as_str(), auth.as_str()), ("wiki_session=abc; portal=xyz", basic));
+    // The public site gets no credentials
+    let (host, _, cookie, auth) = request("/public/page");
+    assert_eq!((host.as_str(), cookie.as_str(), auth.as_str()), ("localhost", "", ""));
+}
+#[test]
+fn test_cli_login_web() {
+    let (api_url, received) = start_recording_stub_api("Page text");
+    // Echoes the Cookie header of each request as the page
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let site = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut line, mut cookie) = (String::new(), String::new());
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some(value) = line.trim_end().strip_prefix("cookie: ") {
+                    cookie = value.to_string();
+                }
+                line.clear();
+            }
+            let body = format!("cookies: {}", cookie);
+            let _ = stream.write_all(format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).as_bytes());
+        }
+    });
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+
+    // Cookies pasted from the browser's developer tools
+    let login = || {
+        let mut child = iris_cmd(dir.path())
+            .args(["login-web", "127.0.0.1", "--no-browser"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to execute command");
+        child.stdin.take().unwrap().write_all(b"Cookie: wiki_session=abc123; theme=dark\n").unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let output = login();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Saved 2 cookies for 127.0.0.1"));
+
+    // Stored encrypted
+    let session = std::fs::read(dir.path().join(".config/vectorize/sessions/127.0.0.1.session")).unwrap();
+    assert!(!String::from_utf8_lossy(&session).contains("abc123"));
+    // The key lives outside the sessions directory, and both are private
+    let sessions = dir.path().join(".config/vectorize/sessions");
+    assert!(!sessions.join("session.key").exists());
+    let key = dir.path().join(".config/vectorize/session.key");
+    assert_eq!(std::fs::read(&key).unwrap().len(), 32);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let session = sessions.join("127.0.0.1.session");
+        for path in [&key, &session] {
+            assert_eq!(std::fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600, "{}", path.display());
+        }
+        // Rewriting a session that was made readable to others makes it private again
+        std::fs::set_permissions(&session, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(login().status.success());
+        assert_eq!(std::fs::metadata(&session).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", &format!("{}/wiki/page", site), "-o", "json"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&received.lock().unwrap().upload), "cookies: wiki_session=abc123; theme=dark");
+}
+
+#[test]
+fn test_cli_files_from0() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    // Names a line-based list can't hold
+    let names = ["annual report.md", "line\nbreak.md", "#1.md"];
+    for name in names {
+        std::fs::write(dir.path().join(name), name).unwrap();
+    }
+
+    let mut child = iris_api_cmd(dir.path(), &api_url)
+        .current_dir(dir.path())
+        .args(["--files-from0", "-", "-o", "json", "-f", "out"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    child.stdin.take().unwrap().write_all(names.join("\0").as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 3"), "{}", stderr);
+    for name in ["annual report.json", "line\nbreak.json", "#1.json"] {
+        assert!(dir.path().join("out").join(name).exists(), "missing {:?}", name);
+    }
+}
+
+#[test]
+fn test_cli_regress() {
+    let api_url = start_stub_api("Alpha beta gamma\n\ndelta");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(corpus.join("contracts")).unwrap();
+    std::fs::write(corpus.join("contracts/lease.pdf"), "lease").unwrap();
+    std::fs::write(corpus.join("memo.md"), "memo").unwrap();
+    let baseline = dir.path().join("baselines");
+
+    let run = |extra: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["regress", "--corpus", corpus.to_str().unwrap(), "--baseline", baseline.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // No baselines yet
+    assert!(!run(&[]).status.success());
+    let output = run(&["--update"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(baseline.join("contracts/lease.json").exists());
+
+    let output = run(&["-o", "text"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().filter(|line| !line.is_empty()).collect();
+    assert_eq!(lines, ["PASS contracts/lease.pdf", "PASS memo.md"]);
+
+    // A baseline from a model version that extracted more text and different metadata
+    let path = baseline.join("memo.json");
+    let mut result: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    result["text"] = "Alpha beta gamma\n\ndelta epsilon".into();
+    result["metadata"] = "{\"title\": \"Memo\"}".into();
+    std::fs::write(&path, result.to_string()).unwrap();
+
+    let report_file = dir.path().join("report.json");
+    let output = run(&["-o", "text", "--report", report_file.to_str().unwrap(), "--max-text-change", "15"]);
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&report_file).unwrap()).unwrap();
+    assert_eq!(report["passed"], 1);
+    assert_eq!(report["failed"], 1);
+    let memo = &report["documents"][1];
+    assert_eq!(memo["outcome"], "fail");
+    // 1 of 9 words differs, within --max-text-change; the title doesn't match
+    assert_eq!(memo["textChange"], 11.11);
+    assert_eq!(memo["metadataChanges"], serde_json::json!(["title"]));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_compare_provider() {
+    let api_url = start_stub_api("Alpha beta gamma\n\ndelta");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    // The other provider "extracts" each document by printing it
+    std::fs::write(corpus.join("same.txt"), "alpha beta gamma delta").unwrap();
+    std::fs::write(corpus.join("worse.txt"), "alpha beta").unwrap();
+    std::fs::write(corpus.join("it's broken.txt"), "").unwrap();
+    let report_file = dir.path().join("comparison.json");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["compare", "--corpus", corpus.to_str().unwrap(), "-o", "json"])
+        .args(["--compare-provider", "cmd:test -s {file} && cat {file}", "--report", report_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(report, serde_json::from_slice::<serde_json::Value>(&std::fs::read(&report_file).unwrap()).unwrap());
+    assert_eq!(report["provider"], "cmd:test -s {file} && cat {file}");
+    // One correlation ID for the whole run
+    assert_eq!(report["correlationId"].as_str().map(str::len), Some(36));
+    assert_eq!(report["providerFailed"], 1);
+    assert_eq!(report["irisFailed"], 0);
+    let documents = report["documents"].as_array().unwrap();
+    let sources: Vec<&str> = documents.iter().map(|d| d["source"].as_str().unwrap()).collect();
+    assert_eq!(sources, ["it's broken.txt", "same.txt", "worse.txt"]);
+    assert!(documents[0]["provider"]["error"].is_string());
+    assert!(documents[0].get("similarity").is_none());
+    assert_eq!(documents[1]["similarity"], 100.0);
+    assert_eq!(documents[1]["iris"]["chunks"], 2);
+    // 2 of 6 words are only in one of the texts
+    assert_eq!(documents[2]["similarity"], 66.67);
+    assert_eq!(report["meanSimilarity"], 83.34);
+    assert!(documents[2]["provider"]["seconds"].is_number());
+
+    let output = iris_cmd(dir.path())
+        .args(["compare", "--corpus", corpus.to_str().unwrap(), "--compare-provider", "cmd:cat"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("{file}"));
+}
+
+#[test]
+fn test_cli_schedule_catches_up() {
+    let api_url = start_stub_api("Nightly refresh");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    std::fs::write(corpus.join("memo.md"), "memo").unwrap();
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    // The last run was long before the most recent 02:00, so it was missed
+    let state = out_dir.join(".vectorize-iris-schedule.json");
+    std::fs::write(&state, r#"{"schedule": "0 2 * * *", "lastRun": "2020-01-01T03:00:00Z"}"#).unwrap();
+
+    let schedule = |dir_arg: &str| {
+        let mut command = iris_api_cmd(dir.path(), &api_url);
+        command
+            .args(["batch", corpus.to_str().unwrap(), "--schedule", "0 2 * * *", "-o", "json", "-f", dir_arg]);
+        command
+    };
+    let mut daemon = schedule(out_dir.to_str().unwrap())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+    while std::fs::read_to_string(&state).unwrap_or_default().contains("2020-01-01") {
+        assert!(std::time::Instant::now() < deadline, "missed run was not caught up");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(out_dir.join("memo.json").exists());
+
+    // A second process on the same output directory is refused
+    let output = schedule(out_dir.to_str().unwrap()).output().expect("Failed to execute command");
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Another scheduled batch is already writing to"));
+
+    let output = iris_cmd(dir.path())
+        .args(["batch", corpus.to_str().unwrap(), "--schedule", "0 0 30 2 *", "-f", out_dir.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("never matches a date"));
+}
+
+#[test]
+fn test_cli_digest_webhook() {
+    let api_url = start_stub_api("Watched text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let drop_dir = dir.path().join("drop");
+    std::fs::create_dir_all(&drop_dir).unwrap();
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    // A tally from before a restart, whose 02:00 report was missed
+    let state = out_dir.join(".vectorize-iris-digest.json");
+    std::fs::write(&state, r#"{"schedule": "0 2 * * *", "lastSent": "2020-01-01T03:00:00Z", "tally": {
+        "since": "2020-01-01T03:00:00Z", "successful": 4, "failed": 1, "pages": 12,
+        "failures": [{"source": "broken.pdf", "error": "Extraction failed: corrupt file"}], "failuresOmitted": 0}}"#).unwrap();
+
+    let (webhook, reports) = start_webhook_receiver();
+
+    let mut watcher = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", drop_dir.to_str().unwrap(), "--watch", "-f", out_dir.to_str().unwrap()])
+        .args(["--digest-webhook", &webhook, "--digest-schedule", "0 2 * * *"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    let report = reports.recv_timeout(std::time::Duration::from_secs(20));
+    watcher.kill().unwrap();
+    watcher.wait().unwrap();
+    let report = report.expect("missed report was not sent");
+    assert_eq!(report["event"], "digest");
+    assert_eq!(report["files"], 5);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["irisPages"], 12);
+    assert_eq!(report["failures"][0]["source"], "broken.pdf");
+    assert_eq!(report["failures"][0]["error"], "Extraction failed: corrupt file");
+    let state: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    assert!(!state["lastSent"].as_str().unwrap().starts_with("2020"));
+
+    // Reports are for long-running batches only
+    let output = iris_cmd(dir.path())
+        .args(["batch", drop_dir.to_str().unwrap(), "-f", out_dir.to_str().unwrap(), "--digest-webhook", &webhook])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("need --watch or --schedule"));
+}
+
+#[test]
+fn test_cli_notify_slack() {
+    let api_url = start_stub_api("Notified text");
+    let (webhook, messages) = start_webhook_receiver();
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let docs = dir.path().join("docs");
+    std::fs::create_dir_all(&docs).unwrap();
+    std::fs::write(docs.join("a.txt"), "a").unwrap();
+    std::fs::write(docs.join("b.txt"), "b").unwrap();
+    let out_dir = dir.path().join("out");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["batch", docs.to_str().unwrap(), "-f", out_dir.to_str().unwrap(), "--layout", "flat"])
+        .args(["--notify-slack", &webhook])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let timeout = std::time::Duration::from_secs(5);
+    let started = messages.recv_timeout(timeout).expect("no start message");
+    assert!(started["text"].as_str().unwrap().contains("batch started:* 2 files"));
+    let finished = messages.recv_timeout(timeout).expect("no completion message");
+    let text = finished["text"].as_str().unwrap();
+    assert!(text.contains("2 succeeded, 0 failed"), "{}", text);
+    assert!(text.contains("index.json"), "{}", text);
+    // Nothing failed, so nobody is pinged
+    assert!(!text.contains("<!here>"), "{}", text);
+}
+
+#[test]
+fn test_cli_handwriting_review() {
+    let api_url = start_stub_api("Jane Doe");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("scan.pdf");
+    std::fs::write(&file, "%PDF").unwrap();
+
+    let run = |extra: &[&str]| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "--handwriting"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["-o", "json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert!(json["regions"][0].get("needsReview").is_none());
+    assert_eq!(json["regions"][1]["needsReview"], true);
+    let warning = json["warnings"].as_array().unwrap().iter().find(|w| w["code"] == "low-confidence").expect("low-confidence warning");
+    assert!(warning["message"].as_str().unwrap().contains("1 of 2 regions below 0.80 confidence (page 2)"), "{}", warning);
+
+    let output = run(&["-o", "pretty"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Recognition Quality") && stdout.contains("1 of 2 regions need review"), "{}", stdout);
+    assert!(stdout.contains("illegible scrawl"), "{}", stdout);
+
+    let output = run(&["-o", "json", "--review-below", "0.4"]);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert!(json.get("warnings").is_none(), "{}", json);
+}
+
+#[test]
+fn test_cli_verify_coverage() {
+    // The service "dropped" everything from the appendix on
+    let api_url = start_stub_api("Intro\n\nAlpha beta gamma delta, see docs.");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("guide.md");
+    std::fs::write(&file, "# Intro\n\nAlpha beta gamma delta, see [docs](https://example.com/docs).\n\n## Appendix\n\nEpsilon zeta eta theta.\n").unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "json", "--verify-coverage"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    // 7 of the source's 12 words; the link target isn't counted
+    assert_eq!(json["coverage"]["percent"], 58.3);
+    assert_eq!(json["coverage"]["sourceWords"], 12);
+    assert_eq!(json["coverage"]["missing"][1], serde_json::json!({ "heading": "Appendix", "text": "Epsilon zeta eta theta." }));
+    assert!(json["warnings"].as_array().unwrap().iter().any(|w| w["code"] == "incomplete-coverage"), "{}", json);
+}
+
+#[test]
+fn test_cli_form_fields() {
+    let api_url = start_stub_api("Application form");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("form.pdf");
+    std::fs::write(&file, "%PDF").unwrap();
+
+    let run = |format: &str| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", format, "--form-fields"])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run("json");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(json["form"][0], serde_json::json!({ "name": "applicant.name", "value": "Jane Doe", "type": "text", "page": 1 }));
+    assert_eq!(json["form"][1]["value"], true);
+    assert!(json["form"][2]["value"].is_null());
+
+    let output = run("pretty");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Form Fields (3)"), "{}", stdout);
+    assert!(stdout.contains("applicant.over18") && stdout.contains("true") && stdout.contains("(empty)"), "{}", stdout);
+}
+
+#[test]
+fn test_cli_waits_out_rate_limits() {
+    let api_url = start_flaky_stub_api("Extracted text", 2, "429 Too Many Requests");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    // Rate limits don't use up --max-retries
+    let started = std::time::Instant::now();
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "text", "--max-retries", "0", "--verbose"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(stderr.matches("Rate limited by the API, waiting 1.0s").count(), 2);
+    assert!(started.elapsed() >= std::time::Duration::from_secs(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Extracted text"));
+}
+
+#[test]
+fn test_cli_tee_output() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let result_file = dir.path().join("result.json");
+    let sink_file = dir.path().join("chunks.jsonl");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "json", "-f", result_file.to_str().unwrap()])
+        .args(["--sink", &format!("jsonl:{}", sink_file.display()), "--tee", "text"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("First chunk"));
+    assert!(!stdout.trim_start().starts_with('{'));
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&result_file).unwrap()).unwrap();
+    assert_eq!(json["chunks"].as_array().unwrap().len(), 2);
+    let sink = std::fs::read_to_string(&sink_file).unwrap();
+    assert!(sink.contains("First chunk") && sink.contains("Second chunk"));
+}
+
+#[test]
+fn test_cli_unstructured_json_output() {
+    let api_url = start_stub_api("# Report\n\nRevenue grew.\n\n## Regions\n\n- North\n\n| Region | Sales |\n|---|---|\n| North | <10 |");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("report.pdf");
+    std::fs::write(&file, "%PDF-1.4").unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "unstructured-json"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let elements: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).expect("Output should be a JSON list");
+    let kinds: Vec<&str> = elements.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["Title", "NarrativeText", "Title", "ListItem", "Table"]);
+    assert_eq!(elements[0]["metadata"]["filename"], "report.pdf");
+    assert_eq!(elements[0]["metadata"]["filetype"], "application/pdf");
+    assert_eq!(elements[2]["metadata"]["category_depth"], 1);
+    // Elements belong to the nearest title above them
+    assert_eq!(elements[1]["metadata"]["parent_id"], elements[0]["element_id"]);
+    assert_eq!(elements[2]["metadata"]["parent_id"], elements[0]["element_id"]);
+    assert_eq!(elements[4]["metadata"]["parent_id"], elements[2]["element_id"]);
+    assert_eq!(elements[4]["text"], "Region Sales\nNorth <10");
+    assert_eq!(
+        elements[4]["metadata"]["text_as_html"],
+        "<table><tr><td>Region</td><td>Sales</td></tr><tr><td>North</td><td>&lt;10</td></tr></table>"
+    );
+
+    // upload only prints an extraction ID, which has no elements
+    let output = iris_cmd(dir.path())
+        .args(["-o", "unstructured-json", "upload", file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-o unstructured-json only applies to extracted documents"));
+}
+
+#[test]
+fn test_cli_doc_graph_output() {
+    let api_url = start_stub_api("Preface\n\n# Report\n\nRevenue grew.\n\n## Regions\n\nNorth led.\n\n```\n# not a heading\n```\n\n# Outlook\n\nSteady.");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("report.pdf");
+    std::fs::write(&file, "%PDF-1.4").unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "doc-graph"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let graph: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    let ids = |nodes: &serde_json::Value| -> Vec<String> {
+        nodes.as_array().unwrap().iter().map(|node| node["id"].as_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(graph["type"], "document");
+    assert_eq!(graph["metadata"]["title"], "Sample");
+    // Chunks before the first heading belong to the document
+    assert_eq!(ids(&graph["chunks"]), ["chunk-0"]);
+    assert_eq!(graph["chunks"][0]["parent"], "document");
+    assert_eq!(ids(&graph["sections"]), ["section-1", "section-2"]);
+
+    let report = &graph["sections"][0];
+    assert_eq!((report["title"].as_str(), report["level"].as_u64(), report["parent"].as_str()), (Some("Report"), Some(1), Some("document")));
+    assert_eq!(ids(&report["chunks"]), ["chunk-1", "chunk-2"]);
+    let regions = &report["sections"][0];
+    assert_eq!(regions["id"], "section-1.1");
+    assert_eq!(regions["parent"], "section-1");
+    assert_eq!(regions["path"], serde_json::json!(["Report", "Regions"]));
+    // A # line in a code block is not a heading
+    assert_eq!(ids(&regions["chunks"]), ["chunk-3", "chunk-4", "chunk-5"]);
+    assert_eq!(regions["chunks"][1]["text"], "North led.");
+    assert_eq!(regions["chunks"][1]["parent"], "section-1.1");
+
+    let outlook = &graph["sections"][1];
+    assert_eq!(ids(&outlook["chunks"]), ["chunk-6", "chunk-7"]);
+    assert_eq!(outlook["sections"], serde_json::json!([]));
+
+    // A list of changed files has no document tree
+    let snapshot = dir.path().join("snapshot.json");
+    let output = iris_cmd(dir.path())
+        .args(["-o", "doc-graph", "changed", dir.path().to_str().unwrap(), "--against", snapshot.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-o doc-graph only applies to extracted documents"));
+}
+
+#[test]
+fn test_cli_chunk_titles() {
+    let api_url = start_stub_api("Preface\n\n# Annual Report\n\nRevenue grew.\n\n## Risk Factors\n\nRates may rise.\n\n# Outlook\n\nSteady.");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("report.pdf");
+    std::fs::write(&file, "%PDF-1.4").unwrap();
+    let run = |args: &[&str]| -> serde_json::Value {
+        let output = iris_api_cmd(dir.path(), &api_url)
+            .args(["extract", file.to_str().unwrap(), "-o", "json"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice(&output.stdout).expect("Output should be JSON")
+    };
+    let metadata = |result: &serde_json::Value, i: usize| -> serde_json::Value {
+        result["chunksMetadata"][i].as_str().map(|m| serde_json::from_str(m).unwrap()).unwrap_or_default()
+    };
+
+    let result = run(&[]);
+    // Nothing before the first heading
+    assert_eq!(result["chunksMetadata"][0], serde_json::Value::Null);
+    assert_eq!(metadata(&result, 2), serde_json::json!({ "title": "Annual Report", "breadcrumb": ["Annual Report"] }));
+    assert_eq!(metadata(&result, 4), serde_json::json!({ "title": "Risk Factors", "breadcrumb": ["Annual Report", "Risk Factors"] }));
+    assert_eq!(metadata(&result, 6), serde_json::json!({ "title": "Outlook", "breadcrumb": ["Outlook"] }));
+
+    // Filters see the titles
+    let result = run(&["--filter-chunks", r#"metadata.title == "Risk Factors""#]);
+    assert_eq!(result["chunks"], serde_json::json!(["## Risk Factors", "Rates may rise."]));
+
+    let result = run(&["--no-chunk-titles"]);
+    assert_eq!(result.get("chunksMetadata"), None);
+}
+
+#[test]
+fn test_cli_csv_output() {
+    let api_url = start_stub_api("First chunk\n\nSecond, \"quoted\" chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "csv"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<&str> = stdout.split("\r\n").filter(|row| !row.is_empty()).collect();
+    assert_eq!(rows, [
+        "index,text,char_count,metadata",
+        "0,First chunk,11,",
+        "1,\"Second, \"\"quoted\"\" chunk\",22,",
+    ]);
+
+    // Subcommands that print a report have no CSV form, rather than printing text
+    let output = iris_cmd(dir.path())
+        .args(["-o", "csv", "config", "list-profiles"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-o csv only applies to extracted documents"));
+}
+
+#[test]
+fn test_cli_parquet_output() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let table = dir.path().join("chunks.parquet");
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "parquet", "-f", table.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    if cfg!(feature = "parquet") {
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = std::fs::read(&table).unwrap();
+        assert!(content.starts_with(b"PAR1") && content.ends_with(b"PAR1"));
+    } else {
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--features parquet"));
+        assert!(!table.exists());
+    }
+
+    // Subcommands that print a report have no Parquet form
+    let output = iris_cmd(dir.path())
+        .args(["-o", "parquet", "config", "list-profiles"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    if cfg!(feature = "parquet") {
+        assert!(String::from_utf8_lossy(&output.stderr).contains("-o parquet only applies to extracted documents"));
+    }
+}
+
+#[test]
+fn test_cli_batch_sinks_stream_records() {
+    let api_url = start_stub_api("First chunk\n\nSecond chunk");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let corpus = dir.path().join("corpus");
+    std::fs::create_dir_all(&corpus).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(corpus.join(name), name).unwrap();
+    }
+    std::fs::write(corpus.join("copy-of-a.txt"), "a.txt").unwrap();
+    let run = |sink: &str| {
+        iris_api_cmd(dir.path(), &api_url)
+            .args(["batch", corpus.to_str().unwrap(), "-o", "json", "--sink", sink, "--flush-every", "2"])
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // The duplicate is written from its original's result, one file at a time
+    let feed = dir.path().join("feed.jsonl");
+    let output = run(&format!("jsonl:{}", feed.display()));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Successful: 4") && stderr.contains("Duplicates: 1"), "{}", stderr);
+    assert!(stderr.contains("copy-of-a.txt is a duplicate of a.txt"), "{}", stderr);
+    let upserts = std::fs::read_to_string(&feed).unwrap().lines().filter(|line| line.contains("\"upsert\"")).count();
+    assert_eq!(upserts, 8);
+
+    let table = dir.path().join("feed.parquet");
+    let output = run(&format!("parquet:{}", table.display()));
+    if cfg!(feature = "parquet") {
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = std::fs::read(&table).unwrap();
+        assert!(content.starts_with(b"PAR1") && content.ends_with(b"PAR1"));
+    } else {
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--features parquet"));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_output_file_fifo() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let fifo = dir.path().join("results.fifo");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+
+    let reader = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || std::fs::read_to_string(fifo).unwrap())
+    };
+
+    let output = iris_api_cmd(dir.path(), &api_url)
+        .args(["extract", file.to_str().unwrap(), "-o", "json", "-f", fifo.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        // Unblock the reader before failing
+        let _ = std::fs::write(&fifo, "");
+        panic!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let json: serde_json::Value = serde_json::from_str(&reader.join().unwrap()).unwrap();
+    assert_eq!(json["text"], "Extracted text");
+}
+
+#[test]
+fn test_cli_config_file() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let config = dir.path().join("iris.toml");
+    std::fs::write(&config, format!("api_url = \"{}\"\napi_token = \"config-token\"\norg_id = \"config-org\"\noutput = \"json\"\n", api_url)).unwrap();
+
+    let run = |extra: &[&str]| {
+        iris_cmd(dir.path())
+            .args(["extract", file.to_str().unwrap(), "--config", config.to_str().unwrap()])
+            .args(extra)
+            .env_remove("VECTORIZE_API_URL")
+            .env_remove("VECTORIZE_TOKEN")
+            .env_remove("VECTORIZE_ORG_ID")
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["text"], "Extracted text");
+
+    // Flags override the file
+    let output = run(&["-o", "text"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Extracted text");
+
+    std::fs::write(&config, "chunk_sise = 10\n").unwrap();
+    let output = run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid config file"));
+}
+
+
+#[test]
+fn test_cli_base_url() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+
+    let run = |base_url: &str| {
+        iris_cmd(dir.path())
+            .args(["extract", file.to_str().unwrap(), "-o", "text", "--base-url", base_url])
+            .env("VECTORIZE_BASE_URL", "https://unused.invalid")
+            .env_remove("VECTORIZE_API_URL")
+            .env("VECTORIZE_TOKEN", "test-token")
+            .env("VECTORIZE_ORG_ID", "test-org")
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // The flag wins over the environment
+    let output = run(&format!("{}/", api_url));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Extracted text");
+
+    let output = run("ftp://iris.example.com");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must be an http or https URL"));
+}
+
+#[test]
+fn test_cli_config_profiles() {
+    let api_url = start_stub_api("Extracted text");
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "doc").unwrap();
+    let config = dir.path().join("iris.toml");
+    std::fs::write(&config, format!(
+        "org_id = \"default-org\"\n\n[profiles.work]\napi_url = \"{}\"\napi_token = \"work-token\"\norg_id = \"work-org\"\n\n[profiles.personal]\norg_id = \"personal-org\"\n",
+        api_url
+    )).unwrap();
+
+    let run = |args: &[&str]| {
+        iris_cmd(dir.path())
+            .args(["--config", config.to_str().unwrap()])
+            .args(args)
+            .env_remove("VECTORIZE_API_URL")
+            .env_remove("VECTORIZE_TOKEN")
+            .env_remove("VECTORIZE_ORG_ID")
+            .env_remove("VECTORIZE_PROFILE")
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["extract", file.to_str().unwrap(), "-o", "text", "--profile", "work"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Extracted text");
+
+    let output = run(&["config", "list-profiles", "-o", "text"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "personal\nwork\n");
+
+    let output = run(&["config", "show", "--profile", "missing"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown profile 'missing' (available: personal, work)"));
+}